@@ -0,0 +1,26 @@
+#![no_main]
+use grin_util::secp::{ContextFlag, Secp256k1};
+use libfuzzer_sys::fuzz_target;
+use mw_btc_swap::bitcoin::btcroutines::{deserialize_priv_key, deserialize_pub_key, deserialize_script};
+use mw_btc_swap::grin::grin_routines::{
+    deserialize_commitment, deserialize_grin_pub_key, deserialize_secret_key, deserialize_slate, MPBPContext,
+};
+use mw_btc_swap::util::SwapRng;
+
+// Everything a peer sends us over the wire is read as a string and handed to
+// one of these parsers, none of them may panic on malformed input
+fuzz_target!(|data: &[u8]| {
+    if let Ok(msg) = std::str::from_utf8(data) {
+        let msg = String::from(msg);
+        let secp = Secp256k1::with_caps(ContextFlag::Commit);
+        let _ = deserialize_slate(&msg);
+        let _ = MPBPContext::from_string(&msg, SwapRng::seeded(0));
+        let _ = deserialize_secret_key(&msg, &secp);
+        let _ = deserialize_grin_pub_key(&msg, &secp);
+        let _ = deserialize_commitment(&msg);
+        let _ = deserialize_pub_key(&msg);
+        let _ = deserialize_priv_key(&msg);
+        let _ = deserialize_script(&msg);
+        let _ = msg.parse::<i64>();
+    }
+});
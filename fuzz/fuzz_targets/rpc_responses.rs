@@ -0,0 +1,48 @@
+#![no_main]
+use grin_core::core::Transaction;
+use libfuzzer_sys::fuzz_target;
+use mw_btc_swap::bitcoin::bitcoin_core::BitcoinCore;
+use mw_btc_swap::grin::grin_core::GrinCore;
+use mw_btc_swap::net::http::{HttpResponse, RequestFactory};
+use mw_btc_swap::settings::{BtcNodeSettings, GrinNodeSettings};
+use mw_btc_swap::util::SwapRng;
+
+fn stub(content : &str) -> RequestFactory {
+    RequestFactory::new(Some(HttpResponse {
+        status : 200,
+        content : String::from(content)
+    }))
+}
+
+// Every node query is answered with the fuzzed body, a misbehaving or
+// compromised node must not be able to crash the swap
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = std::str::from_utf8(data) {
+        let btc = BitcoinCore::new(BtcNodeSettings {
+            url : String::from("localhost"),
+            user : String::from("fuzz"),
+            pass : String::from("fuzz"),
+            port : 8332,
+            id : String::from("fuzz")
+        }, stub(content));
+        let _ = btc.get_network_info();
+        let _ = btc.get_current_block_height();
+        let _ = btc.get_address_final_balance(String::from("tb1qfuzz"));
+        let _ = btc.get_tx_confirmations("00");
+        let _ = btc.get_blockchain_info();
+        let _ = btc.get_block_header("00");
+        let _ = btc.get_median_time_past();
+
+        let grin = GrinCore::with_rng(GrinNodeSettings {
+            url : String::from("localhost"),
+            user : String::from("fuzz"),
+            pass : String::from("fuzz"),
+            port : 3413,
+            id : String::from("fuzz")
+        }, stub(content), SwapRng::seeded(0));
+        let _ = grin.get_block_height();
+        let _ = grin.get_output("00");
+        let _ = grin.get_kernel("00");
+        let _ = grin.push_transaction(Transaction::empty());
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mw_btc_swap::swap::slate::{parse_priv_slate, parse_pub_slate};
+
+// Public slates are shared between the peers and private slates are read back
+// from disk, both have to be rejected with an error when they are corrupted
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_pub_slate(data);
+    let _ = parse_priv_slate(data);
+});
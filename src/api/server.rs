@@ -0,0 +1,392 @@
+use crate::api::types::{ApiError, ImportBtcParams, ImportGrinParams, InitOfferParams, ListSwapsParams, RpcError, RpcRequest, RpcResponse, SwapIdParams, SwapInfo};
+use crate::commands::cmd_types::{accept::Accept, cancel::Cancel, command::Command, execute::Execute, import_btc::ImportBtc, import_grin::ImportGrin, init::Init, lock::Lock, refund::Refund};
+use crate::commands::context::ChainContext;
+use crate::commands::parser::validate_init_args;
+use crate::constants::DEFAULT_OFFER_EXPIRY;
+use crate::enums::BtcLockType;
+use crate::enums::GrinLockType;
+use crate::swap::pricing::CounterAmount;
+use crate::swap::fill::fill_range_from_args;
+use crate::swap::store::store_types::{SwapQuery, SwapStore};
+use crate::SwapSlate;
+use crate::Settings;
+use bitcoin::secp256k1::{All, Secp256k1};
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+use rand::Rng;
+use crate::util::SwapRng;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::{Cursor, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+const API_SECRET_FILE : &str = "api_secret";
+const API_USER : &str = "mwswap";
+const API_PATH : &str = "/v1/swap";
+// Requests are small JSON documents, larger bodies are refused
+const MAX_BODY_SIZE : u64 = 1024 * 1024;
+
+/// Everything the API needs to run the swap commands
+pub struct ApiContext<'a> {
+    pub settings : &'a Settings,
+    pub chain : &'a ChainContext,
+    pub store : &'a mut dyn SwapStore,
+    pub rng : &'a mut SwapRng,
+    pub btc_secp : &'a Secp256k1<All>,
+    pub grin_secp : &'a GrinSecp256k1
+}
+
+/// Run the JSON-RPC server on the configured address, requests are handled one after another.
+/// Every request needs basic auth with the user `mwswap` and the secret from the `api_secret` file.
+///
+/// # Arguments
+///
+/// * `ctx` the API context
+pub fn serve(ctx : &mut ApiContext) -> Result<(), String> {
+    let secret = load_api_secret(&ctx.settings.slate_directory, ctx.rng)?;
+    let expected_auth = basic_auth_header(&secret);
+    let addr = format!("{}:{}", ctx.settings.api.addr, ctx.settings.api.port);
+    let server = Server::http(addr.as_str())
+        .map_err(|e| format!("Unable to start API server on {}: {}", addr, e))?;
+    println!("Swap API listening on http://{}{}", addr, API_PATH);
+
+    for mut request in server.incoming_requests() {
+        let response = handle_request(ctx, &mut request, &expected_auth);
+        if let Err(e) = request.respond(response) {
+            println!("Failed to send API response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Read the API secret from the slate directory, a new one is created on first use.
+/// The secret is only readable by its owner, a secret other users can access is refused.
+///
+/// # Arguments
+///
+/// * `directory` the slate directory
+/// * `rng` Randomness generator
+pub fn load_api_secret(directory : &str, rng : &mut SwapRng) -> Result<String, String> {
+    let path = format!("{}/{}", directory, API_SECRET_FILE);
+    if Path::new(&path).exists() {
+        check_secret_permissions(&path)?;
+        let secret = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read API secret: {}", e))?;
+        let secret = String::from(secret.trim());
+        if secret.is_empty() {
+            Err(format!("API secret {} is empty, remove it to create a new one", path))
+        }
+        else {
+            Ok(secret)
+        }
+    }
+    else {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Unable to create slate directory {}: {}", directory, e))?;
+        let secret : [u8; 20] = rng.gen();
+        let secret = hex::encode(secret);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path)
+            .map_err(|e| format!("Unable to create API secret {}: {}", path, e))?;
+        file.write_all(secret.as_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("Unable to write API secret {}: {}", path, e))?;
+        println!("Created API secret in {}", path);
+        Ok(secret)
+    }
+}
+
+// Refuse a secret which users other than its owner can access
+fn check_secret_permissions(path : &str) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        let mode = fs::metadata(path)
+            .map_err(|e| format!("Unable to read the permissions of {}: {}", path, e))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(format!("API secret {} can be accessed by other users (mode {:o}), restrict it with chmod 600 {}", path, mode & 0o777, path));
+        }
+    }
+    Ok(())
+}
+
+/// Value of the Authorization header a client has to send
+///
+/// # Arguments
+///
+/// * `secret` the API secret
+pub fn basic_auth_header(secret : &str) -> String {
+    format!("Basic {}", base64::encode(format!("{}:{}", API_USER, secret)))
+}
+
+fn handle_request(ctx : &mut ApiContext, request : &mut Request, expected_auth : &str) -> Response<Cursor<Vec<u8>>> {
+    let auth = request.headers().iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .map(|h| String::from(h.value.as_str()));
+    if !is_authorized(auth.as_deref(), expected_auth) {
+        return Response::from_string("Unauthorized")
+            .with_status_code(StatusCode(401))
+            .with_header(Header::from_bytes(&b"WWW-Authenticate"[..], &b"Basic realm=\"mw-btc-swap\""[..]).unwrap());
+    }
+    if request.url() != API_PATH {
+        return Response::from_string("Not found").with_status_code(StatusCode(404));
+    }
+    if *request.method() != Method::Post {
+        return Response::from_string("Method not allowed").with_status_code(StatusCode(405));
+    }
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().take(MAX_BODY_SIZE).read_to_string(&mut body) {
+        return Response::from_string(format!("Unable to read request: {}", e)).with_status_code(StatusCode(400));
+    }
+    let response = handle_body(ctx, &body);
+    Response::from_string(serde_json::to_string(&response).unwrap())
+        .with_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap())
+}
+
+fn is_authorized(header : Option<&str>, expected : &str) -> bool {
+    match header {
+        // compare without exiting early on the first differing byte
+        Some(h) => h.len() == expected.len() && h.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0,
+        None => false
+    }
+}
+
+/// Handle the body of a JSON-RPC request
+///
+/// # Arguments
+///
+/// * `ctx` the API context
+/// * `body` the request body
+pub fn handle_body(ctx : &mut ApiContext, body : &str) -> RpcResponse {
+    let request : RpcRequest = match serde_json::from_str(body) {
+        Ok(r) => r,
+        Err(e) => return rpc_error(Value::Null, -32700, format!("Parse error: {}", e))
+    };
+    if request.jsonrpc != "2.0" {
+        return rpc_error(request.id, -32600, String::from("Invalid request, only JSON-RPC 2.0 is supported"));
+    }
+    let result = match request.method.as_str() {
+        "init_offer" => init_offer(ctx, request.params),
+        "accept_offer" => swap_command(ctx, request.params, |id| Box::new(Accept::new(id, false, None)), false),
+        "import_btc" => import_btc(ctx, request.params),
+        "import_grin" => import_grin(ctx, request.params),
+        "lock" => swap_command(ctx, request.params, |id| Box::new(Lock::new(id)), true),
+        "execute" => swap_command(ctx, request.params, |id| Box::new(Execute::new(id)), true),
+        "cancel" => swap_command(ctx, request.params, |id| Box::new(Cancel::new(id)), true),
+        "refund" => swap_command(ctx, request.params, |id| Box::new(Refund::new(id)), true),
+        "get_swap" => parse_params::<SwapIdParams>(request.params)
+            .and_then(|p| swap_info(ctx, p.swap_id))
+            .and_then(to_value),
+        "list_swaps" => list_swaps(ctx, request.params),
+        other => return rpc_error(request.id, -32601, format!("Method not found: {}", other))
+    };
+    RpcResponse {
+        jsonrpc : String::from("2.0"),
+        id : request.id,
+        result : Some(serde_json::to_value(result).unwrap()),
+        error : None
+    }
+}
+
+fn rpc_error(id : Value, code : i32, message : String) -> RpcResponse {
+    RpcResponse {
+        jsonrpc : String::from("2.0"),
+        id : id,
+        result : None,
+        error : Some(RpcError {
+            code : code,
+            message : message
+        })
+    }
+}
+
+fn parse_params<T : DeserializeOwned>(params : Value) -> Result<T, ApiError> {
+    serde_json::from_value(params)
+        .map_err(|e| ApiError::InvalidParams(e.to_string()))
+}
+
+fn to_value<T : serde::Serialize>(value : T) -> Result<Value, ApiError> {
+    serde_json::to_value(value)
+        .map_err(|e| ApiError::CommandFailed(e.to_string()))
+}
+
+/// Execute a command and persist the slate it returns, like the command line does
+fn run_command(ctx : &mut ApiContext, cmd : Box<dyn Command>) -> Result<Option<u64>, ApiError> {
+    let slate : Option<SwapSlate> = cmd.execute(ctx.settings, ctx.chain, ctx.store, ctx.rng, ctx.btc_secp, ctx.grin_secp)
+        .map_err(|e| ApiError::CommandFailed(e.to_string()))?;
+    match slate {
+        Some(slate) => {
+            ctx.store.write_slate(&slate, true, true)
+                .map_err(ApiError::CommandFailed)?;
+            Ok(Some(slate.id))
+        },
+        None => Ok(None)
+    }
+}
+
+fn swap_info(ctx : &mut ApiContext, id : u64) -> Result<SwapInfo, ApiError> {
+    if !ctx.store.contains(id) {
+        return Err(ApiError::SwapNotFound(id));
+    }
+    let slate = ctx.store.read_slate(id)
+        .map_err(ApiError::CommandFailed)?;
+    Ok(SwapInfo {
+        id : slate.id,
+        role : slate.prv_slate.role,
+        owns_btc : slate.owns_btc(),
+        pub_slate : slate.pub_slate,
+        events : slate.events
+    })
+}
+
+fn swap_command<F>(ctx : &mut ApiContext, params : Value, cmd : F, stored : bool) -> Result<Value, ApiError>
+    where F : Fn(u64) -> Box<dyn Command> {
+    let params : SwapIdParams = parse_params(params)?;
+    // accepting an offer creates the stored swap from the public slate file
+    if stored && !ctx.store.contains(params.swap_id) {
+        return Err(ApiError::SwapNotFound(params.swap_id));
+    }
+    run_command(ctx, cmd(params.swap_id))?;
+    to_value(swap_info(ctx, params.swap_id)?)
+}
+
+fn init_offer(ctx : &mut ApiContext, params : Value) -> Result<Value, ApiError> {
+    let params : InitOfferParams = parse_params(params)?;
+    let expiry = params.expiry_minutes.unwrap_or(DEFAULT_OFFER_EXPIRY);
+    validate_init_args(params.from_currency, params.from_amount, params.to_currency, params.to_amount.unwrap_or(0), params.timeout_minutes, expiry)
+        .map_err(|e| ApiError::InvalidParams(String::from(e)))?;
+    let counter = CounterAmount::from_args(params.to_amount, params.price.as_deref(), params.spread_bps)
+        .map_err(ApiError::InvalidParams)?;
+    let fill = fill_range_from_args(params.min_fill, params.max_fill, params.from_amount)
+        .map_err(ApiError::InvalidParams)?;
+    let cmd = Init::new(params.from_currency, params.to_currency, params.from_amount, counter, params.timeout_minutes, expiry, params.btc_lock.unwrap_or(BtcLockType::HEIGHT), params.grin_lock.unwrap_or(GrinLockType::HEIGHT), fill);
+    let id = run_command(ctx, Box::new(cmd))?
+        .ok_or(ApiError::CommandFailed(String::from("No swap was created")))?;
+    to_value(swap_info(ctx, id)?)
+}
+
+fn import_btc(ctx : &mut ApiContext, params : Value) -> Result<Value, ApiError> {
+    let p : ImportBtcParams = parse_params(params)?;
+    if !ctx.store.contains(p.swap_id) {
+        return Err(ApiError::SwapNotFound(p.swap_id));
+    }
+    run_command(ctx, Box::new(ImportBtc::new(p.swap_id, p.txid, p.vout, p.value, p.sk, p.pub_script)))?;
+    to_value(swap_info(ctx, p.swap_id)?)
+}
+
+fn import_grin(ctx : &mut ApiContext, params : Value) -> Result<Value, ApiError> {
+    let p : ImportGrinParams = parse_params(params)?;
+    if !ctx.store.contains(p.swap_id) {
+        return Err(ApiError::SwapNotFound(p.swap_id));
+    }
+    run_command(ctx, Box::new(ImportGrin::new(p.swap_id, p.commitment, p.blinding_factor, p.value)))?;
+    to_value(swap_info(ctx, p.swap_id)?)
+}
+
+fn list_swaps(ctx : &mut ApiContext, params : Value) -> Result<Value, ApiError> {
+    let params : ListSwapsParams = if params.is_null() { ListSwapsParams::default() } else { parse_params(params)? };
+    let query = SwapQuery {
+        status : params.status,
+        currency : params.currency,
+        from : None,
+        to : None
+    };
+    let entries = ctx.store.query(&query)
+        .map_err(ApiError::CommandFailed)?;
+    to_value(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::types::ApiError;
+    use crate::commands::context::ChainContext;
+    use crate::sim::node::offline_settings;
+    use crate::swap::store::file_store::FileStore;
+    use crate::swap::store::store_types::SwapStore;
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+    use grin_util::secp::{ContextFlag, Secp256k1 as GrinSecp256k1};
+    use rand::Rng;
+    use serde_json::Value;
+    use std::env;
+
+    use super::{basic_auth_header, handle_body, is_authorized, load_api_secret, ApiContext, API_SECRET_FILE};
+
+    #[test]
+    fn test_basic_auth() {
+        let expected = basic_auth_header("secret");
+        assert_eq!("Basic bXdzd2FwOnNlY3JldA==", expected);
+        assert!(is_authorized(Some("Basic bXdzd2FwOnNlY3JldA=="), &expected));
+        assert!(!is_authorized(Some("Basic bXdzd2FwOnNlY3JldB=="), &expected));
+        assert!(!is_authorized(Some("Basic"), &expected));
+        assert!(!is_authorized(None, &expected));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_api_secret_permissions() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("mw-btc-swap-test-{}", rand::thread_rng().gen::<u64>()));
+        let dir = dir.to_string_lossy().to_string();
+        let path = format!("{}/{}", dir, API_SECRET_FILE);
+        let secret = load_api_secret(&dir, &mut get_os_rng()).unwrap();
+        assert_eq!(0o600, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+        assert_eq!(secret, load_api_secret(&dir, &mut get_os_rng()).unwrap());
+
+        // a secret other users can read is refused
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(load_api_secret(&dir, &mut get_os_rng()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rpc_calls() {
+        let settings = offline_settings();
+        let dir = env::temp_dir().join(format!("mw-btc-swap-test-{}", rand::thread_rng().gen::<u64>()));
+        let mut store = FileStore::new(&dir.to_string_lossy()).unwrap();
+        store.write_slate(&sample_slate(7, true), true, true).unwrap();
+        let mut rng = get_os_rng();
+        let btc_secp = get_secp256k1_curve();
+        let grin_secp = GrinSecp256k1::with_caps(ContextFlag::Commit);
+        let chain = ChainContext::from_settings(&settings);
+        let mut ctx = ApiContext {
+            settings : &settings,
+            chain : &chain,
+            store : &mut store,
+            rng : &mut rng,
+            btc_secp : &btc_secp,
+            grin_secp : &grin_secp
+        };
+
+        // protocol errors
+        assert_eq!(-32700, handle_body(&mut ctx, "{").error.unwrap().code);
+        let unknown = handle_body(&mut ctx, r#"{"jsonrpc":"2.0","id":1,"method":"unknown"}"#);
+        assert_eq!(-32601, unknown.error.unwrap().code);
+
+        // typed results
+        let swap = handle_body(&mut ctx, r#"{"jsonrpc":"2.0","id":2,"method":"get_swap","params":{"swap_id":7}}"#);
+        assert_eq!(Value::from(2), swap.id);
+        let result = swap.result.unwrap();
+        assert_eq!(Value::from(7), result["Ok"]["id"]);
+        let list = handle_body(&mut ctx, r#"{"jsonrpc":"2.0","id":3,"method":"list_swaps"}"#);
+        assert_eq!(1, list.result.unwrap()["Ok"].as_array().unwrap().len());
+
+        // typed errors
+        let missing = handle_body(&mut ctx, r#"{"jsonrpc":"2.0","id":4,"method":"lock","params":{"swap_id":8}}"#);
+        let err : ApiError = serde_json::from_value(missing.result.unwrap()["Err"].clone()).unwrap();
+        assert_eq!(ApiError::SwapNotFound(8), err);
+        let invalid = handle_body(&mut ctx, r#"{"jsonrpc":"2.0","id":5,"method":"get_swap","params":{}}"#);
+        assert!(invalid.result.unwrap()["Err"]["InvalidParams"].is_string());
+        let amount = handle_body(&mut ctx, r#"{"jsonrpc":"2.0","id":6,"method":"init_offer","params":{"from_currency":"BTC","to_currency":"GRIN","from_amount":2100000000000001,"to_amount":1,"timeout_minutes":600}}"#);
+        assert!(amount.result.unwrap()["Err"]["InvalidParams"].is_string());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::enums::{BtcLockType, Currency, GrinLockType, SwapRole, SwapStatus};
+use crate::swap::swap_types::{SwapEvent, SwapSlatePub};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+/// JSON-RPC 2.0 request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcRequest {
+    pub jsonrpc : String,
+    pub id : Value,
+    pub method : String,
+    #[serde(default)]
+    pub params : Value
+}
+
+/// JSON-RPC 2.0 response. Like the grin-wallet owner API a handled call returns
+/// its outcome as `result` with either an `Ok` or an `Err` value, `error` is only
+/// set if the request itself could not be handled
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcResponse {
+    pub jsonrpc : String,
+    pub id : Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result : Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error : Option<RpcError>
+}
+
+/// Protocol level error of a JSON-RPC request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcError {
+    pub code : i32,
+    pub message : String
+}
+
+/// Error returned by an API method
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum ApiError {
+    // The parameters of the call are missing or invalid
+    InvalidParams(String),
+    // No swap with this id is stored
+    SwapNotFound(u64),
+    // The command implementing the method failed
+    CommandFailed(String)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InitOfferParams {
+    pub from_currency : Currency,
+    pub to_currency : Currency,
+    pub from_amount : u64,
+    // exactly one of to_amount, price and spread_bps has to be set
+    #[serde(default)]
+    pub to_amount : Option<u64>,
+    #[serde(default)]
+    pub price : Option<String>,
+    #[serde(default)]
+    pub spread_bps : Option<i32>,
+    pub timeout_minutes : u64,
+    #[serde(default)]
+    pub expiry_minutes : Option<u64>,
+    #[serde(default)]
+    pub btc_lock : Option<BtcLockType>,
+    #[serde(default)]
+    pub grin_lock : Option<GrinLockType>,
+    // set min_fill to make the offer partially fillable
+    #[serde(default)]
+    pub min_fill : Option<u64>,
+    #[serde(default)]
+    pub max_fill : Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SwapIdParams {
+    pub swap_id : u64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportBtcParams {
+    pub swap_id : u64,
+    pub txid : String,
+    pub vout : u32,
+    pub value : u64,
+    pub sk : String,
+    pub pub_script : String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportGrinParams {
+    pub swap_id : u64,
+    pub commitment : String,
+    pub blinding_factor : String,
+    pub value : u64
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ListSwapsParams {
+    #[serde(default)]
+    pub status : Option<SwapStatus>,
+    #[serde(default)]
+    pub currency : Option<Currency>
+}
+
+/// A stored swap as returned by the API, the private slate is never exposed
+#[derive(Serialize, Deserialize)]
+pub struct SwapInfo {
+    pub id : u64,
+    pub role : Option<SwapRole>,
+    pub owns_btc : Option<bool>,
+    pub pub_slate : SwapSlatePub,
+    pub events : Vec<SwapEvent>
+}
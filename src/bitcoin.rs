@@ -1,4 +1,5 @@
 pub mod bitcoin_types;
+pub mod bitcoin_backend;
 pub mod btcroutines;
 pub mod bitcoin_core;
 pub mod bitcoin_core_responses;
\ No newline at end of file
@@ -0,0 +1,89 @@
+use crate::bitcoin::bitcoin_core::BitcoinCore;
+use crate::constants::BTC_FEE;
+use bitcoin::util::address::Address;
+use bitcoin::Transaction;
+
+/// Access to the Bitcoin chain needed by the swap protocol
+/// Implemented by the Bitcoin Core RPC client, other backends or mocks
+/// can be used by the protocol and the commands in its place
+pub trait BitcoinBackend {
+    /// Current height of the chain
+    fn get_current_block_height(&self) -> Result<u64, String>;
+
+    /// Median time past of the chain tip, timestamp timelocks (CLTV) are compared against it
+    fn get_median_time_past(&self) -> Result<u64, String>;
+
+    /// Watch an address so the outputs locked to it can be verified
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` the address to watch
+    fn import_btc_address(&self, addr : Address) -> Result<(), String>;
+
+    /// Unspent balance of a watched address in satoshis
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` the address for which to check the balance
+    fn get_address_final_balance_addr(&self, addr : Address) -> Result<u64, String>;
+
+    /// Number of confirmations of a transaction, 0 if it is still in the mempool
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction
+    fn get_tx_confirmations(&self, txid : &str) -> Result<u64, String>;
+
+    /// Returns true if the output has been spent by a transaction in a block
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction which created the output
+    /// * `vout` index of the output
+    fn is_output_spent(&self, txid : &str, vout : u32) -> Result<bool, String>;
+
+    /// Broadcast a signed transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` the signed transaction to send
+    fn send_raw_transaction(&self, tx : Transaction) -> Result<(), String>;
+
+    /// Fee in satoshis to pay for the lock, redeem and refund transactions
+    fn estimate_fee(&self) -> Result<u64, String>;
+}
+
+impl BitcoinBackend for BitcoinCore {
+    fn get_current_block_height(&self) -> Result<u64, String> {
+        BitcoinCore::get_current_block_height(self)
+    }
+
+    fn get_median_time_past(&self) -> Result<u64, String> {
+        BitcoinCore::get_median_time_past(self)
+    }
+
+    fn import_btc_address(&self, addr : Address) -> Result<(), String> {
+        BitcoinCore::import_btc_address(self, addr)
+    }
+
+    fn get_address_final_balance_addr(&self, addr : Address) -> Result<u64, String> {
+        BitcoinCore::get_address_final_balance_addr(self, addr)
+    }
+
+    fn get_tx_confirmations(&self, txid : &str) -> Result<u64, String> {
+        BitcoinCore::get_tx_confirmations(self, txid)
+    }
+
+    fn is_output_spent(&self, txid : &str, vout : u32) -> Result<bool, String> {
+        BitcoinCore::is_output_spent(self, txid, vout)
+    }
+
+    fn send_raw_transaction(&self, tx : Transaction) -> Result<(), String> {
+        BitcoinCore::send_raw_transaction(self, tx)
+    }
+
+    // the transactions of a swap have one or two inputs and outputs, a fixed fee covers them
+    fn estimate_fee(&self) -> Result<u64, String> {
+        Ok(BTC_FEE)
+    }
+}
@@ -9,7 +9,7 @@ use bitcoin::util::address::Address;
 use bitcoin::util::psbt::serialize::Serialize;
 use bitcoin::Transaction;
 
-use super::bitcoin_core_responses::{BlockCountResponse, JsonRpcResponse};
+use super::bitcoin_core_responses::{BlockCountResponse, BlockHeaderResult, BlockchainInfoResult, JsonRpcResponse, RawTransactionResult};
 
 pub struct BitcoinCore {
     settings : BtcNodeSettings,
@@ -20,7 +20,10 @@ pub enum BTC_CORE_RPC_TYPES {
     GET_NETWORK_INFO,
     LIST_UNSPENT,
     SEND_RAW_TRANSACTION,
-    GET_BLOCK_COUNT
+    GET_BLOCK_COUNT,
+    GET_RAW_TRANSACTION,
+    GET_BLOCKCHAIN_INFO,
+    GET_BLOCK_HEADER
 }
 
 impl BitcoinCore {
@@ -46,7 +49,7 @@ impl BitcoinCore {
             Ok(x) => {
                 println!("{}", x.content);
                 let parsed : NetworkInfo = serde_json::from_str(&x.content)
-                    .expect("Failed to parse networkinfo Json response");
+                    .map_err(|e| format!("Failed to parse networkinfo Json response: {}", e))?;
                 if parsed.id != self.settings.id {
                     Err("RPC Request and Response id didn't match!".to_string())
                 }
@@ -55,7 +58,7 @@ impl BitcoinCore {
                         Err(parsed.error.unwrap().message)
                     }
                     else {
-                        Ok(parsed.result.expect("GetNetworkInfo result was empty"))
+                        parsed.result.ok_or(String::from("GetNetworkInfo result was empty"))
                     }
                 }
             },
@@ -81,7 +84,7 @@ impl BitcoinCore {
         match req.execute() {
             Ok(x) => {
                 let parsed : JsonRpcResponse<()> = serde_json::from_str(&x.content)
-                    .expect("Failed to parse import_btc_address response");
+                    .map_err(|e| format!("Failed to parse import_btc_address response: {}", e))?;
                 if parsed.id != self.settings.id {
                     Err("RPC Request and Response id didn't match".to_string())
                 }
@@ -117,7 +120,7 @@ impl BitcoinCore {
             Ok(x) => {
                 println!("{}", x.content);
                 let parsed : ListUnspentResponse = serde_json::from_str(&x.content)
-                    .expect("Failed to parse listunspent rpc response");
+                    .map_err(|e| format!("Failed to parse listunspent rpc response: {}", e))?;
                 if parsed.id != self.settings.id {
                     Err("RPC Request and Response id didn't match!".to_string())
                 }
@@ -128,9 +131,10 @@ impl BitcoinCore {
                     else {
                         let mut balance : u64 = 0;
                         // Sum up the unspent balances of the UTXOs under this address
-                        for e in &parsed.result.expect("GetAddressFinalBalance Result was empty") {
-                            let sat_amount = (e.amount * 100_000_000.0) as u64;
-                            balance = balance + sat_amount;
+                        for e in &parsed.result.ok_or(String::from("GetAddressFinalBalance Result was empty"))? {
+                            let sat_amount = (e.amount * 100_000_000.0).round() as u64;
+                            balance = balance.checked_add(sat_amount)
+                                .ok_or(String::from("Unspent balance overflows"))?;
                         }
                         Ok(balance)
                     }
@@ -150,7 +154,7 @@ impl BitcoinCore {
             Ok(x) => {
                 println!("{}", x.content);
                 let parsed : BlockCountResponse = serde_json::from_str(&x.content)
-                    .unwrap();
+                    .map_err(|e| format!("Failed to parse getblockcount rpc response: {}", e))?;
                 if parsed.id != self.settings.id {
                     Err(String::from("RPC Request and Response id mismacht"))
                 }
@@ -192,7 +196,7 @@ impl BitcoinCore {
             Ok(x) => {
                 println!("{}", x.content);   
                 let parsed : SendRawTxResponse = serde_json::from_str(&x.content)
-                    .expect("Failed to parse sendrawtx rpc response");             
+                    .map_err(|e| format!("Failed to parse sendrawtx rpc response: {}", e))?;             
                 if parsed.id != self.settings.id {
                     Err("RPC Request and Response id didn't match".to_string())
                 }
@@ -209,6 +213,114 @@ impl BitcoinCore {
         }
     }
 
+    /// Query the number of confirmations of a transaction, 0 if it is still in the mempool
+    /// Requires the node to know the transaction (mempool, wallet or txindex)
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction
+    pub fn get_tx_confirmations(&self, txid : &str) -> Result<u64, String> {
+        let mut params : Vec<JsonRpcParam> = Vec::new();
+        params.push(JsonRpcParam::String(String::from(txid)));
+        params.push(JsonRpcParam::Bool(true));
+        let rpc = JsonRpc::new(String::from("1.0"), self.settings.id.clone(), String::from("getrawtransaction"), params);
+        let url = self.get_url();
+        let req = self.req_factory.new_json_rpc_request(url, rpc, self.settings.user.clone(), self.settings.pass.clone());
+        match req.execute() {
+            Ok(x) => {
+                let parsed : JsonRpcResponse<RawTransactionResult> = serde_json::from_str(&x.content)
+                    .map_err(|e| format!("Failed to parse getrawtransaction rpc response: {}", e))?;
+                if parsed.id != self.settings.id {
+                    Err("RPC Request and Response id didn't match!".to_string())
+                }
+                else {
+                    if parsed.error.is_some() {
+                        Err(parsed.error.unwrap().message)
+                    }
+                    else {
+                        match parsed.result {
+                            Some(r) => Ok(r.confirmations.unwrap_or(0)),
+                            None => Err(String::from("GetRawTransaction result was empty"))
+                        }
+                    }
+                }
+            }
+            Err(e) => Err(e.to_string())
+        }
+    }
+
+    /// Check if an output has been spent by a transaction in a block
+    /// Spends which are only in the mempool are not taken into account,
+    /// unknown outputs are reported as spent
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction which created the output
+    /// * `vout` index of the output
+    pub fn is_output_spent(&self, txid : &str, vout : u32) -> Result<bool, String> {
+        let mut params : Vec<JsonRpcParam> = Vec::new();
+        params.push(JsonRpcParam::String(String::from(txid)));
+        params.push(JsonRpcParam::Int(u64::from(vout)));
+        params.push(JsonRpcParam::Bool(false));
+        let rpc = JsonRpc::new(String::from("1.0"), self.settings.id.clone(), String::from("gettxout"), params);
+        let out : Option<serde_json::Value> = self.execute_rpc_optional(rpc, "gettxout")?;
+        Ok(out.is_none())
+    }
+
+    /// Query the state of the chain, including the hash and median time past of the tip
+    pub fn get_blockchain_info(&self) -> Result<BlockchainInfoResult, String> {
+        let rpc = JsonRpc::new(String::from("1.0"), self.settings.id.clone(), String::from("getblockchaininfo"), vec![]);
+        self.execute_rpc(rpc, "getblockchaininfo")
+    }
+
+    /// Query the header of a block, containing its timestamp and median time past
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` hash of the block
+    pub fn get_block_header(&self, hash : &str) -> Result<BlockHeaderResult, String> {
+        let mut params : Vec<JsonRpcParam> = Vec::new();
+        params.push(JsonRpcParam::String(String::from(hash)));
+        params.push(JsonRpcParam::Bool(true));
+        let rpc = JsonRpc::new(String::from("1.0"), self.settings.id.clone(), String::from("getblockheader"), params);
+        self.execute_rpc(rpc, "getblockheader")
+    }
+
+    /// Query the median time past of the chain tip, the time timestamp based
+    /// timelocks (CLTV) are compared against
+    pub fn get_median_time_past(&self) -> Result<u64, String> {
+        Ok(self.get_blockchain_info()?.mediantime)
+    }
+
+    fn execute_rpc<T : serde::de::DeserializeOwned>(&self, rpc : JsonRpc, method : &str) -> Result<T, String> {
+        self.execute_rpc_optional(rpc, method)?
+            .ok_or(format!("{} result was empty", method))
+    }
+
+    // some calls like gettxout answer with a null result instead of an error
+    fn execute_rpc_optional<T : serde::de::DeserializeOwned>(&self, rpc : JsonRpc, method : &str) -> Result<Option<T>, String> {
+        let url = self.get_url();
+        let req = self.req_factory.new_json_rpc_request(url, rpc, self.settings.user.clone(), self.settings.pass.clone());
+        match req.execute() {
+            Ok(x) => {
+                let parsed : JsonRpcResponse<T> = serde_json::from_str(&x.content)
+                    .map_err(|e| format!("Failed to parse {} rpc response: {}", method, e))?;
+                if parsed.id != self.settings.id {
+                    Err("RPC Request and Response id didn't match!".to_string())
+                }
+                else {
+                    if parsed.error.is_some() {
+                        Err(parsed.error.unwrap().message)
+                    }
+                    else {
+                        Ok(parsed.result)
+                    }
+                }
+            }
+            Err(e) => Err(e.to_string())
+        }
+    }
+
     fn get_url(&self) -> String {
         format!("http://{}:{}", self.settings.url, self.settings.port)
     }
@@ -224,21 +336,18 @@ use crate::bitcoin::bitcoin_core::BTC_CORE_RPC_TYPES;
 use crate::bitcoin::bitcoin_core::BitcoinCore;
 use crate::bitcoin::bitcoin_types::BTCInput;
 use crate::net::http::RequestFactory;
-use crate::Settings;
+use crate::sim::node::offline_settings;
 use crate::settings::BtcNodeSettings;
 use crate::net::http::HttpResponse;
 use crate::util;
-use std::{fs, str::FromStr};
+use std::str::FromStr;
 use crate::bitcoin::bitcoin_core_responses::SendRawTxResponse;
 use crate::bitcoin::bitcoin_core_responses::Error;
 use crate::bitcoin::btcroutines::create_lock_transaction;
 
 
     fn get_btc_core_settings() -> BtcNodeSettings {
-        let contents = fs::read_to_string("config/settings.json")
-            .expect("Something went wrong reading the settings file");
-        let read_settings = Settings::parse_json_string(&contents);
-        read_settings.btc
+        offline_settings().btc
     }
 
     fn get_mock_response(rtype : BTC_CORE_RPC_TYPES) -> String {
@@ -254,6 +363,15 @@ use crate::bitcoin::btcroutines::create_lock_transaction;
             },
             BTC_CORE_RPC_TYPES::GET_BLOCK_COUNT => {
                 String::from(r#"{"result":1906786,"error":null,"id":"mw-btc-swap"}"#)
+            },
+            BTC_CORE_RPC_TYPES::GET_BLOCKCHAIN_INFO => {
+                String::from(r#"{"result":{"chain":"test","blocks":1906786,"headers":1906786,"bestblockhash":"000000000000001c7e4a9e7ff86dd2c2bfd0e3c1ea1e4b0a4c1c9b9d7a6f2e31","difficulty":7306565.81,"mediantime":1606814112,"verificationprogress":0.99,"initialblockdownload":false,"chainwork":"","size_on_disk":0,"pruned":false,"warnings":""},"error":null,"id":"mw-btc-swap"}"#)
+            },
+            BTC_CORE_RPC_TYPES::GET_BLOCK_HEADER => {
+                String::from(r#"{"result":{"hash":"000000000000001c7e4a9e7ff86dd2c2bfd0e3c1ea1e4b0a4c1c9b9d7a6f2e31","confirmations":1,"height":1906786,"version":536870912,"versionHex":"20000000","merkleroot":"","time":1606816271,"mediantime":1606814112,"nonce":0,"bits":"1a0ffff0","difficulty":7306565.81,"chainwork":"","nTx":12,"previousblockhash":""},"error":null,"id":"mw-btc-swap"}"#)
+            },
+            BTC_CORE_RPC_TYPES::GET_RAW_TRANSACTION => {
+                String::from(r#"{"result":{"txid":"211db342720933ca50f4b713c462823f0440f0fc29c3b61ba4f9d1d9e65d4e1b","hash":"211db342720933ca50f4b713c462823f0440f0fc29c3b61ba4f9d1d9e65d4e1b","version":2,"size":225,"vsize":225,"weight":900,"locktime":0,"vin":[],"vout":[],"hex":"","blockhash":"000000000000002a6d8a31a8c0ad5b3bd3c8c8d5c5b6a5a24dfbc6df1b1d6b1c","confirmations":12,"time":1606816271,"blocktime":1606816271},"error":null,"id":"mw-btc-swap"}"#)
             }
         }
     }
@@ -457,6 +575,65 @@ use crate::bitcoin::btcroutines::create_lock_transaction;
         assert_eq!(1906786, count);
     }
 
+    #[test]
+    fn test_tx_confirmations() {
+        let stub_response = HttpResponse {
+            status : 200,
+            content : get_mock_response(BTC_CORE_RPC_TYPES::GET_RAW_TRANSACTION)
+        };
+        let factory = RequestFactory::new(Some(stub_response));
+        let core = BitcoinCore::new(get_btc_core_settings(), factory);
+        let conf = core.get_tx_confirmations("211db342720933ca50f4b713c462823f0440f0fc29c3b61ba4f9d1d9e65d4e1b").unwrap();
+        assert_eq!(12, conf);
+    }
+
+    #[test]
+    fn test_tx_confirmations_mempool() {
+        let stub_response = HttpResponse {
+            status : 200,
+            content : String::from(r#"{"result":{"txid":"211db342720933ca50f4b713c462823f0440f0fc29c3b61ba4f9d1d9e65d4e1b","vin":[],"vout":[]},"error":null,"id":"mw-btc-swap"}"#)
+        };
+        let factory = RequestFactory::new(Some(stub_response));
+        let core = BitcoinCore::new(get_btc_core_settings(), factory);
+        assert_eq!(0, core.get_tx_confirmations("211db342720933ca50f4b713c462823f0440f0fc29c3b61ba4f9d1d9e65d4e1b").unwrap());
+    }
+
+    #[test]
+    fn test_tx_confirmations_unknown_tx() {
+        let stub_response = HttpResponse {
+            status : 200,
+            content : get_mock_err_response(String::from("No such mempool or blockchain transaction"), -5, String::from("mw-btc-swap"))
+        };
+        let factory = RequestFactory::new(Some(stub_response));
+        let core = BitcoinCore::new(get_btc_core_settings(), factory);
+        assert_eq!(Some(String::from("No such mempool or blockchain transaction")), core.get_tx_confirmations("00").err());
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        let stub_response = HttpResponse {
+            status : 200,
+            content : get_mock_response(BTC_CORE_RPC_TYPES::GET_BLOCKCHAIN_INFO)
+        };
+        let factory = RequestFactory::new(Some(stub_response));
+        let core = BitcoinCore::new(get_btc_core_settings(), factory);
+        assert_eq!(1606814112, core.get_median_time_past().unwrap());
+    }
+
+    #[test]
+    fn test_block_header() {
+        let stub_response = HttpResponse {
+            status : 200,
+            content : get_mock_response(BTC_CORE_RPC_TYPES::GET_BLOCK_HEADER)
+        };
+        let factory = RequestFactory::new(Some(stub_response));
+        let core = BitcoinCore::new(get_btc_core_settings(), factory);
+        let header = core.get_block_header("000000000000001c7e4a9e7ff86dd2c2bfd0e3c1ea1e4b0a4c1c9b9d7a6f2e31").unwrap();
+        assert_eq!(1906786, header.height);
+        assert_eq!(1606816271, header.time);
+        assert_eq!(1606814112, header.mediantime);
+    }
+
     #[test]
     fn test_import_address() {
         let stub_response = HttpResponse {
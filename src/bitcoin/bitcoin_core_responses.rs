@@ -49,6 +49,30 @@ pub struct SendRawTxResponse {
     pub id : String
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RawTransactionResult {
+    pub txid : String,
+    pub confirmations : Option<u64>,
+    pub blockhash : Option<String>,
+    pub blocktime : Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockchainInfoResult {
+    pub chain : String,
+    pub blocks : u64,
+    pub bestblockhash : String,
+    pub mediantime : u64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlockHeaderResult {
+    pub hash : String,
+    pub height : u64,
+    pub time : u64,
+    pub mediantime : u64
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Error {
     pub code : i32,
@@ -62,7 +86,7 @@ pub struct UTXO {
     pub address : String,
     pub label : String,
     pub scriptPubKey : String,
-    pub amount : f32,
+    pub amount : f64,
     pub confirmations : u32,
     pub spendable : bool,
     pub solvable : bool,
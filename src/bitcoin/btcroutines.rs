@@ -15,7 +15,7 @@ use bitcoin::Txid;
 use bitcoin::TxIn;
 use crate::bitcoin::bitcoin_types::BTCInput;
 use bitcoin::PublicKey;
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use bitcoin::PrivateKey;
 use bitcoin::secp256k1::key::SecretKey;
 use bitcoin::secp256k1::Secp256k1;
@@ -33,7 +33,7 @@ use grin_util::secp::SecretKey as GrinSecretKey;
 /// # Arguments
 /// 
 /// * `rng` Randomness generator
-pub fn create_private_key(rng : &mut OsRng) -> PrivateKey {
+pub fn create_private_key(rng : &mut SwapRng) -> PrivateKey {
     let skey = SecretKey::new(rng);
     let nw = if TEST_NET { Network::Testnet } else { Network::Bitcoin };
     PrivateKey {
@@ -364,10 +364,10 @@ pub fn sign_lock_transaction_refund(tx : Transaction, ix : usize, lock_script :
 /// # Arguments
 ///
 /// * `script_str` a hexencoded string representing a bitcoin script
-pub fn deserialize_script(script_str : &String) -> Script {
+pub fn deserialize_script(script_str : &String) -> Result<Script, String> {
     let bytes = hex::decode(script_str)
-        .unwrap();
-    Script::from(bytes)
+        .map_err(|e| format!("Failed to deserialize script: {}", e))?;
+    Ok(Script::from(bytes))
 }
 
 /// Serializes a Bitcoin Script object into a string
@@ -396,9 +396,11 @@ pub fn serialize_btc_tx(tx : &Transaction) -> String {
 /// # Arguments
 ///
 /// * `str_tx` the transaction as a hex encoded string
-pub fn deserialize_btc_tx(str_tx : &String) -> Transaction {
-    deserialize(&hex::decode(str_tx).unwrap())
-        .unwrap()
+pub fn deserialize_btc_tx(str_tx : &String) -> Result<Transaction, String> {
+    let bytes = hex::decode(str_tx)
+        .map_err(|e| format!("Failed to deserialize transaction: {}", e))?;
+    deserialize(&bytes)
+        .map_err(|e| format!("Failed to deserialize transaction: {}", e))
 }
 
 /// Serialize a Bitcoin PrivateKey to a string
@@ -417,8 +419,9 @@ pub fn serialize_priv_key(sk : &PrivateKey) -> String {
 /// # Arguments
 ///
 /// * `sk` Secretkey in wif format
-pub fn deserialize_priv_key(sk: &String) -> PrivateKey {
-    PrivateKey::from_wif(&sk).unwrap()
+pub fn deserialize_priv_key(sk: &String) -> Result<PrivateKey, String> {
+    PrivateKey::from_wif(&sk)
+        .map_err(|e| format!("Failed to deserialize private key: {}", e))
 }
 
 /// Serialize a Bitcoin PublicKey to a string
@@ -437,8 +440,9 @@ pub fn serialize_pub_key(pk : &PublicKey) -> String {
 /// # Arguments
 ///
 /// * `str` serialized Bitcoin public key
-pub fn deserialize_pub_key(str : &String) -> PublicKey {
-    PublicKey::from_str(str).unwrap()
+pub fn deserialize_pub_key(str : &String) -> Result<PublicKey, String> {
+    PublicKey::from_str(str)
+        .map_err(|e| format!("Failed to deserialize public key: {}", e))
 }
 
 /// Convert from a Grin SecretKey to a Bitcoin PrivateKey
@@ -459,7 +463,7 @@ pub fn private_key_from_grin_sk(sk : &GrinSecretKey) -> PrivateKey {
 #[test]
 fn test_script_serialization() {
     let hex_script = String::from("0014ebcf32c56219bb6782aa51895451f1d818b50af5");
-    let script = deserialize_script(&hex_script);
+    let script = deserialize_script(&hex_script).unwrap();
     let serialized = serialize_script(&script);
     assert_eq!(hex_script, serialized);
 }
@@ -467,7 +471,7 @@ fn test_script_serialization() {
 #[test]
 fn test_tx_serialization() {
     let tx_str = String::from("0100000001a5b9ee765b9d78bb40e7c24005246de8aedf796089474a187041b45c3183ebe3000000006a473045022100e328a3960f10a5d24fda55fedcc71a88d5b0ff431029cd7568f2f0076bcf2a8b022018e57b708b2ad18916296b1cef625468c889064d65bca304e5a8a9e5a4f692172103c7eafa9bb32d43b88580ddd259aab1c76b8f1749ae43a343add030884edaae99ffffffff01be0000000000000017a91424f9fd677d9f32cdf976cf0ca146d55a3ece4d038700000000");
-    let tx = deserialize_btc_tx(&tx_str);
+    let tx = deserialize_btc_tx(&tx_str).unwrap();
     let serialized = serialize_btc_tx(&tx);
     assert_eq!(tx_str, serialized);
 }
@@ -510,7 +514,7 @@ fn test_create_lock_tx() {
     );
     let tx = create_lock_transaction(alice_pk, pub_x, bob_pk, pub_ch, vec![inp.clone()], 100000, 500, refund_time)
         .unwrap();
-    let inp_script = deserialize_script(&inp.pub_script);
+    let inp_script = deserialize_script(&inp.pub_script).unwrap();
     let signed_tx = sign_p2pkh_transaction(tx, vec![inp_script], vec![inp_key], vec![inp_pk], &secp);
     let str_tx = serialize_btc_tx(&signed_tx);
     println!("Change output sk: {}", change_sk.to_wif());
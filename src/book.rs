@@ -0,0 +1,3 @@
+pub mod types;
+pub mod server;
+pub mod client;
\ No newline at end of file
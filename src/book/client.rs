@@ -0,0 +1,98 @@
+use crate::book::server::BOOK_PATH;
+use crate::book::types::{BookEntry, OfferQuery};
+use crate::swap::offer::OfferSignature;
+use crate::swap::slate::parse_pub_slate;
+use crate::swap::swap_types::SwapSlatePub;
+use reqwest::blocking::{Client, RequestBuilder};
+use std::time::Duration;
+
+/// Client of an offer book server
+pub struct BookClient {
+    url : String,
+    client : Client
+}
+
+impl BookClient {
+    /// Construct a new client
+    ///
+    /// # Arguments
+    ///
+    /// * `url` base url of the offer book, for example http://127.0.0.1:3422
+    pub fn new(url : &str) -> BookClient {
+        BookClient {
+            url : String::from(url.trim_end_matches('/')),
+            client : Client::new()
+        }
+    }
+
+    /// Publish an offer to the book
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `pub_slate` the public slate of the offer
+    pub fn publish(&self, id : u64, pub_slate : &SwapSlatePub) -> Result<(), String> {
+        let body = serde_json::json!({
+            "id" : id,
+            "pub_slate" : pub_slate
+        });
+        self.send(self.client.post(&self.offers_url()).json(&body))?;
+        Ok(())
+    }
+
+    /// Withdraw an offer from the book
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `sig` the withdrawal signature of the offer identity key
+    pub fn withdraw(&self, id : u64, sig : &OfferSignature) -> Result<(), String> {
+        self.send(self.client.delete(&self.offer_url(id)).json(sig))?;
+        Ok(())
+    }
+
+    /// Fetch the public slate of an offer
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    pub fn get_offer(&self, id : u64) -> Result<SwapSlatePub, String> {
+        let content = self.send(self.client.get(&self.offer_url(id)))?;
+        parse_pub_slate(content.as_bytes())
+    }
+
+    /// Query the offers in the book
+    ///
+    /// # Arguments
+    ///
+    /// * `query` the filter to apply
+    pub fn query(&self, query : &OfferQuery) -> Result<Vec<BookEntry>, String> {
+        let url = format!("{}?{}", self.offers_url(), query.to_query_string());
+        let content = self.send(self.client.get(&url))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Unable to parse offer book response: {}", e))
+    }
+
+    fn send(&self, req : RequestBuilder) -> Result<String, String> {
+        let res = req.timeout(Duration::new(30, 0))
+            .send()
+            .map_err(|e| format!("Offer book request failed: {}", e))?;
+        let status = res.status();
+        let content = res.text()
+            .map_err(|e| format!("Failed to read offer book response: {}", e))?;
+        if status.is_success() {
+            Ok(content)
+        }
+        else {
+            Err(format!("Offer book refused the request ({}): {}", status.as_u16(), content))
+        }
+    }
+
+    fn offers_url(&self) -> String {
+        format!("{}{}", self.url, BOOK_PATH)
+    }
+
+    fn offer_url(&self, id : u64) -> String {
+        format!("{}{}/{}", self.url, BOOK_PATH, id)
+    }
+}
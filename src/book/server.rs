@@ -0,0 +1,317 @@
+use crate::book::types::{verify_withdrawal, BookEntry, OfferQuery, PublishRequest};
+use crate::enums::SwapStatus;
+use crate::swap::offer::{check_offer_validity, verify_offer, OfferSignature, OfferTerms};
+use crate::swap::slate::parse_pub_slate;
+use crate::swap::store::file_store::write_atomic;
+use crate::swap::swap_types::SwapSlatePub;
+use crate::util::now_secs;
+use bitcoin::secp256k1::{All, Secp256k1};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+pub const BOOK_PATH : &str = "/v1/offers";
+// A public slate is a small JSON document, larger bodies are refused
+const MAX_BODY_SIZE : u64 = 64 * 1024;
+
+/// Offers published to the book, each offer is kept as public slate file in the book directory
+pub struct OfferBook {
+    directory : String,
+    offers : HashMap<u64, SwapSlatePub>
+}
+
+impl OfferBook {
+    /// Open the offer book in a directory, offers published before are loaded
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` the directory the offers are stored in
+    pub fn open(directory : &str) -> Result<OfferBook, String> {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Unable to create book directory {}: {}", directory, e))?;
+        let mut offers = HashMap::new();
+        let entries = fs::read_dir(directory)
+            .map_err(|e| format!("Unable to read book directory {}: {}", directory, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Unable to read book directory {}: {}", directory, e))?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(Ok(id)) = name.strip_suffix(".pub.json").map(|id| id.parse::<u64>()) {
+                let contents = fs::read(entry.path())
+                    .map_err(|e| format!("Unable to read offer {}: {}", name, e))?;
+                offers.insert(id, parse_pub_slate(&contents)?);
+            }
+        }
+        Ok(OfferBook {
+            directory : String::from(directory),
+            offers : offers
+        })
+    }
+
+    /// Publish an offer, it needs to be signed, not yet taken and not expired.
+    /// Publishing the same offer again is accepted.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `pub_slate` the public slate of the offer
+    /// * `secp` Secp256k1 functions, used to verify the offer signature
+    /// * `now` the current unix timestamp
+    pub fn publish(&mut self, id : u64, pub_slate : SwapSlatePub, secp : &Secp256k1<All>, now : u64) -> Result<(), String> {
+        if pub_slate.status != SwapStatus::INITIALIZED {
+            return Err(format!("Only new offers can be published, the offer is {:?}", pub_slate.status));
+        }
+        if pub_slate.btc.amount == 0 || pub_slate.mw.amount == 0 {
+            return Err(String::from("Offers need a Bitcoin and a Grin amount"));
+        }
+        if pub_slate.fill_of.is_some() {
+            return Err(String::from("Child swaps can't be published, publish the partially fillable offer"));
+        }
+        verify_offer(id, &pub_slate, secp)?;
+        check_offer_validity(&pub_slate, now, None, None)?;
+        if let Some(existing) = self.offers.get(&id) {
+            let same_key = existing.offer_sig.as_ref().map(|s| &s.pub_key) == pub_slate.offer_sig.as_ref().map(|s| &s.pub_key);
+            if !same_key || OfferTerms::from_pub_slate(id, existing).hash() != OfferTerms::from_pub_slate(id, &pub_slate).hash() {
+                return Err(format!("Another offer with id {} is already published", id));
+            }
+        }
+        let contents = serde_json::to_string_pretty(&pub_slate)
+            .map_err(|e| format!("Failed to serialize offer: {}", e))?;
+        write_atomic(&self.offer_path(id), &contents)?;
+        self.offers.insert(id, pub_slate);
+        Ok(())
+    }
+
+    /// Remove an offer, the withdrawal has to be signed by the key which signed the offer
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `sig` the withdrawal signature
+    /// * `secp` Secp256k1 functions
+    pub fn withdraw(&mut self, id : u64, sig : &OfferSignature, secp : &Secp256k1<All>) -> Result<(), String> {
+        let pub_slate = self.offers.get(&id)
+            .ok_or(format!("Offer {} is not in the book", id))?;
+        verify_withdrawal(id, pub_slate, sig, secp)?;
+        self.remove(id)
+    }
+
+    /// Returns the offer if it is in the book
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    pub fn get(&self, id : u64) -> Option<&SwapSlatePub> {
+        self.offers.get(&id)
+    }
+
+    /// Query the book, expired offers are removed first
+    ///
+    /// # Arguments
+    ///
+    /// * `query` the filter to apply
+    /// * `now` the current unix timestamp
+    pub fn query(&mut self, query : &OfferQuery, now : u64) -> Result<Vec<BookEntry>, String> {
+        self.prune(now)?;
+        let mut result : Vec<BookEntry> = self.offers.iter()
+            .map(|(id, pub_slate)| BookEntry::from_pub_slate(*id, pub_slate))
+            .filter(|e| query.matches(e))
+            .collect();
+        result.sort_by(|a, b| a.rate.partial_cmp(&b.rate).unwrap().then(a.id.cmp(&b.id)));
+        Ok(result)
+    }
+
+    /// Remove all offers which can't be taken anymore
+    ///
+    /// # Arguments
+    ///
+    /// * `now` the current unix timestamp
+    pub fn prune(&mut self, now : u64) -> Result<(), String> {
+        let expired : Vec<u64> = self.offers.iter()
+            .filter(|(_, pub_slate)| check_offer_validity(pub_slate, now, None, None).is_err())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            println!("Removing expired offer {}", id);
+            self.remove(id)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, id : u64) -> Result<(), String> {
+        self.offers.remove(&id);
+        fs::remove_file(self.offer_path(id))
+            .map_err(|e| format!("Unable to remove offer {}: {}", id, e))
+    }
+
+    fn offer_path(&self, id : u64) -> String {
+        format!("{}/{}.pub.json", self.directory, id)
+    }
+}
+
+/// Run the offer book server, requests are handled one after another.
+/// Reading the book is open to everyone, publishing and withdrawing offers needs the offer signatures.
+///
+/// # Arguments
+///
+/// * `book` the offer book
+/// * `addr` the address to listen on
+/// * `secp` Secp256k1 functions, used to verify signatures
+pub fn serve(book : &mut OfferBook, addr : &str, secp : &Secp256k1<All>) -> Result<(), String> {
+    let server = Server::http(addr)
+        .map_err(|e| format!("Unable to start offer book on {}: {}", addr, e))?;
+    println!("Offer book listening on http://{}{}", addr, BOOK_PATH);
+
+    for mut request in server.incoming_requests() {
+        let response = handle_request(book, &mut request, secp);
+        if let Err(e) = request.respond(response) {
+            println!("Failed to send offer book response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(book : &mut OfferBook, request : &mut Request, secp : &Secp256k1<All>) -> Response<Cursor<Vec<u8>>> {
+    let url = String::from(request.url());
+    let (path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (url.as_str(), "")
+    };
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().take(MAX_BODY_SIZE).read_to_string(&mut body) {
+        return text_response(400, format!("Unable to read request: {}", e));
+    }
+    let method = request.method().clone();
+    let now = now_secs();
+
+    if path == BOOK_PATH {
+        match method {
+            Method::Get => match OfferQuery::parse(query).and_then(|q| book.query(&q, now)) {
+                Ok(entries) => json_response(serde_json::to_string(&entries).unwrap()),
+                Err(e) => text_response(400, e)
+            },
+            Method::Post => {
+                let result = serde_json::from_str::<PublishRequest>(&body)
+                    .map_err(|e| format!("Invalid offer: {}", e))
+                    .and_then(|r| book.publish(r.id, r.pub_slate, secp, now));
+                match result {
+                    Ok(_) => text_response(200, String::from("Published")),
+                    Err(e) => text_response(400, e)
+                }
+            },
+            _ => text_response(405, String::from("Method not allowed"))
+        }
+    }
+    else if let Some(id) = path.strip_prefix(BOOK_PATH).and_then(|p| p.strip_prefix('/')) {
+        let id = match id.parse::<u64>() {
+            Ok(id) => id,
+            Err(_) => return text_response(404, String::from("Not found"))
+        };
+        let _ = book.prune(now);
+        if book.get(id).is_none() {
+            return text_response(404, format!("Offer {} is not in the book", id));
+        }
+        match method {
+            Method::Get => json_response(serde_json::to_string(book.get(id).unwrap()).unwrap()),
+            Method::Delete => {
+                let result = serde_json::from_str::<OfferSignature>(&body)
+                    .map_err(|e| format!("Invalid withdrawal: {}", e))
+                    .and_then(|sig| book.withdraw(id, &sig, secp));
+                match result {
+                    Ok(_) => text_response(200, String::from("Withdrawn")),
+                    Err(e) => text_response(403, e)
+                }
+            },
+            _ => text_response(405, String::from("Method not allowed"))
+        }
+    }
+    else {
+        text_response(404, String::from("Not found"))
+    }
+}
+
+fn text_response(status : u16, text : String) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(text).with_status_code(StatusCode(status))
+}
+
+fn json_response(json : String) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(json)
+        .with_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitcoin::btcroutines::create_private_key;
+    use crate::book::types::{sign_withdrawal, OfferQuery};
+    use crate::enums::SwapStatus;
+    use crate::swap::offer::OfferTerms;
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::swap::swap_types::{OfferValidity, SwapSlatePub};
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+    use rand::Rng;
+    use std::env;
+
+    use super::OfferBook;
+
+    fn temp_dir() -> String {
+        let dir = env::temp_dir().join(format!("mw-btc-swap-book-{}", rand::thread_rng().gen::<u64>()));
+        String::from(dir.to_string_lossy())
+    }
+
+    #[test]
+    fn test_publish_query_withdraw() {
+        let secp = get_secp256k1_curve();
+        let mut rng = get_os_rng();
+        let sk = create_private_key(&mut rng);
+        let dir = temp_dir();
+        let mut book = OfferBook::open(&dir).unwrap();
+
+        let mut slate = sample_slate(5, true);
+        slate.pub_slate.validity = Some(OfferValidity {
+            created : 100,
+            expires : 200,
+            btc_height : None,
+            mw_height : None,
+            nonce : String::from("00")
+        });
+        // unsigned offers are refused
+        assert!(book.publish(5, sample_slate(5, true).pub_slate, &secp, 150).is_err());
+        slate.pub_slate.offer_sig = Some(OfferTerms::from_pub_slate(5, &slate.pub_slate).sign(&sk, &secp));
+        book.publish(5, copy_pub_slate(&slate.pub_slate), &secp, 150).unwrap();
+        // publishing the same offer twice is fine, different terms under the same id are not
+        book.publish(5, copy_pub_slate(&slate.pub_slate), &secp, 150).unwrap();
+        let mut other = copy_pub_slate(&slate.pub_slate);
+        other.btc.amount = 1;
+        other.offer_sig = Some(OfferTerms::from_pub_slate(5, &other).sign(&sk, &secp));
+        assert!(book.publish(5, other, &secp, 150).is_err());
+
+        let entries = book.query(&OfferQuery::parse("offered=BTC").unwrap(), 150).unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(5, entries[0].id);
+        assert!(book.query(&OfferQuery::parse("offered=GRIN").unwrap(), 150).unwrap().is_empty());
+
+        // the book survives a restart
+        let mut book = OfferBook::open(&dir).unwrap();
+        assert!(book.get(5).is_some());
+
+        // only the offer key can withdraw
+        let forged = sign_withdrawal(5, &slate.pub_slate, &create_private_key(&mut rng), &secp);
+        assert!(book.withdraw(5, &forged, &secp).is_err());
+        book.withdraw(5, &sign_withdrawal(5, &slate.pub_slate, &sk, &secp), &secp).unwrap();
+        assert!(book.get(5).is_none());
+        assert!(OfferBook::open(&dir).unwrap().get(5).is_none());
+
+        // taken and expired offers are not listed
+        let mut locked = copy_pub_slate(&slate.pub_slate);
+        locked.status = SwapStatus::LOCKED;
+        assert!(book.publish(5, locked, &secp, 150).is_err());
+        assert!(book.publish(5, copy_pub_slate(&slate.pub_slate), &secp, 200).is_err());
+        book.publish(5, copy_pub_slate(&slate.pub_slate), &secp, 150).unwrap();
+        assert!(book.query(&OfferQuery::default(), 200).unwrap().is_empty());
+        assert!(book.get(5).is_none());
+    }
+
+    fn copy_pub_slate(pub_slate : &SwapSlatePub) -> SwapSlatePub {
+        serde_json::from_str(&serde_json::to_string(pub_slate).unwrap()).unwrap()
+    }
+}
@@ -0,0 +1,292 @@
+use crate::bitcoin::btcroutines::serialize_pub_key;
+use crate::constants::{BTC_SATS, NANO_GRIN};
+use crate::enums::{Currency, SwapType};
+use crate::swap::offer::{OfferSignature, OfferTerms};
+use crate::swap::swap_types::{FillRange, SwapSlatePub};
+use bitcoin::secp256k1::{All, Message, Secp256k1, Signature};
+use bitcoin::{PrivateKey, PublicKey};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::str::FromStr;
+
+// Domain separation of withdrawal signatures from offer signatures
+const WITHDRAW_TAG : &[u8] = b"mw-btc-swap/book/withdraw";
+
+/// Request of a maker to publish an offer
+#[derive(Serialize, Deserialize)]
+pub struct PublishRequest {
+    pub id : u64,
+    pub pub_slate : SwapSlatePub
+}
+
+/// Summary of an offer in the book as returned by queries
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BookEntry {
+    pub id : u64,
+    pub offered : Currency,
+    pub requested : Currency,
+    pub offered_amount : u64,
+    pub requested_amount : u64,
+    // price of one BTC in GRIN
+    pub rate : f64,
+    pub expires : Option<u64>,
+    pub pub_key : Option<String>,
+    // set if the offer can be taken in parts
+    #[serde(default)]
+    pub fill : Option<FillRange>
+}
+
+impl BookEntry {
+    /// Build the book entry of a published offer
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `pub_slate` the public slate of the offer
+    pub fn from_pub_slate(id : u64, pub_slate : &SwapSlatePub) -> BookEntry {
+        let btc_offered = pub_slate.btc.swap_type == SwapType::OFFERED;
+        BookEntry {
+            id : id,
+            offered : if btc_offered { Currency::BTC } else { Currency::GRIN },
+            requested : if btc_offered { Currency::GRIN } else { Currency::BTC },
+            offered_amount : if btc_offered { pub_slate.btc.amount } else { pub_slate.mw.amount },
+            requested_amount : if btc_offered { pub_slate.mw.amount } else { pub_slate.btc.amount },
+            rate : offer_rate(pub_slate.btc.amount, pub_slate.mw.amount),
+            expires : pub_slate.validity.as_ref().map(|v| v.expires),
+            pub_key : pub_slate.offer_sig.as_ref().map(|s| s.pub_key.clone()),
+            fill : pub_slate.fill
+        }
+    }
+}
+
+/// Filter for querying the offer book, unset fields match everything
+#[derive(Default, Debug, PartialEq)]
+pub struct OfferQuery {
+    pub offered : Option<Currency>,
+    pub requested : Option<Currency>,
+    // range of the offered amount in satoshis or nanogrin, partially fillable
+    // offers match if a fill within the range is possible
+    pub min_amount : Option<u64>,
+    pub max_amount : Option<u64>,
+    // range of the price of one BTC in GRIN
+    pub min_rate : Option<f64>,
+    pub max_rate : Option<f64>
+}
+
+impl OfferQuery {
+    /// Returns true if the book entry matches the query
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` the book entry to check
+    pub fn matches(&self, entry : &BookEntry) -> bool {
+        let (smallest, largest) = entry.fill.map_or((entry.offered_amount, entry.offered_amount), |f| (f.min, f.max));
+        self.offered.map_or(true, |c| c == entry.offered)
+            && self.requested.map_or(true, |c| c == entry.requested)
+            && self.min_amount.map_or(true, |a| largest >= a)
+            && self.max_amount.map_or(true, |a| smallest <= a)
+            && self.min_rate.map_or(true, |r| entry.rate >= r)
+            && self.max_rate.map_or(true, |r| entry.rate <= r)
+    }
+
+    /// Encode the query as url query string, without the leading `?`
+    pub fn to_query_string(&self) -> String {
+        let mut params : Vec<String> = Vec::new();
+        if let Some(c) = self.offered {
+            params.push(format!("offered={:?}", c));
+        }
+        if let Some(c) = self.requested {
+            params.push(format!("requested={:?}", c));
+        }
+        if let Some(a) = self.min_amount {
+            params.push(format!("min_amount={}", a));
+        }
+        if let Some(a) = self.max_amount {
+            params.push(format!("max_amount={}", a));
+        }
+        if let Some(r) = self.min_rate {
+            params.push(format!("min_rate={}", r));
+        }
+        if let Some(r) = self.max_rate {
+            params.push(format!("max_rate={}", r));
+        }
+        params.join("&")
+    }
+
+    /// Parse a url query string, without the leading `?`
+    ///
+    /// # Arguments
+    ///
+    /// * `query` the query string
+    pub fn parse(query : &str) -> Result<OfferQuery, String> {
+        let mut q = OfferQuery::default();
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next().unwrap();
+            let value = kv.next()
+                .ok_or(format!("Missing value for query parameter {}", key))?;
+            match key {
+                "offered" => q.offered = Some(parse_currency(value)?),
+                "requested" => q.requested = Some(parse_currency(value)?),
+                "min_amount" => q.min_amount = Some(parse_number(key, value)?),
+                "max_amount" => q.max_amount = Some(parse_number(key, value)?),
+                "min_rate" => q.min_rate = Some(parse_number(key, value)?),
+                "max_rate" => q.max_rate = Some(parse_number(key, value)?),
+                other => return Err(format!("Unknown query parameter {}", other))
+            }
+        }
+        Ok(q)
+    }
+}
+
+/// Price of one BTC in GRIN offered by a swap
+///
+/// # Arguments
+///
+/// * `btc_amount` the Bitcoin amount in satoshis
+/// * `mw_amount` the Grin amount in nanogrin
+pub fn offer_rate(btc_amount : u64, mw_amount : u64) -> f64 {
+    (mw_amount as f64 / NANO_GRIN as f64) / (btc_amount as f64 / BTC_SATS as f64)
+}
+
+/// Sign the withdrawal of an offer with the identity key which signed the offer
+///
+/// # Arguments
+///
+/// * `id` the id of the Atomic Swap
+/// * `pub_slate` the public slate of the offer
+/// * `sk` the identity key of the offer creator
+/// * `secp` Secp256k1 functions
+pub fn sign_withdrawal(id : u64, pub_slate : &SwapSlatePub, sk : &PrivateKey, secp : &Secp256k1<All>) -> OfferSignature {
+    let msg = withdrawal_message(id, pub_slate);
+    let sig = secp.sign(&msg, &sk.key);
+    OfferSignature {
+        pub_key : serialize_pub_key(&sk.public_key(secp)),
+        signature : hex::encode(sig.serialize_der())
+    }
+}
+
+/// Verify that a withdrawal was signed by the identity key which signed the offer
+///
+/// # Arguments
+///
+/// * `id` the id of the Atomic Swap
+/// * `pub_slate` the public slate of the offer
+/// * `sig` the withdrawal signature
+/// * `secp` Secp256k1 functions
+pub fn verify_withdrawal(id : u64, pub_slate : &SwapSlatePub, sig : &OfferSignature, secp : &Secp256k1<All>) -> Result<(), String> {
+    let offer_key = pub_slate.offer_sig.as_ref()
+        .map(|s| s.pub_key.as_str())
+        .ok_or(String::from("Offer is not signed"))?;
+    if offer_key != sig.pub_key {
+        return Err(String::from("Withdrawal is not signed by the offer identity key"));
+    }
+    let pk = PublicKey::from_str(&sig.pub_key)
+        .map_err(|e| format!("Invalid identity key: {}", e))?;
+    let der = hex::decode(&sig.signature)
+        .map_err(|e| format!("Invalid withdrawal signature encoding: {}", e))?;
+    let signature = Signature::from_der(&der)
+        .map_err(|e| format!("Invalid withdrawal signature: {}", e))?;
+    secp.verify(&withdrawal_message(id, pub_slate), &signature, &pk.key)
+        .map_err(|_| String::from("Withdrawal signature is invalid"))
+}
+
+/// The signed message binds the withdrawal to the terms of the offer
+fn withdrawal_message(id : u64, pub_slate : &SwapSlatePub) -> Message {
+    let mut hasher = Sha256::new();
+    hasher.update(WITHDRAW_TAG);
+    hasher.update(OfferTerms::from_pub_slate(id, pub_slate).hash());
+    Message::from_slice(&hasher.finalize()).unwrap()
+}
+
+fn parse_currency(value : &str) -> Result<Currency, String> {
+    match value.to_uppercase().as_str() {
+        "BTC" | "BITCOIN" => Ok(Currency::BTC),
+        "GRIN" => Ok(Currency::GRIN),
+        other => Err(format!("Invalid currency {}", other))
+    }
+}
+
+fn parse_number<T : FromStr>(key : &str, value : &str) -> Result<T, String> {
+    value.parse::<T>()
+        .map_err(|_| format!("Invalid value {} for query parameter {}", value, key))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitcoin::btcroutines::create_private_key;
+    use crate::enums::Currency;
+    use crate::swap::offer::OfferTerms;
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::swap::swap_types::FillRange;
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+
+    use super::{sign_withdrawal, verify_withdrawal, BookEntry, OfferQuery};
+
+    #[test]
+    fn test_query_string() {
+        let q = OfferQuery {
+            offered : Some(Currency::BTC),
+            requested : Some(Currency::GRIN),
+            min_amount : Some(1000),
+            max_amount : None,
+            min_rate : Some(1.5),
+            max_rate : Some(2000.0)
+        };
+        let s = q.to_query_string();
+        assert_eq!("offered=BTC&requested=GRIN&min_amount=1000&min_rate=1.5&max_rate=2000", s);
+        assert_eq!(q, OfferQuery::parse(&s).unwrap());
+        assert_eq!(OfferQuery::default(), OfferQuery::parse("").unwrap());
+        assert!(OfferQuery::parse("offered=DOGE").is_err());
+        assert!(OfferQuery::parse("min_amount=abc").is_err());
+        assert!(OfferQuery::parse("amount=1").is_err());
+    }
+
+    #[test]
+    fn test_query_matches() {
+        // 100000 sats for 1 grin, 1000 GRIN per BTC
+        let slate = sample_slate(1, true);
+        let entry = BookEntry::from_pub_slate(1, &slate.pub_slate);
+        assert_eq!(Currency::BTC, entry.offered);
+        assert_eq!(100000, entry.offered_amount);
+        assert_eq!(1000.0, entry.rate);
+        assert!(OfferQuery::default().matches(&entry));
+
+        let mut q = OfferQuery::parse("offered=btc&min_amount=100000&max_rate=1000").unwrap();
+        assert!(q.matches(&entry));
+        q.min_rate = Some(1000.5);
+        assert!(!q.matches(&entry));
+        q = OfferQuery::parse("requested=BTC").unwrap();
+        assert!(!q.matches(&entry));
+        q = OfferQuery::parse("max_amount=99999").unwrap();
+        assert!(!q.matches(&entry));
+
+        // a partially fillable offer matches any overlapping range
+        let mut fillable = entry.clone();
+        fillable.fill = Some(FillRange { min : 10000, max : 50000 });
+        assert!(q.matches(&fillable));
+        assert!(OfferQuery::parse("min_amount=50000").unwrap().matches(&fillable));
+        assert!(!OfferQuery::parse("min_amount=50001").unwrap().matches(&fillable));
+        assert!(!OfferQuery::parse("max_amount=9999").unwrap().matches(&fillable));
+    }
+
+    #[test]
+    fn test_withdrawal_signature() {
+        let secp = get_secp256k1_curve();
+        let mut rng = get_os_rng();
+        let sk = create_private_key(&mut rng);
+        let mut slate = sample_slate(3, false);
+        let withdrawal = sign_withdrawal(3, &slate.pub_slate, &sk, &secp);
+        // unsigned offers can't be withdrawn
+        assert!(verify_withdrawal(3, &slate.pub_slate, &withdrawal, &secp).is_err());
+
+        slate.pub_slate.offer_sig = Some(OfferTerms::from_pub_slate(3, &slate.pub_slate).sign(&sk, &secp));
+        assert!(verify_withdrawal(3, &slate.pub_slate, &withdrawal, &secp).is_ok());
+        assert!(verify_withdrawal(4, &slate.pub_slate, &withdrawal, &secp).is_err());
+
+        // another key can't withdraw the offer
+        let other = create_private_key(&mut rng);
+        let forged = sign_withdrawal(3, &slate.pub_slate, &other, &secp);
+        assert!(verify_withdrawal(3, &slate.pub_slate, &forged, &secp).is_err());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod cmd_types;
+pub mod context;
 pub mod parser;
\ No newline at end of file
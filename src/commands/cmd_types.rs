@@ -1,4 +1,9 @@
 pub mod accept;
+pub mod api;
+pub mod book_list;
+pub mod book_publish;
+pub mod book_serve;
+pub mod book_withdraw;
 pub mod command;
 pub mod execute;
 pub mod import_btc;
@@ -6,4 +11,7 @@ pub mod import_grin;
 pub mod init;
 pub mod listen;
 pub mod cancel;
-pub mod lock;
\ No newline at end of file
+pub mod lock;
+pub mod refund;
+pub mod list;
+pub mod status;
\ No newline at end of file
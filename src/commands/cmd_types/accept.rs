@@ -1,31 +1,81 @@
-use crate::swap::slate::create_priv_from_pub;
+use crate::book::client::BookClient;
+use crate::swap::slate::{create_priv_from_pub, read_pub_slate_file};
+use crate::swap::offer::check_offer_expiry;
+use crate::swap::pricing::{format_effective_rate, verify_quote};
+use crate::units::format_amount;
+use crate::enums::{Currency, SwapType};
+use crate::swap::fill::derive_child;
+use crate::units::parse_amount;
+use rand::Rng;
+use crate::swap::validator::{node_fee_policy, validate_fee_base, validate_offer_timelocks};
 use crate::SwapSlate;
-use rand::rngs::OsRng;
+use crate::error::SwapError;
+use crate::util::SwapRng;
 use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
 use crate::commands::cmd_types::command::Command;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
 
-/// Accept command allows a peer to accept the public slate file from an offerer
+/// Accept command allows a peer to accept the public slate file from an offerer,
+/// the slate is read from the slate directory or fetched from the offer book.
+/// Taking a part of a partially fillable offer creates a child swap with an id of its own.
 pub struct Accept {
-    swapid : u64
+    swapid : u64,
+    from_book : bool,
+    fill : Option<String>
 }
 
 impl Accept {
-    pub fn new(swapid : u64) -> Accept {
+    pub fn new(swapid : u64, from_book : bool, fill : Option<String>) -> Accept {
         Accept {
-            swapid : swapid
+            swapid : swapid,
+            from_book : from_book,
+            fill : fill
         }
     }
 }
 
 impl Command for Accept {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
-        let slate : SwapSlate = create_priv_from_pub(self.swapid, &settings.slate_directory)
-            .expect("Unable to locate public slate file");
-        println!("Created private slate file for {}", self.swapid);
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let pub_slate = if self.from_book {
+            println!("Fetching offer {} from {}", self.swapid, settings.book.url);
+            BookClient::new(&settings.book.url).get_offer(self.swapid).map_err(SwapError::PeerProtocol)?
+        }
+        else {
+            read_pub_slate_file(self.swapid, &settings.slate_directory).map_err(SwapError::Io)?
+        };
+        check_offer_expiry(&pub_slate, chain.btc.as_ref(), chain.grin.as_ref())?;
+        validate_offer_timelocks(&pub_slate, &settings.safety).map_err(SwapError::Timelock)?;
+        validate_fee_base(&pub_slate, &node_fee_policy(&settings.fees, chain.grin.as_ref()))?;
+        verify_quote(&pub_slate).map_err(SwapError::Validation)?;
+        println!("Amounts: {} / {}", format_amount(pub_slate.btc.amount, Currency::BTC), format_amount(pub_slate.mw.amount, Currency::GRIN));
+        if let Some(q) = &pub_slate.quote {
+            println!("Offer quoted at {} BTC per GRIN with a spread of {} bps", q.price, q.spread_bps);
+        }
+        println!("Effective rate: {}", format_effective_rate(pub_slate.btc.amount, pub_slate.mw.amount));
+        let offered = if pub_slate.btc.swap_type == SwapType::OFFERED { Currency::BTC } else { Currency::GRIN };
+        let (id, pub_slate) = match (&self.fill, pub_slate.fill) {
+            (Some(fill), Some(_)) => {
+                let amount = parse_amount(fill, offered).map_err(SwapError::Argument)?;
+                let child_id : u64 = rng.gen();
+                let child = derive_child(self.swapid, &pub_slate, child_id, amount).map_err(SwapError::Argument)?;
+                println!("Filling {} of offer {} with child swap {}", format_amount(amount, offered), self.swapid, child_id);
+                println!("Amounts of the fill: {} / {}", format_amount(child.btc.amount, Currency::BTC), format_amount(child.mw.amount, Currency::GRIN));
+                (child_id, child)
+            },
+            (None, Some(range)) => {
+                return Err(SwapError::Argument(format!("Offer {} is partially fillable, take a part of it with --fill between {} and {}",
+                    self.swapid, format_amount(range.min, offered), format_amount(range.max, offered))));
+            },
+            (Some(_), None) => return Err(SwapError::Argument(format!("Offer {} can't be partially filled", self.swapid))),
+            (None, None) => (self.swapid, pub_slate)
+        };
+        let slate : SwapSlate = create_priv_from_pub(store, id, pub_slate, btc_secp).map_err(SwapError::Validation)?;
+        println!("Created private slate file for {}", id);
         println!("Please import your inputs before starting the swap");
-        Ok(slate)
+        Ok(Some(slate))
     }
 }
\ No newline at end of file
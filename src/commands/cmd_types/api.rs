@@ -0,0 +1,37 @@
+use crate::api::server::{serve, ApiContext};
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// Run the JSON-RPC API server until it is stopped
+pub struct Api {
+}
+
+impl Api {
+    pub fn new() -> Api {
+        Api {
+        }
+    }
+}
+
+impl Command for Api {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut ctx = ApiContext {
+            settings : settings,
+            chain : chain,
+            store : store,
+            rng : rng,
+            btc_secp : btc_secp,
+            grin_secp : grin_secp
+        };
+        serve(&mut ctx).map_err(SwapError::Io)?;
+        Ok(None)
+    }
+}
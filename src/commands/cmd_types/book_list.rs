@@ -0,0 +1,50 @@
+use crate::book::client::BookClient;
+use crate::book::types::OfferQuery;
+use crate::enums::Currency;
+use crate::units::format_amount;
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// List the offers of the offer book matching a query
+pub struct BookList {
+    query : OfferQuery
+}
+
+impl BookList {
+    pub fn new(query : OfferQuery) -> BookList {
+        BookList {
+            query : query
+        }
+    }
+}
+
+impl Command for BookList {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let entries = BookClient::new(&settings.book.url).query(&self.query).map_err(SwapError::PeerProtocol)?;
+        if entries.is_empty() {
+            println!("No offers found");
+        }
+        else {
+            println!("{:<20} {:<12} {:>34} {:>34} {:>14} {:>12}", "id", "offer", "offered", "requested", "GRIN per BTC", "expires");
+            for e in entries {
+                let direction = if e.offered == Currency::BTC { "BTC -> GRIN" } else { "GRIN -> BTC" };
+                let expires = e.expires.map_or(String::from("-"), |t| t.to_string());
+                println!("{:<20} {:<12} {:>34} {:>34} {:>14.4} {:>12}", e.id, direction,
+                    format_amount(e.offered_amount, e.offered), format_amount(e.requested_amount, e.requested), e.rate, expires);
+                if let Some(fill) = &e.fill {
+                    println!("{:<20} fillable in parts of {} to {}", "", format_amount(fill.min, e.offered), format_amount(fill.max, e.offered));
+                }
+            }
+            println!("Take an offer with: accept --swapid <id> --book, add --fill <amount> to take a part of it");
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,44 @@
+use crate::book::client::BookClient;
+use crate::enums::{SwapRole, SwapStatus};
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// Publish one of our offers to the configured offer book
+pub struct BookPublish {
+    swapid : u64
+}
+
+impl BookPublish {
+    pub fn new(swapid : u64) -> BookPublish {
+        BookPublish {
+            swapid : swapid
+        }
+    }
+}
+
+impl Command for BookPublish {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
+        if slate.prv_slate.role != Some(SwapRole::MAKER) {
+            return Err(SwapError::Validation(String::from("Only offers we created can be published")));
+        }
+        if slate.pub_slate.status != SwapStatus::INITIALIZED {
+            return Err(SwapError::Validation(String::from("The offer has already been taken")));
+        }
+        let client = BookClient::new(&settings.book.url);
+        client.publish(slate.id, &slate.pub_slate).map_err(SwapError::PeerProtocol)?;
+        println!("Published offer {} to {}", slate.id, settings.book.url);
+        println!("Start listening for the taker with: listen --swapid {}", slate.id);
+        slate.prv_slate.book = Some(settings.book.url.clone());
+        slate.log_event(None, None, None, &format!("Published offer to the offer book {}", settings.book.url));
+        Ok(Some(slate))
+    }
+}
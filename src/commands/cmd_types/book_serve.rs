@@ -0,0 +1,31 @@
+use crate::book::server::{serve, OfferBook};
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// Run the offer book server until it is stopped, offers are kept in the book
+/// folder of the slate directory
+pub struct BookServe {
+}
+
+impl BookServe {
+    pub fn new() -> BookServe {
+        BookServe {
+        }
+    }
+}
+
+impl Command for BookServe {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut book = OfferBook::open(&format!("{}/book", settings.slate_directory)).map_err(SwapError::Io)?;
+        serve(&mut book, &format!("{}:{}", settings.book.addr, settings.book.port), btc_secp).map_err(SwapError::Io)?;
+        Ok(None)
+    }
+}
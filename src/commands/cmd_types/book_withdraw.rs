@@ -0,0 +1,57 @@
+use crate::book::client::BookClient;
+use crate::book::types::sign_withdrawal;
+use crate::swap::offer::load_identity_key;
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// Withdraw one of our offers from the offer book
+pub struct BookWithdraw {
+    swapid : u64
+}
+
+impl BookWithdraw {
+    pub fn new(swapid : u64) -> BookWithdraw {
+        BookWithdraw {
+            swapid : swapid
+        }
+    }
+}
+
+impl Command for BookWithdraw {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
+        if slate.prv_slate.book.is_none() {
+            slate.prv_slate.book = Some(settings.book.url.clone());
+        }
+        withdraw_from_book(&mut slate, settings, rng, btc_secp)?;
+        Ok(Some(slate))
+    }
+}
+
+/// Withdraw an offer from the book it was published to, signed with our identity key
+///
+/// # Arguments
+///
+/// * `slate` the swap slate, the book url is read from the private slate
+/// * `settings` application settings
+/// * `rng` Randomness generator
+/// * `btc_secp` Bitcoin version of Secp256k1 functions
+pub fn withdraw_from_book(slate : &mut SwapSlate, settings : &Settings, rng : &mut SwapRng, btc_secp : &Secp256k1<All>) -> Result<(), SwapError> {
+    let url = slate.prv_slate.book.clone()
+        .ok_or(SwapError::Validation(String::from("The offer has not been published to an offer book")))?;
+    let sk = load_identity_key(&settings.slate_directory, rng).map_err(SwapError::Io)?;
+    let sig = sign_withdrawal(slate.id, &slate.pub_slate, &sk, btc_secp);
+    BookClient::new(&url).withdraw(slate.id, &sig).map_err(SwapError::PeerProtocol)?;
+    println!("Withdrew offer {} from {}", slate.id, url);
+    slate.prv_slate.book = None;
+    slate.log_event(None, None, None, &format!("Withdrew offer from the offer book {}", url));
+    Ok(())
+}
@@ -1,17 +1,19 @@
-use crate::{bitcoin::bitcoin_core::BitcoinCore, enums::SwapStatus, grin::{grin_core::GrinCore, grin_tx::GrinTx}, net::http::RequestFactory, swap::{protocol::refund_phase_swap_btc, protocol::refund_phase_swap_mw, slate::{self, get_slate_checksum, write_slate_to_disk}}};
-use crate::swap::slate::read_slate_from_disk;
-use rand::rngs::OsRng;
+use crate::{commands::cmd_types::refund::refund_own_side, enums::{ChainStatus, SwapRole, SwapStatus}, swap::slate::get_slate_checksum};
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
 use crate::Settings;
-use crate::commands::cmd_types::command::Command;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::{Command, persist_on_error};
 use std::net::{TcpStream};
 use crate::net::tcp::send_msg;
 use crate::net::tcp::receive_msg;
-use crate::enums::SwapType;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
 
+/// Cancel an Atomic Swap and refund our locked coins
 pub struct Cancel {
     swapid : u64
 }
@@ -25,39 +27,35 @@ impl Cancel {
 }
 
 impl Command for Cancel {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
-        let mut slate : SwapSlate = read_slate_from_disk(self.swapid, &settings.slate_directory)
-            .expect("Unable to read slate files from disk");
-        let mut stream : TcpStream = TcpStream::connect(format!("{}:{}", slate.pub_slate.meta.server, slate.pub_slate.meta.port))
-            .expect("Failed to connect to peer via TCP");
-        let mut btc_core = BitcoinCore::new(settings.btc.clone(), RequestFactory::new(None));
-        let mut grin_core = GrinCore::new(settings.grin.clone(), RequestFactory::new(None));
-        let mut grin_tx = GrinTx::new(settings.grin.clone(), RequestFactory::new(None));
-        // first message exchanged is a hash of the pub slate file
-        println!("Connected to peer");
-        let checksum = get_slate_checksum(slate.id, &settings.slate_directory).unwrap();
-        send_msg(&mut stream, &checksum);
-        let resp = receive_msg(&mut stream);
-        if resp.eq_ignore_ascii_case("OK") == false {
-            Err(String::from("Checksums didn't match"))
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
+        let owns_btc = slate.owns_btc()
+            .ok_or(SwapError::Validation(String::from("Unable to tell which side of the swap we own")))?;
+        let own_status = if owns_btc { slate.pub_slate.btc.status } else { slate.pub_slate.mw.status };
+        if own_status != ChainStatus::LOCKED {
+            return Err(SwapError::Validation(format!("Only locked funds can be refunded, ours are {:?}", own_status)));
         }
-        else {
-            if slate.pub_slate.status != SwapStatus::SETUP {
-                Err("Slate must be in state SETUP to be executed".to_string())
-            }
-            else {
-                send_msg(&mut stream, &String::from("CANCEL"));
-                if slate.pub_slate.btc.swap_type == SwapType::OFFERED {
-                    // Offered value is btc, requested is grin
-                    refund_phase_swap_mw(&mut slate, &mut btc_core, &mut grin_core)?;
-                    Ok(slate)
-                }
-                else {
-                    // Offered value is grin, requested is btc
-                    refund_phase_swap_btc(&mut slate, &mut btc_core, &mut grin_core, btc_secp, rng)?;
-                    Ok(slate)
-                }
+
+        // If both sides are locked the listening peer is told to refund as well
+        if slate.pub_slate.status == SwapStatus::LOCKED && slate.prv_slate.role == Some(SwapRole::TAKER) {
+            match TcpStream::connect(format!("{}:{}", slate.pub_slate.meta.server, slate.pub_slate.meta.port)) {
+                Ok(mut stream) => {
+                    println!("Connected to peer");
+                    // first message exchanged is a hash of the pub slate file
+                    let checksum = get_slate_checksum(store, slate.id).map_err(SwapError::Io)?;
+                    send_msg(&mut stream, &checksum).map_err(SwapError::PeerProtocol)?;
+                    let resp = receive_msg(&mut stream).map_err(SwapError::PeerProtocol)?;
+                    if resp.eq_ignore_ascii_case("OK") == false {
+                        return Err(SwapError::PeerProtocol(String::from("Checksums didn't match")));
+                    }
+                    send_msg(&mut stream, &String::from("CANCEL")).map_err(SwapError::PeerProtocol)?;
+                },
+                Err(e) => println!("Unable to notify peer ({}), refunding our side only", e)
             }
         }
+
+        let result = refund_own_side(&mut slate, chain, rng, btc_secp);
+        persist_on_error(store, &slate, result)?;
+        Ok(Some(slate))
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,35 @@
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
 use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
 
 /// Interface trait to be implemented by all commands
+/// Commands return the slate which should be persisted, or None if they
+/// only inspect swaps. Commands which fail after changing the slate write it
+/// themselves before returning the error
 pub trait Command {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String>;
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError>;
+}
+
+/// Persist the slate when a swap phase failed, so an aborted or partially
+/// locked swap can still be inspected and refunded. The error of the phase is
+/// passed on, a failure to write the slate is only reported.
+///
+/// # Arguments
+///
+/// * `store` the slate storage
+/// * `slate` the slate as the failed phase left it
+/// * `result` result of the swap phase
+pub fn persist_on_error(store : &mut dyn SwapStore, slate : &SwapSlate, result : Result<(), SwapError>) -> Result<(), SwapError> {
+    if result.is_err() {
+        if let Err(e) = store.write_slate(slate, true, true) {
+            println!("Failed to write slate of the failed swap: {}", e);
+        }
+    }
+    result
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
-use crate::{bitcoin::bitcoin_core::BitcoinCore, enums::SwapStatus, grin::{grin_core::GrinCore, grin_tx::GrinTx}, net::http::RequestFactory, swap::{protocol::exec_phase_swap_btc, protocol::exec_phase_swap_mw, slate::{self, get_slate_checksum, write_slate_to_disk}}};
-use crate::swap::slate::read_slate_from_disk;
-use rand::rngs::OsRng;
+use crate::{enums::SwapStatus, grin::grin_tx::GrinTx, swap::{protocol::exec_phase_swap_btc, protocol::exec_phase_swap_mw, slate::get_slate_checksum}};
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
 use crate::Settings;
-use crate::commands::cmd_types::command::Command;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::{Command, persist_on_error};
 use std::net::{TcpStream};
 use crate::net::tcp::send_msg;
 use crate::net::tcp::receive_msg;
@@ -26,37 +28,36 @@ impl Execute {
 }
 
 impl Command for Execute {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
-        let mut slate : SwapSlate = read_slate_from_disk(self.swapid, &settings.slate_directory)
-            .expect("Unable to read slate files from disk");
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
         let mut stream : TcpStream = TcpStream::connect(format!("{}:{}", slate.pub_slate.meta.server, slate.pub_slate.meta.port))
-            .expect("Failed to connect to peer via TCP");
-        let mut btc_core = BitcoinCore::new(settings.btc.clone(), RequestFactory::new(None));
-        let mut grin_core = GrinCore::new(settings.grin.clone(), RequestFactory::new(None));
-        let mut grin_tx = GrinTx::new(settings.grin.clone(), RequestFactory::new(None));
+            .map_err(|e| SwapError::PeerProtocol(format!("Failed to connect to peer via TCP: {}", e)))?;
+        let mut grin_tx = GrinTx::new(rng.fork());
         // first message exchanged is a hash of the pub slate file
         println!("Connected to peer");
-        let checksum = get_slate_checksum(slate.id, &settings.slate_directory).unwrap();
-        send_msg(&mut stream, &checksum);
-        let resp = receive_msg(&mut stream);
+        let checksum = get_slate_checksum(store, slate.id).map_err(SwapError::Io)?;
+        send_msg(&mut stream, &checksum).map_err(SwapError::PeerProtocol)?;
+        let resp = receive_msg(&mut stream).map_err(SwapError::PeerProtocol)?;
         if resp.eq_ignore_ascii_case("OK") == false {
-            Err(String::from("Checksums didn't match"))
+            Err(SwapError::PeerProtocol(String::from("Checksums didn't match")))
         }
         else {
-            if slate.pub_slate.status != SwapStatus::SETUP {
-                Err("Slate must be in state SETUP to be executed".to_string())
+            if slate.pub_slate.status != SwapStatus::LOCKED {
+                Err(SwapError::Validation(format!("Slate must be in state LOCKED to be executed, it is {:?}", slate.pub_slate.status)))
             }
             else {
-                send_msg(&mut stream, &String::from("EXECUTE"));
+                send_msg(&mut stream, &String::from("EXECUTE")).map_err(SwapError::PeerProtocol)?;
                 if slate.pub_slate.btc.swap_type == SwapType::OFFERED {
                     // Offered value is btc, requested is grin
-                    exec_phase_swap_mw(&mut slate, &mut stream, &mut btc_core, rng, &mut grin_tx, &mut grin_core, &grin_secp, btc_secp)?;
-                    Ok(slate)
+                    let result = exec_phase_swap_mw(&mut slate, &mut stream, chain.btc.as_ref(), rng, &mut grin_tx, chain.grin.as_ref(), &grin_secp, btc_secp);
+                    persist_on_error(store, &slate, result)?;
+                    Ok(Some(slate))
                 }
                 else {
                     // Offered value is grin, requested is btc
-                    exec_phase_swap_btc(&mut slate, &mut stream, &mut btc_core, &mut grin_core, &mut grin_tx, &grin_secp)?;
-                    Ok(slate)
+                    let result = exec_phase_swap_btc(&mut slate, &mut stream, chain.btc.as_ref(), chain.grin.as_ref(), &mut grin_tx, &grin_secp);
+                    persist_on_error(store, &slate, result)?;
+                    Ok(Some(slate))
                 }
             }
         }
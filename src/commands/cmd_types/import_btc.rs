@@ -1,11 +1,15 @@
 use bitcoin::PublicKey;
 use crate::bitcoin::bitcoin_types::BTCInput;
-use crate::swap::slate::read_slate_from_disk;
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::enums::Currency;
+use crate::units::format_amount;
 use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
 use crate::commands::cmd_types::command::Command;
 use bitcoin::util::key::PrivateKey;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
@@ -32,11 +36,10 @@ impl ImportBtc {
 }
 
 impl Command for ImportBtc {
-    fn execute(&self, settings: &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
-        let mut slate : SwapSlate = read_slate_from_disk(self.swpid, &settings.slate_directory)
-            .expect("Failed to read SwapSlate");
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swpid).map_err(SwapError::Io)?;
         let sec_key = PrivateKey::from_wif(&self.sk)
-            .expect("Unable to parse private key, please provide in WIF format");
+            .map_err(|e| SwapError::Argument(format!("Unable to parse private key, please provide in WIF format: {}", e)))?;
         let pub_key = PublicKey::from_private_key(btc_secp, &sec_key);
         slate.prv_slate.btc.inputs.push(BTCInput{
             txid : self.txid.clone(),
@@ -46,6 +49,9 @@ impl Command for ImportBtc {
             pub_key : pub_key.to_string(),
             pub_script: self.pub_script.clone()
         });
-        Ok(slate)
+        let msg = format!("Imported input {}:{} with value {}", self.txid, self.vout, format_amount(self.value, Currency::BTC));
+        println!("{}", msg);
+        slate.log_event(Some(Currency::BTC), Some(self.txid.clone()), None, &msg);
+        Ok(Some(slate))
     }
 }
\ No newline at end of file
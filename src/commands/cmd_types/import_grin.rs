@@ -1,10 +1,14 @@
 use crate::grin::grin_types::MWCoin;
-use crate::swap::slate::read_slate_from_disk;
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::enums::Currency;
+use crate::units::format_amount;
 use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
 use crate::commands::cmd_types::command::Command;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
 pub struct ImportGrin {
@@ -26,14 +30,16 @@ impl ImportGrin {
 }
 
 impl Command for ImportGrin {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
-        let mut slate : SwapSlate = read_slate_from_disk(self.swpid, &settings.slate_directory)
-            .expect("Failed to read SwapSlate from file");
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swpid).map_err(SwapError::Io)?;
         slate.prv_slate.mw.inputs.push(MWCoin{
             commitment : self.commitment.clone(),
             blinding_factor : self.blinding_factor.clone(),
             value : self.value
         });
-        Ok(slate)
+        let msg = format!("Imported coin {} with value {}", self.commitment, format_amount(self.value, Currency::GRIN));
+        println!("{}", msg);
+        slate.log_event(Some(Currency::GRIN), None, None, &msg);
+        Ok(Some(slate))
     }
 }
\ No newline at end of file
@@ -1,58 +1,87 @@
-use crate::constants::{BTC_BLOCK_TIME, GRIN_BLOCK_TIME};
+use crate::constants::{BTC_BLOCK_TIME, CURRENT_SLATE_VERSION, GRIN_BLOCK_TIME};
 use crate::enums::Currency;
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
 use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
 use rand::Rng;
 use crate::commands::cmd_types::command::Command;
 use crate::swap::swap_types::Meta;
 use crate::swap::swap_types::MWPub;
 use crate::enums::SwapStatus;
+use crate::enums::ChainStatus;
+use crate::enums::BtcLockType;
+use crate::enums::GrinLockType;
 use crate::enums::SwapType;
+use crate::enums::SwapRole;
 use crate::swap::swap_types::SwapSlatePub;
 use crate::swap::swap_types::SwapSlatePriv;
 use crate::swap::swap_types::MWPriv;
 use crate::swap::swap_types::BTCPriv;
 use crate::swap::swap_types::BTCPub;
+use crate::swap::offer::{load_identity_key, OfferTerms};
+use crate::swap::swap_types::{FillRange, OfferValidity};
+use crate::swap::fill::validate_fill_range;
+use crate::swap::validator::{node_fee_policy, required_btc_timelock, validate_timelocks};
+use crate::swap::pricing::{format_effective_rate, resolve_counter_amount, CounterAmount, FilePriceFeed};
+use crate::commands::parser::validate_init_args;
+use crate::units::format_amount;
+use crate::util::now_secs;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
+use crate::grin::grin_types::KernelLock;
 
 /// The Init command will create a new Atomic Swap slate 
 pub struct Init {
     from : Currency,
     to : Currency,
     from_amount : u64,
-    to_amount : u64,
-    timeout_btc : u64,
-    timeout_grin : u64
+    to_amount : CounterAmount,
+    timeout_grin : u64,
+    expiry_minutes : u64,
+    btc_lock_type : BtcLockType,
+    grin_lock_type : GrinLockType,
+    fill : Option<FillRange>
 }
 
 impl Init {
-    pub fn new(from : Currency, to : Currency, from_amount : u64, to_amount : u64, timeout_minutes: u64) -> Init {
+    pub fn new(from : Currency, to : Currency, from_amount : u64, to_amount : CounterAmount, timeout_minutes: u64, expiry_minutes : u64, btc_lock_type : BtcLockType, grin_lock_type : GrinLockType, fill : Option<FillRange>) -> Init {
         let timeout_grin : u64 = timeout_minutes  / GRIN_BLOCK_TIME;
-        let timeout_btc : u64 = timeout_minutes / BTC_BLOCK_TIME;
 
         Init {
             from : from,
             to: to,
             from_amount: from_amount,
             to_amount: to_amount,
-            timeout_btc : timeout_btc,
-            timeout_grin : timeout_grin
+            timeout_grin : timeout_grin,
+            expiry_minutes : expiry_minutes,
+            btc_lock_type : btc_lock_type,
+            grin_lock_type : grin_lock_type,
+            fill : fill
         }
     }
 }
 
 impl Command for Init {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
         println!("Executing init command");
-        let mut rng = rand::thread_rng();
+        let identity = load_identity_key(&settings.slate_directory, rng).map_err(SwapError::Io)?;
 
         // Create the initial Swapslate
         let id : u64 = rng.gen();
         println!("Swap id: {}", id);
         if self.from == Currency::BTC && self.to == Currency::GRIN || self.from == Currency::GRIN && self.to == Currency::BTC {
+            // The Bitcoin refund has to come after the Grin refund, see swap::validator
+            let timeout_btc = required_btc_timelock(self.timeout_grin, &settings.safety);
+            validate_timelocks(timeout_btc, self.timeout_grin, &settings.safety).map_err(SwapError::Timelock)?;
+            println!("Refund timelocks: {} Grin blocks, {} Bitcoin blocks", self.timeout_grin, timeout_btc);
+            // A NRD refund kernel is relative to the funding transaction, consensus caps its relative height
+            if self.grin_lock_type == GrinLockType::NRD {
+                KernelLock::NRD(self.timeout_grin).validate().map_err(SwapError::Argument)?;
+            }
             // Private parts are unset for now
             let mwpriv = MWPriv{
                 inputs : Vec::new(),
@@ -75,47 +104,99 @@ impl Command for Init {
                 refunded : None
             };
             let prv_slate = SwapSlatePriv{
+                version : CURRENT_SLATE_VERSION,
                 mw : mwpriv,
-                btc : btcpriv
+                btc : btcpriv,
+                role : Some(SwapRole::MAKER),
+                book : None,
+                fills : Vec::new()
             };
 
-            let btc_amount = if Currency::BTC == self.from { self.from_amount } else { self.to_amount };
-            let mw_amount = if Currency::GRIN == self.from { self.from_amount } else { self.to_amount };
+            // The requested amount is either given or computed from a price
+            let feed = FilePriceFeed::new(&settings.price_file);
+            let (to_amount, quote) = resolve_counter_amount(self.from, self.from_amount, self.to_amount, &feed).map_err(SwapError::Argument)?;
+            validate_init_args(self.from, self.from_amount, self.to, to_amount, self.timeout_grin * GRIN_BLOCK_TIME, self.expiry_minutes)
+                .map_err(|e| SwapError::Argument(String::from(e)))?;
+            if let Some(q) = &quote {
+                println!("Quoted price: {} BTC per GRIN, spread {} bps", q.price, q.spread_bps);
+            }
+            let btc_amount = if Currency::BTC == self.from { self.from_amount } else { to_amount };
+            let mw_amount = if Currency::GRIN == self.from { self.from_amount } else { to_amount };
+            println!("Offering {} for {}", format_amount(self.from_amount, self.from), format_amount(to_amount, self.to));
+            println!("Effective rate: {}", format_effective_rate(btc_amount, mw_amount));
+            if let Some(fill) = &self.fill {
+                validate_fill_range(fill, self.from_amount).map_err(SwapError::Argument)?;
+                println!("Partially fillable in fills of {} to {}", format_amount(fill.min, self.from), format_amount(fill.max, self.from));
+            }
 
             // Public parts set depening on from to which currency is swapped
             let btcpub = BTCPub {
                 amount : btc_amount,
-                timelock : self.timeout_btc,
+                timelock : timeout_btc,
                 lock_time : None,
                 swap_type : if self.from == Currency::BTC { SwapType::OFFERED } else { SwapType::REQUESTED },
                 pub_a : None,
                 pub_b : None,
-                pub_x : None
+                pub_x : None,
+                status : ChainStatus::NONE,
+                lock_type : self.btc_lock_type
             };
             let mwpub = MWPub {
                 amount : mw_amount,
                 timelock : self.timeout_grin,
                 lock_time : None,
-                swap_type : if self.from == Currency::GRIN { SwapType::OFFERED } else { SwapType::REQUESTED }
+                swap_type : if self.from == Currency::GRIN { SwapType::OFFERED } else { SwapType::REQUESTED },
+                status : ChainStatus::NONE,
+                fee_base : Some(node_fee_policy(&settings.fees, chain.grin.as_ref()).offer_fee_base()?),
+                lock_type : self.grin_lock_type
             };
+            // The offer can be taken until the expiry time or heights are reached
+            let now = now_secs();
+            let btc_height = chain.btc.get_current_block_height()
+                .map_err(|e| println!("Unable to query Bitcoin node, offer expires by time only: {}", e))
+                .ok();
+            let mw_height = chain.grin.get_block_height()
+                .map_err(|e| println!("Unable to query Grin node, offer expires by time only: {}", e))
+                .ok();
+            let nonce : [u8; 16] = rng.gen();
+            let validity = OfferValidity {
+                created : now,
+                expires : now + self.expiry_minutes * 60,
+                btc_height : btc_height.map(|h| h + self.expiry_minutes / BTC_BLOCK_TIME + 1),
+                mw_height : mw_height.map(|h| h + self.expiry_minutes / GRIN_BLOCK_TIME + 1),
+                nonce : hex::encode(nonce)
+            };
+            println!("Offer expires at {} ({} minutes)", validity.expires, self.expiry_minutes);
             let meta = Meta {
                 server : settings.tcp_addr.clone(),
                 port : settings.tcp_port.clone()
             };
             let pub_slate = SwapSlatePub {
+                version : CURRENT_SLATE_VERSION,
                 status : SwapStatus::INITIALIZED,
                 mw : mwpub,
                 btc : btcpub,
-                meta : meta
+                meta : meta,
+                validity : Some(validity),
+                offer_sig : None,
+                quote : quote,
+                fill : self.fill,
+                fill_of : None
             };
-            Ok(SwapSlate{
+            let mut slate = SwapSlate{
                 id : id,
                 pub_slate : pub_slate,
-                prv_slate : prv_slate
-            })
+                prv_slate : prv_slate,
+                events : Vec::new()
+            };
+            // Sign the offer terms so the peer can verify who created the offer
+            let terms = OfferTerms::from_pub_slate(id, &slate.pub_slate);
+            slate.pub_slate.offer_sig = Some(terms.sign(&identity, btc_secp));
+            slate.log_event(None, None, None, "Created swap offer");
+            Ok(Some(slate))
         }
         else {
-            Err(String::from("Swapped currency setup not supported"))
+            Err(SwapError::Argument(String::from("Swapped currency setup not supported")))
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,54 @@
+use crate::enums::Currency;
+use crate::enums::SwapStatus;
+use crate::swap::store::store_types::{SwapQuery, SwapStore};
+use crate::units::format_units;
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// List all swaps found in the slate storage
+pub struct List {
+    status : Option<SwapStatus>,
+    currency : Option<Currency>
+}
+
+impl List {
+    pub fn new(status : Option<SwapStatus>, currency : Option<Currency>) -> List {
+        List {
+            status : status,
+            currency : currency
+        }
+    }
+}
+
+impl Command for List {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let query = SwapQuery {
+            status : self.status,
+            currency : self.currency,
+            from : None,
+            to : None
+        };
+        let entries = store.query(&query).map_err(SwapError::Io)?;
+        if entries.is_empty() {
+            println!("No swaps found");
+        }
+        else {
+            println!("{:<20} {:<12} {:>16} {:>14} {:>20} {:>14} {:<12} {:>12}", "id", "offer", "btc (sats)", "BTC", "grin (nanogrin)", "GRIN", "status", "created");
+            for e in entries {
+                let direction = if e.offered == Currency::BTC { "BTC -> GRIN" } else { "GRIN -> BTC" };
+                println!("{:<20} {:<12} {:>16} {:>14} {:>20} {:>14} {:<12} {:>12}", e.id, direction,
+                    e.btc_amount, format_units(e.btc_amount, Currency::BTC),
+                    e.mw_amount, format_units(e.mw_amount, Currency::GRIN),
+                    format!("{:?}", e.status), e.created);
+            }
+        }
+        Ok(None)
+    }
+}
@@ -1,44 +1,72 @@
-use crate::{bitcoin::bitcoin_core::BitcoinCore, enums::SwapStatus, grin::{grin_core::GrinCore, grin_tx::GrinTx}, net::http::RequestFactory, swap::{protocol::{exec_phase_swap_btc, exec_phase_swap_mw, locking_phase_swap_mw}, slate}};
+use crate::{enums::SwapStatus, grin::grin_tx::GrinTx, swap::protocol::{exec_phase_swap_btc, exec_phase_swap_mw, locking_phase_swap_mw}};
 use crate::swap::protocol::locking_phase_swap_btc;
 use crate::swap::protocol::refund_phase_swap_mw;
 use crate::swap::protocol::refund_phase_swap_btc;
-use crate::net::tcp::send_msg;
+use crate::net::tcp::{send_msg, MessageChannel};
 use crate::swap::slate::get_slate_checksum;
+use crate::swap::offer::check_offer_expiry;
+use crate::commands::cmd_types::book_withdraw::withdraw_from_book;
 use crate::net::tcp::receive_msg;
-use crate::swap::slate::read_slate_from_disk;
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
 use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
 use crate::enums::SwapType;
 use crate::enums::Currency;
-use std::net::{TcpListener, TcpStream};
+use std::net::TcpListener;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
 
-use crate::commands::cmd_types::command::Command;
+use crate::commands::cmd_types::command::{Command, persist_on_error};
+use crate::units::format_amount;
+use crate::swap::fill::{parse_fill_request, derive_child, remaining_fill, spawn_child, release_child};
+use crate::swap::offer::OfferTerms;
+use crate::enums::ChainStatus;
 
 pub struct Listen {
-    swapid : u64
+    swapid : u64,
+    // bound by the caller, otherwise the address in the settings is bound
+    listener : Option<TcpListener>
 }
 
 impl Listen {
     pub fn new(swapid : u64) -> Listen {
         Listen {
-            swapid : swapid
+            swapid : swapid,
+            listener : None
+        }
+    }
+
+    /// Serve the swap on a listener which is already bound,
+    /// peers can connect to it before the command runs
+    ///
+    /// # Arguments
+    ///
+    /// * `swapid` the id of the Atomic Swap
+    /// * `listener` the bound listener
+    pub fn with_listener(swapid : u64, listener : TcpListener) -> Listen {
+        Listen {
+            swapid : swapid,
+            listener : Some(listener)
         }
     }
 }
 
 impl Command for Listen {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
 
-        let mut swp_slate = read_slate_from_disk(self.swapid, &settings.slate_directory)
-            .expect("Failed to read SwapSlate from file");
+        let mut swp_slate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
 
         // Check if we have enough value
         let offered_currency = if swp_slate.pub_slate.mw.swap_type == SwapType::OFFERED { Currency::GRIN } else { Currency::BTC };
-        let from_amount : u64 = if offered_currency == Currency::GRIN { swp_slate.pub_slate.mw.amount } else { swp_slate.pub_slate.btc.amount };
+        // a partially fillable offer has to cover at least the smallest fill
+        let from_amount : u64 = match &swp_slate.pub_slate.fill {
+            Some(fill) => fill.min,
+            None => if offered_currency == Currency::GRIN { swp_slate.pub_slate.mw.amount } else { swp_slate.pub_slate.btc.amount }
+        };
         let mut value : u64 = 0;
         if offered_currency == Currency::GRIN {
             for inp in &swp_slate.prv_slate.mw.inputs {
@@ -52,71 +80,249 @@ impl Command for Listen {
                 value = value + inp.value
             }
         }
-        if swp_slate.pub_slate.status != SwapStatus::SETUP && value < from_amount {
-            Err(String::from("Not enough value in inputs, please import more Coins"))
+        if swp_slate.pub_slate.status == SwapStatus::INITIALIZED && value < from_amount {
+            Err(SwapError::InsufficientFunds(format!("Not enough value in inputs, imported {} of {}, please import more Coins", format_amount(value, offered_currency), format_amount(from_amount, offered_currency))))
         }
         else {    
             // Start TCP server
             let tcpaddr : String = format!("{}:{}", settings.tcp_addr, settings.tcp_port);
             println!("Starting TCP Listener on {}", tcpaddr);
             println!("Please share {}.pub.json with a interested peer. Never share your private file", self.swapid);
-            let mut btc_core = BitcoinCore::new(settings.btc.clone(), RequestFactory::new(None));
-            let mut grin_core = GrinCore::new(settings.grin.clone(), RequestFactory::new(None));
-            let mut grin_tx = GrinTx::new(settings.grin.clone(), RequestFactory::new(None));
-            let listener = TcpListener::bind(tcpaddr).unwrap(); 
+            let mut grin_tx = GrinTx::new(rng.fork());
+            let bound;
+            let listener = match &self.listener {
+                Some(listener) => listener,
+                None => {
+                    bound = TcpListener::bind(&tcpaddr)
+                        .map_err(|e| SwapError::Io(format!("Unable to listen on {}: {}", tcpaddr, e)))?;
+                    &bound
+                }
+            };
             for client in listener.incoming() {
                 println!("A client connected");
-                let mut stream = client.unwrap();
-                let msg = receive_msg(&mut stream);
+                let mut stream = match client {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        println!("Unable to accept the client: {}", e);
+                        continue;
+                    }
+                };
+                let msg = match receive_msg(&mut stream) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        println!("Unable to read from the client: {}", e);
+                        continue;
+                    }
+                };
+
+                // A taker of a partially fillable offer announces its child swap first
+                if let Some((parent_id, child_id, amount)) = parse_fill_request(&msg) {
+                    if let Err(e) = lock_child(&mut swp_slate, parent_id, child_id, amount, &mut stream, store, settings, chain, rng, btc_secp, &mut grin_tx) {
+                        println!("Child swap {} failed: {}", child_id, e);
+                    }
+                    store.write_slate(&swp_slate, true, true).map_err(SwapError::Io)?;
+                    if fills_done(&swp_slate, store) {
+                        println!("All of the offer has been filled");
+                        break;
+                    }
+                    continue;
+                }
+
                 let id = swp_slate.id.clone();
-                let checksum = get_slate_checksum(id, &settings.slate_directory).unwrap();
+                let checksum = get_slate_checksum(store, id).map_err(SwapError::Io)?;
                 println!("Calculated slate checksum {}", checksum);
 
                 if msg.eq_ignore_ascii_case(&checksum) {
                     println!("Swap Checksum matched");
+                    // The lock phase is only run once per offer and only before it expired
+                    let refusal = match swp_slate.pub_slate.status {
+                        SwapStatus::INITIALIZED if swp_slate.pub_slate.fill.is_some() => {
+                            println!("Offer is partially fillable and can only be taken by child swaps, refusing");
+                            Some("UNAVAILABLE")
+                        },
+                        SwapStatus::INITIALIZED => check_offer_expiry(&swp_slate.pub_slate, chain.btc.as_ref(), chain.grin.as_ref())
+                            .err()
+                            .map(|e| {
+                                println!("Refusing to lock: {}", e);
+                                "EXPIRED"
+                            }),
+                        SwapStatus::LOCKED => None,
+                        status => {
+                            println!("Offer has already been taken (status {:?}), refusing", status);
+                            Some("TAKEN")
+                        }
+                    };
+                    if let Some(reason) = refusal {
+                        send_msg(&mut stream, &String::from(reason))
+                            .unwrap_or_else(|e| println!("Unable to answer the client: {}", e));
+                        continue;
+                    }
                     // Send back OK message
-                    send_msg(&mut stream, &String::from("OK"));
+                    send_msg(&mut stream, &String::from("OK")).map_err(SwapError::PeerProtocol)?;
                     if swp_slate.pub_slate.status == SwapStatus::INITIALIZED {
                         // Run the setup phase
-                        if swp_slate.pub_slate.btc.swap_type == SwapType::OFFERED {
-                            locking_phase_swap_btc(&mut swp_slate, &mut stream, rng, &btc_secp, &mut grin_core, &mut btc_core, &mut grin_tx)?;
-                            break;
-                        }
-                        else {
-                            locking_phase_swap_mw(&mut swp_slate, &mut stream, rng, &btc_secp, &mut grin_core, &mut btc_core, &mut grin_tx)?;
-                            break;
-                        }
-                    }
-                    else if swp_slate.pub_slate.status == SwapStatus::SETUP {
-                        let msg = receive_msg(&mut stream);
-                        if msg == "EXECUTE" {
-                            if swp_slate.pub_slate.btc.swap_type == SwapType::OFFERED {
-                                exec_phase_swap_btc(&mut swp_slate, &mut stream, &mut btc_core, &mut grin_core, &mut grin_tx, grin_secp)?;
-                                break;
-                            }
-                            else {
-                                exec_phase_swap_mw(&mut swp_slate, &mut stream, &mut btc_core, rng, &mut grin_tx, &mut grin_core, grin_secp, btc_secp)?;
-                                break;
-                            }
-                        } else {
-                            println!("Cancelling atomic swap...");
-                            if swp_slate.pub_slate.btc.swap_type == SwapType::OFFERED {
-                                refund_phase_swap_btc(&mut swp_slate, &mut btc_core, &mut grin_core, btc_secp, rng)?;
-                                break;
-                            }
-                            else {
-                                refund_phase_swap_mw(&mut swp_slate, &mut btc_core, &mut grin_core)?;
-                                break;
+                        let result = lock_swap(&mut swp_slate, &mut stream, settings, chain, rng, btc_secp, &mut grin_tx);
+                        persist_on_error(store, &swp_slate, result)?;
+                        // The offer has been taken, remove it from the book
+                        if swp_slate.prv_slate.book.is_some() {
+                            if let Err(e) = withdraw_from_book(&mut swp_slate, settings, rng, btc_secp) {
+                                println!("Unable to withdraw the offer from the offer book: {}", e);
                             }
                         }
+                        break;
+                    }
+                    else {
+                        let result = finish_swap(&mut swp_slate, &mut stream, chain, rng, btc_secp, grin_secp, &mut grin_tx);
+                        persist_on_error(store, &swp_slate, result)?;
+                        break;
+                    }
+                }
+                else if let Some(child_id) = find_child(&swp_slate, store, &msg).map_err(SwapError::Io)? {
+                    // Execution or cancellation of a locked child swap
+                    let mut child = store.read_slate(child_id).map_err(SwapError::Io)?;
+                    if child.pub_slate.status != SwapStatus::LOCKED {
+                        println!("Child swap {} is {:?}, refusing", child_id, child.pub_slate.status);
+                        send_msg(&mut stream, &String::from("TAKEN"))
+                            .unwrap_or_else(|e| println!("Unable to answer the client: {}", e));
+                        continue;
+                    }
+                    send_msg(&mut stream, &String::from("OK")).map_err(SwapError::PeerProtocol)?;
+                    let result = finish_swap(&mut child, &mut stream, chain, rng, btc_secp, grin_secp, &mut grin_tx);
+                    store.write_slate(&child, true, true).map_err(SwapError::Io)?;
+                    if let Err(e) = result {
+                        println!("Child swap {} failed: {}", child_id, e);
+                    }
+                    if fills_done(&swp_slate, store) {
+                        println!("All of the offer has been filled");
+                        break;
                     }
                 }
                 else {
                     println!("Swap Checksum did not match, cancelling");
-                    send_msg(&mut stream, &String::from("FAULT"));
+                    send_msg(&mut stream, &String::from("FAULT"))
+                        .unwrap_or_else(|e| println!("Unable to answer the client: {}", e));
                 }
             };
-            Ok(swp_slate)
+            Ok(Some(swp_slate))
         }
     } 
-}
\ No newline at end of file
+}
+
+/// Run the locking phase of a swap we offered
+fn lock_swap(slate : &mut SwapSlate, stream : &mut dyn MessageChannel, settings : &Settings, chain : &ChainContext, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_tx : &mut GrinTx) -> Result<(), SwapError> {
+    if slate.pub_slate.btc.swap_type == SwapType::OFFERED {
+        locking_phase_swap_btc(slate, stream, rng, btc_secp, chain.grin.as_ref(), chain.btc.as_ref(), grin_tx, &settings.safety)
+    }
+    else {
+        locking_phase_swap_mw(slate, stream, rng, btc_secp, chain.grin.as_ref(), chain.btc.as_ref(), grin_tx, &settings.safety)
+    }
+}
+
+/// Execute or cancel a locked swap, as requested by the peer
+fn finish_swap(slate : &mut SwapSlate, stream : &mut dyn MessageChannel, chain : &ChainContext, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1, grin_tx : &mut GrinTx) -> Result<(), SwapError> {
+    let btc_core = chain.btc.as_ref();
+    let grin_core = chain.grin.as_ref();
+    let msg = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    if msg == "EXECUTE" {
+        if slate.pub_slate.btc.swap_type == SwapType::OFFERED {
+            exec_phase_swap_btc(slate, stream, btc_core, grin_core, grin_tx, grin_secp)
+        }
+        else {
+            exec_phase_swap_mw(slate, stream, btc_core, rng, grin_tx, grin_core, grin_secp, btc_secp)
+        }
+    } else {
+        println!("Cancelling atomic swap...");
+        if slate.pub_slate.btc.swap_type == SwapType::OFFERED {
+            refund_phase_swap_btc(slate, btc_core, grin_core, btc_secp, rng)
+        }
+        else {
+            refund_phase_swap_mw(slate, btc_core, grin_core)
+        }
+    }
+}
+
+/// Spawn a child swap of a partially fillable offer and run its locking phase.
+/// The inputs of the child are reserved and stored before anything is locked,
+/// they are returned to the offer if the child didn't lock any funds.
+///
+/// # Arguments
+///
+/// * `parent` the slate of the partially fillable offer
+/// * `parent_id` the offer id announced by the taker
+/// * `child_id` the id of the child swap
+/// * `amount` the filled amount of the offered currency
+fn lock_child(parent : &mut SwapSlate, parent_id : u64, child_id : u64, amount : u64, stream : &mut dyn MessageChannel, store : &mut dyn SwapStore, settings : &Settings, chain : &ChainContext, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_tx : &mut GrinTx) -> Result<(), SwapError> {
+    let checksum = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let refusal = if parent_id != parent.id {
+        println!("Fill requested for offer {}, we are serving {}", parent_id, parent.id);
+        Some("FAULT")
+    }
+    else if parent.pub_slate.status != SwapStatus::INITIALIZED || store.contains(child_id) {
+        Some("TAKEN")
+    }
+    else if let Err(e) = check_offer_expiry(&parent.pub_slate, chain.btc.as_ref(), chain.grin.as_ref()) {
+        println!("Refusing to lock: {}", e);
+        Some("EXPIRED")
+    }
+    else {
+        match derive_child(parent.id, &parent.pub_slate, child_id, amount) {
+            Ok(child_pub) if checksum.eq_ignore_ascii_case(&OfferTerms::from_pub_slate(child_id, &child_pub).hash_hex()) => None,
+            Ok(_) => {
+                println!("Child swap checksum did not match, cancelling");
+                Some("FAULT")
+            },
+            Err(e) => {
+                println!("Refusing fill: {}", e);
+                Some("UNAVAILABLE")
+            }
+        }
+    };
+    if let Some(reason) = refusal {
+        send_msg(stream, &String::from(reason)).map_err(SwapError::PeerProtocol)?;
+        return Ok(());
+    }
+    let mut child = match spawn_child(parent, child_id, amount) {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Refusing fill: {}", e);
+            send_msg(stream, &String::from("UNAVAILABLE")).map_err(SwapError::PeerProtocol)?;
+            return Ok(());
+        }
+    };
+    // the reservation is stored before any funds get locked
+    store.write_slate(&child, true, true).map_err(SwapError::Io)?;
+    store.write_slate(parent, true, true).map_err(SwapError::Io)?;
+    send_msg(stream, &String::from("OK")).map_err(SwapError::PeerProtocol)?;
+    println!("Locking child swap {} of offer {}", child_id, parent.id);
+    let result = lock_swap(&mut child, stream, settings, chain, rng, btc_secp, grin_tx);
+    if result.is_err() && child.pub_slate.btc.status == ChainStatus::NONE && child.pub_slate.mw.status == ChainStatus::NONE {
+        release_child(parent, &mut child);
+    }
+    store.write_slate(&child, true, true).map_err(SwapError::Io)?;
+    // Once the rest can't be filled anymore, remove the offer from the book
+    let exhausted = parent.pub_slate.fill.map_or(true, |f| remaining_fill(parent) < f.min);
+    if result.is_ok() && exhausted && parent.prv_slate.book.is_some() {
+        if let Err(e) = withdraw_from_book(parent, settings, rng, btc_secp) {
+            println!("Unable to withdraw the offer from the offer book: {}", e);
+        }
+    }
+    result
+}
+
+/// Find the child swap of an offer whose checksum a peer sent
+fn find_child(parent : &SwapSlate, store : &dyn SwapStore, msg : &str) -> Result<Option<u64>, String> {
+    for fill in &parent.prv_slate.fills {
+        if msg.eq_ignore_ascii_case(&get_slate_checksum(store, fill.id)?) {
+            return Ok(Some(fill.id));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns true once the rest of an offer can't be filled and no child swap is waiting to be executed
+fn fills_done(parent : &SwapSlate, store : &dyn SwapStore) -> bool {
+    let exhausted = parent.pub_slate.fill.map_or(true, |f| remaining_fill(parent) < f.min);
+    exhausted && parent.prv_slate.fills.iter().all(|f| {
+        store.read_pub_slate(f.id).map_or(true, |s| s.status != SwapStatus::LOCKED)
+    })
+}
@@ -1,17 +1,21 @@
-use crate::{bitcoin::bitcoin_core::BitcoinCore, enums::SwapStatus, grin::{grin_core::GrinCore, grin_tx::GrinTx}, net::http::RequestFactory, swap::{slate::{get_slate_checksum}}};
-use crate::swap::slate::read_slate_from_disk;
-use rand::rngs::OsRng;
+use crate::{enums::SwapStatus, grin::grin_tx::GrinTx, swap::{slate::{get_slate_checksum}}};
+use crate::util::SwapRng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
 use crate::SwapSlate;
+use crate::error::SwapError;
 use crate::Settings;
-use crate::commands::cmd_types::command::Command;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::{Command, persist_on_error};
 use std::net::{TcpStream};
 use crate::net::tcp::send_msg;
 use crate::net::tcp::receive_msg;
 use crate::enums::SwapType;
 use crate::swap::protocol::locking_phase_swap_btc;
 use crate::swap::protocol::locking_phase_swap_mw;
+use crate::swap::offer::{check_offer_expiry, verify_offer};
+use crate::swap::fill::fill_request;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
 
 pub struct Lock {
@@ -27,36 +31,54 @@ impl Lock {
 }
 
 impl Command for Lock {
-    fn execute(&self, settings : &Settings, rng : &mut OsRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<SwapSlate, String> {
-        let mut slate : SwapSlate = read_slate_from_disk(self.swapid, &settings.slate_directory)
-            .expect("Unable to read slate files from disk");
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
+        verify_offer(slate.id, &slate.pub_slate, btc_secp).map_err(SwapError::Validation)?;
+        if slate.pub_slate.fill.is_some() && slate.pub_slate.fill_of.is_none() {
+            return Err(SwapError::Validation(String::from("Offer is partially fillable, accept it with --fill and lock the child swap")));
+        }
+        check_offer_expiry(&slate.pub_slate, chain.btc.as_ref(), chain.grin.as_ref())?;
         let mut stream : TcpStream = TcpStream::connect(format!("{}:{}", slate.pub_slate.meta.server, slate.pub_slate.meta.port))
-            .expect("Failed to connect to peer via TCP");
-        let mut btc_core = BitcoinCore::new(settings.btc.clone(), RequestFactory::new(None));
-        let mut grin_core = GrinCore::new(settings.grin.clone(), RequestFactory::new(None));
-        let mut grin_tx = GrinTx::new(settings.grin.clone(), RequestFactory::new(None));
+            .map_err(|e| SwapError::PeerProtocol(format!("Failed to connect to peer via TCP: {}", e)))?;
+        let mut grin_tx = GrinTx::new(rng.fork());
         // first message exchanged is a hash of the pub slate file
         println!("Connected to peer");
-        let checksum = get_slate_checksum(slate.id, &settings.slate_directory).unwrap();
-        send_msg(&mut stream, &checksum);
-        let resp = receive_msg(&mut stream);
-        if resp.eq_ignore_ascii_case("OK") == false {
-            Err(String::from("Checksums didn't match!"))
+        // A child swap is announced first so the peer can spawn it from the offer
+        if let Some(parent) = &slate.pub_slate.fill_of {
+            let amount = if slate.pub_slate.btc.swap_type == SwapType::OFFERED { slate.pub_slate.btc.amount } else { slate.pub_slate.mw.amount };
+            send_msg(&mut stream, &fill_request(parent.parent_id, slate.id, amount)).map_err(SwapError::PeerProtocol)?;
+        }
+        let checksum = get_slate_checksum(store, slate.id).map_err(SwapError::Io)?;
+        send_msg(&mut stream, &checksum).map_err(SwapError::PeerProtocol)?;
+        let resp = receive_msg(&mut stream).map_err(SwapError::PeerProtocol)?;
+        if resp == "EXPIRED" {
+            Err(SwapError::Timelock(String::from("Peer refused, the offer has expired")))
+        }
+        else if resp == "TAKEN" {
+            Err(SwapError::PeerProtocol(String::from("Peer refused, the offer has already been taken")))
+        }
+        else if resp == "UNAVAILABLE" {
+            Err(SwapError::PeerProtocol(String::from("Peer refused, not enough of the offer is left for this fill")))
+        }
+        else if resp.eq_ignore_ascii_case("OK") == false {
+            Err(SwapError::PeerProtocol(String::from("Checksums didn't match!")))
         }
         else {
             if slate.pub_slate.status != SwapStatus::INITIALIZED {
-                Err(String::from("Slate is not in initialized state!"))
+                Err(SwapError::Validation(String::from("Slate is not in initialized state!")))
             }
             else {
                 if slate.pub_slate.btc.swap_type == SwapType::OFFERED {
                     // Offered value is btc, requested is grin
-                    locking_phase_swap_mw(&mut slate, &mut stream, rng, &btc_secp, &mut grin_core, &mut btc_core, &mut grin_tx)?;
-                    Ok(slate)
+                    let result = locking_phase_swap_mw(&mut slate, &mut stream, rng, &btc_secp, chain.grin.as_ref(), chain.btc.as_ref(), &mut grin_tx, &settings.safety);
+                    persist_on_error(store, &slate, result)?;
+                    Ok(Some(slate))
                 }
                 else {
                     // Offered value is grin, requested is btc
-                    locking_phase_swap_btc(&mut slate, &mut stream, rng, &btc_secp, &mut grin_core, &mut btc_core, &mut grin_tx)?;
-                    Ok(slate)
+                    let result = locking_phase_swap_btc(&mut slate, &mut stream, rng, &btc_secp, chain.grin.as_ref(), chain.btc.as_ref(), &mut grin_tx, &settings.safety);
+                    persist_on_error(store, &slate, result)?;
+                    Ok(Some(slate))
                 }
             }
         }
@@ -0,0 +1,56 @@
+use crate::{enums::ChainStatus, swap::{protocol::refund_phase_swap_btc, protocol::refund_phase_swap_mw}};
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::swap::store::store_types::SwapStore;
+use crate::commands::cmd_types::command::{Command, persist_on_error};
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+
+/// Refund our locked coins once the lock time has passed, without contacting the peer
+pub struct Refund {
+    swapid : u64
+}
+
+impl Refund {
+    pub fn new(swapid : u64) -> Refund {
+        Refund {
+            swapid : swapid
+        }
+    }
+}
+
+impl Command for Refund {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate : SwapSlate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
+        let result = refund_own_side(&mut slate, chain, rng, btc_secp);
+        persist_on_error(store, &slate, result)?;
+        Ok(Some(slate))
+    }
+}
+
+/// Publish the refund transaction for the side of the swap we own
+///
+/// # Arguments
+///
+/// * `slate` the swap slate, our funds need to be locked
+/// * `chain` the chain backends
+/// * `rng` Randomness generator
+/// * `btc_secp` Bitcoin version of Secp256k1 functions
+pub fn refund_own_side(slate : &mut SwapSlate, chain : &ChainContext, rng : &mut SwapRng, btc_secp : &Secp256k1<All>) -> Result<(), SwapError> {
+    let owns_btc = slate.owns_btc()
+        .ok_or(SwapError::Validation(String::from("Unable to tell which side of the swap we own")))?;
+    let own_status = if owns_btc { slate.pub_slate.btc.status } else { slate.pub_slate.mw.status };
+    if own_status != ChainStatus::LOCKED {
+        return Err(SwapError::Validation(format!("Only locked funds can be refunded, ours are {:?}", own_status)));
+    }
+    if owns_btc {
+        refund_phase_swap_btc(slate, chain.btc.as_ref(), chain.grin.as_ref(), btc_secp, rng)
+    }
+    else {
+        refund_phase_swap_mw(slate, chain.btc.as_ref(), chain.grin.as_ref())
+    }
+}
@@ -0,0 +1,336 @@
+use crate::constants::{BTC_BLOCK_TIME, GRIN_BLOCK_TIME};
+use crate::enums::{BtcLockType, ChainStatus, Currency, SwapRole, SwapStatus, SwapType};
+use crate::swap::store::store_types::SwapStore;
+use crate::swap::validator::btc_minutes_left;
+use crate::swap::protocol::grin_refund_height;
+use crate::units::format_amount;
+use crate::swap::fill::remaining_fill;
+use crate::util::SwapRng;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::secp256k1::All;
+use crate::SwapSlate;
+use crate::error::SwapError;
+use crate::Settings;
+use crate::commands::context::ChainContext;
+use crate::commands::cmd_types::command::Command;
+use grin_util::secp::Secp256k1 as GrinSecp256k1;
+use std::convert::TryFrom;
+
+/// Show the state of a swap together with the live progress on both chains
+pub struct Status {
+    swapid : u64
+}
+
+impl Status {
+    pub fn new(swapid : u64) -> Status {
+        Status {
+            swapid : swapid
+        }
+    }
+}
+
+impl Command for Status {
+    fn execute(&self, settings : &Settings, chain : &ChainContext, store : &mut dyn SwapStore, rng : &mut SwapRng, btc_secp : &Secp256k1<All>, grin_secp : &GrinSecp256k1) -> Result<Option<SwapSlate>, SwapError> {
+        let mut slate = store.read_slate(self.swapid).map_err(SwapError::Io)?;
+        let btc_core = chain.btc.as_ref();
+        let grin_core = chain.grin.as_ref();
+        let owns_btc = slate.owns_btc();
+
+        println!("Swap {}", slate.id);
+        let role = match slate.prv_slate.role {
+            Some(SwapRole::MAKER) => "maker",
+            Some(SwapRole::TAKER) => "taker",
+            None => "unknown"
+        };
+        let side = match owns_btc {
+            Some(true) => "selling BTC for GRIN",
+            Some(false) => "selling GRIN for BTC",
+            None => "side not known yet"
+        };
+        println!("Role: {} ({})", role, side);
+        println!("Amounts: {} / {}", format_amount(slate.pub_slate.btc.amount, Currency::BTC), format_amount(slate.pub_slate.mw.amount, Currency::GRIN));
+        println!("Status: {:?} (Bitcoin funds {:?}, Grin funds {:?})", slate.pub_slate.status, slate.pub_slate.btc.status, slate.pub_slate.mw.status);
+        let offered = if slate.pub_slate.btc.swap_type == SwapType::OFFERED { Currency::BTC } else { Currency::GRIN };
+        match (&slate.pub_slate.fill, &slate.pub_slate.fill_of) {
+            (_, Some(parent)) => println!("Child swap of offer {}", parent.parent_id),
+            (Some(fill), None) => {
+                println!("Partially fillable in fills of {} to {}, {} left", format_amount(fill.min, offered), format_amount(fill.max, offered), format_amount(remaining_fill(&slate), offered));
+                for child in &slate.prv_slate.fills {
+                    let status = store.read_pub_slate(child.id)
+                        .map(|s| format!("{:?}", s.status))
+                        .unwrap_or(String::from("unknown"));
+                    println!("  Child swap {}: {} ({})", child.id, format_amount(child.amount, offered), status);
+                }
+            },
+            (None, None) => ()
+        }
+
+        let btc_height = btc_core.get_current_block_height()
+            .map_err(|e| println!("Unable to query Bitcoin node: {}", e))
+            .ok();
+        let grin_height = grin_core.get_block_height()
+            .map_err(|e| println!("Unable to query Grin node: {}", e))
+            .ok();
+        let btc_left = match slate.pub_slate.btc.lock_type {
+            BtcLockType::HEIGHT => print_chain_progress("Bitcoin", btc_height, slate.pub_slate.btc.lock_time, BTC_BLOCK_TIME),
+            BtcLockType::TIME => {
+                let mtp = btc_core.get_median_time_past()
+                    .map_err(|e| println!("Unable to query Bitcoin median time past: {}", e))
+                    .ok();
+                print_btc_time_progress(mtp, slate.pub_slate.btc.lock_time)
+            }
+        };
+        // a NRD refund lock height is known once the funding transaction confirmed
+        let grin_lock = grin_refund_height(grin_core, &slate)
+            .map_err(|e| println!("Unable to query the Grin refund lock height: {}", e))
+            .unwrap_or(None)
+            .and_then(|h| i64::try_from(h).ok());
+        let grin_left = print_chain_progress("Grin", grin_height, grin_lock, GRIN_BLOCK_TIME);
+
+        // Bitcoin transactions
+        let btc_txs = vec![
+            ("lock", slate.prv_slate.btc.lock.as_ref()),
+            ("redeem", slate.prv_slate.btc.swapped.as_ref()),
+            ("refund", slate.prv_slate.btc.refunded.as_ref())
+        ];
+        let mut btc_lock_confirmed = false;
+        let mut btc_refund_confirmed = false;
+        for (name, inp) in btc_txs {
+            if let Some(inp) = inp {
+                match btc_core.get_tx_confirmations(&inp.txid) {
+                    Ok(c) => {
+                        println!("Bitcoin {} transaction {}: {} confirmations", name, inp.txid, c);
+                        btc_lock_confirmed = btc_lock_confirmed || (name == "lock" && c > 0);
+                        btc_refund_confirmed = btc_refund_confirmed || (name == "refund" && c > 0);
+                    },
+                    Err(e) => println!("Bitcoin {} transaction {}: unknown ({})", name, inp.txid, e)
+                }
+            }
+        }
+
+        // Grin outputs created by the lock, contract and refund transactions
+        let grin_outs = vec![
+            ("lock", slate.prv_slate.mw.shared_coin.as_ref()),
+            ("contract", slate.prv_slate.mw.swapped_coin.as_ref()),
+            ("refund", slate.prv_slate.mw.refund_coin.as_ref())
+        ];
+        let mut grin_refund_confirmed = false;
+        for (name, coin) in grin_outs {
+            if let Some(coin) = coin {
+                match grin_core.get_output(&coin.commitment) {
+                    Ok(Some(o)) => {
+                        let conf = match (grin_height, o.block_height) {
+                            (Some(h), Some(b)) if h >= b => h - b + 1,
+                            _ => 0
+                        };
+                        println!("Grin {} output {}: {} confirmations{}", name, coin.commitment, conf, if o.spent { " (spent)" } else { "" });
+                        grin_refund_confirmed = grin_refund_confirmed || (name == "refund" && conf > 0);
+                    },
+                    Ok(None) => println!("Grin {} output {}: not on chain", name, coin.commitment),
+                    Err(e) => println!("Grin {} output {}: unknown ({})", name, coin.commitment, e)
+                }
+            }
+        }
+
+        // Grin transactions we published, identified by their kernel excess
+        for e in slate.events.iter().filter(|e| e.chain == Some(Currency::GRIN)) {
+            if let Some(excess) = &e.txid {
+                match grin_core.get_kernel(excess) {
+                    Ok(Some(k)) => {
+                        let conf = match grin_height {
+                            Some(h) if h >= k.height => h - k.height + 1,
+                            _ => 0
+                        };
+                        println!("Grin kernel {}: {} confirmations", excess, conf);
+                    },
+                    Ok(None) => println!("Grin kernel {}: not on chain", excess),
+                    Err(e) => println!("Grin kernel {}: unknown ({})", excess, e)
+                }
+            }
+        }
+
+        // Published refunds are final once they are confirmed
+        let mut changed = false;
+        if slate.pub_slate.btc.status == ChainStatus::REFUND_BROADCAST && btc_refund_confirmed {
+            slate.set_chain_status(Currency::BTC, ChainStatus::REFUNDED);
+            changed = true;
+        }
+        if slate.pub_slate.mw.status == ChainStatus::REFUND_BROADCAST && grin_refund_confirmed {
+            slate.set_chain_status(Currency::GRIN, ChainStatus::REFUNDED);
+            changed = true;
+        }
+        if changed {
+            println!("Refund confirmed, status is now {:?}", slate.pub_slate.status);
+        }
+
+        // Bob learns that the peer redeemed the Bitcoin once the lock output is spent in a block
+        let btc_locked = slate.pub_slate.btc.status == ChainStatus::LOCKED && slate.prv_slate.btc.refunded.is_none();
+        if owns_btc == Some(true) && btc_locked && btc_lock_confirmed {
+            if let Some(lock) = slate.prv_slate.btc.lock.clone() {
+                match btc_core.is_output_spent(&lock.txid, lock.vout) {
+                    Ok(true) => {
+                        slate.log_event(Some(Currency::BTC), None, btc_height, "Bitcoin lock output was redeemed");
+                        slate.set_chain_status(Currency::BTC, ChainStatus::REDEEMED);
+                        println!("Bitcoin redeem confirmed, status is now {:?}", slate.pub_slate.status);
+                        changed = true;
+                    },
+                    Ok(false) => (),
+                    Err(e) => println!("Unable to check the Bitcoin lock output: {}", e)
+                }
+            }
+        }
+
+        // A refund is possible once both chains have passed their lock times
+        let refund_minutes = match (btc_left, grin_left) {
+            (Some(b), Some(g)) => {
+                let minutes = std::cmp::max(b, g);
+                if minutes == 0 {
+                    println!("Refund is possible now");
+                }
+                else {
+                    println!("Refund possible in ~{}", format_minutes(minutes));
+                }
+                Some(minutes)
+            },
+            _ => None
+        };
+
+        println!("Next action: {}", next_action(&slate, owns_btc, refund_minutes));
+
+        if !slate.events.is_empty() {
+            println!("History:");
+            for e in &slate.events {
+                let chain = e.chain.map(|c| format!(" [{:?}]", c)).unwrap_or_default();
+                let txid = e.txid.clone().map(|t| format!(" tx {}", t)).unwrap_or_default();
+                let height = e.height.map(|h| format!(" height {}", h)).unwrap_or_default();
+                println!("  {} {:?}{} {}{}{}", e.timestamp, e.status, chain, e.message, txid, height);
+            }
+        }
+        if changed {
+            Ok(Some(slate))
+        }
+        else {
+            Ok(None)
+        }
+    }
+}
+
+/// Print the height of a chain compared to the lock time of the swap
+/// and return the estimated minutes left until the lock time has passed
+fn print_chain_progress(chain : &str, height : Option<u64>, lock_time : Option<i64>, block_time : u64) -> Option<u64> {
+    let lock_time = lock_time.and_then(|l| u64::try_from(l).ok());
+    match (height, lock_time) {
+        (Some(h), Some(l)) => {
+            let left = if h > l { 0 } else { l - h + 1 };
+            println!("{}: height {}, refund lock height {} ({} blocks, ~{} left)", chain, h, l, left, format_minutes(left * block_time));
+            Some(left * block_time)
+        },
+        (Some(h), None) => {
+            println!("{}: height {}, refund lock height not set yet", chain, h);
+            None
+        },
+        (None, Some(l)) => {
+            println!("{}: height unknown, refund lock height {}", chain, l);
+            None
+        },
+        (None, None) => None
+    }
+}
+
+/// Print the median time past of the Bitcoin chain compared to a timestamp lock
+/// and return the minutes left until the lock time has passed
+fn print_btc_time_progress(mtp : Option<u64>, lock_time : Option<i64>) -> Option<u64> {
+    let lock_time = lock_time.and_then(|l| u64::try_from(l).ok());
+    match (mtp, lock_time) {
+        (Some(m), Some(l)) => {
+            let left = btc_minutes_left(BtcLockType::TIME, l, 0, m);
+            println!("Bitcoin: median time past {}, refund lock time {} (~{} left)", m, l, format_minutes(left));
+            Some(left)
+        },
+        (Some(m), None) => {
+            println!("Bitcoin: median time past {}, refund lock time not set yet", m);
+            None
+        },
+        (None, Some(l)) => {
+            println!("Bitcoin: median time past unknown, refund lock time {}", l);
+            None
+        },
+        (None, None) => None
+    }
+}
+
+fn format_minutes(minutes : u64) -> String {
+    format!("{}h {}m", minutes / 60, minutes % 60)
+}
+
+fn next_action(slate : &SwapSlate, owns_btc : Option<bool>, refund_minutes : Option<u64>) -> String {
+    let maker = slate.prv_slate.role == Some(SwapRole::MAKER);
+    let can_refund = refund_minutes == Some(0);
+    match slate.pub_slate.status {
+        SwapStatus::INITIALIZED => {
+            let (value, amount, cur) = match owns_btc {
+                Some(true) => (slate.prv_slate.btc.inputs.iter().map(|i| i.value).sum::<u64>(), slate.pub_slate.btc.amount, "btc"),
+                _ => (slate.prv_slate.mw.inputs.iter().map(|i| i.value).sum::<u64>(), slate.pub_slate.mw.amount, "grin")
+            };
+            // a partially fillable offer needs inputs for the smallest fill
+            let amount = match (&slate.pub_slate.fill, &slate.pub_slate.fill_of) {
+                (Some(fill), None) => fill.min,
+                _ => amount
+            };
+            if value < amount {
+                format!("Import your {} inputs with the import {} command", cur, cur)
+            }
+            else if maker {
+                String::from("Run listen and wait for the peer to lock")
+            }
+            else {
+                String::from("Run lock to start the locking phase")
+            }
+        },
+        SwapStatus::SETUP | SwapStatus::ABORTED => {
+            String::from("Nothing of ours is locked, the swap can be abandoned")
+        },
+        SwapStatus::BTC_LOCKED | SwapStatus::GRIN_LOCKED | SwapStatus::EXECUTING => {
+            if can_refund {
+                String::from("The refund time has passed, run cancel to get your coins back")
+            }
+            else {
+                String::from("The swap was interrupted, wait until the refund time has passed and run cancel")
+            }
+        },
+        SwapStatus::LOCKED => {
+            if can_refund {
+                String::from("The refund time has passed, run cancel to get your coins back")
+            }
+            else if maker {
+                String::from("Run listen and wait for the peer to execute or cancel")
+            }
+            else {
+                String::from("Run execute to complete the swap")
+            }
+        },
+        SwapStatus::GRIN_REDEEMED => {
+            if owns_btc == Some(true) && can_refund {
+                String::from("The peer did not redeem the Bitcoin before the refund time, run cancel to get them back")
+            }
+            else if owns_btc == Some(true) {
+                String::from("Wait for the peer to redeem the Bitcoin and run status again")
+            }
+            else {
+                String::from("The Bitcoin redeem transaction was not published, run execute again before the Bitcoin refund time")
+            }
+        },
+        SwapStatus::BTC_REDEEMED => String::from("Wait for the Grin contract transaction to confirm"),
+        SwapStatus::FINISHED => String::from("Nothing to do, the swap is complete"),
+        SwapStatus::REFUND_BROADCAST => String::from("Wait for the refund transaction to confirm and run status again"),
+        SwapStatus::REFUNDED => String::from("Nothing to do, your coins were refunded"),
+        SwapStatus::FAILED => {
+            if can_refund {
+                String::from("If your coins are locked and no refund was published yet run cancel, otherwise wait for the refund to confirm")
+            }
+            else {
+                String::from("Wait until the refund time has passed, then run cancel")
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::bitcoin::bitcoin_backend::BitcoinBackend;
+use crate::bitcoin::bitcoin_core::BitcoinCore;
+use crate::grin::grin_backend::GrinBackend;
+use crate::grin::grin_core::GrinCore;
+use crate::net::http::RequestFactory;
+use crate::Settings;
+
+/// The chain backends the commands query and publish transactions with
+pub struct ChainContext {
+    pub btc : Box<dyn BitcoinBackend + Send>,
+    pub grin : Box<dyn GrinBackend + Send>
+}
+
+impl ChainContext {
+    /// Create a context from any backends
+    ///
+    /// # Arguments
+    ///
+    /// * `btc` the Bitcoin backend
+    /// * `grin` the Grin backend
+    pub fn new(btc : Box<dyn BitcoinBackend + Send>, grin : Box<dyn GrinBackend + Send>) -> ChainContext {
+        ChainContext {
+            btc : btc,
+            grin : grin
+        }
+    }
+
+    /// Create a context talking to the nodes configured in the settings over RPC
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` the application settings
+    pub fn from_settings(settings : &Settings) -> ChainContext {
+        ChainContext::new(
+            Box::new(BitcoinCore::new(settings.btc.clone(), RequestFactory::new(None))),
+            Box::new(GrinCore::new(settings.grin.clone(), RequestFactory::new(None)))
+        )
+    }
+}
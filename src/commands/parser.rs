@@ -9,8 +9,12 @@ use crate::commands::cmd_types::command::Command;
 use crate::commands::cmd_types::init::Init;
 use crate::enums::Currency;
 use crate::enums::parse_currency_from_string;
+use crate::enums::parse_status_from_string;
+use crate::enums::{parse_btc_lock_type_from_string, parse_grin_lock_type_from_string, BtcLockType, GrinLockType};
+use crate::error::SwapError;
 use crate::constants;
 
+use std::str::FromStr;
 use std::u32;
 
 use bitcoin::{PrivateKey, PublicKey};
@@ -18,39 +22,76 @@ use clap::{
     ArgMatches
 };
 
-use super::cmd_types::{cancel::Cancel, lock::Lock};
+use crate::book::types::OfferQuery;
+use crate::swap::pricing::CounterAmount;
+use crate::swap::fill::fill_range_from_args;
+use crate::units::parse_amount;
+use super::cmd_types::{api::Api, book_list::BookList, book_publish::BookPublish, book_serve::BookServe, book_withdraw::BookWithdraw, cancel::Cancel, list::List, lock::Lock, refund::Refund, status::Status};
 
-pub fn parse_arguments(matches: ArgMatches) -> Result<Box<dyn Command>, &'static str> {
+/// Parse the command line into the command to execute
+///
+/// # Arguments
+///
+/// * `matches` the matched command line arguments
+pub fn parse_arguments(matches: ArgMatches) -> Result<Box<dyn Command>, SwapError> {
+    parse_command(matches)
+        .map_err(|e| SwapError::Argument(String::from(e)))
+}
+
+fn parse_command(matches: ArgMatches) -> Result<Box<dyn Command>, &'static str> {
     match matches.subcommand() {
         ("init", Some(args)) => {
         let from_currency_arg = String::from(args.value_of("from-currency").unwrap());
         let to_currency_arg = String::from(args.value_of("to-currency").unwrap());
         let from_amount_arg = String::from(args.value_of("from-amount").unwrap());
-        let to_amount_arg = String::from(args.value_of("to-amount").unwrap());
         let timeout_arg = String::from(args.value_of("timeout").unwrap());
         
         // Parse arguments
-        let from_currency = parse_currency_from_string(from_currency_arg);
-        let to_currency = parse_currency_from_string(to_currency_arg);
-        let from_amount : u64 = from_amount_arg.parse::<u64>().unwrap();
-        let to_amount : u64 = to_amount_arg.parse::<u64>().unwrap();
-        let timeout_min : u64 = timeout_arg.parse::<u64>().unwrap();
-
-        // Validate arguments
-        let from_overflow = ( from_currency == Currency::BTC && from_amount > constants::BTC_MAX_SATS ) || ( from_currency == Currency::GRIN && from_amount > constants::GRIN_MAX_NANOGRIN );
-        let to_overflow = ( to_currency == Currency::BTC && to_amount > constants::BTC_MAX_SATS ) || ( to_currency == Currency::GRIN && to_amount > constants::GRIN_MAX_NANOGRIN );
-
-        if from_overflow {
-            panic!("From amount is too high!");
-        }
-        if  to_overflow  {
-            panic!("To amount is too high!");
-        }
-        if timeout_min > constants::MAX_TIMEOUT {
-            panic!("Timeout too high! Max timeout is 5 days");
-        }
-
-        Ok(Box::new(Init::new(from_currency, to_currency, from_amount, to_amount, timeout_min)))
+        let from_currency = parse_currency_from_string(from_currency_arg).ok_or("Invalid from-currency supplied, use btc or grin")?;
+        let to_currency = parse_currency_from_string(to_currency_arg).ok_or("Invalid to-currency supplied, use btc or grin")?;
+        let from_amount : u64 = parse_amount_arg(&from_amount_arg, from_currency, "Invalid from-amount supplied")?;
+        let to_amount : Option<u64> = match args.value_of("to-amount") {
+            Some(a) => Some(parse_amount_arg(a, to_currency, "Invalid to-amount supplied")?),
+            None => None
+        };
+        let spread : Option<i32> = parse_optional(args.value_of("spread"), "Invalid spread supplied, use basis points")?;
+        let counter = CounterAmount::from_args(to_amount, args.value_of("price"), spread)
+            .map_err(|e| {
+                println!("{}", e);
+                "Invalid to-amount, price or spread supplied"
+            })?;
+        let timeout_min : u64 = timeout_arg.parse::<u64>().map_err(|_| "Invalid timeout supplied")?;
+        let expiry_min : u64 = match args.value_of("expiry") {
+            Some(e) => e.parse::<u64>().map_err(|_| "Invalid expiry supplied")?,
+            None => constants::DEFAULT_OFFER_EXPIRY
+        };
+
+        // Validate arguments, a requested amount computed from a price is validated by init
+        validate_init_args(from_currency, from_amount, to_currency, to_amount.unwrap_or(0), timeout_min, expiry_min)?;
+        let btc_lock_type = match args.value_of("btc-lock") {
+            Some(l) => parse_btc_lock_type_from_string(String::from(l)).ok_or("Invalid btc-lock supplied, use height or time")?,
+            None => BtcLockType::HEIGHT
+        };
+        let grin_lock_type = match args.value_of("grin-lock") {
+            Some(l) => parse_grin_lock_type_from_string(String::from(l)).ok_or("Invalid grin-lock supplied, use height or nrd")?,
+            None => GrinLockType::HEIGHT
+        };
+
+        let min_fill : Option<u64> = match args.value_of("min-fill") {
+            Some(a) => Some(parse_amount_arg(a, from_currency, "Invalid min-fill supplied")?),
+            None => None
+        };
+        let max_fill : Option<u64> = match args.value_of("max-fill") {
+            Some(a) => Some(parse_amount_arg(a, from_currency, "Invalid max-fill supplied")?),
+            None => None
+        };
+        let fill = fill_range_from_args(min_fill, max_fill, from_amount)
+            .map_err(|e| {
+                println!("{}", e);
+                "Invalid fill range supplied"
+            })?;
+
+        Ok(Box::new(Init::new(from_currency, to_currency, from_amount, counter, timeout_min, expiry_min, btc_lock_type, grin_lock_type, fill)))
         },
         ("import", Some(args)) => {
             match args.subcommand() {
@@ -63,9 +104,9 @@ pub fn parse_arguments(matches: ArgMatches) -> Result<Box<dyn Command>, &'static
                     let pub_script = String::from(subargs.value_of("pub_script").unwrap());
                     
                     // Parse arguments
-                    let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
-                    let vout : u32 = vout_arg.parse::<u32>().unwrap();
-                    let value : u64 = value_arg.parse::<u64>().unwrap();
+                    let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+                    let vout : u32 = vout_arg.parse::<u32>().map_err(|_| "Invalid vout supplied")?;
+                    let value : u64 = parse_amount_arg(&value_arg, Currency::BTC, "Invalid value supplied")?;
 
                     Ok(Box::new(ImportBtc::new(swapid, txid, vout, value, sk_wif, pub_script)))
                 },
@@ -76,8 +117,8 @@ pub fn parse_arguments(matches: ArgMatches) -> Result<Box<dyn Command>, &'static
                     let value_arg = String::from(subargs.value_of("value").unwrap());
 
                     // Parse arguments
-                    let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
-                    let value : u64 = value_arg.parse::<u64>().unwrap();
+                    let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+                    let value : u64 = parse_amount_arg(&value_arg, Currency::GRIN, "Invalid value supplied")?;
 
                     Ok(Box::new(ImportGrin::new(swapid, commitment, blinding_factor, value)))
                 },
@@ -87,38 +128,166 @@ pub fn parse_arguments(matches: ArgMatches) -> Result<Box<dyn Command>, &'static
         ("listen", Some(args)) => {
             let swapid_arg = String::from(args.value_of("swapid").unwrap());
 
-            let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
 
             Ok(Box::new(Listen::new(swapid)))
         },
         ("accept", Some(args)) => {
             let swapid_arg = String::from(args.value_of("swapid").unwrap());
 
-            let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+
+            let fill = args.value_of("fill").map(String::from);
 
-            Ok(Box::new(Accept::new(swapid)))
+            Ok(Box::new(Accept::new(swapid, args.is_present("book"), fill)))
         },
         ("lock", Some(args)) => {
             let swapid_arg = String::from(args.value_of("swapid").unwrap());
 
-            let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
 
             Ok(Box::new(Lock::new(swapid)))
         },
         ("cancel", Some(args)) => {
             let swapid_arg = String::from(args.value_of("swapid").unwrap());
 
-            let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
 
             Ok(Box::new(Cancel::new(swapid)))
         },
+        ("refund", Some(args)) => {
+            let swapid_arg = String::from(args.value_of("swapid").unwrap());
+
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+
+            Ok(Box::new(Refund::new(swapid)))
+        },
+        ("api", Some(_)) => {
+            Ok(Box::new(Api::new()))
+        },
+        ("book", Some(args)) => {
+            match args.subcommand() {
+                ("serve", Some(_)) => Ok(Box::new(BookServe::new())),
+                ("publish", Some(subargs)) => {
+                    let swapid : u64 = subargs.value_of("swapid").unwrap().parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+
+                    Ok(Box::new(BookPublish::new(swapid)))
+                },
+                ("withdraw", Some(subargs)) => {
+                    let swapid : u64 = subargs.value_of("swapid").unwrap().parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+
+                    Ok(Box::new(BookWithdraw::new(swapid)))
+                },
+                ("list", Some(subargs)) => {
+                    let query = OfferQuery {
+                        offered : parse_optional_currency(subargs.value_of("offered"), "Invalid offered currency supplied")?,
+                        requested : parse_optional_currency(subargs.value_of("requested"), "Invalid requested currency supplied")?,
+                        min_amount : parse_optional(subargs.value_of("min-amount"), "Invalid min-amount supplied")?,
+                        max_amount : parse_optional(subargs.value_of("max-amount"), "Invalid max-amount supplied")?,
+                        min_rate : parse_optional(subargs.value_of("min-rate"), "Invalid min-rate supplied")?,
+                        max_rate : parse_optional(subargs.value_of("max-rate"), "Invalid max-rate supplied")?
+                    };
+
+                    Ok(Box::new(BookList::new(query)))
+                },
+                _ => Err("Invalid subcommand for book supplied")
+            }
+        },
         ("execute", Some(args)) => {
             let swapid_arg = String::from(args.value_of("swapid").unwrap());
 
-            let swapid : u64 = swapid_arg.parse::<u64>().unwrap();
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
 
             Ok(Box::new(Execute::new(swapid)))
         },
+        ("list", Some(args)) => {
+            let status = match args.value_of("status") {
+                Some(s) => Some(parse_status_from_string(String::from(s)).ok_or("Invalid status supplied")?),
+                None => None
+            };
+            let currency = parse_optional_currency(args.value_of("currency"), "Invalid currency supplied")?;
+
+            Ok(Box::new(List::new(status, currency)))
+        },
+        ("status", Some(args)) => {
+            let swapid_arg = String::from(args.value_of("swapid").unwrap());
+
+            let swapid : u64 = swapid_arg.parse::<u64>().map_err(|_| "Invalid swapid supplied")?;
+
+            Ok(Box::new(Status::new(swapid)))
+        },
         _ => Err("Invalid command supplied")
     }
-}
\ No newline at end of file
+}
+
+/// Parse an optional numeric argument
+///
+/// # Arguments
+///
+/// * `value` the argument value if it was supplied
+/// * `err` the error returned if the value can't be parsed
+fn parse_optional<T : FromStr>(value : Option<&str>, err : &'static str) -> Result<Option<T>, &'static str> {
+    match value {
+        Some(v) => v.parse::<T>().map(Some).map_err(|_| err),
+        None => Ok(None)
+    }
+}
+
+/// Parse an optional currency argument
+///
+/// # Arguments
+///
+/// * `value` the argument value if it was supplied
+/// * `err` the error returned if the currency is unknown
+fn parse_optional_currency(value : Option<&str>, err : &'static str) -> Result<Option<Currency>, &'static str> {
+    match value {
+        Some(c) => parse_currency_from_string(String::from(c)).map(Some).ok_or(err),
+        None => Ok(None)
+    }
+}
+
+/// Parse an amount argument with an optional unit, see units::parse_amount
+///
+/// # Arguments
+///
+/// * `value` the argument value
+/// * `currency` the currency of the amount
+/// * `err` the error returned if the amount is invalid, the reason is printed
+fn parse_amount_arg(value : &str, currency : Currency, err : &'static str) -> Result<u64, &'static str> {
+    parse_amount(value, currency)
+        .map_err(|e| {
+            println!("{}", e);
+            err
+        })
+}
+
+/// Validate the amounts, timeout and expiry of a new offer
+///
+/// # Arguments
+///
+/// * `from` the offered currency
+/// * `from_amount` the offered amount in satoshis or nanogrin
+/// * `to` the requested currency
+/// * `to_amount` the requested amount in satoshis or nanogrin
+/// * `timeout_min` the Grin refund window in minutes
+/// * `expiry_min` how long the offer can be taken in minutes
+pub fn validate_init_args(from : Currency, from_amount : u64, to : Currency, to_amount : u64, timeout_min : u64, expiry_min : u64) -> Result<(), &'static str> {
+    let from_overflow = ( from == Currency::BTC && from_amount > constants::BTC_MAX_SATS ) || ( from == Currency::GRIN && from_amount > constants::GRIN_MAX_NANOGRIN );
+    let to_overflow = ( to == Currency::BTC && to_amount > constants::BTC_MAX_SATS ) || ( to == Currency::GRIN && to_amount > constants::GRIN_MAX_NANOGRIN );
+
+    if from_overflow {
+        Err("From amount is too high!")
+    }
+    else if to_overflow {
+        Err("To amount is too high!")
+    }
+    else if timeout_min > constants::MAX_TIMEOUT {
+        Err("Timeout too high! Max timeout is 5 days")
+    }
+    else if expiry_min == 0 || expiry_min > constants::MAX_TIMEOUT {
+        Err("Expiry must be between 1 minute and 5 days")
+    }
+    else {
+        Ok(())
+    }
+}
@@ -21,4 +21,10 @@ pub const SIGHASH_ALL : u8 = 0x01;
 // Standard fee we use on the Bitcoin transactions
 pub const BTC_FEE : u64 = 500;
 // Max attempts of verifing locked funds;
-pub const MAX_ATTEMPTS_VERF_FUNDS : u64 = 120;
\ No newline at end of file
+pub const MAX_ATTEMPTS_VERF_FUNDS : u64 = 120;
+// Bitcoin lock times below this value are block heights, above they are timestamps
+pub const LOCKTIME_THRESHOLD : u32 = 500_000_000;
+// Version of the slate layout written by this build
+pub const CURRENT_SLATE_VERSION : u16 = 10;
+// Offers expire after one day by default (in minutes)
+pub const DEFAULT_OFFER_EXPIRY : u64 = 60 * 24;
\ No newline at end of file
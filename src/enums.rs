@@ -6,35 +6,144 @@ pub enum HttpMethod {
     POST
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Currency {
     BTC,
     GRIN
 }
 
-pub fn parse_currency_from_string(cur : String) -> Currency {
+pub fn parse_currency_from_string(cur : String) -> Option<Currency> {
     if cur.to_uppercase() == "BTC" || cur.to_uppercase() == "BITCOIN" {
-        Currency::BTC
+        Some(Currency::BTC)
     }
     else if cur.to_uppercase() == "GRIN" {
-        Currency::GRIN
+        Some(Currency::GRIN)
     }
     else {
-        panic!("Invalid Currency provided");
+        None
     }
 }
 
-#[derive(PartialEq, Serialize, Deserialize)]
+/// Overall status of a swap, derived from the status of both chains once funds are locked
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum SwapStatus {
+    // Offer created, nothing exchanged yet
     INITIALIZED,
+    // Locking phase started, no funds locked yet
     SETUP,
+    // Only the Bitcoin side is locked
+    BTC_LOCKED,
+    // Only the Grin side is locked
+    GRIN_LOCKED,
+    // Both sides locked, the swap can be executed or cancelled
+    LOCKED,
+    // Execution phase started
     EXECUTING,
+    // The Grin contract transaction was completed, Bitcoin not redeemed yet
+    GRIN_REDEEMED,
+    // The Bitcoin side was redeemed, Grin not yet
+    BTC_REDEEMED,
+    // Both sides redeemed
     FINISHED,
+    // A refund transaction was published but is not confirmed yet
+    REFUND_BROADCAST,
+    // The refund transaction is confirmed
+    REFUNDED,
+    // The swap was stopped before any of our funds were locked
+    ABORTED,
+    // Legacy status of slates written before the per chain status existed
     FAILED
 }
 
-#[derive(PartialEq, Serialize, Deserialize)]
+pub fn parse_status_from_string(status : String) -> Option<SwapStatus> {
+    match status.to_uppercase().as_str() {
+        "INITIALIZED" => Some(SwapStatus::INITIALIZED),
+        "SETUP" => Some(SwapStatus::SETUP),
+        "BTC_LOCKED" => Some(SwapStatus::BTC_LOCKED),
+        "GRIN_LOCKED" => Some(SwapStatus::GRIN_LOCKED),
+        "LOCKED" => Some(SwapStatus::LOCKED),
+        "EXECUTING" => Some(SwapStatus::EXECUTING),
+        "GRIN_REDEEMED" => Some(SwapStatus::GRIN_REDEEMED),
+        "BTC_REDEEMED" => Some(SwapStatus::BTC_REDEEMED),
+        "FINISHED" => Some(SwapStatus::FINISHED),
+        "REFUND_BROADCAST" => Some(SwapStatus::REFUND_BROADCAST),
+        "REFUNDED" => Some(SwapStatus::REFUNDED),
+        "ABORTED" => Some(SwapStatus::ABORTED),
+        "FAILED" => Some(SwapStatus::FAILED),
+        _ => None
+    }
+}
+
+/// Status of the funds of one chain of the swap
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ChainStatus {
+    NONE,
+    LOCKED,
+    REDEEMED,
+    REFUND_BROADCAST,
+    REFUNDED
+}
+
+impl Default for ChainStatus {
+    fn default() -> ChainStatus {
+        ChainStatus::NONE
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum SwapType {
     OFFERED,
     REQUESTED
-}
\ No newline at end of file
+}
+
+/// Our role in a swap, the maker created the offer with init the taker accepted it
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum SwapRole {
+    MAKER,
+    TAKER
+}
+
+/// How the Bitcoin refund lock is expressed, as an absolute block height or as a
+/// unix timestamp which CLTV compares against the median time past of the chain
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum BtcLockType {
+    HEIGHT,
+    TIME
+}
+
+impl Default for BtcLockType {
+    fn default() -> BtcLockType {
+        BtcLockType::HEIGHT
+    }
+}
+
+pub fn parse_btc_lock_type_from_string(lock_type : String) -> Option<BtcLockType> {
+    match lock_type.to_uppercase().as_str() {
+        "HEIGHT" => Some(BtcLockType::HEIGHT),
+        "TIME" => Some(BtcLockType::TIME),
+        _ => None
+    }
+}
+
+/// How the Grin refund lock is expressed, as an absolute block height or as a NRD
+/// kernel which can only be mined a relative number of blocks after the anchor kernel
+/// in the funding transaction
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum GrinLockType {
+    HEIGHT,
+    NRD
+}
+
+impl Default for GrinLockType {
+    fn default() -> GrinLockType {
+        GrinLockType::HEIGHT
+    }
+}
+
+pub fn parse_grin_lock_type_from_string(lock_type : String) -> Option<GrinLockType> {
+    match lock_type.to_uppercase().as_str() {
+        "HEIGHT" => Some(GrinLockType::HEIGHT),
+        "NRD" => Some(GrinLockType::NRD),
+        _ => None
+    }
+}
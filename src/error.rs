@@ -0,0 +1,99 @@
+use std::fmt;
+
+/// Error of a swap command. The kind of the error decides the exit code of the
+/// process, scripts driving the swap can rely on the codes not changing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SwapError {
+    // The settings file is missing or invalid
+    Config(String),
+    // A command line argument is missing or invalid
+    Argument(String),
+    // Reading or writing the slate storage, a file or a socket failed
+    Io(String),
+    // A Bitcoin or Grin node couldn't be reached or answered with an error
+    NodeRpc(String),
+    // The peer couldn't be reached, hung up or sent an unexpected message
+    PeerProtocol(String),
+    // The swap, the offer or a transaction is not in the expected state
+    Validation(String),
+    // The imported inputs don't cover the amount and fees
+    InsufficientFunds(String),
+    // A lock time is unsafe, has passed already or has not passed yet
+    Timelock(String)
+}
+
+impl SwapError {
+    /// Exit code of the process when a command fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SwapError::Config(_) => 2,
+            SwapError::Argument(_) => 3,
+            SwapError::Io(_) => 4,
+            SwapError::NodeRpc(_) => 5,
+            SwapError::PeerProtocol(_) => 6,
+            SwapError::Validation(_) => 7,
+            SwapError::InsufficientFunds(_) => 8,
+            SwapError::Timelock(_) => 9
+        }
+    }
+
+    /// Short name of the kind of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SwapError::Config(_) => "configuration",
+            SwapError::Argument(_) => "argument",
+            SwapError::Io(_) => "storage",
+            SwapError::NodeRpc(_) => "node",
+            SwapError::PeerProtocol(_) => "peer",
+            SwapError::Validation(_) => "validation",
+            SwapError::InsufficientFunds(_) => "insufficient funds",
+            SwapError::Timelock(_) => "timelock"
+        }
+    }
+
+    /// Message of the error without its kind
+    pub fn message(&self) -> &str {
+        match self {
+            SwapError::Config(m) | SwapError::Argument(m) | SwapError::Io(m) | SwapError::NodeRpc(m)
+                | SwapError::PeerProtocol(m) | SwapError::Validation(m) | SwapError::InsufficientFunds(m)
+                | SwapError::Timelock(m) => m
+        }
+    }
+}
+
+impl fmt::Display for SwapError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+// Callers which only report the error, like the API and the test clients, keep using strings
+impl From<SwapError> for String {
+    fn from(e : SwapError) -> String {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SwapError;
+
+    #[test]
+    fn test_exit_codes() {
+        let errors = vec![
+            SwapError::Config(String::from("a")),
+            SwapError::Argument(String::from("b")),
+            SwapError::Io(String::from("c")),
+            SwapError::NodeRpc(String::from("d")),
+            SwapError::PeerProtocol(String::from("e")),
+            SwapError::Validation(String::from("f")),
+            SwapError::InsufficientFunds(String::from("g")),
+            SwapError::Timelock(String::from("h"))
+        ];
+        let codes : Vec<i32> = errors.iter().map(|e| e.exit_code()).collect();
+        // the codes are part of the command line interface, never renumber them
+        assert_eq!(vec![2, 3, 4, 5, 6, 7, 8, 9], codes);
+        assert_eq!("d", errors[3].to_string());
+        assert_eq!(String::from("h"), String::from(errors[7].clone()));
+    }
+}
@@ -1,5 +1,8 @@
 pub mod grin_types;
 pub mod grin_core;
+pub mod grin_backend;
 pub mod grin_routines;
 pub mod grin_tx;
-pub mod grin_core_responses;
\ No newline at end of file
+pub mod grin_core_responses;
+#[cfg(test)]
+pub mod test_vectors;
\ No newline at end of file
@@ -0,0 +1,58 @@
+use crate::grin::grin_core::GrinCore;
+use crate::grin::grin_core_responses::{LocatedTxKernel, OutputPrintable};
+use grin_core::core::Transaction;
+
+/// Access to the Grin chain needed by the swap protocol
+/// Implemented by the Grin node RPC client, other backends or mocks
+/// can be used by the protocol and the commands in its place
+pub trait GrinBackend {
+    /// Current height of the chain
+    fn get_block_height(&self) -> Result<u64, String>;
+
+    /// Look up an output by its commitment, None if it isn't known
+    ///
+    /// # Arguments
+    ///
+    /// * `commit` hex encoded commitment of the output
+    fn get_output(&self, commit : &str) -> Result<Option<OutputPrintable>, String>;
+
+    /// Look up a mined kernel by its excess, None if it isn't on chain
+    ///
+    /// # Arguments
+    ///
+    /// * `excess` hex encoded excess of the kernel
+    fn get_kernel(&self, excess : &str) -> Result<Option<LocatedTxKernel>, String>;
+
+    /// Fee base in nanogrin per weight unit the node accepts transactions at,
+    /// None if the node can't tell
+    fn get_fee_base(&self) -> Result<Option<u64>, String>;
+
+    /// Broadcast a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` the transaction to broadcast
+    fn push_transaction(&self, tx : Transaction) -> Result<(), String>;
+}
+
+impl GrinBackend for GrinCore {
+    fn get_block_height(&self) -> Result<u64, String> {
+        GrinCore::get_block_height(self)
+    }
+
+    fn get_output(&self, commit : &str) -> Result<Option<OutputPrintable>, String> {
+        GrinCore::get_output(self, commit)
+    }
+
+    fn get_kernel(&self, excess : &str) -> Result<Option<LocatedTxKernel>, String> {
+        GrinCore::get_kernel(self, excess)
+    }
+
+    fn get_fee_base(&self) -> Result<Option<u64>, String> {
+        GrinCore::get_fee_base(self)
+    }
+
+    fn push_transaction(&self, tx : Transaction) -> Result<(), String> {
+        GrinCore::push_transaction(self, tx)
+    }
+}
@@ -1,33 +1,39 @@
 use crate::{grin::grin_routines::*, net::http::{JsonRpc, JsonRpcParam, RequestFactory}, settings::GrinNodeSettings};
-use crate::grin::grin_types::MWCoin;
+use crate::grin::grin_types::{AnchorSigMsg, KernelLock, MWCoin};
 use crate::util::get_os_rng;
 use grin_core::core::transaction::OutputFeatures;
-use grin_core::core::{transaction::FeeFields, KernelFeatures};
-use grin_core::core::{Input, Inputs, Output, Transaction};
-use grin_core::libtx::tx_fee;
-use std::convert::TryFrom;
+use grin_core::core::{transaction::FeeFields, KernelFeatures, NRDRelativeHeight};
+use grin_core::core::{Input, Inputs, Output, Transaction, TxKernel, Weighting};
+use grin_core::core::verifier_cache::LruVerifierCache;
+use grin_core::global::DEFAULT_ACCEPT_FEE_BASE;
 use grin_keychain::{BlindSum, BlindingFactor, ExtKeychain, Identifier, Keychain};
 use grin_util::secp::pedersen::Commitment;
 use grin_util::secp::{
     aggsig,
     key::{PublicKey, SecretKey},
-    Signature,
+    Message, Signature,
 };
 use grin_util::secp::{ContextFlag, Secp256k1};
+use grin_util::RwLock;
 use grin_wallet_libwallet::{
     slate_versions::v4::{KernelFeaturesArgsV4, SlateV4},
     Context, Slate,
 };
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
+use rand::Rng;
+use std::sync::Arc;
+use uuid::{Builder, Variant, Version};
 
-use super::grin_core_responses::{GetTipResult, JsonRPCResponse, PushTransactionResult};
+use super::grin_core_responses::{GetKernelResult, GetOutputsResult, GetTipResult, GetUnconfirmedTransactionsResult, JsonRPCResponse, LocatedTxKernel, OutputPrintable, PushTransactionResult};
 
 pub struct GrinCore {
-    pub rng: OsRng,
+    pub rng: SwapRng,
     pub secp: Secp256k1,
     pub chain: ExtKeychain,
     pub settings : GrinNodeSettings,
-    pub req_factory : RequestFactory
+    pub req_factory : RequestFactory,
+    // fee in nanogrin per weight unit used to build and validate transactions
+    pub fee_base : u64
 }
 
 pub struct SpendCoinsResult {
@@ -58,15 +64,44 @@ pub struct DRecvCoinsResult {
 
 impl GrinCore {
     pub fn new(settings : GrinNodeSettings, req_factory : RequestFactory) -> GrinCore {
-        let rng = get_os_rng();
+        GrinCore::with_rng(settings, req_factory, get_os_rng())
+    }
+
+    /// Create the Grin functions without a node, they only build and sign transactions.
+    /// Node requests fail, as there is no node address to send them to.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` randomness generator, a seeded one makes the transactions reproducible
+    pub fn offline(rng : SwapRng) -> GrinCore {
+        let settings = GrinNodeSettings {
+            url : String::new(),
+            user : String::new(),
+            pass : String::new(),
+            port : 0,
+            id : String::new()
+        };
+        GrinCore::with_rng(settings, RequestFactory::new(None), rng)
+    }
+
+    /// Create the Grin functions drawing all keys, nonces and the keychain seed from a given generator
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` Grin node settings
+    /// * `req_factory` factory for the node requests
+    /// * `rng` randomness generator, a seeded one makes the transactions reproducible
+    pub fn with_rng(settings : GrinNodeSettings, req_factory : RequestFactory, mut rng : SwapRng) -> GrinCore {
         let secp = Secp256k1::with_caps(ContextFlag::Commit);
-        let keychain = ExtKeychain::from_random_seed(true).unwrap();
+        let seed : [u8; 32] = rng.gen();
+        let keychain = ExtKeychain::from_seed(&seed, true).unwrap();
         GrinCore {
             rng: rng,
             secp: secp,
             chain: keychain,
             settings : settings,
-            req_factory : req_factory
+            req_factory : req_factory,
+            fee_base : DEFAULT_ACCEPT_FEE_BASE
         }
     }
 
@@ -79,34 +114,63 @@ impl GrinCore {
     /// * `inputs` the inputs which should be spent
     /// * `fund_value` value which should be transferred to a receiver (IN NANO GRIN)
     /// * `fee` transaction fee
-    /// * `timelock` optional transaction timelock
+    /// * `lock` kernel lock of the transaction
     /// * `num_participants` number of participants in the usual case this would be 2, if we intend to create or spend a multiouput then it should be 3 or 4
     pub fn spend_coins(
         &mut self,
         inputs: Vec<MWCoin>,
         fund_value: u64,
-        timelock: u64,
+        lock: KernelLock,
         num_of_outputs: usize,
         num_participants: u8,
     ) -> Result<SpendCoinsResult, String> {
+        self.spend_coins_with_kernels(inputs, fund_value, lock, num_of_outputs, num_participants, 1)
+    }
+
+    /// Variant of spend_coins for transactions which get additional kernels once they are signed,
+    /// i.e. the NRD anchor kernel of a funding transaction. The fee covers all kernels.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` the inputs which should be spent
+    /// * `fund_value` value which should be transferred to a receiver (IN NANO GRIN)
+    /// * `lock` kernel lock of the transaction
+    /// * `num_of_outputs` number of outputs the transaction will have
+    /// * `num_participants` number of participants signing the main kernel
+    /// * `num_kernels` number of kernels the transaction will have
+    pub fn spend_coins_with_kernels(
+        &mut self,
+        inputs: Vec<MWCoin>,
+        fund_value: u64,
+        lock: KernelLock,
+        num_of_outputs: usize,
+        num_participants: u8,
+        num_kernels: usize,
+    ) -> Result<SpendCoinsResult, String> {
+        lock.validate()?;
         // Initial transaction slate
-        let lock_height_param = if timelock != 0 { Some(timelock) } else { None };
-        let kernel_feat_param = if lock_height_param.is_some() { 2 } else { 0 };
         let mut slate = Slate::blank_with_kernel_features(
             num_participants,
             false,
-            kernel_feat_param,
-            lock_height_param,
+            lock.features(),
+            lock.lock_height(),
         );
-        // Calculcate basefee based on number of inputs and expected outputs
-        let fee = tx_fee(inputs.len(), num_of_outputs, 1);
+        // the slate id is drawn from our generator as well to keep the slate reproducible
+        slate.id = Builder::from_bytes(self.rng.gen())
+            .set_variant(Variant::RFC4122)
+            .set_version(Version::Random)
+            .build();
+        // Calculcate fee based on number of inputs and expected outputs
+        let fee = estimate_fees(inputs.len(), num_of_outputs, num_kernels, self.fee_base);
         println!("Fee is {}", fee);
 
         // Some input param validations
         let mut inpval: u64 = 0;
         let mut duplicate = false;
         for (i, coin) in inputs.iter().enumerate() {
-            inpval = inpval + coin.value;
+            inpval = inpval
+                .checked_add(coin.value)
+                .ok_or(String::from("Spend coins function failed, input coin values overflow"))?;
             for (j, cmp) in inputs.iter().enumerate() {
                 if i != j && coin.commitment == cmp.commitment {
                     duplicate = true;
@@ -136,7 +200,8 @@ impl GrinCore {
             let offset =
                 BlindingFactor::from_secret_key(create_secret_key(&mut self.rng, &self.secp));
 
-            let fee_field = FeeFields::new(0, fee).unwrap();
+            let fee_field = FeeFields::new(0, fee)
+                .map_err(|e| format!("Invalid fee {}: {:?}", fee, e))?;
             let mut tx = Transaction::empty();
             slate.fee_fields = fee_field;
             slate.amount = fund_value;
@@ -145,8 +210,8 @@ impl GrinCore {
             // Add the input coins
             let mut inp_vector: Vec<Input> = vec![];
             for coin in inputs {
-                let commitment = deserialize_commitment(&coin.commitment);
-                let inp_key = deserialize_secret_key(&coin.blinding_factor, &self.secp);
+                let commitment = deserialize_commitment(&coin.commitment)?;
+                let inp_key = deserialize_secret_key(&coin.blinding_factor, &self.secp)?;
                 let input = Input::new(OutputFeatures::Plain, commitment);
                 inp_vector.push(input);
                 let inp_bf = BlindingFactor::from_secret_key(inp_key.clone());
@@ -156,19 +221,20 @@ impl GrinCore {
             tx = Transaction::new(inputs, &tx.body.outputs, tx.body.kernels());
 
             // Add changecoin output
-            let change_value = i64::try_from(inpval).unwrap() - i64::try_from(fund_value).unwrap() - i64::try_from(fee).unwrap();
-            let mut change_value_u64 = 0;
+            let change_value_u64 = inpval
+                .checked_sub(fund_value)
+                .and_then(|v| v.checked_sub(fee))
+                .unwrap_or(0);
             // Only create an output coin if there is actually a change value
             let mut com: Option<Commitment> = None;
-            if change_value > 0 {
+            if change_value_u64 > 0 {
                 let out_bf = BlindingFactor::from_secret_key(change_coin_key.clone());
                 blind_sum = blind_sum.add_blinding_factor(out_bf);
-                change_value_u64 = u64::try_from(change_value).unwrap();
-                println!("Creating change coin with value {}", change_value);
+                println!("Creating change coin with value {}", change_value_u64);
                 let commitment = self
                     .secp
                     .commit(change_value_u64, change_coin_key.clone())
-                    .expect("Failed to create change coin commitment");
+                    .map_err(|e| format!("Failed to create change coin commitment: {:?}", e))?;
                 // Compute bulletproof rangeproof
                 let proof = self.secp.bullet_proof(
                     change_value_u64,
@@ -187,19 +253,21 @@ impl GrinCore {
             let final_bf = self
                 .chain
                 .blind_sum(&blind_sum)
-                .expect("Failure when calculating blinding factor sum");
+                .map_err(|e| format!("Failure when calculating blinding factor sum: {:?}", e))?;
             tx.offset = offset.clone();
             slate.tx = Some(tx);
             let final_key = final_bf
                 .split(&offset, &self.secp)
-                .unwrap()
+                .and_then(|bf| bf.secret_key(&self.secp))
+                .map_err(|e| format!("Failed to split the offset off the blinding factor: {:?}", e))?;
+            let initial_sec_key = final_bf
                 .secret_key(&self.secp)
-                .unwrap();
+                .map_err(|e| format!("Invalid blinding factor sum: {:?}", e))?;
             let mut ctx: Context = Context {
                 parent_key_id: Identifier::zero(),
                 sec_key: final_key.clone(),
                 sec_nonce: sig_nonce.clone(),
-                initial_sec_key: final_bf.secret_key(&self.secp).unwrap(),
+                initial_sec_key: initial_sec_key,
                 initial_sec_nonce: sig_nonce.clone(),
                 output_ids: vec![],
                 input_ids: vec![],
@@ -211,7 +279,7 @@ impl GrinCore {
             };
             slate
                 .fill_round_1(&self.chain, &mut ctx)
-                .expect("Failed to complete round 1 on the senders turn");
+                .map_err(|e| format!("Failed to complete round 1 on the senders turn: {}", e))?;
             let change_coin_output = if com == None {
                 None
             } else {
@@ -236,13 +304,13 @@ impl GrinCore {
     /// * `inputs` the input coins containing the shares of the blinding factor
     /// * `slate` Transaction slate as provided by the first sender
     /// * `fund_value` amount that should be spend and transferred to a receiver
-    /// * `timelock` optional transaction height lock
+    /// * `lock` expected kernel lock of the transaction
     pub fn d_spend_coins(
         &mut self,
         inputs: Vec<MWCoin>,
         mut slate: Slate,
         fund_value: u64,
-        timelock: u64,
+        lock: KernelLock,
     ) -> Result<SpendCoinsResult, String> {
         // Validate output coin rangeproofs
         let tx = slate.tx.clone().unwrap_or_else(|| Transaction::empty());
@@ -251,41 +319,42 @@ impl GrinCore {
             let com = out.identifier.commit;
             self.secp
                 .verify_bullet_proof(com, prf, None)
-                .expect("Failed to verify outputcoin rangeproof");
+                .map_err(|e| format!("Failed to verify outputcoin rangeproof: {:?}", e))?;
         }
 
+        // The fee has to match our fee base, the receiver adds one more output
+        let expected_fee = estimate_fees(tx.inputs().len(), tx.outputs().len() + 1, 1, self.fee_base);
+        let fee = slate.fee_fields.fee(0);
+
         // Some more validations
         if slate.amount != fund_value {
             Err(String::from("Transaction amount found to be invalid"))
+        } else if fee != expected_fee {
+            Err(format!("Transaction fee of {} does not match the expected fee of {}", fee, expected_fee))
         } else if tx.inputs().len() != inputs.len() {
             Err(String::from(
                 "Inputs don't match with coins given in parameters",
             ))
         } else {
-            // Validate Kernel features (transaction lock height)
-            let valid_features;
-            if timelock != 0 {
-                valid_features = slate.kernel_features == 2
-                    && slate.kernel_features_args.clone().unwrap().lock_height == timelock;
-            } else {
-                valid_features = slate.kernel_features == 0;
-            }
+            // Validate Kernel features (transaction lock height or NRD relative height)
+            let valid_features = slate.kernel_features == lock.features()
+                && slate.kernel_features_args.clone().map(|a| a.lock_height) == lock.lock_height();
             if !valid_features {
                 Err(String::from("Transaction timelock is not setup correctly!"))
             } else {
                 // Now we create the signing keys for this participant
                 let mut blind_sum = BlindSum::new();
                 for coin in inputs {
-                    let inp_key = deserialize_secret_key(&coin.blinding_factor, &self.secp);
+                    let inp_key = deserialize_secret_key(&coin.blinding_factor, &self.secp)?;
                     let inp_bf = BlindingFactor::from_secret_key(inp_key.clone());
                     blind_sum = blind_sum.sub_blinding_factor(inp_bf);
                 }
                 let final_key = self
                     .chain
                     .blind_sum(&blind_sum)
-                    .expect("Failed to calculate final blinding factor sum")
+                    .map_err(|e| format!("Failed to calculate final blinding factor sum: {:?}", e))?
                     .secret_key(&self.secp)
-                    .unwrap();
+                    .map_err(|e| format!("Failed to calculate final blinding factor sum: {:?}", e))?;
                 let sig_nonce = create_secret_key(&mut self.rng, &self.secp);
                 let mut ctx: Context = Context {
                     parent_key_id: Identifier::zero(),
@@ -303,7 +372,7 @@ impl GrinCore {
                 };
                 slate
                     .fill_round_1(&self.chain, &mut ctx)
-                    .expect("Failed to complete round 1 on the senders turn");
+                    .map_err(|e| format!("Failed to complete round 1 on the senders turn: {}", e))?;
                 Ok(SpendCoinsResult {
                     slate: slate,
                     sig_key: final_key.clone(),
@@ -323,9 +392,36 @@ impl GrinCore {
     /// * `slate` the pre-transaction slate as received from the sender
     /// * `fund_value` the value that should be transferred to the reciever (IN NANO GRIN)
     pub fn recv_coins(
+        &mut self,
+        slate: Slate,
+        fund_value: u64,
+    ) -> Result<RecvCoinsResult, String> {
+        self.recv_coins_excess(slate, fund_value, None)
+    }
+
+    /// Variant of recv_coins which signs for a given public excess instead of the output
+    /// blinding factor, the difference is moved into the transaction offset.
+    /// Used to make a refund kernel reuse the excess of an NRD anchor kernel.
+    ///
+    /// # Arguments
+    ///
+    /// * `slate` the pre-transaction slate as received from the sender
+    /// * `fund_value` the value that should be transferred to the reciever (IN NANO GRIN)
+    /// * `excess_key` the key to sign the kernel with
+    pub fn recv_coins_with_excess(
+        &mut self,
+        slate: Slate,
+        fund_value: u64,
+        excess_key: &SecretKey,
+    ) -> Result<RecvCoinsResult, String> {
+        self.recv_coins_excess(slate, fund_value, Some(excess_key))
+    }
+
+    fn recv_coins_excess(
         &mut self,
         mut slate: Slate,
         fund_value: u64,
+        excess_key: Option<&SecretKey>,
     ) -> Result<RecvCoinsResult, String> {
         // Validate output coin rangeproofs
         let mut tx = slate.tx.unwrap_or_else(|| Transaction::empty());
@@ -334,7 +430,7 @@ impl GrinCore {
             let com = out.identifier.commit;
             self.secp
                 .verify_bullet_proof(com, prf, None)
-                .expect("Failed to verify outputcoin rangeproof");
+                .map_err(|e| format!("Failed to verify outputcoin rangeproof: {:?}", e))?;
         }
 
         // Create new output coins
@@ -347,7 +443,7 @@ impl GrinCore {
         let commitment = self
             .secp
             .commit(fund_value, out_coin_key.clone())
-            .expect("Failed to generate pedersen commitment for recv_coins output coin");
+            .map_err(|e| format!("Failed to generate pedersen commitment for recv_coins output coin: {:?}", e))?;
         let proof = self.secp.bullet_proof(
             fund_value,
             out_coin_key.clone(),
@@ -362,21 +458,28 @@ impl GrinCore {
         slate.tx = Some(tx);
         slate
             .update_kernel()
-            .expect("Failed to udpate kernel in recv_coins");
+            .map_err(|e| format!("Failed to udpate kernel in recv_coins: {}", e))?;
+        let sig_key = match excess_key {
+            Some(excess_key) => {
+                self.shift_offset(&mut slate, &out_coin_key, excess_key)?;
+                excess_key.clone()
+            },
+            None => out_coin_key.clone()
+        };
         let mut ctx = create_minimal_ctx(
-            out_coin_key.clone(),
+            sig_key,
             sig_nonce.clone(),
             fund_value,
             slate.fee_fields,
         );
         slate
             .fill_round_1(&self.chain, &mut ctx)
-            .expect("Failed to complete round 1 on receivers turn");
+            .map_err(|e| format!("Failed to complete round 1 on receivers turn: {}", e))?;
 
         // Signs the transaction
         slate
             .fill_round_2(&self.chain, &ctx.sec_key, &ctx.sec_nonce)
-            .expect("Failed to complete round 2 on receivers turn");
+            .map_err(|e| format!("Failed to complete round 2 on receivers turn: {}", e))?;
 
         Ok(RecvCoinsResult {
             slate: slate,
@@ -408,7 +511,7 @@ impl GrinCore {
             let com = out.identifier.commit;
             self.secp
                 .verify_bullet_proof(com, prf, None)
-                .expect("Failed to verify outputcoin rangeproof");
+                .map_err(|e| format!("Failed to verify outputcoin rangeproof: {:?}", e))?;
         }
 
         // Create new output coins
@@ -421,7 +524,7 @@ impl GrinCore {
         let commitment = self
             .secp
             .commit(fund_value, out_coin_key.clone())
-            .expect("Failed to generate pedersen commitment for recv_coins output coin");
+            .map_err(|e| format!("Failed to generate pedersen commitment for recv_coins output coin: {:?}", e))?;
         let proof = self.secp.bullet_proof(
             fund_value,
             out_coin_key.clone(),
@@ -436,7 +539,7 @@ impl GrinCore {
         slate.tx = Some(tx);
         slate
             .update_kernel()
-            .expect("Failed to udpate kernel in recv_coins");
+            .map_err(|e| format!("Failed to udpate kernel in recv_coins: {}", e))?;
         let mut ctx = create_minimal_ctx(
             out_coin_key.clone(),
             sig_nonce.clone(),
@@ -445,11 +548,14 @@ impl GrinCore {
         );
         slate
             .fill_round_1(&self.chain, &mut ctx)
-            .expect("Failed to complete round 1 on receivers turn");
-
-        let pub_nonce_sum = slate.pub_nonce_sum(&self.secp).unwrap();
-        let pub_blind_sum = slate.pub_blind_sum(&self.secp).unwrap();
-        let msg = slate.msg_to_sign().unwrap();
+            .map_err(|e| format!("Failed to complete round 1 on receivers turn: {}", e))?;
+
+        let pub_nonce_sum = slate.pub_nonce_sum(&self.secp)
+            .map_err(|e| format!("Failed to sum the public nonces: {}", e))?;
+        let pub_blind_sum = slate.pub_blind_sum(&self.secp)
+            .map_err(|e| format!("Failed to sum the public blind excesses: {}", e))?;
+        let msg = slate.msg_to_sign()
+            .map_err(|e| format!("Failed to compute the kernel message: {}", e))?;
         // Signs the transaction
         let sig = aggsig::sign_single(
             &self.secp,
@@ -461,7 +567,7 @@ impl GrinCore {
             Some(&pub_blind_sum),
             Some(&pub_nonce_sum),
         )
-        .expect("Failed to calculate adapted signature in apt_recv");
+        .map_err(|e| format!("Failed to calculate adapted signature in apt_recv: {:?}", e))?;
         let apt_sig = aggsig::sign_single(
             &self.secp,
             &msg,
@@ -472,7 +578,7 @@ impl GrinCore {
             Some(&pub_blind_sum),
             Some(&pub_nonce_sum),
         )
-        .expect("Failed to calculate unadapted signature in apt_recv");
+        .map_err(|e| format!("Failed to calculate unadapted signature in apt_recv: {:?}", e))?;
 
         // Add the adapted signature
         let pub_excess = PublicKey::from_secret_key(&self.secp, &out_coin_key).unwrap();
@@ -505,10 +611,12 @@ impl GrinCore {
     /// # Arguments
     /// * `slate` the slate as received from the sender
     /// * `fund_value` amount of funds which should be received (in nanogrin)
+    /// * `anchor_key` our share of an NRD anchor kernel excess, it is split off our kernel signing key
     pub fn drecv_coins_r1(
         &mut self,
         mut slate: Slate,
         fund_value: u64,
+        anchor_key: Option<&SecretKey>,
     ) -> Result<DRecvCoinsResult, String> {
         // Validate senders output coins
         let tx = slate.tx.clone().unwrap_or_else(|| Transaction::empty());
@@ -517,35 +625,36 @@ impl GrinCore {
             let com = out.identifier.commit;
             self.secp
                 .verify_bullet_proof(com, prf, None)
-                .expect("Failed to verify outputcoin rangeproof");
+                .map_err(|e| format!("Failed to verify outputcoin rangeproof: {:?}", e))?;
         }
 
         let out_coin_blind = create_secret_key(&mut self.rng, &self.secp);
         let shared_nonce = create_secret_key(&mut self.rng, &self.secp);
         let prf_nonce = create_secret_key(&mut self.rng, &self.secp);
         let sig_nonce = create_secret_key(&mut self.rng, &self.secp);
+        let sig_key = self.split_anchor_key(&out_coin_blind, anchor_key)?;
 
         // Fill participant data
         slate
             .fill_round_1(
                 &self.chain,
                 &mut create_minimal_ctx(
-                    out_coin_blind.clone(),
+                    sig_key,
                     sig_nonce.clone(),
                     fund_value,
                     slate.fee_fields,
                 ),
             )
-            .expect("Faile to fill_round_1 on drecv_coins r1");
+            .map_err(|e| format!("Faile to fill_round_1 on drecv_coins r1: {}", e))?;
 
         // Create partial commitment for the output coin and initiate multiparty rangeproof
         let com = self
             .secp
             .commit(fund_value, out_coin_blind.clone())
-            .expect("Failed to generate pedersen commitment for drecv_coins_r1");
-        let mut prf_ctx = MPBPContext::new(shared_nonce, fund_value, com);
+            .map_err(|e| format!("Failed to generate pedersen commitment for drecv_coins_r1: {:?}", e))?;
+        let mut prf_ctx = MPBPContext::new(shared_nonce, fund_value, com, self.rng.fork());
         prf_ctx = mp_bullet_proof_r1(prf_ctx, out_coin_blind.clone(), prf_nonce.clone())
-            .expect("Failed to run round 1A of mp bulletproofs");
+            .map_err(|e| format!("Failed to run round 1A of mp bulletproofs: {}", e))?;
         // Add the partial signature
         Ok(DRecvCoinsResult {
             slate: slate,
@@ -569,11 +678,13 @@ impl GrinCore {
     /// * `slate` the slate as returned from the call to round 1 of the protocol
     /// * `fund_value` the value of the output coin
     /// * `prf_ctx` the mutliparty bulletproof context
+    /// * `anchor_key` our share of an NRD anchor kernel excess, it is split off our kernel signing key
     pub fn drecv_coins_r2(
         &mut self,
         mut slate: Slate,
         fund_value: u64,
         mut prf_ctx: MPBPContext,
+        anchor_key: Option<&SecretKey>,
     ) -> Result<(RecvCoinsResult, MPBPContext), String> {
         // Validate senders output coins
         let tx = slate.tx.clone().unwrap_or_else(|| Transaction::empty());
@@ -582,7 +693,7 @@ impl GrinCore {
             let com = out.identifier.commit;
             self.secp
                 .verify_bullet_proof(com, prf, None)
-                .expect("Failed to verify outputcoin rangeproof");
+                .map_err(|e| format!("Failed to verify outputcoin rangeproof: {:?}", e))?;
         }
 
         // Add our share to the coin commitment created by receiver 1
@@ -593,30 +704,31 @@ impl GrinCore {
         let com = self
             .secp
             .commit(0, out_coin_blind.clone())
-            .expect("Failed to generete pedersen commitment for drecv_coins_r2");
-        prf_ctx.add_commit(com);
+            .map_err(|e| format!("Failed to generete pedersen commitment for drecv_coins_r2: {:?}", e))?;
+        prf_ctx.add_commit(com)?;
         prf_ctx = mp_bullet_proof_r1(prf_ctx, out_coin_blind.clone(), prf_nonce.clone())
-            .expect("Failed to run round 1B of mp bulletproofs");
+            .map_err(|e| format!("Failed to run round 1B of mp bulletproofs: {}", e))?;
         // T1 and T2 and the commitment are now finalized we can start round 2
         prf_ctx = mp_bullet_proof_r2(prf_ctx, out_coin_blind.clone(), prf_nonce.clone())
-            .expect("Failed to run round 2A of mp bulletproofs");
+            .map_err(|e| format!("Failed to run round 2A of mp bulletproofs: {}", e))?;
 
+        let sig_key = self.split_anchor_key(&out_coin_blind, anchor_key)?;
         slate
             .fill_round_1(
                 &self.chain,
                 &mut create_minimal_ctx(
-                    out_coin_blind.clone(),
+                    sig_key.clone(),
                     sig_nonce.clone(),
                     fund_value,
                     slate.fee_fields,
                 ),
             )
-            .expect("Failed to run fill_round_1 on drecv_coins_r2");
+            .map_err(|e| format!("Failed to run fill_round_1 on drecv_coins_r2: {}", e))?;
 
         // Now we are ready to create the first partial signature
         slate
-            .fill_round_2(&self.chain, &out_coin_blind, &sig_nonce)
-            .expect("Failed to run fill_round_2 on drecv_coins_r2");
+            .fill_round_2(&self.chain, &sig_key, &sig_nonce)
+            .map_err(|e| format!("Failed to run fill_round_2 on drecv_coins_r2: {}", e))?;
 
         let coin = MWCoin::new(&prf_ctx.commit.clone(), &out_coin_blind, fund_value);
         Ok((
@@ -643,6 +755,7 @@ impl GrinCore {
     /// * `out_coin_blind` share of the output coin blinding factor
     /// * `prf_nonce` the nonce used in the rangeproof
     /// * `sig_nonce` the nonce used for the signature creation
+    /// * `anchor_key` our share of an NRD anchor kernel excess as given in round 1
     pub fn drecv_coins_r3(
         &mut self,
         mut slate: Slate,
@@ -650,26 +763,28 @@ impl GrinCore {
         out_coin_blind: SecretKey,
         prf_nonce: SecretKey,
         sig_nonce: SecretKey,
+        anchor_key: Option<&SecretKey>,
     ) -> Result<RecvCoinsResult, String> {
         let commit = prf_ctx.commit.clone();
         let amount = prf_ctx.amount.clone();
         // Run round 2 of the the mp bulletproof protocol
         prf_ctx = mp_bullet_proof_r2(prf_ctx, out_coin_blind.clone(), prf_nonce.clone())
-            .expect("failed to run round 2 of mp_bullet_proof");
+            .map_err(|e| format!("failed to run round 2 of mp_bullet_proof: {}", e))?;
         // Finalize the bulletproof
         let proof = mp_bullet_proof_fin(prf_ctx, out_coin_blind.clone(), prf_nonce.clone())
-            .expect("Failed to finalize mp bulletproof");
+            .map_err(|e| format!("Failed to finalize mp bulletproof: {}", e))?;
         let output = Output::new(OutputFeatures::Plain, commit, proof);
-        let mut tx = slate.tx.unwrap();
+        let mut tx = slate.tx.ok_or(String::from("Slate doesn't contain a transaction"))?;
         tx = tx.with_output(output);
         slate.tx = Some(tx);
+        let sig_key = self.split_anchor_key(&out_coin_blind, anchor_key)?;
         slate
-            .fill_round_2(&self.chain, &out_coin_blind.clone(), &sig_nonce.clone())
-            .unwrap();
+            .fill_round_2(&self.chain, &sig_key, &sig_nonce.clone())
+            .map_err(|e| format!("Failed to run fill_round_2 on drecv_coins_r3: {}", e))?;
 
         slate
             .update_kernel()
-            .expect("Failed to update kernel in drecv_coins_r3");
+            .map_err(|e| format!("Failed to update kernel in drecv_coins_r3: {}", e))?;
         let out_coin = MWCoin::new(&commit, &out_coin_blind, amount);
         Ok(RecvCoinsResult {
             slate: slate,
@@ -698,32 +813,35 @@ impl GrinCore {
         replace_sig: Option<(Signature, Signature)>,
     ) -> Result<Slate, String> {
         // First we verify output coin rangeproofs
-        let tx = slate.tx.clone().unwrap();
+        let tx = slate.tx.clone().ok_or(String::from("Slate doesn't contain a transaction"))?;
         for out in tx.outputs() {
             let prf = out.proof;
             let com = out.identifier.commit;
             self.secp
                 .verify_bullet_proof(com, prf, None)
-                .expect("Failed to verify outputcoin rangeproof");
+                .map_err(|e| format!("Failed to verify outputcoin rangeproof: {:?}", e))?;
         }
-        if pub_x.is_some() {
-            let pub_nonce_sum = slate.pub_nonce_sum(&self.secp).unwrap();
-            let pub_blind_sum = slate.pub_blind_sum(&self.secp).unwrap();
-            let msg = slate.msg_to_sign().unwrap();
+        if let Some(pub_x) = pub_x {
+            let pub_nonce_sum = slate.pub_nonce_sum(&self.secp)
+                .map_err(|e| format!("Failed to sum the public nonces: {}", e))?;
+            let pub_blind_sum = slate.pub_blind_sum(&self.secp)
+                .map_err(|e| format!("Failed to sum the public blind excesses: {}", e))?;
+            let msg = slate.msg_to_sign()
+                .map_err(|e| format!("Failed to compute the kernel message: {}", e))?;
             // In the dAptFinTx we can't use fill_round_2 because we need to verify the adapted pt sig
             for p in slate.participant_data.iter() {
-                if p.is_complete() {
+                if let Some(part_sig) = p.part_sig {
                     if !aggsig::verify_single(
                         &self.secp,
-                        &p.part_sig.unwrap(),
+                        &part_sig,
                         &msg,
                         Some(&pub_nonce_sum),
                         &p.public_blind_excess,
                         Some(&pub_blind_sum),
-                        Some(&pub_x.unwrap()),
+                        Some(&pub_x),
                         true,
                     ) {
-                        panic!("Partial adapted signature verification failed");
+                        return Err(String::from("Partial adapted signature verification failed"));
                     }
                 }
             }
@@ -738,11 +856,13 @@ impl GrinCore {
                 Some(&pub_blind_sum),
                 Some(&pub_nonce_sum),
             )
-            .expect("Failed to calculate signature in fin_tx");
+            .map_err(|e| format!("Failed to calculate signature in fin_tx: {:?}", e))?;
 
             // Add the signature
-            let pub_excess = PublicKey::from_secret_key(&self.secp, &sec_key).unwrap();
-            let pub_nonce = PublicKey::from_secret_key(&self.secp, &sec_nonce).unwrap();
+            let pub_excess = PublicKey::from_secret_key(&self.secp, &sec_key)
+                .map_err(|e| format!("Invalid signing key: {:?}", e))?;
+            let pub_nonce = PublicKey::from_secret_key(&self.secp, &sec_nonce)
+                .map_err(|e| format!("Invalid signing nonce: {:?}", e))?;
             for i in 0..slate.num_participants() as usize {
                 // find my entry
                 if slate.participant_data[i].public_blind_excess == pub_excess
@@ -754,9 +874,7 @@ impl GrinCore {
             }
         } else {
             // Replace adapted signature with the unadapted one before transaction completion
-            if replace_sig.is_some() {
-                let old_sig = replace_sig.unwrap().0;
-                let new_sig = replace_sig.unwrap().1;
+            if let Some((old_sig, new_sig)) = replace_sig {
                 for i in 0..slate.num_participants() as usize {
                     if slate.participant_data[i].part_sig == Some(old_sig) {
                         println!("Replacing that sig");
@@ -765,21 +883,212 @@ impl GrinCore {
                     }
                 }
             }
-            let j = serde_json::to_string(&slate).unwrap();
-            println!("Final slate {}", j);
+            if let Ok(j) = serde_json::to_string(&slate) {
+                println!("Final slate {}", j);
+            }
             slate
                 .fill_round_2(&self.chain, sec_key, sec_nonce)
-                .expect("Failed to complete round 2 on senders turn");
+                .map_err(|e| format!("Failed to complete round 2 on senders turn: {}", e))?;
         }
         
         if finalize {
             slate
                 .finalize(&self.chain)
-                .expect("Failed to finalize transaction");
+                .map_err(|e| format!("Failed to finalize transaction: {}", e))?;
+        }
+        Ok(slate)
+    }
+
+    /// Finalize a transaction which gets an additional, already signed kernel, i.e. the NRD
+    /// anchor kernel of a funding transaction. The slate finalization only handles a single kernel.
+    ///
+    /// # Arguments
+    ///
+    /// * `slate` the slate holding all partial signatures of the main kernel
+    /// * `kernel` the signed kernel to add to the transaction
+    pub fn fin_tx_with_kernel(&self, mut slate: Slate, kernel: TxKernel) -> Result<Slate, String> {
+        let pub_nonce_sum = slate.pub_nonce_sum(&self.secp)
+            .map_err(|e| format!("Failed to sum the public nonces: {}", e))?;
+        let pub_blind_sum = slate.pub_blind_sum(&self.secp)
+            .map_err(|e| format!("Failed to sum the public blind excesses: {}", e))?;
+        let msg = slate.msg_to_sign()
+            .map_err(|e| format!("Failed to compute the kernel message: {}", e))?;
+        let mut part_sigs: Vec<Signature> = vec![];
+        for p in slate.participant_data.iter() {
+            let part_sig = p.part_sig.ok_or(String::from("Transaction is missing a partial signature"))?;
+            if !aggsig::verify_single(
+                &self.secp,
+                &part_sig,
+                &msg,
+                Some(&pub_nonce_sum),
+                &p.public_blind_excess,
+                Some(&pub_blind_sum),
+                None,
+                true,
+            ) {
+                return Err(String::from("Partial signature verification failed"));
+            }
+            part_sigs.push(part_sig);
         }
+        let sig = aggsig::add_signatures_single(&self.secp, part_sigs.iter().collect(), &pub_nonce_sum)
+            .map_err(|e| format!("Failed to add the partial signatures: {:?}", e))?;
+
+        let tx = slate.tx.clone().ok_or(String::from("Slate doesn't contain a transaction"))?;
+        let mut main_kernel = tx.kernels()[0];
+        main_kernel.excess = Commitment::from_pubkey(&self.secp, &pub_blind_sum)
+            .map_err(|e| format!("Invalid kernel excess: {:?}", e))?;
+        main_kernel.excess_sig = sig;
+        main_kernel.verify()
+            .map_err(|e| format!("Kernel signature verification failed: {:?}", e))?;
+        let tx = tx.replace_kernel(main_kernel).with_kernel(kernel);
+        tx.validate(Weighting::AsTransaction, Arc::new(RwLock::new(LruVerifierCache::new())), 0)
+            .map_err(|e| format!("Failed to finalize transaction: {:?}", e))?;
+        slate.tx = Some(tx);
         Ok(slate)
     }
 
+    /// Make our entry of the kernel signers use a given public excess instead of our
+    /// signing key, the difference is moved into the transaction offset.
+    /// Used by the sender to make a refund kernel reuse the excess of an NRD anchor kernel.
+    ///
+    /// # Arguments
+    ///
+    /// * `slate` the slate after running spend_coins
+    /// * `sig_key` the signing key as returned by spend_coins
+    /// * `excess_key` the key to sign the kernel with from now on
+    pub fn set_excess_key(&self, slate: &mut Slate, sig_key: &SecretKey, excess_key: &SecretKey) -> Result<(), String> {
+        let pub_key = PublicKey::from_secret_key(&self.secp, sig_key)
+            .map_err(|e| format!("Invalid signing key: {:?}", e))?;
+        let pub_excess = PublicKey::from_secret_key(&self.secp, excess_key)
+            .map_err(|e| format!("Invalid excess key: {:?}", e))?;
+        let entry = slate.participant_data.iter_mut()
+            .find(|p| p.public_blind_excess == pub_key && p.part_sig.is_none())
+            .ok_or(String::from("Slate doesn't contain an unsigned entry for the signing key"))?;
+        entry.public_blind_excess = pub_excess;
+        self.shift_offset(slate, sig_key, excess_key)
+    }
+
+    /// Partial signature of an NRD anchor kernel which two parties sign jointly,
+    /// the partial signature of the peer is verified if it is given
+    ///
+    /// # Arguments
+    ///
+    /// * `key` our share of the anchor kernel excess
+    /// * `nonce` our signing nonce
+    /// * `own` our public excess share and nonce
+    /// * `peer` public excess share, nonce and optionally partial signature of the peer
+    /// * `relative_height` relative height of the NRD kernel
+    pub fn sign_anchor(
+        &self,
+        key: &SecretKey,
+        nonce: &SecretKey,
+        own: &AnchorSigMsg,
+        peer: &AnchorSigMsg,
+        relative_height: u64,
+    ) -> Result<Signature, String> {
+        let (excess_sum, nonce_sum, msg) = self.anchor_sig_data(own, peer, relative_height)?;
+        if let Some(part_sig) = peer.part_sig {
+            if !aggsig::verify_single(&self.secp, &part_sig, &msg, Some(&nonce_sum), &peer.excess, Some(&excess_sum), None, true) {
+                return Err(String::from("Partial signature of the anchor kernel is invalid"));
+            }
+        }
+        aggsig::sign_single(
+            &self.secp,
+            &msg,
+            key,
+            Some(nonce),
+            None,
+            Some(&nonce_sum),
+            Some(&excess_sum),
+            Some(&nonce_sum),
+        )
+        .map_err(|e| format!("Failed to sign the anchor kernel: {:?}", e))
+    }
+
+    /// Build the NRD anchor kernel from the partial signatures of both parties
+    ///
+    /// # Arguments
+    ///
+    /// * `own` our public excess share, nonce and partial signature
+    /// * `peer` public excess share, nonce and partial signature of the peer
+    /// * `relative_height` relative height of the NRD kernel
+    pub fn anchor_kernel(&self, own: &AnchorSigMsg, peer: &AnchorSigMsg, relative_height: u64) -> Result<TxKernel, String> {
+        let (excess_sum, nonce_sum, _) = self.anchor_sig_data(own, peer, relative_height)?;
+        let part_sigs = match (&own.part_sig, &peer.part_sig) {
+            (Some(a), Some(b)) => vec![a, b],
+            _ => return Err(String::from("Anchor kernel is missing a partial signature"))
+        };
+        let mut kernel = TxKernel::with_features(anchor_features(relative_height)?);
+        kernel.excess = Commitment::from_pubkey(&self.secp, &excess_sum)
+            .map_err(|e| format!("Invalid anchor kernel excess: {:?}", e))?;
+        kernel.excess_sig = aggsig::add_signatures_single(&self.secp, part_sigs, &nonce_sum)
+            .map_err(|e| format!("Failed to add the anchor kernel signatures: {:?}", e))?;
+        kernel.verify()
+            .map_err(|e| format!("Anchor kernel signature verification failed: {:?}", e))?;
+        Ok(kernel)
+    }
+
+    fn anchor_sig_data(
+        &self,
+        own: &AnchorSigMsg,
+        peer: &AnchorSigMsg,
+        relative_height: u64,
+    ) -> Result<(PublicKey, PublicKey, Message), String> {
+        let excess_sum = PublicKey::from_combination(&self.secp, vec![&own.excess, &peer.excess])
+            .map_err(|e| format!("Failed to sum the anchor excesses: {:?}", e))?;
+        let nonce_sum = PublicKey::from_combination(&self.secp, vec![&own.nonce, &peer.nonce])
+            .map_err(|e| format!("Failed to sum the anchor nonces: {:?}", e))?;
+        let msg = anchor_features(relative_height)?
+            .kernel_sig_msg()
+            .map_err(|e| format!("Failed to compute the anchor kernel message: {:?}", e))?;
+        Ok((excess_sum, nonce_sum, msg))
+    }
+
+    /// Difference of two keys
+    ///
+    /// # Arguments
+    ///
+    /// * `key` the key to subtract from
+    /// * `sub` the key to subtract
+    pub fn sub_key(&self, key: &SecretKey, sub: &SecretKey) -> Result<SecretKey, String> {
+        self.blind_sum_key(
+            BlindSum::new()
+                .add_blinding_factor(BlindingFactor::from_secret_key(key.clone()))
+                .sub_blinding_factor(BlindingFactor::from_secret_key(sub.clone())),
+        )
+    }
+
+    // Our kernel signing key with our share of an NRD anchor excess split off
+    fn split_anchor_key(&self, key: &SecretKey, anchor_key: Option<&SecretKey>) -> Result<SecretKey, String> {
+        match anchor_key {
+            Some(anchor_key) => self.sub_key(key, anchor_key),
+            None => Ok(key.clone()),
+        }
+    }
+
+    // Add the difference of two keys to the transaction offset
+    fn shift_offset(&self, slate: &mut Slate, add: &SecretKey, sub: &SecretKey) -> Result<(), String> {
+        let offset = self.blind_sum_key(
+            BlindSum::new()
+                .add_blinding_factor(slate.offset.clone())
+                .add_blinding_factor(BlindingFactor::from_secret_key(add.clone()))
+                .sub_blinding_factor(BlindingFactor::from_secret_key(sub.clone())),
+        )?;
+        slate.offset = BlindingFactor::from_secret_key(offset);
+        if let Some(tx) = slate.tx.as_mut() {
+            tx.offset = slate.offset.clone();
+        }
+        Ok(())
+    }
+
+    fn blind_sum_key(&self, blind_sum: BlindSum) -> Result<SecretKey, String> {
+        self.chain
+            .blind_sum(&blind_sum)
+            .map_err(|e| format!("Failed to calculate blinding factor sum: {:?}", e))?
+            .secret_key(&self.secp)
+            .map_err(|e| format!("Failed to calculate blinding factor sum: {:?}", e))
+    }
+
     /// Extract Secret Witness value from two partial signatures
     /// Essentially calculates the difference in s between prt_sig and apt_sig
     /// Return the x as SecretKey as hidden in an adapted signature
@@ -787,23 +1096,26 @@ impl GrinCore {
     /// # Arguments
     /// * `prt_sig` the unadapted partial signature (does not hold the x)
     /// * `apt_sig` the adapted signature (holds the x)
-    pub fn ext_witness(&mut self, prt_sig: Signature, apt_sig: Signature) -> SecretKey {
-        let mut apt_s = sig_extract_s(&apt_sig, &self.secp);
-        let mut prt_s = sig_extract_s(&prt_sig, &self.secp);
-        prt_s.neg_assign(&self.secp).unwrap();
-        apt_s.add_assign(&self.secp, &prt_s).unwrap();
-        apt_s
+    pub fn ext_witness(&mut self, prt_sig: Signature, apt_sig: Signature) -> Result<SecretKey, String> {
+        let mut apt_s = sig_extract_s(&apt_sig, &self.secp)?;
+        let mut prt_s = sig_extract_s(&prt_sig, &self.secp)?;
+        prt_s.neg_assign(&self.secp)
+            .map_err(|e| format!("Failed to extract the witness: {}", e))?;
+        // fails if both signatures are equal, they don't hide a witness then
+        apt_s.add_assign(&self.secp, &prt_s)
+            .map_err(|e| format!("Failed to extract the witness: {}", e))?;
+        Ok(apt_s)
     }
 
     /// Return the current height of the blockchain
-    pub fn get_block_height(&mut self) -> Result<u64,String> {
+    pub fn get_block_height(&self) -> Result<u64,String> {
         let rpc = JsonRpc::new(String::from("2.0"), self.settings.id.clone(), String::from("get_tip"), vec![]);
         let url = format!("http://{}:{}/v2/foreign", self.settings.url, self.settings.port);
         let req = self.req_factory.new_json_rpc_request(url, rpc, self.settings.user.clone(), self.settings.pass.clone());
         match req.execute() {
             Ok(x) => {
                 let parsed : JsonRPCResponse<GetTipResult> = serde_json::from_str(&x.content)
-                    .unwrap();
+                    .map_err(|e| format!("Failed to parse get_tip response: {}", e))?;
                 if parsed.error.is_some() {
                     Err(parsed.error.unwrap().message)
                 }
@@ -812,7 +1124,132 @@ impl GrinCore {
                         Err(String::from("Invalid JRPC response id"))
                     }
                     else {
-                        Ok(parsed.result.unwrap().Ok.height)
+                        match parsed.result {
+                            Some(r) => Ok(r.Ok.height),
+                            None => Err(String::from("get_tip result was empty"))
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Look up an output on chain by its commitment
+    /// Returns None if the node doesn't know the output
+    ///
+    /// # Arguments
+    ///
+    /// * `commit` hex encoded commitment of the output
+    pub fn get_output(&self, commit : &str) -> Result<Option<OutputPrintable>, String> {
+        let mut params : Vec<JsonRpcParam> = Vec::new();
+        params.push(JsonRpcParam::Vec(vec![String::from(commit)]));
+        params.push(JsonRpcParam::Null);
+        params.push(JsonRpcParam::Null);
+        params.push(JsonRpcParam::Bool(false));
+        params.push(JsonRpcParam::Bool(false));
+        let rpc = JsonRpc::new(String::from("2.0"), self.settings.id.clone(), String::from("get_outputs"), params);
+        let url = format!("http://{}:{}/v2/foreign", self.settings.url, self.settings.port);
+        let req = self.req_factory.new_json_rpc_request(url, rpc, self.settings.user.clone(), self.settings.pass.clone());
+        match req.execute() {
+            Ok(x) => {
+                let parsed : JsonRPCResponse<GetOutputsResult> = serde_json::from_str(&x.content)
+                    .map_err(|e| format!("Failed to parse get_outputs response: {}", e))?;
+                if parsed.error.is_some() {
+                    Err(parsed.error.unwrap().message)
+                }
+                else {
+                    if parsed.id != self.settings.id {
+                        Err(String::from("Invalid JRPC response id"))
+                    }
+                    else {
+                        match parsed.result {
+                            Some(r) => Ok(r.Ok.into_iter().find(|o| o.commit == commit)),
+                            None => Err(String::from("get_outputs result was empty"))
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Look up a mined kernel by its excess
+    /// Returns None if the kernel isn't on chain
+    ///
+    /// # Arguments
+    ///
+    /// * `excess` hex encoded excess of the kernel
+    pub fn get_kernel(&self, excess : &str) -> Result<Option<LocatedTxKernel>, String> {
+        let mut params : Vec<JsonRpcParam> = Vec::new();
+        params.push(JsonRpcParam::String(String::from(excess)));
+        params.push(JsonRpcParam::Null);
+        params.push(JsonRpcParam::Null);
+        let rpc = JsonRpc::new(String::from("2.0"), self.settings.id.clone(), String::from("get_kernel"), params);
+        let url = format!("http://{}:{}/v2/foreign", self.settings.url, self.settings.port);
+        let req = self.req_factory.new_json_rpc_request(url, rpc, self.settings.user.clone(), self.settings.pass.clone());
+        match req.execute() {
+            Ok(x) => {
+                let parsed : JsonRPCResponse<GetKernelResult> = serde_json::from_str(&x.content)
+                    .map_err(|e| format!("Failed to parse get_kernel response: {}", e))?;
+                if parsed.error.is_some() {
+                    Err(parsed.error.unwrap().message)
+                }
+                else {
+                    if parsed.id != self.settings.id {
+                        Err(String::from("Invalid JRPC response id"))
+                    }
+                    else {
+                        // the node answers with an Err result if the kernel is not found
+                        match parsed.result {
+                            Some(r) => Ok(r.Ok),
+                            None => Err(String::from("get_kernel result was empty"))
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Estimate the fee base the node relays transactions at.
+    /// The node doesn't report its accept_fee_base, it only accepts transactions paying at least
+    /// their weight times it into its pool. The lowest fee per weight unit of the transactions
+    /// in the pool is used, None if the pool is empty.
+    pub fn get_fee_base(&self) -> Result<Option<u64>, String> {
+        let rpc = JsonRpc::new(String::from("2.0"), self.settings.id.clone(), String::from("get_unconfirmed_transactions"), vec![]);
+        let url = format!("http://{}:{}/v2/foreign", self.settings.url, self.settings.port);
+        let req = self.req_factory.new_json_rpc_request(url, rpc, self.settings.user.clone(), self.settings.pass.clone());
+        match req.execute() {
+            Ok(x) => {
+                let parsed : JsonRPCResponse<GetUnconfirmedTransactionsResult> = serde_json::from_str(&x.content)
+                    .map_err(|e| format!("Failed to parse get_unconfirmed_transactions response: {}", e))?;
+                if parsed.error.is_some() {
+                    Err(parsed.error.unwrap().message)
+                }
+                else {
+                    if parsed.id != self.settings.id {
+                        Err(String::from("Invalid JRPC response id"))
+                    }
+                    else {
+                        let entries = match parsed.result {
+                            Some(r) => r.Ok,
+                            None => return Err(String::from("get_unconfirmed_transactions result was empty"))
+                        };
+                        if entries.is_empty() {
+                            return Ok(None);
+                        }
+                        // the fee shift depends on the header version, so the fees are read at the next height
+                        let height = self.get_block_height()? + 1;
+                        Ok(entries.iter()
+                            .map(|e| e.tx.shifted_fee(height) / e.tx.weight().max(1))
+                            .min())
                     }
                 }
             }
@@ -827,7 +1264,7 @@ impl GrinCore {
     /// # Arguments
     ///
     /// * `tx` the transaction to broadcast
-    pub fn push_transaction(&mut self, tx : Transaction) -> Result<(), String> {
+    pub fn push_transaction(&self, tx : Transaction) -> Result<(), String> {
         let mut params : Vec<JsonRpcParam> = Vec::new();
         params.push(JsonRpcParam::Tx(tx));
         params.push(JsonRpcParam::Bool(true));
@@ -838,7 +1275,7 @@ impl GrinCore {
             Ok(x) => {
                 println!("Respone from Grin node {}", &x.content);
                 let parsed : JsonRPCResponse<PushTransactionResult> = serde_json::from_str(&x.content)
-                    .unwrap();
+                    .map_err(|e| format!("Failed to parse push_transaction response: {}", e))?;
                 if parsed.error.is_some() {
                     Err(parsed.error.unwrap().message)
                 }
@@ -858,13 +1295,27 @@ impl GrinCore {
     }
 }
 
+/// Kernel features of an NRD anchor kernel, its fee is paid by the main kernel of the transaction
+///
+/// # Arguments
+///
+/// * `relative_height` relative height of the NRD kernel
+pub fn anchor_features(relative_height: u64) -> Result<KernelFeatures, String> {
+    let relative_height = NRDRelativeHeight::new(relative_height)
+        .map_err(|_| format!("Invalid relative height {} for a NRD kernel", relative_height))?;
+    Ok(KernelFeatures::NoRecentDuplicate {
+        fee: FeeFields::zero(),
+        relative_height: relative_height,
+    })
+}
+
 #[cfg(test)]
 mod test {
-    use std::{fs, sync::Arc};
+    use std::sync::Arc;
 
-    use crate::{grin::grin_core::GrinCore, net::http::{HttpResponse, RequestFactory}, settings};
-    use crate::grin::{grin_routines::*, grin_types::MWCoin};
-    use grin_core::{core::{verifier_cache::LruVerifierCache, Weighting}, global::{set_local_chain_type, ChainTypes}, libtx::tx_fee};
+    use crate::{grin::grin_core::GrinCore, net::http::{HttpResponse, RequestFactory}, sim::node::offline_settings};
+    use crate::grin::{grin_routines::*, grin_types::{KernelLock, MWCoin}};
+    use grin_core::{core::{verifier_cache::LruVerifierCache, Weighting}, global::{set_local_chain_type, set_local_nrd_enabled, ChainTypes}, libtx::tx_fee};
     use grin_util::{secp::PublicKey, RwLock};
     use grin_wallet_libwallet::{Slate, Slatepacker, SlatepackerArgs};
     use crate::bitcoin::btcroutines::{deserialize_priv_key, private_key_from_grin_sk};
@@ -872,9 +1323,7 @@ mod test {
     #[test]
     fn test_spend_coins() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let fund_value = grin_to_nanogrin(2);
@@ -888,7 +1337,7 @@ mod test {
             value: input_val,
         };
 
-        let result = core.spend_coins(vec![coin], fund_value, 0, 2, 2).unwrap();
+        let result = core.spend_coins(vec![coin], fund_value, KernelLock::NONE, 2, 2).unwrap();
         let ser = serde_json::to_string(&result.slate).unwrap();
         let tx = result.slate.tx.unwrap();
         let fee: u64 = result.slate.fee_fields.fee(0);
@@ -905,21 +1354,17 @@ mod test {
     #[test]
     #[should_panic(expected = "No inputs provided")]
     fn test_spend_coin_no_inputs() {
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let fund_value = grin_to_nanogrin(2);
-        core.spend_coins(vec![], fund_value, 0, 2, 2).unwrap();
+        core.spend_coins(vec![], fund_value, KernelLock::NONE, 2, 2).unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid parameters for fund_value provided")]
     fn test_spend_coins_invalid_fundvalue() {
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let fund_value = 0;
@@ -932,15 +1377,13 @@ mod test {
             blinding_factor: serialize_secret_key(&input_bf),
             value: input_val,
         };
-        core.spend_coins(vec![coin], fund_value, 0, 2, 2).unwrap();
+        core.spend_coins(vec![coin], fund_value, KernelLock::NONE, 2, 2).unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Spend coins function failed, input coins do not have enough value")]
     fn test_spend_coins_too_little_input_funds() {
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let fund_value = grin_to_nanogrin(1);
@@ -953,15 +1396,13 @@ mod test {
             blinding_factor: serialize_secret_key(&input_bf),
             value: input_val,
         };
-        core.spend_coins(vec![coin], fund_value, 0, 2, 2).unwrap();
+        core.spend_coins(vec![coin], fund_value, KernelLock::NONE, 2, 2).unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Spend coins function failed, duplicate input coins provided")]
     fn test_spend_coins_duplicate_inputs() {
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let fund_value = grin_to_nanogrin(1);
@@ -979,7 +1420,7 @@ mod test {
             blinding_factor: serialize_secret_key(&input_bf),
             value: input_val,
         };
-        core.spend_coins(vec![coin, coin2], fund_value, 0, 2, 2)
+        core.spend_coins(vec![coin, coin2], fund_value, KernelLock::NONE, 2, 2)
             .unwrap();
     }
 
@@ -988,9 +1429,7 @@ mod test {
         // Should create an updated partially signed pre-transaction
         let str_slate = r#"{"ver":"4:3","id":"0ef39863-c759-44de-892c-538826e3a8f8","sta":"S1","off":"d3c5484ee792e95b9c83583154a4b6c9df31cb3b3c46d080b35841e809e9d02d","amt":"2000000000","fee":"23000000","sigs":[{"xs":"02a4c554bebf29b4361a582dfcb689cf08472673d2c71df8416ed3d4352a4f5f4e","nonce":"034bdb3f5d6dd8a08edaf86722cf14214908c527725f7a0298428cb82e76724dbf"}],"coms":[{"c":"08a6d28ddfc43a95b391cac473d6778d29f973f0b2886b4768aed393502936d82b"},{"c":"081046ca3d0fa2d298c855de4a7454fd5e537fd21674c3b6d3f82bc5884c54b5a7","p":"05c7e1be11bd3358cbad8931a176a099a220ff553eb8eea8b43da0297702486af5260b404f0f9fa66e808a73a1518bf3af3751c73e162a7a80fd7f57574c176e0486bfcd4055e7f9dd8ab2ceacc2baeae25f78bce79058338f38cc0e5b8624f599056153a14250e134bfd78e95a8cffa718c71dbe3fa39d5fa0c177be9258f96832e12cc7e37d0fde54d1012cf3a64c1ab913ebfdeb0790a6e4b78eaff7db9f205fd57f9603f7736a6babd37036ed47f69a472c9ee9ece15c1bb32fdbabfaf3afe148cb16e4fcf6d6ba1945b5dc3e488ad28745f0283468efe901fb8f4c328b178d532aa99fcb3132f8d0d4bc2a91a106ff97395c2fc6414799f06bd839de8883d9fcca6a4f62fe08ac9350283db0590614264458626e05549bf2ffd1ffe4ab0a526f9677afb0d92efa452d760145e5a72142d19cb5715ddfcb061579c588192a1183dad37eeea538726a9f253a2ef7687a9b5b600154f04f51766403a03d7a4aa1703ed63dc67df48b3addcbc3bd7285aebc6b153b747992f82f43aabb0246f04f3d3ae355c91860c61f464a46cf32d68ade9f8cb9b60eb86a8915a86c426ff002552c4ce179ccdbceaa9005d706dc735157091b1af914ea1c69e4eee7aeaeabfeb028b17ef345ca8dc325fe8d7e82cdf19eeb1d5153a1dc03ddd343685cce6d915d71a24ddfbe156cce1c3630513aa426c693c0f5e6d290511e6b37b66a3d7ad2e22ddf0656c3a56c7a48edcac51cd55ff913aa311e9a1057573fae3e7b3c91ccc52813741cdec72bb3be1ff592cbdc42511ddda390dea7e9fd5fdd38c2d13e7dc6aabd12ac67d8e6ea2625c0a0444f9215113627f637434febb4f364c3e7ef9dbe202e9540f5a42d7aa30db39e4f96074491d6294bfa941fd150d08336a6a6aad1e057da6363ecb11313532e1ea5328283148a5dfca277ae516e09f69c17344eb41"}]}"#;
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let slateid = slate.id;
@@ -1008,9 +1447,7 @@ mod test {
         // Should create an updated partially signed pre-transaction
         let str_slate = r#"{"ver":"4:3","id":"0ef39863-c759-44de-892c-538826e3a8f8","sta":"S1","off":"d3c5484ee792e95b9c83583154a4b6c9df31cb3b3c46d080b35841e809e9d02d","amt":"2000000000","fee":"23000000","sigs":[{"xs":"02a4c554bebf29b4361a582dfcb689cf08472673d2c71df8416ed3d4352a4f5f4e","nonce":"034bdb3f5d6dd8a08edaf86722cf14214908c527725f7a0298428cb82e76724dbf"}],"coms":[{"c":"08a6d28ddfc43a95b391cac473d6778d29f973f0b2886b4768aed393502936d82b"},{"c":"081046ca3d0fa2d298c855de4a7454fd5e537fd21674c3b6d3f82bc5884c54b5a7","p":"05c7e1be11bd3358cbad8931a176a099a220ff553eb8eea8b43da0297702486af5260b404f0f9fa66e808a73a1518bf3af3751c73e162a7a80fd7f57574c176e0486bfcd4055e7f9dd8ab2ceacc2baeae25f78bce79058338f38cc0e5b8624f599056153a14250e134bfd78e95a8cffa718c71dbe3fa39d5fa0c177be9258f96832e12cc7e37d0fde54d1012cf3a64c1ab913ebfdeb0790a6e4b78eaff7db9f205fd57f9603f7736a6babd37036ed47f69a472c9ee9ece15c1bb32fdbabfaf3afe148cb16e4fcf6d6ba1945b5dc3e488ad28745f0283468efe901fb8f4c328b178d532aa99fcb3132f8d0d4bc2a91a106ff97395c2fc6414799f06bd839de8883d9fcca6a4f62fe08ac9350283db0590614264458626e05549bf2ffd1ffe4ab0a526f9677afb0d92efa452d760145e5a72142d19cb5715ddfcb061579c588192a1183dad37eeea538726a9f253a2ef7687a9b5b600154f04f51766403a03d7a4aa1703ed63dc67df48b3addcbc3bd7285aebc6b153b747992f82f43aabb0246f04f3d3ae355c91860c61f464a46cf32d68ade9f8cb9b60eb86a8915a86c426ff002552c4ce179ccdbceaa9005d706dc735157091b1af914ea1c69e4eee7aeaeabfeb028b17ef345ca8dc325fe8d7e82cdf19eeb1d5153a1dc03ddd343685cce6d915d71a24ddfbe156cce1c3630513aa426c693c0f5e6d290511e6b37b66a3d7ad2e22ddf0656c3a56c7a48edcac51cd55ff913aa311e9a1057573fae3e7b3c91ccc52813741cdec72bb3be1ff592cbdc42511ddda390dea7e9fd5fdd38c2d13e7dc6aabd12ac67d8e6ea2625c0a0444f9215113627f637434febb4f364c3e7ef9dbe202e9540f5a42d7aa30db39e4f96074491d6294bfa941fd150d08336a6a6aad1e057da6363ecb11313532e1ea5328283148a5dfca277ae516e09f69c17344eb42"}]}"#;
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         core.recv_coins(slate, 600).unwrap();
@@ -1019,20 +1456,18 @@ mod test {
     #[test]
     fn test_fin_tx() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let str_slate = r#"{"ver":"4:3","id":"0ef39863-c759-44de-892c-538826e3a8f8","sta":"S1","off":"d3c5484ee792e95b9c83583154a4b6c9df31cb3b3c46d080b35841e809e9d02d","amt":"2000000000","fee":"23000000","sigs":[{"xs":"02a4c554bebf29b4361a582dfcb689cf08472673d2c71df8416ed3d4352a4f5f4e","nonce":"034bdb3f5d6dd8a08edaf86722cf14214908c527725f7a0298428cb82e76724dbf"},{"xs":"022d6700b009ac43b1ce52bc487728744849ce772f2fc305a8c474fa3a62870e89","nonce":"02933e2e529efe010269a0d8bb2925ba127b66deb7bae25a94aaa0a8d665e61db4","part":"b41de665d6a8a0aa945ae2bab7de667b12ba2529bbd8a0690201fe9e522e3e933bcadbfff1e9b27b6a404f8cfe94d338b9c68618ed56fa81a93bb27de79a7ed8"}],"coms":[{"c":"08a6d28ddfc43a95b391cac473d6778d29f973f0b2886b4768aed393502936d82b"},{"c":"08e0534f3747d17221dc19e285694eb018af14727896c219ee5663e9d7c683fbec","p":"5effa9d9e66e983a8f48376113984eb18796c9ac9ac19097b6f3c5012b5d00acfe22d4a5f65357ca8e3decfaaf86cb4cefb6b7185db92643692f872eec8fb5b3074f07ead20fe14c8aec48c22aedc5459b77d9591af672e97bac752f5137f47c1799952fdc5a8ae361dccece1cc9c838e9817a0c8b9691d2cadc83c436acfffdffed7455f45383bc31a19fa90b8d2d32131dbedb25736f3fd7c1e00cb5483c68a121f8f53a2e6a0b54aa6a7adcfc1e2ca20b85be0b9663535a75bbf00e32f0c0e6cdcf5f28c70e3d1cddf9fbf35c63f9206d95d347c7cc2576ccda5832bfac7bc40fa86eb1f9a2a32089d4c2f542d8129ea4360e2e1a6ea02057aa244b4401bf0ec86732bba4c8be97c2e9fb14eb4eaeba237d9d2f35070430381f1cbc59190f7e58c5b594de7cf4af4428ef024874a569bed4eb933d5ec830937e7f5de31dfd9b103a94b878798b5a6fc9093c16f81366fa67ecf9ee9b2455a4a5e8d51d1d02d25001d7e1da14f56c7fd3d8df60aa87af19ef25a549f1f3450e5c444dc8010c8d5db63f9db66720112eca236186ab4ed45f9ab9da740b7e2233b87b473458a90fb0a1b35295753c003e39d79648add3542473e5e7dce0c1c0fc7bd0d8d039ec9fc80b61aabdfbfb829234ad73d365daefc3cdd4a4014dc4170f0254be7e5b0432e0900e8d3ef41e8a3c97702d8354e781110b8bd9e85258af1d55f2a1697073be44b0cdc774b93848d245e6702b883ec9a1b3a504838bba08f059c14d89f54a63af84e57cfe19ae634fb84c921fe892923443b6bb9766837313c62282633afd3674066bfc5f10578638800277e43426ee953f6fd419656a7fc8eeb857f8f70e0eccb84ac0596915404087885a3a254362f08a308dd14ab9b2377c28505bc9c5e7daae960c190e455b8c85d68070aadb4b2dfb90ab3a8f886cee527c53be9e2665962c"},{"c":"081046ca3d0fa2d298c855de4a7454fd5e537fd21674c3b6d3f82bc5884c54b5a7","p":"05c7e1be11bd3358cbad8931a176a099a220ff553eb8eea8b43da0297702486af5260b404f0f9fa66e808a73a1518bf3af3751c73e162a7a80fd7f57574c176e0486bfcd4055e7f9dd8ab2ceacc2baeae25f78bce79058338f38cc0e5b8624f599056153a14250e134bfd78e95a8cffa718c71dbe3fa39d5fa0c177be9258f96832e12cc7e37d0fde54d1012cf3a64c1ab913ebfdeb0790a6e4b78eaff7db9f205fd57f9603f7736a6babd37036ed47f69a472c9ee9ece15c1bb32fdbabfaf3afe148cb16e4fcf6d6ba1945b5dc3e488ad28745f0283468efe901fb8f4c328b178d532aa99fcb3132f8d0d4bc2a91a106ff97395c2fc6414799f06bd839de8883d9fcca6a4f62fe08ac9350283db0590614264458626e05549bf2ffd1ffe4ab0a526f9677afb0d92efa452d760145e5a72142d19cb5715ddfcb061579c588192a1183dad37eeea538726a9f253a2ef7687a9b5b600154f04f51766403a03d7a4aa1703ed63dc67df48b3addcbc3bd7285aebc6b153b747992f82f43aabb0246f04f3d3ae355c91860c61f464a46cf32d68ade9f8cb9b60eb86a8915a86c426ff002552c4ce179ccdbceaa9005d706dc735157091b1af914ea1c69e4eee7aeaeabfeb028b17ef345ca8dc325fe8d7e82cdf19eeb1d5153a1dc03ddd343685cce6d915d71a24ddfbe156cce1c3630513aa426c693c0f5e6d290511e6b37b66a3d7ad2e22ddf0656c3a56c7a48edcac51cd55ff913aa311e9a1057573fae3e7b3c91ccc52813741cdec72bb3be1ff592cbdc42511ddda390dea7e9fd5fdd38c2d13e7dc6aabd12ac67d8e6ea2625c0a0444f9215113627f637434febb4f364c3e7ef9dbe202e9540f5a42d7aa30db39e4f96074491d6294bfa941fd150d08336a6a6aad1e057da6363ecb11313532e1ea5328283148a5dfca277ae516e09f69c17344eb41"}]}"#;
         let sk = deserialize_secret_key(
             &String::from("4f9851e6252daec8a0cec6e16ee16184e0da5024f5cc3dae49096bc778483594"),
             &core.secp,
-        );
+        ).unwrap();
         let nonce = deserialize_secret_key(
             &String::from("01c41476c59be2bdf5f88e2c43aa5b2133c6b38f241754b19c167912e6df2fb3"),
             &core.secp,
-        );
+        ).unwrap();
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
         let fin_slate = core.fin_tx(slate, &sk, &nonce, true, None, None).unwrap();
         let ser = serde_json::to_string(&fin_slate).unwrap();
@@ -1051,20 +1486,18 @@ mod test {
     #[should_panic(expected = "Failed to verify outputcoin rangeproof: InvalidRangeProof")]
     fn test_fin_tx_invalid_rproof() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let str_slate = r#"{"ver":"4:3","id":"0ef39863-c759-44de-892c-538826e3a8f8","sta":"S1","off":"d3c5484ee792e95b9c83583154a4b6c9df31cb3b3c46d080b35841e809e9d02d","amt":"2000000000","fee":"23000000","sigs":[{"xs":"02a4c554bebf29b4361a582dfcb689cf08472673d2c71df8416ed3d4352a4f5f4e","nonce":"034bdb3f5d6dd8a08edaf86722cf14214908c527725f7a0298428cb82e76724dbf"},{"xs":"022d6700b009ac43b1ce52bc487728744849ce772f2fc305a8c474fa3a62870e89","nonce":"02933e2e529efe010269a0d8bb2925ba127b66deb7bae25a94aaa0a8d665e61db4","part":"b41de665d6a8a0aa945ae2bab7de667b12ba2529bbd8a0690201fe9e522e3e933bcadbfff1e9b27b6a404f8cfe94d338b9c68618ed56fa81a93bb27de79a7ed8"}],"coms":[{"c":"08a6d28ddfc43a95b391cac473d6778d29f973f0b2886b4768aed393502936d82b"},{"c":"08e0534f3747d17221dc19e285694eb018af14727896c219ee5663e9d7c683fbec","p":"5effa9d9e66e983a8f48376113984eb18796c9ac9ac19097b6f3c5012b5d00acfe22d4a5f65357ca8e3decfaaf86cb4cefb6b7185db92643692f872eec8fb5b3074f07ead20fe14c8aec48c22aedc5459b77d9591af672e97bac752f5137f47c1799952fdc5a8ae361dccece1cc9c838e9817a0c8b9691d2cadc83c436acfffdffed7455f45383bc31a19fa90b8d2d32131dbedb25736f3fd7c1e00cb5483c68a121f8f53a2e6a0b54aa6a7adcfc1e2ca20b85be0b9663535a75bbf00e32f0c0e6cdcf5f28c70e3d1cddf9fbf35c63f9206d95d347c7cc2576ccda5832bfac7bc40fa86eb1f9a2a32089d4c2f542d8129ea4360e2e1a6ea02057aa244b4401bf0ec86732bba4c8be97c2e9fb14eb4eaeba237d9d2f35070430381f1cbc59190f7e58c5b594de7cf4af4428ef024874a569bed4eb933d5ec830937e7f5de31dfd9b103a94b878798b5a6fc9093c16f81366fa67ecf9ee9b2455a4a5e8d51d1d02d25001d7e1da14f56c7fd3d8df60aa87af19ef25a549f1f3450e5c444dc8010c8d5db63f9db66720112eca236186ab4ed45f9ab9da740b7e2233b87b473458a90fb0a1b35295753c003e39d79648add3542473e5e7dce0c1c0fc7bd0d8d039ec9fc80b61aabdfbfb829234ad73d365daefc3cdd4a4014dc4170f0254be7e5b0432e0900e8d3ef41e8a3c97702d8354e781110b8bd9e85258af1d55f2a1697073be44b0cdc774b93848d245e6702b883ec9a1b3a504838bba08f059c14d89f54a63af84e57cfe19ae634fb84c921fe892923443b6bb9766837313c62282633afd3674066bfc5f10578638800277e43426ee953f6fd419656a7fc8eeb857f8f70e0eccb84ac0596915404087885a3a254362f08a308dd14ab9b2377c28505bc9c5e7daae960c190e455b8c85d68070aadb4b2dfb90ab3a8f886cee527c53be9e26659622"},{"c":"081046ca3d0fa2d298c855de4a7454fd5e537fd21674c3b6d3f82bc5884c54b5a7","p":"05c7e1be11bd3358cbad8931a176a099a220ff553eb8eea8b43da0297702486af5260b404f0f9fa66e808a73a1518bf3af3751c73e162a7a80fd7f57574c176e0486bfcd4055e7f9dd8ab2ceacc2baeae25f78bce79058338f38cc0e5b8624f599056153a14250e134bfd78e95a8cffa718c71dbe3fa39d5fa0c177be9258f96832e12cc7e37d0fde54d1012cf3a64c1ab913ebfdeb0790a6e4b78eaff7db9f205fd57f9603f7736a6babd37036ed47f69a472c9ee9ece15c1bb32fdbabfaf3afe148cb16e4fcf6d6ba1945b5dc3e488ad28745f0283468efe901fb8f4c328b178d532aa99fcb3132f8d0d4bc2a91a106ff97395c2fc6414799f06bd839de8883d9fcca6a4f62fe08ac9350283db0590614264458626e05549bf2ffd1ffe4ab0a526f9677afb0d92efa452d760145e5a72142d19cb5715ddfcb061579c588192a1183dad37eeea538726a9f253a2ef7687a9b5b600154f04f51766403a03d7a4aa1703ed63dc67df48b3addcbc3bd7285aebc6b153b747992f82f43aabb0246f04f3d3ae355c91860c61f464a46cf32d68ade9f8cb9b60eb86a8915a86c426ff002552c4ce179ccdbceaa9005d706dc735157091b1af914ea1c69e4eee7aeaeabfeb028b17ef345ca8dc325fe8d7e82cdf19eeb1d5153a1dc03ddd343685cce6d915d71a24ddfbe156cce1c3630513aa426c693c0f5e6d290511e6b37b66a3d7ad2e22ddf0656c3a56c7a48edcac51cd55ff913aa311e9a1057573fae3e7b3c91ccc52813741cdec72bb3be1ff592cbdc42511ddda390dea7e9fd5fdd38c2d13e7dc6aabd12ac67d8e6ea2625c0a0444f9215113627f637434febb4f364c3e7ef9dbe202e9540f5a42d7aa30db39e4f96074491d6294bfa941fd150d08336a6a6aad1e057da6363ecb11313532e1ea5328283148a5dfca277ae516e09f69c17344eb41"}]}"#;
         let sk = deserialize_secret_key(
             &String::from("4f9851e6252daec8a0cec6e16ee16184e0da5024f5cc3dae49096bc778483594"),
             &core.secp,
-        );
+        ).unwrap();
         let nonce = deserialize_secret_key(
             &String::from("01c41476c59be2bdf5f88e2c43aa5b2133c6b38f241754b19c167912e6df2fb3"),
             &core.secp,
-        );
+        ).unwrap();
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
         core.fin_tx(slate, &sk, &nonce, true, None, None).unwrap();
     }
@@ -1073,20 +1506,18 @@ mod test {
     #[should_panic]
     fn test_fin_tx_invalid_secret() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let str_slate = r#"{"ver":"4:3","id":"0ef39863-c759-44de-892c-538826e3a8f8","sta":"S1","off":"d3c5484ee792e95b9c83583154a4b6c9df31cb3b3c46d080b35841e809e9d02d","amt":"2000000000","fee":"23000000","sigs":[{"xs":"02a4c554bebf29b4361a582dfcb689cf08472673d2c71df8416ed3d4352a4f5f4e","nonce":"034bdb3f5d6dd8a08edaf86722cf14214908c527725f7a0298428cb82e76724dbf"},{"xs":"022d6700b009ac43b1ce52bc487728744849ce772f2fc305a8c474fa3a62870e89","nonce":"02933e2e529efe010269a0d8bb2925ba127b66deb7bae25a94aaa0a8d665e61db4","part":"b41de665d6a8a0aa945ae2bab7de667b12ba2529bbd8a0690201fe9e522e3e933bcadbfff1e9b27b6a404f8cfe94d338b9c68618ed56fa81a93bb27de79a7ed8"}],"coms":[{"c":"08a6d28ddfc43a95b391cac473d6778d29f973f0b2886b4768aed393502936d82b"},{"c":"08e0534f3747d17221dc19e285694eb018af14727896c219ee5663e9d7c683fbec","p":"5effa9d9e66e983a8f48376113984eb18796c9ac9ac19097b6f3c5012b5d00acfe22d4a5f65357ca8e3decfaaf86cb4cefb6b7185db92643692f872eec8fb5b3074f07ead20fe14c8aec48c22aedc5459b77d9591af672e97bac752f5137f47c1799952fdc5a8ae361dccece1cc9c838e9817a0c8b9691d2cadc83c436acfffdffed7455f45383bc31a19fa90b8d2d32131dbedb25736f3fd7c1e00cb5483c68a121f8f53a2e6a0b54aa6a7adcfc1e2ca20b85be0b9663535a75bbf00e32f0c0e6cdcf5f28c70e3d1cddf9fbf35c63f9206d95d347c7cc2576ccda5832bfac7bc40fa86eb1f9a2a32089d4c2f542d8129ea4360e2e1a6ea02057aa244b4401bf0ec86732bba4c8be97c2e9fb14eb4eaeba237d9d2f35070430381f1cbc59190f7e58c5b594de7cf4af4428ef024874a569bed4eb933d5ec830937e7f5de31dfd9b103a94b878798b5a6fc9093c16f81366fa67ecf9ee9b2455a4a5e8d51d1d02d25001d7e1da14f56c7fd3d8df60aa87af19ef25a549f1f3450e5c444dc8010c8d5db63f9db66720112eca236186ab4ed45f9ab9da740b7e2233b87b473458a90fb0a1b35295753c003e39d79648add3542473e5e7dce0c1c0fc7bd0d8d039ec9fc80b61aabdfbfb829234ad73d365daefc3cdd4a4014dc4170f0254be7e5b0432e0900e8d3ef41e8a3c97702d8354e781110b8bd9e85258af1d55f2a1697073be44b0cdc774b93848d245e6702b883ec9a1b3a504838bba08f059c14d89f54a63af84e57cfe19ae634fb84c921fe892923443b6bb9766837313c62282633afd3674066bfc5f10578638800277e43426ee953f6fd419656a7fc8eeb857f8f70e0eccb84ac0596915404087885a3a254362f08a308dd14ab9b2377c28505bc9c5e7daae960c190e455b8c85d68070aadb4b2dfb90ab3a8f886cee527c53be9e2665962c"},{"c":"081046ca3d0fa2d298c855de4a7454fd5e537fd21674c3b6d3f82bc5884c54b5a7","p":"05c7e1be11bd3358cbad8931a176a099a220ff553eb8eea8b43da0297702486af5260b404f0f9fa66e808a73a1518bf3af3751c73e162a7a80fd7f57574c176e0486bfcd4055e7f9dd8ab2ceacc2baeae25f78bce79058338f38cc0e5b8624f599056153a14250e134bfd78e95a8cffa718c71dbe3fa39d5fa0c177be9258f96832e12cc7e37d0fde54d1012cf3a64c1ab913ebfdeb0790a6e4b78eaff7db9f205fd57f9603f7736a6babd37036ed47f69a472c9ee9ece15c1bb32fdbabfaf3afe148cb16e4fcf6d6ba1945b5dc3e488ad28745f0283468efe901fb8f4c328b178d532aa99fcb3132f8d0d4bc2a91a106ff97395c2fc6414799f06bd839de8883d9fcca6a4f62fe08ac9350283db0590614264458626e05549bf2ffd1ffe4ab0a526f9677afb0d92efa452d760145e5a72142d19cb5715ddfcb061579c588192a1183dad37eeea538726a9f253a2ef7687a9b5b600154f04f51766403a03d7a4aa1703ed63dc67df48b3addcbc3bd7285aebc6b153b747992f82f43aabb0246f04f3d3ae355c91860c61f464a46cf32d68ade9f8cb9b60eb86a8915a86c426ff002552c4ce179ccdbceaa9005d706dc735157091b1af914ea1c69e4eee7aeaeabfeb028b17ef345ca8dc325fe8d7e82cdf19eeb1d5153a1dc03ddd343685cce6d915d71a24ddfbe156cce1c3630513aa426c693c0f5e6d290511e6b37b66a3d7ad2e22ddf0656c3a56c7a48edcac51cd55ff913aa311e9a1057573fae3e7b3c91ccc52813741cdec72bb3be1ff592cbdc42511ddda390dea7e9fd5fdd38c2d13e7dc6aabd12ac67d8e6ea2625c0a0444f9215113627f637434febb4f364c3e7ef9dbe202e9540f5a42d7aa30db39e4f96074491d6294bfa941fd150d08336a6a6aad1e057da6363ecb11313532e1ea5328283148a5dfca277ae516e09f69c17344eb41"}]}"#;
         let sk = deserialize_secret_key(
             &String::from("4f9851e6252daec8a0cec6e16ee16184e0da5024f5cc3dae49096bc778483593"),
             &core.secp,
-        );
+        ).unwrap();
         let nonce = deserialize_secret_key(
             &String::from("01c41476c59be2bdf5f88e2c43aa5b2133c6b38f241754b19c167912e6df2fb3"),
             &core.secp,
-        );
+        ).unwrap();
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
         core.fin_tx(slate, &sk, &nonce, true, None, None).unwrap();
     }
@@ -1094,9 +1525,7 @@ mod test {
     #[test]
     fn test_full_tx_flow() {
         let fund_value = grin_to_nanogrin(2);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
 
@@ -1111,7 +1540,7 @@ mod test {
         };
 
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let result1 = core.spend_coins(vec![coin], fund_value, 0, 2, 2).unwrap();
+        let result1 = core.spend_coins(vec![coin], fund_value, KernelLock::NONE, 2, 2).unwrap();
         println!(
             "sec_key: {} nonce: {}",
             serialize_secret_key(&result1.sig_key),
@@ -1138,9 +1567,7 @@ mod test {
     #[test]
     fn test_full_tx_flow_timelock() {
         let fund_value = grin_to_nanogrin(2);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
 
@@ -1156,7 +1583,7 @@ mod test {
         let timelock : u64 = 1052054;
 
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let result1 = core.spend_coins(vec![coin], fund_value, timelock, 2, 2).unwrap();
+        let result1 = core.spend_coins(vec![coin], fund_value, KernelLock::HEIGHT(timelock), 2, 2).unwrap();
         println!(
             "sec_key: {} nonce: {}",
             serialize_secret_key(&result1.sig_key),
@@ -1184,9 +1611,7 @@ mod test {
     fn test_full_flow_dspend() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
         let fund_value = grin_to_nanogrin(2);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
 
@@ -1210,9 +1635,9 @@ mod test {
             blinding_factor: serialize_secret_key(&bf_b),
             value: input_val,
         };
-        let result1 = core.spend_coins(vec![coin_a], fund_value, 711042, 2, 3).unwrap();
+        let result1 = core.spend_coins(vec![coin_a], fund_value, KernelLock::HEIGHT(711042), 2, 3).unwrap();
         let result2 = core
-            .d_spend_coins(vec![coin_b], result1.slate, fund_value, 711042)
+            .d_spend_coins(vec![coin_b], result1.slate, fund_value, KernelLock::HEIGHT(711042))
             .unwrap();
         let result3 = core.recv_coins(result2.slate, fund_value).unwrap();
         let result4 = core
@@ -1244,9 +1669,7 @@ mod test {
         set_local_chain_type(ChainTypes::AutomatedTesting);
         let fund_value = grin_to_nanogrin(2);
         let fee = tx_fee(1, 1, 1);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
 
@@ -1270,11 +1693,11 @@ mod test {
             blinding_factor: serialize_secret_key(&bf_b),
             value: input_val,
         };
-        let result1 = core.spend_coins(vec![coin_a], fund_value, 711042, 1, 3).unwrap();
+        let result1 = core.spend_coins(vec![coin_a], fund_value, KernelLock::HEIGHT(711042), 1, 3).unwrap();
         let slate1_str = serde_json::to_string(&result1.slate).unwrap();
         let slate1 = Slate::deserialize_upgrade(&slate1_str).unwrap();
         let result2 = core
-            .d_spend_coins(vec![coin_b], slate1, fund_value, 711042)
+            .d_spend_coins(vec![coin_b], slate1, fund_value, KernelLock::HEIGHT(711042))
             .unwrap();
         let result3 = core.recv_coins(result2.slate, fund_value).unwrap();
         let result4 = core
@@ -1306,12 +1729,122 @@ mod test {
     }
 
     #[test]
-    fn test_full_tx_flow_drecv() {
+    fn test_dspend_coins_nrd() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
+        set_local_nrd_enabled(true);
         let fund_value = grin_to_nanogrin(2);
-        let contents = fs::read_to_string("config/settings.json")
+        let fee = tx_fee(1, 1, 1);
+        let read_settings = offline_settings();
+        let factory = RequestFactory::new(None);
+        let mut core = GrinCore::new(read_settings.grin, factory);
+
+        // Create a shared input coin
+        let input_val = fund_value + fee;
+        let bf_a = create_secret_key(&mut core.rng, &core.secp);
+        let bf_b = create_secret_key(&mut core.rng, &core.secp);
+        let commit_a = core.secp.commit(input_val, bf_a.clone()).unwrap();
+        let commit_b = core.secp.commit(0, bf_b.clone()).unwrap();
+        let commit = core
+            .secp
+            .commit_sum(vec![commit_a, commit_b], vec![])
+            .unwrap();
+        let coin_a = MWCoin {
+            commitment: serialize_commitment(&commit),
+            blinding_factor: serialize_secret_key(&bf_a),
+            value: input_val,
+        };
+        let coin_b = MWCoin {
+            commitment: serialize_commitment(&commit),
+            blinding_factor: serialize_secret_key(&bf_b),
+            value: input_val,
+        };
+        // Relative heights are limited to one week of blocks
+        assert!(core.spend_coins(vec![coin_a.clone()], fund_value, KernelLock::NRD(0), 1, 3).is_err());
+        assert!(core.spend_coins(vec![coin_a.clone()], fund_value, KernelLock::NRD(10081), 1, 3).is_err());
+
+        let result1 = core.spend_coins(vec![coin_a], fund_value, KernelLock::NRD(60), 1, 3).unwrap();
+        let slate1_str = serde_json::to_string(&result1.slate).unwrap();
+        let slate1 = Slate::deserialize_upgrade(&slate1_str).unwrap();
+        assert_eq!(3, slate1.kernel_features);
+        assert_eq!(60, slate1.kernel_features_args.clone().unwrap().lock_height);
+        // The second spender refuses a different lock than expected
+        assert!(core.d_spend_coins(vec![coin_b.clone()], slate1.clone(), fund_value, KernelLock::HEIGHT(60)).is_err());
+        assert!(core.d_spend_coins(vec![coin_b.clone()], slate1.clone(), fund_value, KernelLock::NRD(61)).is_err());
+        let result2 = core
+            .d_spend_coins(vec![coin_b], slate1, fund_value, KernelLock::NRD(60))
             .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let result3 = core.recv_coins(result2.slate, fund_value).unwrap();
+        let result4 = core
+            .fin_tx(
+                result3.slate,
+                &result2.sig_key,
+                &result2.sig_nonce,
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+        let slate4_str = serde_json::to_string(&result4).unwrap();
+        let mut slate4 = Slate::deserialize_upgrade(&slate4_str).unwrap();
+        slate4.update_kernel().unwrap();
+        let fin_slate = core
+            .fin_tx(
+                slate4,
+                &result1.sig_key,
+                &result1.sig_nonce,
+                true,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(3, fin_slate.kernel_features);
+    }
+
+    #[test]
+    fn test_dspend_coins_inflated_fee() {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let fund_value = grin_to_nanogrin(2);
+        let fee = tx_fee(1, 1, 1);
+        let read_settings = offline_settings();
+        let factory = RequestFactory::new(None);
+        let mut core = GrinCore::new(read_settings.grin, factory);
+
+        // Create a shared input coin which can pay a higher fee
+        let input_val = fund_value + 2 * fee;
+        let bf_a = create_secret_key(&mut core.rng, &core.secp);
+        let bf_b = create_secret_key(&mut core.rng, &core.secp);
+        let commit_a = core.secp.commit(input_val, bf_a.clone()).unwrap();
+        let commit_b = core.secp.commit(0, bf_b.clone()).unwrap();
+        let commit = core
+            .secp
+            .commit_sum(vec![commit_a, commit_b], vec![])
+            .unwrap();
+        let coin_a = MWCoin {
+            commitment: serialize_commitment(&commit),
+            blinding_factor: serialize_secret_key(&bf_a),
+            value: input_val,
+        };
+        let coin_b = MWCoin {
+            commitment: serialize_commitment(&commit),
+            blinding_factor: serialize_secret_key(&bf_b),
+            value: input_val,
+        };
+        let fee_base = core.fee_base;
+        core.fee_base = 2 * fee_base;
+        let result1 = core.spend_coins(vec![coin_a], fund_value, KernelLock::NONE, 1, 3).unwrap();
+        assert_eq!(2 * fee, result1.slate.fee_fields.fee(0));
+        // A peer using the agreed fee base refuses the inflated fee
+        core.fee_base = fee_base;
+        assert!(core.d_spend_coins(vec![coin_b.clone()], result1.slate.clone(), fund_value, KernelLock::NONE).is_err());
+        core.fee_base = 2 * fee_base;
+        assert!(core.d_spend_coins(vec![coin_b], result1.slate, fund_value, KernelLock::NONE).is_ok());
+    }
+
+    #[test]
+    fn test_full_tx_flow_drecv() {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let fund_value = grin_to_nanogrin(2);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
 
@@ -1325,10 +1858,10 @@ mod test {
             value: inp_val,
         };
 
-        let result1 = core.spend_coins(vec![coin], fund_value, 0, 2, 3).unwrap();
-        let result2 = core.drecv_coins_r1(result1.slate, fund_value).unwrap();
+        let result1 = core.spend_coins(vec![coin], fund_value, KernelLock::NONE, 2, 3).unwrap();
+        let result2 = core.drecv_coins_r1(result1.slate, fund_value, None).unwrap();
         let result3 = core
-            .drecv_coins_r2(result2.slate, fund_value, result2.prf_ctx)
+            .drecv_coins_r2(result2.slate, fund_value, result2.prf_ctx, None)
             .unwrap();
         let recv_coins_res = result3.0;
         let prf_ctx = result3.1;
@@ -1339,6 +1872,7 @@ mod test {
                 result2.out_key_blind,
                 result2.prf_nonce,
                 result2.sig_nonce,
+                None,
             )
             .unwrap();
         let fin_slate = core
@@ -1359,9 +1893,7 @@ mod test {
     fn test_full_flow_apt() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
         let fund_value = grin_to_nanogrin(2);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
 
@@ -1385,12 +1917,12 @@ mod test {
             blinding_factor: serialize_secret_key(&bf_b),
             value: input_val,
         };
-        let result1 = core.spend_coins(vec![coin_a], fund_value, 0, 2, 3).unwrap();
+        let result1 = core.spend_coins(vec![coin_a], fund_value, KernelLock::NONE, 2, 3).unwrap();
         let result2 = core
-            .d_spend_coins(vec![coin_b], result1.slate, fund_value, 0)
+            .d_spend_coins(vec![coin_b], result1.slate, fund_value, KernelLock::NONE)
             .unwrap();
         // Hide a secret x
-        let x_btc = deserialize_priv_key(&String::from("cNScs27pnjxb4GbVbX2124pPUVSPLbjzDtV1frYFZhh9k4zr6uN9"));
+        let x_btc = deserialize_priv_key(&String::from("cNScs27pnjxb4GbVbX2124pPUVSPLbjzDtV1frYFZhh9k4zr6uN9")).unwrap();
         let x = grin_sk_from_btc_sk(&x_btc, &core.secp);
         let pub_x = PublicKey::from_secret_key(&core.secp, &x).unwrap();
         let result3 = core
@@ -1422,7 +1954,7 @@ mod test {
         let bob_apt_sig = result3.slate.participant_data.get(2).unwrap().part_sig.unwrap();
 
         // Extract x from final transaction
-        let x_2 = core.ext_witness(bob_recv_sig, bob_apt_sig);
+        let x_2 = core.ext_witness(bob_recv_sig, bob_apt_sig).unwrap();
         let x_2_btc = private_key_from_grin_sk(&x_2);
         assert_eq!(x_btc, x_2_btc);
     }
@@ -1430,9 +1962,7 @@ mod test {
     #[test]
     fn read_from_slatepack() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let factory = RequestFactory::new(None);
         let mut core = GrinCore::new(read_settings.grin, factory);
         let slatepack_str = String::from(
@@ -1481,9 +2011,7 @@ mod test {
               }"#)
         };
         let factory = RequestFactory::new(Some(stub_response));
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let mut core = GrinCore::new(read_settings.grin, factory);
         let height = core.get_block_height().unwrap();
         assert_eq!(697466, height);
@@ -1503,20 +2031,48 @@ mod test {
               }"#)
         };
         let factory = RequestFactory::new(Some(stub_response));
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let mut core = GrinCore::new(read_settings.grin, factory);
         let r = core.get_block_height();
         assert_eq!(Some(String::from("Some failure")), r.err());
     }
 
+    #[test]
+    fn test_get_output() {
+        let stub_response = HttpResponse {
+            status : 200,
+            content : String::from(r#"{
+                "id": "mw-btc-swap",
+                "jsonrpc": "2.0",
+                "result": {
+                  "Ok": [
+                    {
+                      "output_type": "Transaction",
+                      "commit": "09cbbb7a9a4ef4f5f1d1c3e6a8ae2d0e7d1f8b6c7a3b3e1c0a4f5b6c7d8e9f0a1b",
+                      "spent": false,
+                      "proof": null,
+                      "proof_hash": "ab",
+                      "block_height": 697460,
+                      "merkle_proof": null,
+                      "mmr_index": 100
+                    }
+                  ]
+                }
+              }"#)
+        };
+        let factory = RequestFactory::new(Some(stub_response));
+        let read_settings = offline_settings();
+        let mut core = GrinCore::new(read_settings.grin, factory);
+        let out = core.get_output("09cbbb7a9a4ef4f5f1d1c3e6a8ae2d0e7d1f8b6c7a3b3e1c0a4f5b6c7d8e9f0a1b").unwrap().unwrap();
+        assert_eq!(Some(697460), out.block_height);
+        assert!(!out.spent);
+        assert!(core.get_output("08aa").unwrap().is_none());
+    }
+
     #[test]
     fn test_push_transaction() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let stub_response = HttpResponse {
             status : 200,
             content : String::from(r#"{
@@ -1533,11 +2089,11 @@ mod test {
         let sk = deserialize_secret_key(
             &String::from("4f9851e6252daec8a0cec6e16ee16184e0da5024f5cc3dae49096bc778483594"),
             &core.secp,
-        );
+        ).unwrap();
         let nonce = deserialize_secret_key(
             &String::from("01c41476c59be2bdf5f88e2c43aa5b2133c6b38f241754b19c167912e6df2fb3"),
             &core.secp,
-        );
+        ).unwrap();
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
         let fin_slate = core.fin_tx(slate, &sk, &nonce, true, None, None).unwrap();
 
@@ -1549,10 +2105,8 @@ mod test {
     #[test]
     fn test_tx_deserialization() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
         let factory = RequestFactory::new(None);
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let mut core = GrinCore::new(read_settings.grin, factory);
         let json = r#"{"ver":"4:3","id":"c880c23d-50df-43fa-ab45-17d6253a9ba3","sta":"S1","off":"659513de52602ae2160865ab18d3b1f07a5afba314ef2f5223b1540e0fc36053","num_parts":3,"amt":"87500000","fee":"12500000","feat":2,"sigs":[{"xs":"02992bb7e5b1df6e892829aec8b8ead73afa6f0748af920720b3a5e4543f7abd79","nonce":"031b570d25adeedf046f8814d2f8f490d29d1966183eb2241d0c8dc75cd99ff5ff","part":"fff59fd95cc78d0c1d24b23e1866199dd290f4f8d214886f04dfeead250d571b39c522ae636072867ebfefd41a0ac3be1b0a2ad913c0188d804b6ad3828d94a7"},{"xs":"02670392c6b08621c81a1432d18e10f87ff60306451ed8704cdd5f4396b9f28645","nonce":"030491c60966dde7eb3ee21c0f5c263cfde8038389bddcb70603f52fb346c2fa48","part":"48fac246b32ff50306b7dcbd898303e8fd3c265c0f1ce23eebe7dd6609c691046cb3398e2ce5a9ea4d358ebfce904e2d7a780391828a33ccc96bb2f72dfc0e27"},{"xs":"024c2cdef09fd7a9231263b593696de798e76857e9ad3061da522d852f78ff23bf","nonce":"02165e981a6428207e70eb86dda47fe73d3e14a4467115f3d55e221262bef72f34","part":"342ff7be6212225ed5f3157146a4143e3de77fa4dd86eb707e2028641a985e169a8ccdd9f407b7fdf3ad10db5db9f5532e8f7aaa66efefeed9910fea3a032fc6"}],"coms":[{"c":"08ca68a97be6bcad3261eb4f629fd812a92c49a0f49782fdb5a9ee3312ea021e07"},{"c":"09fd68cde4bdbfea94d50631b2e588eb4b5e6d03cc2567aba24d9c53f24bfd8579","p":"18335bdd5bab540f5269d977cd24777f836ccf47a073d68ece68ef2108ec956eed9368ae45f26117c2be51f30ab466ee638e29e6624dc2768de7dcf454878a330d30057ab6b14753901b9634c9ea1197fcf83f8e6c4107a7dac537f0a38b634693a91292b617fa8b12832eeef8118b36bbd59d9ff658e31266989a874698c63cdd74709f18d6051c4bc90189d4adcee10835faecc6a17e2d7e467874289c1433b2405cbfbfc6b400c5a018fda2ecc47b9afc67070cae75476e493f00619f89d12483acacf9435b806b8d6b15e4f1e339b3ec24d4828b47a54c55d179ed3a77be12ec7db096fb656a77400e560d183d9696856acf4c81ffdb832a19b7670b9ead46b908a4438ac9fb86945cdb818c4bbb6c744fd3d09404fb7bdb5641d3e1976f22316e2f7b1ddf43f6ac3908d988d4aeded248776a36f491860b97e783ad35501733cf409e97bd8f70d0f3392a174d66dfb433826b6211ce646442b1f7104a85ff6c0176cb0bd3b3dea4258a2680ed3ab60e3669d0d8931adcd35426c092d944c5eda4fd2876ee687686278cbd8f4331da94b1b7d9a4abdfe8f08b374e421e5e98ea63d17486a433c0092d699ca593c1f68f7955eab8748606395f3b0c37e666a3af0d6ed185be39528e7155af715427cdab610eab4abf021c3c31f50dd41be1ec1dc8338267fb3b590680a1968a533897a7a3d903b9f497d37544465feda8b258c25cbdbe2f7575617642df37a488a30f5b2a6154869f41fabc2ade3627e3403274d38c093b8c9eb1510edd12d4f3df6c94018ca82996ef3ffca8731477da47d192184807266d139f02dd19a110931c4608ca61c59308e22273b2fefe3baa6364bd89f5bba2a878843447dd528cf996189170d5839d62901940605ce1adb3d289380f318075a144c4900cd3e8db065d6bf5d71e3958bc21c41b7b6b237b6138a71807"}],"feat_args":{"lock_hgt":712110}}"#;
         let mut slate = Slate::deserialize_upgrade(&json).unwrap();
@@ -1566,9 +2120,7 @@ mod test {
     #[should_panic]
     fn test_push_invalid_transaction() {
         set_local_chain_type(ChainTypes::AutomatedTesting);
-        let contents = fs::read_to_string("config/settings.json")
-            .unwrap();
-        let read_settings = settings::Settings::parse_json_string(&contents);
+        let read_settings = offline_settings();
         let stub_response = HttpResponse {
             status : 200,
             content : String::from(r#"{
@@ -1586,11 +2138,11 @@ mod test {
         let sk = deserialize_secret_key(
             &String::from("4f9851e6252daec8a0cec6e16ee16184e0da5024f5cc3dae49096bc778483594"),
             &core.secp,
-        );
+        ).unwrap();
         let nonce = deserialize_secret_key(
             &String::from("01c41476c59be2bdf5f88e2c43aa5b2133c6b38f241754b19c167912e6df2fb3"),
             &core.secp,
-        );
+        ).unwrap();
         let slate = Slate::deserialize_upgrade(&str_slate).unwrap();
         let fin_slate = core.fin_tx(slate, &sk, &nonce, true, None, None).unwrap();
 
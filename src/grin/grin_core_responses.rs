@@ -1,3 +1,4 @@
+use grin_core::core::Transaction;
 use serde::{Serialize, Deserialize};
 
 // {
@@ -37,6 +38,40 @@ pub struct GetTipResult {
     pub Ok : GetTipResultOk
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetOutputsResult {
+    pub Ok : Vec<OutputPrintable>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetKernelResult {
+    pub Ok : Option<LocatedTxKernel>
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetUnconfirmedTransactionsResult {
+    pub Ok : Vec<PoolEntry>
+}
+
+// Transaction in the pool of the node, the source and the time it was received at are not needed
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PoolEntry {
+    pub tx : Transaction
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LocatedTxKernel {
+    pub height : u64,
+    pub mmr_index : u64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OutputPrintable {
+    pub commit : String,
+    pub spent : bool,
+    pub block_height : Option<u64>
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetTipResultOk {
     pub height : u64,
@@ -1,20 +1,21 @@
-use grin_core::{core::FeeFields, libtx::tx_fee};
+use grin_core::{core::FeeFields, core::Transaction};
 use grin_keychain::Identifier;
 use grin_util::secp::{Signature, pedersen::Commitment};
 use grin_util::secp::SecretKey;
 use grin_util::secp::{
+    constants::PEDERSEN_COMMITMENT_SIZE,
     key::ZERO_KEY,
     pedersen::{ProofMessage, RangeProof},
     ContextFlag, PublicKey, Secp256k1,
 };
 use grin_wallet_libwallet::{Context, Slate};
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use serde::{Serialize, Deserialize};
 use bitcoin::PublicKey as BTCPublicKey;
 use bitcoin::PrivateKey as BTCPrivKey;
 
 
-use crate::{bitcoin::btcroutines::create_private_key, constants::NANO_GRIN, util::get_os_rng};
+use crate::{bitcoin::btcroutines::create_private_key, constants::NANO_GRIN};
 
 pub struct MPBPContext {
     t_1: PublicKey,
@@ -22,7 +23,7 @@ pub struct MPBPContext {
     pub amount: u64,
     shared_nonce: SecretKey,
     tau_x: SecretKey,
-    rng: OsRng,
+    rng: SwapRng,
     secp: Secp256k1,
     pub commit: Commitment
 }
@@ -38,8 +39,7 @@ struct SerMPCtx {
 }
 
 impl MPBPContext {
-    pub fn new(shared_nonce: SecretKey, amount: u64, com: Commitment) -> MPBPContext {
-        let rng = get_os_rng();
+    pub fn new(shared_nonce: SecretKey, amount: u64, com: Commitment, rng: SwapRng) -> MPBPContext {
         let secp = Secp256k1::with_caps(ContextFlag::Commit);
         MPBPContext {
             t_1: PublicKey::new(),
@@ -65,50 +65,55 @@ impl MPBPContext {
         serde_json::to_string(&ser_obj).unwrap()
     }
 
-    pub fn from_string(str : &String) -> MPBPContext {
-        let rng = get_os_rng();
+    pub fn from_string(str : &String, rng : SwapRng) -> Result<MPBPContext, String> {
         let secp = Secp256k1::with_caps(ContextFlag::Commit);
         let ser_obj : SerMPCtx = serde_json::from_str(str)
-            .unwrap();
-        MPBPContext {
-            t_1 : deserialize_grin_pub_key(&ser_obj.t_1, &secp),
-            t_2 : deserialize_grin_pub_key(&ser_obj.t_2, &secp),
+            .map_err(|e| format!("Failed to parse the rangeproof context: {}", e))?;
+        Ok(MPBPContext {
+            t_1 : deserialize_grin_pub_key(&ser_obj.t_1, &secp)?,
+            t_2 : deserialize_grin_pub_key(&ser_obj.t_2, &secp)?,
             amount : ser_obj.amount,
-            shared_nonce : deserialize_secret_key(&ser_obj.shared_nonce, &secp),
-            tau_x : deserialize_secret_key(&ser_obj.tau_x, &secp),
+            shared_nonce : deserialize_secret_key(&ser_obj.shared_nonce, &secp)?,
+            tau_x : deserialize_secret_key(&ser_obj.tau_x, &secp)?,
             rng : rng,
             secp : secp,
-            commit : deserialize_commitment(&ser_obj.commit)
-        }
+            commit : deserialize_commitment(&ser_obj.commit)?
+        })
     }
 
-    pub fn add_commit(&mut self, c2 : Commitment) {
+    pub fn add_commit(&mut self, c2 : Commitment) -> Result<(), String> {
         self.commit = self
             .secp
             .commit_sum(vec![self.commit,c2], vec![])
-            .unwrap()
+            .map_err(|e| format!("Failed to add the commitments: {}", e))?;
+        Ok(())
     }
 
-    fn add_t_1(&mut self, t_1: PublicKey) {
+    fn add_t_1(&mut self, t_1: PublicKey) -> Result<(), String> {
         if !self.t_1.is_valid() {
             self.t_1 = t_1.clone();
         }
         else {
-            self.t_1 = PublicKey::from_combination(&self.secp, vec![&self.t_1.clone(), &t_1]).unwrap();
+            self.t_1 = PublicKey::from_combination(&self.secp, vec![&self.t_1.clone(), &t_1])
+                .map_err(|e| format!("Failed to add T1: {}", e))?;
         }
+        Ok(())
     }
 
-    fn add_t_2(&mut self, t_2: PublicKey) {
+    fn add_t_2(&mut self, t_2: PublicKey) -> Result<(), String> {
         if !self.t_2.is_valid() {
             self.t_2 = t_2.clone();
         }
         else {
-            self.t_2 = PublicKey::from_combination(&self.secp, vec![&self.t_2.clone(), &t_2]).unwrap();
+            self.t_2 = PublicKey::from_combination(&self.secp, vec![&self.t_2.clone(), &t_2])
+                .map_err(|e| format!("Failed to add T2: {}", e))?;
         }
+        Ok(())
     }
 
-    fn add_tau_x(&mut self, tau_x: SecretKey) {
-        self.tau_x.add_assign(&self.secp, &tau_x).unwrap();
+    fn add_tau_x(&mut self, tau_x: SecretKey) -> Result<(), String> {
+        self.tau_x.add_assign(&self.secp, &tau_x)
+            .map_err(|e| format!("Failed to add tau_x: {}", e))
     }
 }
 
@@ -117,7 +122,7 @@ impl MPBPContext {
 /// # Arguments
 /// * `rng` randomness generator
 /// * `secp` Elliptic curve functionalities
-pub fn create_secret_key(rng: &mut OsRng, secp: &Secp256k1) -> SecretKey {
+pub fn create_secret_key(rng: &mut SwapRng, secp: &Secp256k1) -> SecretKey {
     SecretKey::new(secp, rng)
 }
 
@@ -127,9 +132,9 @@ pub fn create_secret_key(rng: &mut OsRng, secp: &Secp256k1) -> SecretKey {
 /// # Arguments
 /// * `sig` the signature from which to extract s
 /// * `secp` elliptic cureve functionalities
-pub fn sig_extract_s(sig : &Signature, secp: &Secp256k1) -> SecretKey {
+pub fn sig_extract_s(sig : &Signature, secp: &Secp256k1) -> Result<SecretKey, String> {
     SecretKey::from_slice(secp, &sig.to_raw_data()[32..])
-        .unwrap()
+        .map_err(|e| format!("Invalid signature s value: {}", e))
 }
 
 /// Serialze a secret key to a hex encoded string
@@ -145,16 +150,15 @@ pub fn serialize_secret_key(key: &SecretKey) -> String {
 /// # Arguments
 /// * `key` the key serialized as hex string
 /// * `secp` Secp256 functionatlity
-pub fn deserialize_secret_key(key: &String, secp: &Secp256k1) -> SecretKey {
+pub fn deserialize_secret_key(key: &String, secp: &Secp256k1) -> Result<SecretKey, String> {
     if key == "0000000000000000000000000000000000000000000000000000000000000000" {
-        ZERO_KEY
+        Ok(ZERO_KEY)
     }
     else {
-        SecretKey::from_slice(
-            secp,
-            &hex::decode(key).expect("Failed to deserialize a secret key from hex string"),
-        )
-        .expect("Failed to deserialize a secret key from hex string")
+        let bytes = hex::decode(key)
+            .map_err(|e| format!("Failed to deserialize a secret key from hex string: {}", e))?;
+        SecretKey::from_slice(secp, &bytes)
+            .map_err(|e| format!("Failed to deserialize a secret key from hex string: {}", e))
     }
 }
 
@@ -207,11 +211,11 @@ pub fn serialize_grin_public_key(pk: &PublicKey, secp: &Secp256k1) -> String {
 ///
 /// * `str` the string containing the hex encoded public key
 /// * `secp` the Secp256k1 engine
-pub fn deserialize_grin_pub_key(str: &String, secp: &Secp256k1) -> PublicKey {
+pub fn deserialize_grin_pub_key(str: &String, secp: &Secp256k1) -> Result<PublicKey, String> {
     let pk_bytes = hex::decode(str)
-        .unwrap();
+        .map_err(|e| format!("Failed to deserialize public key: {}", e))?;
     PublicKey::from_slice(secp, &pk_bytes)
-        .unwrap()
+        .map_err(|e| format!("Failed to deserialize public key: {}", e))
 }
 
 /// Deserialize a pedersen commitment from a hex encoded string
@@ -219,8 +223,24 @@ pub fn deserialize_grin_pub_key(str: &String, secp: &Secp256k1) -> PublicKey {
 /// # Arguments
 ///
 /// * `com` commitment encoded as hex string
-pub fn deserialize_commitment(com: &String) -> Commitment {
-    Commitment::from_vec(hex::decode(com).expect("Failed to deserialize pedersen commitment"))
+pub fn deserialize_commitment(com: &String) -> Result<Commitment, String> {
+    let bytes = hex::decode(com)
+        .map_err(|e| format!("Failed to deserialize pedersen commitment: {}", e))?;
+    if bytes.len() != PEDERSEN_COMMITMENT_SIZE {
+        return Err(format!("Failed to deserialize pedersen commitment: expected {} bytes, got {}", PEDERSEN_COMMITMENT_SIZE, bytes.len()));
+    }
+    Ok(Commitment::from_vec(bytes))
+}
+
+/// Deserialize a grin slate received from the peer or read from a file,
+/// upgrading older slate versions
+///
+/// # Arguments
+///
+/// * `str` the json serialized slate
+pub fn deserialize_slate(str: &String) -> Result<Slate, String> {
+    Slate::deserialize_upgrade(str)
+        .map_err(|e| format!("Failed to deserialize grin slate: {}", e))
 }
 
 /// Conversion from grin to nanogrin
@@ -267,8 +287,8 @@ pub fn mp_bullet_proof_r1(
         Some(&sec_nonce),
         1,
     );
-    ctx.add_t_1(t_1);
-    ctx.add_t_2(t_2);
+    ctx.add_t_1(t_1)?;
+    ctx.add_t_2(t_2)?;
     Ok(ctx)
 }
 
@@ -302,7 +322,7 @@ pub fn mp_bullet_proof_r2(
         Some(&sec_nonce.clone()),
         2,
     );
-    ctx.add_tau_x(tau_x.clone());
+    ctx.add_tau_x(tau_x.clone())?;
     Ok(ctx)
 }
 
@@ -359,12 +379,32 @@ pub fn mp_bullet_proof_fin(
             Some(&sec_nonce.clone()),
             0,
         )
-        .expect("Failed to finalize MP bulletproof");
+        .ok_or(String::from("Failed to finalize MP bulletproof"))?;
     Ok(proof)
 }
 
-pub fn estimate_fees(num_inp : usize, num_out : usize, num_ker : usize) -> u64 {
-    tx_fee(num_inp, num_out, num_ker)
+/// Fee of a transaction given its number of inputs, outputs and kernels,
+/// the transaction weight times the fee base. An overflowing fee saturates,
+/// no coin can pay it.
+///
+/// # Arguments
+///
+/// * `num_inp` number of inputs
+/// * `num_out` number of outputs
+/// * `num_ker` number of kernels
+/// * `fee_base` fee in nanogrin per weight unit
+pub fn estimate_fees(num_inp : usize, num_out : usize, num_ker : usize, fee_base : u64) -> u64 {
+    Transaction::weight_by_iok(num_inp as u64, num_out as u64, num_ker as u64).saturating_mul(fee_base)
+}
+
+/// Hex encoded excess commitment of the first kernel of a transaction,
+/// used to identify grin transactions in the swap event log
+///
+/// # Arguments
+///
+/// * `tx` the grin transaction
+pub fn get_kernel_excess(tx: &Transaction) -> Option<String> {
+    tx.kernels().first().map(|k| serialize_commitment(&k.excess))
 }
 
 #[cfg(test)]
@@ -372,9 +412,19 @@ mod test {
     use grin_util::secp::{ContextFlag, PublicKey, Secp256k1, SecretKey, pedersen::{Commitment, ProofMessage, ProofRange, RangeProof}};
     use grin_util::secp::Error;
 
+    use grin_core::{global::DEFAULT_ACCEPT_FEE_BASE, libtx::tx_fee};
+
     use crate::util::get_os_rng;
 
-    use super::{MPBPContext, create_secret_key, deserialize_commitment, deserialize_grin_pub_key, deserialize_secret_key, grin_to_nanogrin, mp_bullet_proof_fin, mp_bullet_proof_r1, mp_bullet_proof_r2, serialize_commitment, serialize_grin_public_key, serialize_secret_key};
+    use super::{MPBPContext, create_secret_key, deserialize_commitment, deserialize_grin_pub_key, deserialize_secret_key, estimate_fees, grin_to_nanogrin, mp_bullet_proof_fin, mp_bullet_proof_r1, mp_bullet_proof_r2, serialize_commitment, serialize_grin_public_key, serialize_secret_key};
+
+    #[test]
+    fn test_estimate_fees() {
+        // same as the library with the default fee base
+        assert_eq!(tx_fee(1, 1, 1), estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE));
+        assert_eq!(tx_fee(2, 2, 1), estimate_fees(2, 2, 1, DEFAULT_ACCEPT_FEE_BASE));
+        assert_eq!(2 * estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE), estimate_fees(1, 1, 1, 2 * DEFAULT_ACCEPT_FEE_BASE));
+    }
 
     #[test]
     fn test_key_serializiation() {
@@ -382,7 +432,7 @@ mod test {
         let secp = Secp256k1::with_caps(ContextFlag::Commit);
         let sk = create_secret_key(&mut rng, &secp);
         let ser = serialize_secret_key(&sk);
-        let deser = deserialize_secret_key(&ser, &secp);
+        let deser = deserialize_secret_key(&ser, &secp).unwrap();
         assert_eq!(sk, deser);
     }
 
@@ -394,7 +444,7 @@ mod test {
         let pk = PublicKey::from_secret_key(&secp, &sk)
             .unwrap();
         let ser = serialize_grin_public_key(&pk, &secp);
-        let deser = deserialize_grin_pub_key(&ser, &secp);
+        let deser = deserialize_grin_pub_key(&ser, &secp).unwrap();
         assert_eq!(pk, deser);
     }
 
@@ -406,7 +456,7 @@ mod test {
         let value: u64 = 1000000;
         let commit = secp.commit(value, sk).unwrap();
         let ser = serialize_commitment(&commit);
-        let deser = deserialize_commitment(&ser);
+        let deser = deserialize_commitment(&ser).unwrap();
         assert_eq!(commit, deser);
     }
 
@@ -423,7 +473,7 @@ mod test {
         let shared_nonce = create_secret_key(&mut rng, &secp);
         let commit_a = secp.commit(amount, bf_a.clone())
             .unwrap();
-        let mut ctx = MPBPContext::new(shared_nonce, amount, commit_a);
+        let mut ctx = MPBPContext::new(shared_nonce, amount, commit_a, rng.fork());
 
         // Round 1
         ctx = mp_bullet_proof_r1(ctx, bf_a.clone(), sec_nonce_a.clone())
@@ -433,7 +483,7 @@ mod test {
 
         let commit_b = secp.commit(0, bf_b.clone())
             .unwrap();
-        ctx.add_commit(commit_b);
+        ctx.add_commit(commit_b).unwrap();
         let com = ctx.commit.clone();
 
         // Round 2
@@ -455,6 +505,19 @@ mod test {
         let secp = Secp256k1::with_caps(ContextFlag::Commit);
         let hex = String::from("0000000000000000000000000000000000000000000000000000000000000000");
         let key = deserialize_secret_key(&hex, &secp);
+        assert!(key.is_ok());
+    }
+
+    #[test]
+    fn test_deserialize_malformed() {
+        let secp = Secp256k1::with_caps(ContextFlag::Commit);
+        for malformed in vec!["", "zz", "00", "ff".repeat(32).as_str(), "02".repeat(40).as_str()] {
+            let malformed = String::from(malformed);
+            assert!(deserialize_secret_key(&malformed, &secp).is_err());
+            assert!(deserialize_grin_pub_key(&malformed, &secp).is_err());
+            assert!(deserialize_commitment(&malformed).is_err());
+            assert!(MPBPContext::from_string(&malformed, get_os_rng()).is_err());
+        }
     }
 
 }
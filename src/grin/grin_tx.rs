@@ -1,10 +1,8 @@
-use std::net::TcpStream;
-
-use crate::{net::{http::RequestFactory, tcp::{receive_msg, send_msg}}, settings::GrinNodeSettings};
+use crate::{net::tcp::{receive_msg, send_msg, MessageChannel}, util::SwapRng};
 use grin_util::secp::{PublicKey, SecretKey};
 use grin_wallet_libwallet::Slate;
 
-use super::{grin_core::GrinCore, grin_routines::{MPBPContext, sig_extract_s}, grin_types::MWCoin};
+use super::{grin_core::GrinCore, grin_routines::{create_secret_key, deserialize_slate, MPBPContext}, grin_types::{AnchorSigMsg, KernelLock, MWCoin, NRDAnchor}};
 
 pub struct GrinTx {
     core: GrinCore,
@@ -19,6 +17,16 @@ pub struct DSharedOutMwTxResult {
     pub tx: Slate,
     pub change_coin: Option<MWCoin>,
     pub shared_coin: MWCoin,
+    // our share of the NRD anchor kernel, if the transaction has one
+    pub anchor: Option<NRDAnchor>,
+}
+
+// Our share of an NRD anchor kernel while it is signed
+struct AnchorShare {
+    key: SecretKey,
+    nonce: SecretKey,
+    msg: AnchorSigMsg,
+    relative_height: u64,
 }
 
 pub struct ContractMwResult {
@@ -28,13 +36,27 @@ pub struct ContractMwResult {
 }
 
 impl GrinTx {
-    pub fn new(settings : GrinNodeSettings, req_factory : RequestFactory) -> GrinTx {
-        let core = GrinCore::new(settings, req_factory);
+    /// Create the two-party transaction protocols, they don't need a Grin node
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` randomness generator, a seeded one makes the transactions reproducible
+    pub fn new(rng : SwapRng) -> GrinTx {
+        let core = GrinCore::offline(rng);
         GrinTx { core: core }
     }
 
+    /// Set the fee base used to build and validate the transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `fee_base` fee in nanogrin per weight unit as agreed in the swap slate
+    pub fn set_fee_base(&mut self, fee_base: u64) {
+        self.core.fee_base = fee_base;
+    }
+
     /// The Alice part of the dBuildMWTx protocol of the thesis
-    /// Communicate via TCP Stream with Bob to build a mimblewimble transaction
+    /// Communicate with Bob to build a mimblewimble transaction
     ///
     /// # Arguments
     ///
@@ -47,15 +69,17 @@ impl GrinTx {
         inp: Vec<MWCoin>,
         fund_value: u64,
         timelock: u64,
-        stream: &mut TcpStream,
+        stream: &mut dyn MessageChannel,
     ) -> Result<DBuildMWTxResult, String> {
         // Create initial pre-transaction by calling spend coins
-        let spend_coins_result = self.core.spend_coins(inp, fund_value, timelock, 2, 2)?;
+        let spend_coins_result = self.core.spend_coins(inp, fund_value, KernelLock::from_height(timelock), 2, 2)?;
         // Send the pre-tx to Bob
         let ptx = serde_json::to_string(&spend_coins_result.slate).unwrap();
-        send_msg(stream, &ptx);
-        let bob_msg = receive_msg(stream);
-        let ptx2: Slate = Slate::deserialize_upgrade(&bob_msg).unwrap();
+        send_msg(stream, &ptx)?;
+        let bob_msg = receive_msg(stream)?;
+        let mut ptx2: Slate = deserialize_slate(&bob_msg)?;
+        ptx2.update_kernel()
+            .map_err(|e| format!("Failed to update the kernel: {}", e))?;
         let fin = self
             .core
             .fin_tx(
@@ -65,10 +89,9 @@ impl GrinTx {
                 true,
                 None,
                 None,
-            )
-            .unwrap();
+            )?;
         let tx = serde_json::to_string(&fin).unwrap();
-        send_msg(stream, &tx);
+        send_msg(stream, &tx)?;
         Ok(DBuildMWTxResult {
             tx: fin,
             coin: spend_coins_result.change_coin,
@@ -76,28 +99,32 @@ impl GrinTx {
     }
 
     /// The Bob part of the dBuildMwTx protocol from the thesis
-    /// Communicate via TCP with Alice to build a Mimblewimble transaction
+    /// Communicate with Alice to build a Mimblewimble transaction
     ///
     /// # Arguments
     ///
     /// * `fund_value` the amount in nanogrin transfered with the transaction
-    /// * `stream` the tcp stream on which we exchange messages with Alice
+    /// * `stream` the channel on which we exchange messages with Alice
     pub fn dbuild_mw_tx_bob(
         &mut self,
         fund_value: u64,
-        stream: &mut TcpStream,
+        stream: &mut dyn MessageChannel,
     ) -> Result<DBuildMWTxResult, String> {
         // Retrieve initial pre-transaction from Alice
-        let mut alice_msg = receive_msg(stream);
-        let ptx: Slate = Slate::deserialize_upgrade(&alice_msg).unwrap();
+        let mut alice_msg = receive_msg(stream)?;
+        let ptx: Slate = deserialize_slate(&alice_msg)?;
         // Now we create the updated pre-transaction
-        let ptx2 = self.core.recv_coins(ptx, fund_value).unwrap();
+        let ptx2 = self.core.recv_coins(ptx, fund_value)?;
         // Send the updated pre-transaction back to Alice
         let ptx2_str = serde_json::to_string(&ptx2.slate).unwrap();
-        send_msg(stream, &ptx2_str);
+        send_msg(stream, &ptx2_str)?;
         // Retrieve the final tx from Alice
-        alice_msg = receive_msg(stream);
-        let tx = Slate::deserialize_upgrade(&alice_msg).unwrap();
+        alice_msg = receive_msg(stream)?;
+        let mut tx = deserialize_slate(&alice_msg)?;
+        tx.update_kernel()
+            .map_err(|e| format!("Failed to update the kernel: {}", e))?;
+        tx.finalize(&self.core.chain)
+            .map_err(|e| format!("Failed to finalize the transaction: {}", e))?;
         Ok(DBuildMWTxResult {
             tx: tx,
             coin: Some(ptx2.output_coin),
@@ -112,31 +139,44 @@ impl GrinTx {
     ///
     /// * `fund_value` the transaction amount for the shared output coin
     /// * `timelock` optional block height to timelock the transaction
-    /// * `stream` TCP stream to exchange messages with bob
+    /// * `anchor` relative height of an NRD anchor kernel to add, a refund kernel reusing its excess is locked by it
+    /// * `stream` channel to exchange messages with bob
     pub fn dshared_out_mw_tx_alice(
         &mut self,
         inp: Vec<MWCoin>,
         fund_value: u64,
         timelock: u64,
-        stream: &mut TcpStream,
+        anchor: Option<u64>,
+        stream: &mut dyn MessageChannel,
     ) -> Result<DSharedOutMwTxResult, String> {
+        let anchor_share = match anchor {
+            Some(relative_height) => Some(self.anchor_share(relative_height)?),
+            None => None,
+        };
+        let num_kernels = if anchor_share.is_some() { 2 } else { 1 };
         // Create the initial pre-transaction
         let spend_coins_result = self
             .core
-            .spend_coins(inp, fund_value, timelock, 2, 3)
-            .unwrap();
-        // Run the first round of the drecvcoins protocol
+            .spend_coins_with_kernels(inp, fund_value, KernelLock::from_height(timelock), 2, 3, num_kernels)?;
+        // Run the first round of the drecvcoins protocol, our anchor share is split off the main kernel
+        let anchor_key = anchor_share.as_ref().map(|a| &a.key);
         let mut recv_coins_result = self
             .core
-            .drecv_coins_r1(spend_coins_result.slate, fund_value)
-            .unwrap();
+            .drecv_coins_r1(spend_coins_result.slate, fund_value, anchor_key)?;
         let ptx = serde_json::to_string(&recv_coins_result.slate).unwrap();
-        send_msg(stream, &ptx);
-        send_msg(stream, &recv_coins_result.prf_ctx.to_string());
-        let bob_msg = receive_msg(stream);
-        let bob_msg2 = receive_msg(stream);
-        let ptx2: Slate = Slate::deserialize_upgrade(&bob_msg).unwrap();
-        let prf_ctx = MPBPContext::from_string(&bob_msg2);
+        send_msg(stream, &ptx)?;
+        send_msg(stream, &recv_coins_result.prf_ctx.to_string())?;
+        if let Some(share) = &anchor_share {
+            send_msg(stream, &serde_json::to_string(&share.msg).unwrap())?;
+        }
+        let bob_msg = receive_msg(stream)?;
+        let bob_msg2 = receive_msg(stream)?;
+        let ptx2: Slate = deserialize_slate(&bob_msg)?;
+        let prf_ctx = MPBPContext::from_string(&bob_msg2, self.core.rng.fork())?;
+        let peer_anchor = match &anchor_share {
+            Some(_) => Some(receive_anchor_msg(stream)?),
+            None => None,
+        };
         // round 3 of the recv coins
         let drecv_coins_result3 = self
             .core
@@ -146,28 +186,36 @@ impl GrinTx {
                 recv_coins_result.out_key_blind,
                 recv_coins_result.prf_nonce,
                 recv_coins_result.sig_nonce,
-            )
-            .unwrap();
-        // finalize the transaction
-        let fin_slate = self
+                anchor_key,
+            )?;
+        // finalize the transaction, a transaction with an anchor is finalized once the anchor is added
+        let mut fin_slate = self
             .core
             .fin_tx(
                 drecv_coins_result3.slate,
                 &spend_coins_result.sig_key,
                 &spend_coins_result.sig_nonce,
-                true,
+                anchor_share.is_none(),
                 None,
                 None,
-            )
-            .unwrap();
+            )?;
+        let mut nrd_anchor = None;
+        if let (Some(share), Some(peer)) = (anchor_share, peer_anchor) {
+            let mut own = share.msg;
+            own.part_sig = Some(self.core.sign_anchor(&share.key, &share.nonce, &own, &peer, share.relative_height)?);
+            let kernel = self.core.anchor_kernel(&own, &peer, share.relative_height)?;
+            fin_slate = self.core.fin_tx_with_kernel(fin_slate, kernel)?;
+            nrd_anchor = Some(self.nrd_anchor(share.key, &own, &peer)?);
+        }
         // Send final tx to bob
         let tx = serde_json::to_string(&fin_slate).unwrap();
-        send_msg(stream, &tx);
+        send_msg(stream, &tx)?;
 
         Ok(DSharedOutMwTxResult {
             tx: fin_slate,
             change_coin: spend_coins_result.change_coin,
             shared_coin: drecv_coins_result3.output_coin,
+            anchor: nrd_anchor,
         })
     }
 
@@ -178,33 +226,55 @@ impl GrinTx {
     /// # Arguments
     ///
     /// * `fund_value` the transaction amount for the shared output coin
+    /// * `anchor` relative height of the NRD anchor kernel Alice adds to the transaction
     /// * `stream` channel to communicate with Alice
     pub fn dshared_out_mw_tx_bob(
         &mut self,
         fund_value: u64,
-        stream: &mut TcpStream,
+        anchor: Option<u64>,
+        stream: &mut dyn MessageChannel,
     ) -> Result<DSharedOutMwTxResult, String> {
         // Read the initial pre-transaction from Alice
-        let alice_msg1 = receive_msg(stream);
+        let alice_msg1 = receive_msg(stream)?;
         // Read the multi-party rangeproof context
-        let alice_msg2 = receive_msg(stream);
-        let ptx = Slate::deserialize_upgrade(&alice_msg1).unwrap();
-        let prf_ctx = MPBPContext::from_string(&alice_msg2);
-        let mut drecv_coins2_result = self.core.drecv_coins_r2(ptx, fund_value, prf_ctx)?;
+        let alice_msg2 = receive_msg(stream)?;
+        let ptx = deserialize_slate(&alice_msg1)?;
+        let prf_ctx = MPBPContext::from_string(&alice_msg2, self.core.rng.fork())?;
+        // Read Alice's share of the anchor kernel
+        let peer_anchor = match anchor {
+            Some(_) => Some(receive_anchor_msg(stream)?),
+            None => None,
+        };
+        let anchor_share = match anchor {
+            Some(relative_height) => Some(self.anchor_share(relative_height)?),
+            None => None,
+        };
+        let mut drecv_coins2_result = self
+            .core
+            .drecv_coins_r2(ptx, fund_value, prf_ctx, anchor_share.as_ref().map(|a| &a.key))?;
         // Send the updated pre-transaction to Alice
         let ptx2 = serde_json::to_string(&drecv_coins2_result.0.slate).unwrap();
         let prf_ctx2 = drecv_coins2_result.1.to_string();
-        send_msg(stream, &ptx2);
+        send_msg(stream, &ptx2)?;
         // Send the updated proof context
-        send_msg(stream, &prf_ctx2);
+        send_msg(stream, &prf_ctx2)?;
+        // Send our share of the anchor kernel with our partial signature
+        let mut nrd_anchor = None;
+        if let (Some(share), Some(peer)) = (anchor_share, peer_anchor) {
+            let mut own = share.msg;
+            own.part_sig = Some(self.core.sign_anchor(&share.key, &share.nonce, &own, &peer, share.relative_height)?);
+            send_msg(stream, &serde_json::to_string(&own).unwrap())?;
+            nrd_anchor = Some(self.nrd_anchor(share.key, &own, &peer)?);
+        }
 
-        let tx_str = receive_msg(stream);
-        let tx = Slate::deserialize_upgrade(&tx_str).unwrap();
+        let tx_str = receive_msg(stream)?;
+        let tx = deserialize_slate(&tx_str)?;
 
         Ok(DSharedOutMwTxResult {
             tx: tx,
             change_coin: None,
             shared_coin: drecv_coins2_result.0.output_coin,
+            anchor: nrd_anchor,
         })
     }
 
@@ -216,31 +286,54 @@ impl GrinTx {
     ///
     /// * `inp` the shared input coin
     /// * `fund_value` the value which should be transferred to Bob
-    /// * `timelock` optional timelock
+    /// * `lock` kernel lock of the transaction
+    /// * `anchor` our share of the NRD anchor kernel, the kernel reuses its excess
     /// * `steam` channel to exchange messages with Bob 
     pub fn dshared_inp_mw_tx_alice(
         &mut self,
         inp: MWCoin,
         fund_value: u64,
-        timelock: u64,
-        stream: &mut TcpStream,
+        lock: KernelLock,
+        anchor: Option<&NRDAnchor>,
+        stream: &mut dyn MessageChannel,
     ) -> Result<DBuildMWTxResult, String> {
-        let dspend_coins_result = self
+        let mut dspend_coins_result = self
             .core
-            .spend_coins(vec![inp], fund_value, timelock, 1, 3)?;
+            .spend_coins(vec![inp], fund_value, lock, 1, 3)?;
+        let sig_key = match anchor {
+            Some(anchor) => {
+                self.core.set_excess_key(&mut dspend_coins_result.slate, &dspend_coins_result.sig_key, &anchor.key)?;
+                anchor.key.clone()
+            },
+            None => dspend_coins_result.sig_key.clone(),
+        };
         
         // Send initial slate to Bob
         let ptx = serde_json::to_string(&dspend_coins_result.slate)
             .unwrap();
-        send_msg(stream, &ptx);
+        send_msg(stream, &ptx)?;
         // Receive updated pre-transaction from Bob
-        let bob_msg = receive_msg(stream);
-        let mut ptx2 = Slate::deserialize_upgrade(&bob_msg).unwrap();
-        ptx2.update_kernel().unwrap();
+        let bob_msg = receive_msg(stream)?;
+        let mut ptx2 = deserialize_slate(&bob_msg)?;
+        // We sign for the kernel lock we proposed only
+        let valid_features = ptx2.kernel_features == lock.features()
+            && ptx2.kernel_features_args.clone().map(|a| a.lock_height) == lock.lock_height();
+        if !valid_features {
+            return Err(String::from("Transaction timelock is not setup correctly!"));
+        }
+        ptx2.update_kernel()
+            .map_err(|e| format!("Failed to update the kernel: {}", e))?;
+        if let Some(anchor) = anchor {
+            let excess = ptx2.pub_blind_sum(&self.core.secp)
+                .map_err(|e| format!("Failed to sum the public blind excesses: {}", e))?;
+            if excess != anchor.excess {
+                return Err(String::from("Kernel doesn't reuse the excess of the anchor kernel"));
+            }
+        }
         // Second round of finalize tx
         let fin_slate = self.core.fin_tx(
             ptx2,
-            &dspend_coins_result.sig_key,
+            &sig_key,
             &dspend_coins_result.sig_nonce,
             true,
             None,
@@ -249,7 +342,7 @@ impl GrinTx {
         let tx = serde_json::to_string(&fin_slate)
             .unwrap();
         // Send final tx to Bob
-        send_msg(stream, &tx);
+        send_msg(stream, &tx)?;
         Ok(DBuildMWTxResult {
             tx: fin_slate,
             coin: dspend_coins_result.change_coin,
@@ -264,25 +357,33 @@ impl GrinTx {
     ///
     /// * `inp` the shared input coin
     /// * `fund_value` the value which should be transferred to Bob
-    /// * `timelock` optional transaction timelock
+    /// * `lock` expected kernel lock of the transaction
+    /// * `anchor` our share of the NRD anchor kernel, the kernel reuses its excess
     /// * `stream` channel to communicate with Alice
     pub fn dshared_inp_mw_tx_bob(
         &mut self,
         inp: MWCoin,
         fund_value: u64,
-        timelock: u64,
-        stream: &mut TcpStream,
+        lock: KernelLock,
+        anchor: Option<&NRDAnchor>,
+        stream: &mut dyn MessageChannel,
     ) -> Result<DBuildMWTxResult, String> {
         // Receive initial pre-transaction from alice
-        let alice_msg = receive_msg(stream);
-        let ptx = Slate::deserialize_upgrade(&alice_msg).unwrap();
+        let alice_msg = receive_msg(stream)?;
+        let ptx = deserialize_slate(&alice_msg)?;
 
         // Add our spending info
         let dspend_result = self
             .core
-            .d_spend_coins(vec![inp], ptx, fund_value, timelock)?;
-        // Create out output coin
-        let recv_result = self.core.recv_coins(dspend_result.slate, fund_value)?;
+            .d_spend_coins(vec![inp], ptx, fund_value, lock)?;
+        // Create out output coin, with an anchor we sign for the rest of our anchor share
+        let recv_result = match anchor {
+            Some(anchor) => {
+                let excess_key = self.core.sub_key(&anchor.key, &dspend_result.sig_key)?;
+                self.core.recv_coins_with_excess(dspend_result.slate, fund_value, &excess_key)?
+            },
+            None => self.core.recv_coins(dspend_result.slate, fund_value)?,
+        };
         // First round of the dfin_tx
         let fin_result = self.core.fin_tx(
             recv_result.slate,
@@ -291,18 +392,19 @@ impl GrinTx {
             false,
             None,
             None,
-        ).unwrap();
+        )?;
         // Send the updated pre-tx to Alice
         let ptx2 = serde_json::to_string(&fin_result)
             .unwrap();
-        send_msg(stream, &ptx2);
+        send_msg(stream, &ptx2)?;
 
         // Read final tx from Alice
-        let alice_msg = receive_msg(stream);
-        let mut fin_slate = Slate::deserialize_upgrade(&alice_msg)
-            .unwrap();
-        fin_slate.update_kernel().unwrap();
-        fin_slate.finalize(&self.core.chain).unwrap();
+        let alice_msg = receive_msg(stream)?;
+        let mut fin_slate = deserialize_slate(&alice_msg)?;
+        fin_slate.update_kernel()
+            .map_err(|e| format!("Failed to update the kernel: {}", e))?;
+        fin_slate.finalize(&self.core.chain)
+            .map_err(|e| format!("Failed to finalize the transaction: {}", e))?;
         Ok(DBuildMWTxResult{
             tx : fin_slate,
             coin : Some(recv_result.output_coin)
@@ -326,25 +428,23 @@ impl GrinTx {
         fund_value : u64,
         timelock : u64,
         pub_x : PublicKey,
-        stream : &mut TcpStream
+        stream : &mut dyn MessageChannel
     ) -> Result<ContractMwResult, String> {
         let dspend_coins_result = self
             .core
-            .spend_coins(vec![inp], fund_value, timelock, 1, 3)?;
+            .spend_coins(vec![inp], fund_value, KernelLock::from_height(timelock), 1, 3)?;
         let ptx = serde_json::to_string(&dspend_coins_result.slate)
             .unwrap();
         // Send initial slate to Bob
-        send_msg(stream, &ptx);
+        send_msg(stream, &ptx)?;
         // Receive updated pre-transaction from Bob
-        let bob_msg = receive_msg(stream);
-        let ptx2 = Slate::deserialize_upgrade(&bob_msg)
-            .unwrap();
+        let bob_msg = receive_msg(stream)?;
+        let ptx2 = deserialize_slate(&bob_msg)?;
         let apt_sig_bob = ptx2
             .participant_data
             .get(2)
-            .unwrap()
-            .part_sig
-            .unwrap();
+            .and_then(|p| p.part_sig)
+            .ok_or(String::from("Bob's adapted signature is missing"))?;
         // First round of finalize tx
         let fin_tx_result = self.core.fin_tx(
             ptx2, 
@@ -357,19 +457,21 @@ impl GrinTx {
         // Send ptx3 to Bob which he should then complete into the final tx
         let ptx3 = serde_json::to_string(&fin_tx_result)
             .unwrap();
-        send_msg(stream, &ptx3);
+        send_msg(stream, &ptx3)?;
         // Receive final tx from Bob
-        let bob_msg2 = receive_msg(stream);
-        let final_slate = Slate::deserialize_upgrade(&bob_msg2)
-            .unwrap();
+        let bob_msg2 = receive_msg(stream)?;
+        let mut final_slate = deserialize_slate(&bob_msg2)?;
         let sig_bob = final_slate
             .participant_data
             .get(2)
-            .unwrap()
-            .part_sig
-            .unwrap();
+            .and_then(|p| p.part_sig)
+            .ok_or(String::from("Bob's partial signature is missing"))?;
 
-        let x = self.core.ext_witness(sig_bob, apt_sig_bob);
+        let x = self.core.ext_witness(sig_bob, apt_sig_bob)?;
+        final_slate.update_kernel()
+            .map_err(|e| format!("Failed to update the kernel: {}", e))?;
+        final_slate.finalize(&self.core.chain)
+            .map_err(|e| format!("Failed to finalize the transaction: {}", e))?;
 
         Ok(ContractMwResult{
             tx : final_slate,
@@ -395,20 +497,22 @@ impl GrinTx {
         fund_value : u64,
         timelock : u64,
         x : SecretKey,
-        stream : &mut TcpStream
+        stream : &mut dyn MessageChannel
     ) -> Result<ContractMwResult, String> {
         // Receive initial pre-transaction from Alice
-        let alice_msg = receive_msg(stream);
-        let ptx = Slate::deserialize_upgrade(&alice_msg).unwrap();
+        let alice_msg = receive_msg(stream)?;
+        let ptx = deserialize_slate(&alice_msg)?;
         // Build updated pre-transaction
-        let dspend_coins_result = self.core.d_spend_coins(vec![inp], ptx, fund_value, timelock)?;
+        let dspend_coins_result = self.core.d_spend_coins(vec![inp], ptx, fund_value, KernelLock::from_height(timelock))?;
         let rec_coins_result = self.core.apt_recv_coins(dspend_coins_result.slate, fund_value, x.clone())?;
         // Send to Alice the update pre-transaction
         let ptx2 = serde_json::to_string(&rec_coins_result.slate).unwrap();
-        send_msg(stream, &ptx2);
+        send_msg(stream, &ptx2)?;
         // Receive the partially finalized tx from alice
-        let alice_msg2 = receive_msg(stream);
-        let ptx3 = Slate::deserialize_upgrade(&alice_msg2).unwrap();
+        let alice_msg2 = receive_msg(stream)?;
+        let mut ptx3 = deserialize_slate(&alice_msg2)?;
+        ptx3.update_kernel()
+            .map_err(|e| format!("Failed to update the kernel: {}", e))?;
         // Finalize the transaction
         let fin_tx_result = self.core.fin_tx(
             ptx3, 
@@ -420,7 +524,7 @@ impl GrinTx {
         )?;
         // send final tx to alice
         let tx = serde_json::to_string(&fin_tx_result).unwrap();
-        send_msg(stream, &tx);
+        send_msg(stream, &tx)?;
         Ok(ContractMwResult{
             tx : fin_tx_result,
             coin : Some(rec_coins_result.output_coin),
@@ -428,4 +532,425 @@ impl GrinTx {
         })
     }
 
+    // Create our share of the excess and the signing nonce of an NRD anchor kernel
+    fn anchor_share(&mut self, relative_height: u64) -> Result<AnchorShare, String> {
+        let key = create_secret_key(&mut self.core.rng, &self.core.secp);
+        let nonce = create_secret_key(&mut self.core.rng, &self.core.secp);
+        let msg = AnchorSigMsg {
+            excess: PublicKey::from_secret_key(&self.core.secp, &key)
+                .map_err(|e| format!("Invalid anchor key: {:?}", e))?,
+            nonce: PublicKey::from_secret_key(&self.core.secp, &nonce)
+                .map_err(|e| format!("Invalid anchor nonce: {:?}", e))?,
+            part_sig: None,
+        };
+        Ok(AnchorShare {
+            key: key,
+            nonce: nonce,
+            msg: msg,
+            relative_height: relative_height,
+        })
+    }
+
+    // Our share of the signed anchor kernel, kept to build the refund kernel
+    fn nrd_anchor(&self, key: SecretKey, own: &AnchorSigMsg, peer: &AnchorSigMsg) -> Result<NRDAnchor, String> {
+        let excess = PublicKey::from_combination(&self.core.secp, vec![&own.excess, &peer.excess])
+            .map_err(|e| format!("Failed to sum the anchor excesses: {:?}", e))?;
+        Ok(NRDAnchor {
+            key: key,
+            excess: excess,
+        })
+    }
+}
+
+fn receive_anchor_msg(stream: &mut dyn MessageChannel) -> Result<AnchorSigMsg, String> {
+    let msg = receive_msg(stream)?;
+    serde_json::from_str(&msg).map_err(|e| format!("Failed to parse the anchor kernel message: {}", e))
 }
+
+#[cfg(test)]
+mod test {
+    use super::GrinTx;
+    use crate::constants::NANO_GRIN;
+    use crate::grin::grin_routines::{create_secret_key, deserialize_secret_key, estimate_fees, grin_to_nanogrin, serialize_commitment};
+    use crate::grin::grin_types::{KernelLock, MWCoin};
+    use crate::net::memory::{memory_pair, MemoryChannel};
+    use crate::sim::grin_chain::GrinChain;
+    use crate::util::{get_os_rng, SwapRng};
+    use grin_core::core::{KernelFeatures, Transaction};
+    use grin_core::global::{set_local_chain_type, ChainTypes, DEFAULT_ACCEPT_FEE_BASE};
+    use grin_util::secp::{ContextFlag, PublicKey, Secp256k1, SecretKey};
+    use proptest::prelude::*;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn grin_tx() -> GrinTx {
+        GrinTx::new(get_os_rng())
+    }
+
+    /// Run Alice's side of a protocol on this thread and Bob's side on a
+    /// second one, connected by an in memory channel
+    fn run_protocol<A, B, FA, FB>(alice : FA, bob : FB) -> (A, B)
+    where
+        FA : FnOnce(&mut MemoryChannel) -> A,
+        FB : FnOnce(&mut MemoryChannel) -> B + Send + 'static,
+        B : Send + 'static
+    {
+        let (mut alice_channel, mut bob_channel) = memory_pair();
+        let bob = thread::spawn(move || {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            bob(&mut bob_channel)
+        });
+        let alice_result = alice(&mut alice_channel);
+        (alice_result, bob.join().unwrap())
+    }
+
+    /// Create coins with the given values and fund them on the chain
+    fn fund_coins(chain : &mut GrinChain, secp : &Secp256k1, rng : &mut SwapRng, values : &Vec<u64>) -> Vec<MWCoin> {
+        values.iter().map(|value| {
+            let blind = create_secret_key(rng, secp);
+            let commit = secp.commit(*value, blind.clone()).unwrap();
+            chain.fund(&commit);
+            MWCoin::new(&commit, &blind, *value)
+        }).collect()
+    }
+
+    /// Check if a coin opens with the sum of the given blinding factor shares
+    fn opens_with(secp : &Secp256k1, coin : &MWCoin, shares : Vec<&MWCoin>) -> bool {
+        let blinds : Vec<SecretKey> = shares.iter()
+            .map(|c| deserialize_secret_key(&c.blinding_factor, secp).unwrap())
+            .collect();
+        let blind = secp.blind_sum(blinds, vec![]).unwrap();
+        serialize_commitment(&secp.commit(coin.value, blind).unwrap()) == coin.commitment
+    }
+
+    /// Push a transaction to the chain as soon as its kernel lock allows it.
+    /// The kernel has to carry the expected lock, pay at least the minimum
+    /// fee and a locked transaction has to be rejected before its lock height.
+    fn push_unlocked(chain : &mut GrinChain, tx : &Transaction, lock : KernelLock) -> Result<(), String> {
+        for kernel in tx.kernels() {
+            let lock_height = match kernel.features {
+                KernelFeatures::HeightLocked { lock_height, .. } => Some(lock_height),
+                _ => None
+            };
+            if lock_height != lock.lock_height() {
+                return Err(format!("Kernel lock {:?} doesn't match the expected {:?}", lock_height, lock.lock_height()));
+            }
+        }
+        let min_fee = estimate_fees(tx.inputs().len(), tx.outputs().len(), tx.kernels().len(), DEFAULT_ACCEPT_FEE_BASE);
+        if tx.fee(chain.height()) < min_fee {
+            return Err(format!("Transaction fee {} is below the minimum fee {}", tx.fee(chain.height()), min_fee));
+        }
+        if let Some(lock_height) = lock.lock_height() {
+            if lock_height > chain.height() + 1 {
+                if chain.push_transaction(tx).is_ok() {
+                    return Err(String::from("Transaction was accepted before its lock height"));
+                }
+                chain.mine(lock_height - chain.height() - 1);
+            }
+        }
+        chain.push_transaction(tx)
+    }
+
+    /// Run the dsharedOutMWTx protocol funding a shared coin from Alice's inputs,
+    /// returns Alice's and Bob's shared coin
+    fn fund_shared_coin(
+        chain : &mut GrinChain,
+        secp : &Secp256k1,
+        rng : &mut SwapRng,
+        values : &Vec<u64>,
+        fund_value : u64,
+        timelock : u64
+    ) -> (MWCoin, MWCoin) {
+        let coins = fund_coins(chain, secp, rng, values);
+        let lock_height = if timelock == 0 { 0 } else { chain.height() + timelock };
+        let mut alice = GrinTx::new(rng.fork());
+        let mut bob = GrinTx::new(rng.fork());
+        let (alice_result, bob_result) = run_protocol(
+            |channel| alice.dshared_out_mw_tx_alice(coins, fund_value, lock_height, None, channel).unwrap(),
+            move |channel| bob.dshared_out_mw_tx_bob(fund_value, None, channel).unwrap()
+        );
+        push_unlocked(chain, alice_result.tx.tx.as_ref().unwrap(), KernelLock::from_height(lock_height)).unwrap();
+        if let Some(change) = &alice_result.change_coin {
+            assert!(opens_with(secp, change, vec![change]));
+        }
+        (alice_result.shared_coin, bob_result.shared_coin)
+    }
+
+    /// Amount for a shared coin which leaves room for the fee of spending it,
+    /// `share` is the percentage of the remaining input value to use
+    fn shared_fund_value(values : &Vec<u64>, share : u64) -> u64 {
+        let total : u64 = values.iter().sum();
+        let fee = estimate_fees(values.len(), 2, 1, DEFAULT_ACCEPT_FEE_BASE);
+        let spend_fee = estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE);
+        spend_fee + 1 + (total - fee - spend_fee - 1) * share / 100
+    }
+
+    /// Run the shared output protocol with generators derived from a seed,
+    /// returns the messages written by Alice and by Bob
+    fn shared_output_transcript(seed : u64) -> (Vec<String>, Vec<String>) {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let secp = Secp256k1::with_caps(ContextFlag::Commit);
+        let mut rng = SwapRng::seeded(seed);
+        let value = grin_to_nanogrin(3);
+        let blind = create_secret_key(&mut rng, &secp);
+        let coin = MWCoin::new(&secp.commit(value, blind.clone()).unwrap(), &blind, value);
+        let fund_value = grin_to_nanogrin(2);
+
+        let alice_sent = Arc::new(Mutex::new(Vec::new()));
+        let bob_sent = Arc::new(Mutex::new(Vec::new()));
+        let (mut alice_channel, mut bob_channel) = memory_pair();
+        let record = alice_sent.clone();
+        alice_channel.tamper(move |msg| {
+            record.lock().unwrap().push(msg.clone());
+            msg
+        });
+        let record = bob_sent.clone();
+        bob_channel.tamper(move |msg| {
+            record.lock().unwrap().push(msg.clone());
+            msg
+        });
+        let mut alice = GrinTx::new(rng.fork());
+        let mut bob = GrinTx::new(rng.fork());
+        let bob = thread::spawn(move || {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            bob.dshared_out_mw_tx_bob(fund_value, None, &mut bob_channel).unwrap();
+        });
+        alice.dshared_out_mw_tx_alice(vec![coin], fund_value, 0, None, &mut alice_channel).unwrap();
+        bob.join().unwrap();
+        let alice_sent = alice_sent.lock().unwrap().clone();
+        let bob_sent = bob_sent.lock().unwrap().clone();
+        (alice_sent, bob_sent)
+    }
+
+    #[test]
+    fn test_shared_output_and_contract() {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let secp = Secp256k1::with_caps(ContextFlag::Commit);
+        let mut rng = get_os_rng();
+        let mut chain = GrinChain::new(1000);
+
+        let value = grin_to_nanogrin(3);
+        let blind = create_secret_key(&mut rng, &secp);
+        let commit = secp.commit(value, blind.clone()).unwrap();
+        chain.fund(&commit);
+        let coin = MWCoin::new(&commit, &blind, value);
+        let fund_value = grin_to_nanogrin(2);
+        let x = create_secret_key(&mut rng, &secp);
+        let pub_x = PublicKey::from_secret_key(&secp, &x).unwrap();
+        let bob_x = x.clone();
+        let fee = estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE);
+
+        let (mut alice_channel, mut bob_channel) = memory_pair();
+        let bob = thread::spawn(move || {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            let mut bob = grin_tx();
+            let shared = bob.dshared_out_mw_tx_bob(fund_value, None, &mut bob_channel).unwrap();
+            let shared_coin = shared.shared_coin.clone();
+            let contract = bob.dcontract_mw_tx_bob(shared_coin, fund_value - fee, 0, bob_x, &mut bob_channel).unwrap();
+            (shared, contract)
+        });
+        let mut alice = grin_tx();
+        let shared = alice.dshared_out_mw_tx_alice(vec![coin], fund_value, 0, None, &mut alice_channel).unwrap();
+        let shared_coin = shared.shared_coin.clone();
+        let contract = alice.dcontract_mw_tx_alice(shared_coin, fund_value - fee, 0, pub_x, &mut alice_channel).unwrap();
+        let (bob_shared, bob_contract) = bob.join().unwrap();
+
+        // both know the shared output and the funding transaction is valid
+        assert_eq!(shared.shared_coin.commitment, bob_shared.shared_coin.commitment);
+        chain.push_transaction(shared.tx.tx.as_ref().unwrap()).unwrap();
+        assert!(chain.get_output(&shared.shared_coin.commitment).is_some());
+        // the contract transaction pays Bob and reveals x to Alice
+        chain.push_transaction(bob_contract.tx.tx.as_ref().unwrap()).unwrap();
+        assert!(chain.get_output(&shared.shared_coin.commitment).unwrap().spent);
+        assert_eq!(x, contract.x);
+    }
+
+    #[test]
+    fn test_nrd_anchored_refund() {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let secp = Secp256k1::with_caps(ContextFlag::Commit);
+        let mut rng = get_os_rng();
+        let mut chain = GrinChain::new(1000);
+
+        let value = grin_to_nanogrin(3);
+        let blind = create_secret_key(&mut rng, &secp);
+        let commit = secp.commit(value, blind.clone()).unwrap();
+        chain.fund(&commit);
+        let coin = MWCoin::new(&commit, &blind, value);
+        let fund_value = grin_to_nanogrin(2);
+        let relative_height = 5;
+        let lock = KernelLock::NRD(relative_height);
+        let spend_value = fund_value - estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE);
+
+        // Alice funds the shared output and gets the refund, Bob finalizes the refund
+        let (mut alice_channel, mut bob_channel) = memory_pair();
+        let bob = thread::spawn(move || {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            let mut bob = grin_tx();
+            let shared = bob.dshared_out_mw_tx_bob(fund_value, Some(relative_height), &mut bob_channel).unwrap();
+            bob.dshared_inp_mw_tx_alice(shared.shared_coin.clone(), spend_value, lock, shared.anchor.as_ref(), &mut bob_channel).unwrap();
+        });
+        let mut alice = grin_tx();
+        let shared = alice.dshared_out_mw_tx_alice(vec![coin], fund_value, 0, Some(relative_height), &mut alice_channel).unwrap();
+        let refund = alice.dshared_inp_mw_tx_bob(shared.shared_coin.clone(), spend_value, lock, shared.anchor.as_ref(), &mut alice_channel).unwrap();
+        bob.join().unwrap();
+
+        // the funding transaction carries the anchor, the refund kernel reuses its excess
+        let funding_tx = shared.tx.tx.unwrap();
+        let refund_tx = refund.tx.tx.unwrap();
+        assert_eq!(2, funding_tx.kernels().len());
+        let anchor = funding_tx.kernels().iter().find(|k| k.is_nrd()).unwrap().clone();
+        assert_eq!(1, refund_tx.kernels().len());
+        match refund_tx.kernels()[0].features {
+            KernelFeatures::NoRecentDuplicate { relative_height : h, .. } => assert_eq!(relative_height, u64::from(h)),
+            _ => panic!("Refund kernel is not NRD locked")
+        }
+        assert_eq!(anchor.excess, refund_tx.kernels()[0].excess);
+
+        // the refund can't be mined before the relative height passed since the funding
+        chain.push_transaction(&funding_tx).unwrap();
+        assert!(chain.push_transaction(&refund_tx).unwrap_err().starts_with("NRD kernel is locked"));
+        chain.mine(1);
+        chain.mine(relative_height - 2);
+        assert!(chain.push_transaction(&refund_tx).unwrap_err().starts_with("NRD kernel is locked"));
+        chain.mine(1);
+        chain.push_transaction(&refund_tx).unwrap();
+        assert!(chain.get_output(&refund.coin.unwrap().commitment).is_some());
+    }
+
+    #[test]
+    fn test_reproducible_transcript() {
+        let (alice, bob) = shared_output_transcript(1);
+        assert!(!alice.is_empty() && !bob.is_empty());
+        assert_eq!((alice.clone(), bob.clone()), shared_output_transcript(1));
+        assert_ne!(alice, shared_output_transcript(2).0);
+    }
+
+    proptest! {
+        // every case runs a full protocol including rangeproofs, keep the number small
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn prop_dbuildmw_tx(
+            seed in any::<u64>(),
+            values in prop::collection::vec(NANO_GRIN / 10..10 * NANO_GRIN, 1..5),
+            share in 1..=100u64,
+            timelock in 0..10u64
+        ) {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            let secp = Secp256k1::with_caps(ContextFlag::Commit);
+            let mut rng = SwapRng::seeded(seed);
+            let mut chain = GrinChain::new(1000);
+            let coins = fund_coins(&mut chain, &secp, &mut rng, &values);
+            let total : u64 = values.iter().sum();
+            let fee = estimate_fees(values.len(), 2, 1, DEFAULT_ACCEPT_FEE_BASE);
+            let fund_value = ((total - fee) * share / 100).max(1);
+            let lock_height = if timelock == 0 { 0 } else { chain.height() + timelock };
+
+            let mut alice = GrinTx::new(rng.fork());
+            let mut bob = GrinTx::new(rng.fork());
+            let (alice_result, bob_result) = run_protocol(
+                |channel| alice.dbuildmw_tx_alice(coins, fund_value, lock_height, channel).unwrap(),
+                move |channel| bob.dbuild_mw_tx_bob(fund_value, channel).unwrap()
+            );
+
+            // both end up with the same valid transaction
+            let tx = alice_result.tx.tx.unwrap();
+            prop_assert_eq!(&tx, bob_result.tx.tx.as_ref().unwrap());
+            push_unlocked(&mut chain, &tx, KernelLock::from_height(lock_height)).unwrap();
+            // Bob's coin and Alice's change open with their keys
+            let bob_coin = bob_result.coin.unwrap();
+            prop_assert_eq!(fund_value, bob_coin.value);
+            prop_assert!(opens_with(&secp, &bob_coin, vec![&bob_coin]));
+            prop_assert!(chain.get_output(&bob_coin.commitment).is_some());
+            match alice_result.coin {
+                Some(change) => {
+                    prop_assert_eq!(total - fund_value - fee, change.value);
+                    prop_assert!(opens_with(&secp, &change, vec![&change]));
+                },
+                None => {
+                    prop_assert_eq!(total, fund_value + fee);
+                }
+            }
+        }
+
+        #[test]
+        fn prop_dshared_out_and_inp_mw_tx(
+            seed in any::<u64>(),
+            values in prop::collection::vec(NANO_GRIN / 10..10 * NANO_GRIN, 1..5),
+            share in 1..=100u64,
+            timelock in 0..10u64,
+            refund_timelock in 0..10u64
+        ) {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            let secp = Secp256k1::with_caps(ContextFlag::Commit);
+            let mut rng = SwapRng::seeded(seed);
+            let mut chain = GrinChain::new(1000);
+            let fund_value = shared_fund_value(&values, share);
+            let (alice_shared, bob_shared) = fund_shared_coin(&mut chain, &secp, &mut rng, &values, fund_value, timelock);
+
+            // the shared coin opens with the sum of both blinding factor shares only
+            prop_assert_eq!(&alice_shared.commitment, &bob_shared.commitment);
+            prop_assert_eq!(fund_value, alice_shared.value);
+            prop_assert!(opens_with(&secp, &alice_shared, vec![&alice_shared, &bob_shared]));
+            prop_assert!(!opens_with(&secp, &alice_shared, vec![&alice_shared]));
+            prop_assert!(!opens_with(&secp, &alice_shared, vec![&bob_shared]));
+
+            // spend the shared coin to Bob
+            let lock = KernelLock::from_height(if refund_timelock == 0 { 0 } else { chain.height() + refund_timelock });
+            let spend_value = fund_value - estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE);
+            let mut alice = GrinTx::new(rng.fork());
+            let mut bob = GrinTx::new(rng.fork());
+            let (alice_result, bob_result) = run_protocol(
+                |channel| alice.dshared_inp_mw_tx_alice(alice_shared.clone(), spend_value, lock, None, channel).unwrap(),
+                move |channel| bob.dshared_inp_mw_tx_bob(bob_shared, spend_value, lock, None, channel).unwrap()
+            );
+            let tx = bob_result.tx.tx.unwrap();
+            prop_assert_eq!(&tx.kernels(), &alice_result.tx.tx.as_ref().unwrap().kernels());
+            prop_assert!(alice_result.coin.is_none());
+            push_unlocked(&mut chain, &tx, lock).unwrap();
+            prop_assert!(chain.get_output(&alice_shared.commitment).unwrap().spent);
+            let bob_coin = bob_result.coin.unwrap();
+            prop_assert_eq!(spend_value, bob_coin.value);
+            prop_assert!(opens_with(&secp, &bob_coin, vec![&bob_coin]));
+        }
+
+        #[test]
+        fn prop_dcontract_mw_tx(
+            seed in any::<u64>(),
+            values in prop::collection::vec(NANO_GRIN / 10..10 * NANO_GRIN, 1..5),
+            share in 1..=100u64,
+            timelock in 0..10u64,
+            contract_timelock in 0..10u64
+        ) {
+            set_local_chain_type(ChainTypes::AutomatedTesting);
+            let secp = Secp256k1::with_caps(ContextFlag::Commit);
+            let mut rng = SwapRng::seeded(seed);
+            let mut chain = GrinChain::new(1000);
+            let fund_value = shared_fund_value(&values, share);
+            let (alice_shared, bob_shared) = fund_shared_coin(&mut chain, &secp, &mut rng, &values, fund_value, timelock);
+
+            let x = create_secret_key(&mut rng, &secp);
+            let pub_x = PublicKey::from_secret_key(&secp, &x).unwrap();
+            let bob_x = x.clone();
+            let lock_height = if contract_timelock == 0 { 0 } else { chain.height() + contract_timelock };
+            let spend_value = fund_value - estimate_fees(1, 1, 1, DEFAULT_ACCEPT_FEE_BASE);
+            let mut alice = GrinTx::new(rng.fork());
+            let mut bob = GrinTx::new(rng.fork());
+            let (alice_result, bob_result) = run_protocol(
+                |channel| alice.dcontract_mw_tx_alice(alice_shared.clone(), spend_value, lock_height, pub_x, channel).unwrap(),
+                move |channel| bob.dcontract_mw_tx_bob(bob_shared, spend_value, lock_height, bob_x, channel).unwrap()
+            );
+
+            // Alice extracted Bob's secret from the adapted signature
+            prop_assert_eq!(&x, &alice_result.x);
+            let tx = bob_result.tx.tx.unwrap();
+            prop_assert_eq!(&tx, alice_result.tx.tx.as_ref().unwrap());
+            push_unlocked(&mut chain, &tx, KernelLock::from_height(lock_height)).unwrap();
+            prop_assert!(chain.get_output(&alice_shared.commitment).unwrap().spent);
+            let bob_coin = bob_result.coin.unwrap();
+            prop_assert_eq!(spend_value, bob_coin.value);
+            prop_assert!(opens_with(&secp, &bob_coin, vec![&bob_coin]));
+        }
+    }
+}
\ No newline at end of file
@@ -1,5 +1,8 @@
 use serde::{Serialize, Deserialize};
-use grin_util::secp::key::SecretKey;
+use grin_core::core::NRDRelativeHeight;
+use grin_core::libtx::secp_ser;
+use grin_util::secp::key::{PublicKey, SecretKey};
+use grin_util::secp::Signature;
 use grin_util::secp::pedersen::Commitment;
 use crate::grin::grin_routines::{*};
 
@@ -30,4 +33,82 @@ impl MWCoin {
     pub fn to_string(&self) -> String {
         format!("Commitment : {}, blinding_factor : {}, value : {}", self.commitment, self.blinding_factor, self.value)
     }
-}
\ No newline at end of file
+}
+
+/// Lock of a transaction kernel as supported by the Grin kernel features
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum KernelLock {
+    /// Plain kernel, the transaction can be mined right away
+    NONE,
+    /// Height locked kernel, the transaction can be mined once the absolute block height is reached
+    HEIGHT(u64),
+    /// No recent duplicate kernel, can only be mined the given number of blocks after an earlier
+    /// NRD kernel with the same excess (the anchor). Without an anchor it is not locked at all.
+    NRD(u64)
+}
+
+impl KernelLock {
+    /// Kernel lock for an optional absolute lock height, 0 meaning no lock
+    ///
+    /// # Arguments
+    ///
+    /// * `lock_height` the absolute block height or 0
+    pub fn from_height(lock_height : u64) -> KernelLock {
+        if lock_height == 0 {
+            KernelLock::NONE
+        }
+        else {
+            KernelLock::HEIGHT(lock_height)
+        }
+    }
+
+    /// Kernel features id as used by the transaction slate (0 plain, 2 height locked, 3 NRD)
+    pub fn features(&self) -> u8 {
+        match self {
+            KernelLock::NONE => 0,
+            KernelLock::HEIGHT(_) => 2,
+            KernelLock::NRD(_) => 3
+        }
+    }
+
+    /// The absolute lock height or the relative height of an NRD kernel
+    pub fn lock_height(&self) -> Option<u64> {
+        match self {
+            KernelLock::NONE => None,
+            KernelLock::HEIGHT(h) => Some(*h),
+            KernelLock::NRD(h) => Some(*h)
+        }
+    }
+
+    /// Validate the lock parameters, the relative height of NRD kernels is limited to one week of blocks
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            KernelLock::NRD(h) => NRDRelativeHeight::new(*h)
+                .map(|_| ())
+                .map_err(|_| format!("Invalid relative height {} for a NRD kernel", h)),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Our share of the NRD anchor kernel added to a funding transaction. A refund kernel
+/// reusing the excess of the anchor can only be mined its relative height after the funding
+#[derive(Clone)]
+pub struct NRDAnchor {
+    /// our share of the anchor kernel excess
+    pub key : SecretKey,
+    /// the full anchor kernel excess, the sum of both shares
+    pub excess : PublicKey
+}
+
+/// Message exchanged to jointly sign the NRD anchor kernel, the first signer sends
+/// its public excess share and nonce, the second one also its partial signature
+#[derive(Serialize, Deserialize)]
+pub struct AnchorSigMsg {
+    #[serde(with = "secp_ser::pubkey_serde")]
+    pub excess : PublicKey,
+    #[serde(with = "secp_ser::pubkey_serde")]
+    pub nonce : PublicKey,
+    #[serde(with = "secp_ser::option_sig_serde")]
+    pub part_sig : Option<Signature>
+}
@@ -0,0 +1,294 @@
+use crate::grin::grin_core::GrinCore;
+use crate::grin::grin_routines::{
+    create_secret_key, deserialize_commitment, deserialize_grin_pub_key, deserialize_secret_key,
+    mp_bullet_proof_fin, mp_bullet_proof_r1, mp_bullet_proof_r2, serialize_commitment,
+    serialize_grin_public_key, serialize_secret_key, MPBPContext,
+};
+use crate::net::http::RequestFactory;
+use crate::sim::node::offline_settings;
+use crate::util::SwapRng;
+use grin_util::secp::constants::MAX_PROOF_SIZE;
+use grin_util::secp::pedersen::RangeProof;
+use grin_util::secp::{aggsig, ContextFlag, Message, PublicKey, Secp256k1, SecretKey, Signature};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+
+/// Checked in test vectors, created by the ignored `generate_vectors` test
+pub const VECTOR_FILE : &str = "test_vectors/grin_adaptor_bulletproof.json";
+/// Seeds the checked in vectors are generated from
+pub const VECTOR_SEEDS : [u64; 4] = [1, 2, 3, 4];
+
+/// Two party signature where the second party adapts its partial signature with
+/// the secret x, as in aptRecvCoins, finTx with pub_x and extWitness
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AdaptorVector {
+    pub seed : u64,
+    // inputs
+    pub msg : String,
+    pub sec_key_a : String,
+    pub sec_nonce_a : String,
+    pub sec_key_b : String,
+    pub sec_nonce_b : String,
+    pub x : String,
+    // outputs
+    pub pub_nonce_sum : String,
+    pub pub_blind_sum : String,
+    pub pub_x : String,
+    pub part_sig_a : String,
+    pub part_sig_b : String,
+    pub apt_sig_b : String,
+    pub final_sig : String,
+    pub witness : String
+}
+
+/// Two party bulletproof over a shared output, rounds r1, r2 and fin.
+/// Round 1 draws a helper commitment from the context generator, seeded with `ctx_seed`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BulletproofVector {
+    pub seed : u64,
+    // inputs
+    pub amount : u64,
+    pub blind_a : String,
+    pub blind_b : String,
+    pub nonce_a : String,
+    pub nonce_b : String,
+    pub shared_nonce : String,
+    pub ctx_seed : u64,
+    // outputs
+    pub commit : String,
+    pub t_1 : String,
+    pub t_2 : String,
+    pub tau_x : String,
+    pub proof : String
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Vectors {
+    pub adaptor : Vec<AdaptorVector>,
+    pub bulletproof : Vec<BulletproofVector>
+}
+
+fn secp() -> Secp256k1 {
+    Secp256k1::with_caps(ContextFlag::Commit)
+}
+
+fn serialize_sig(sig : &Signature) -> String {
+    hex::encode(&sig.to_raw_data()[..])
+}
+
+fn deserialize_sig(sig : &str) -> Signature {
+    let bytes = hex::decode(sig).unwrap();
+    let mut raw = [0u8; 64];
+    raw.copy_from_slice(&bytes);
+    Signature::from_raw_data(&raw).unwrap()
+}
+
+fn serialize_proof(proof : &RangeProof) -> String {
+    hex::encode(&proof.proof[..proof.plen])
+}
+
+fn deserialize_proof(proof : &str) -> RangeProof {
+    let bytes = hex::decode(proof).unwrap();
+    let mut raw = [0u8; MAX_PROOF_SIZE];
+    raw[..bytes.len()].copy_from_slice(&bytes);
+    RangeProof {
+        proof : raw,
+        plen : bytes.len()
+    }
+}
+
+/// Compute the outputs of an adaptor signature vector from its inputs
+///
+/// # Arguments
+///
+/// * `seed` seed recorded in the vector
+/// * `msg` the signed message
+/// * `keys` secret key and nonce of the first, then of the second party
+/// * `x` the secret the second party adapts its signature with
+fn adaptor_vector(seed : u64, msg : [u8; 32], keys : [SecretKey; 4], x : SecretKey) -> AdaptorVector {
+    let secp = secp();
+    let [key_a, nonce_a, key_b, nonce_b] = keys;
+    let message = Message::from_slice(&msg).unwrap();
+    let pub_key = |k : &SecretKey| PublicKey::from_secret_key(&secp, k).unwrap();
+    let pub_nonce_sum = PublicKey::from_combination(&secp, vec![&pub_key(&nonce_a), &pub_key(&nonce_b)]).unwrap();
+    let pub_blind_sum = PublicKey::from_combination(&secp, vec![&pub_key(&key_a), &pub_key(&key_b)]).unwrap();
+    let pub_x = pub_key(&x);
+    let sign = |key : &SecretKey, nonce : &SecretKey, extra : Option<&SecretKey>| {
+        aggsig::sign_single(&secp, &message, key, Some(nonce), extra, Some(&pub_nonce_sum), Some(&pub_blind_sum), Some(&pub_nonce_sum)).unwrap()
+    };
+    let part_sig_a = sign(&key_a, &nonce_a, None);
+    let part_sig_b = sign(&key_b, &nonce_b, None);
+    let apt_sig_b = sign(&key_b, &nonce_b, Some(&x));
+    // the adapted signature only verifies against the public x, as checked in finTx
+    assert!(aggsig::verify_single(&secp, &apt_sig_b, &message, Some(&pub_nonce_sum), &pub_key(&key_b), Some(&pub_blind_sum), Some(&pub_x), true));
+    assert!(!aggsig::verify_single(&secp, &apt_sig_b, &message, Some(&pub_nonce_sum), &pub_key(&key_b), Some(&pub_blind_sum), None, true));
+    let final_sig = aggsig::add_signatures_single(&secp, vec![&part_sig_a, &part_sig_b], &pub_nonce_sum).unwrap();
+    assert!(aggsig::verify_single(&secp, &final_sig, &message, None, &pub_blind_sum, Some(&pub_blind_sum), None, false));
+    let mut core = GrinCore::with_rng(offline_settings().grin, RequestFactory::new(None), SwapRng::seeded(seed));
+    let witness = core.ext_witness(part_sig_b, apt_sig_b).unwrap();
+    AdaptorVector {
+        seed : seed,
+        msg : hex::encode(msg),
+        sec_key_a : serialize_secret_key(&key_a),
+        sec_nonce_a : serialize_secret_key(&nonce_a),
+        sec_key_b : serialize_secret_key(&key_b),
+        sec_nonce_b : serialize_secret_key(&nonce_b),
+        x : serialize_secret_key(&x),
+        pub_nonce_sum : serialize_grin_public_key(&pub_nonce_sum, &secp),
+        pub_blind_sum : serialize_grin_public_key(&pub_blind_sum, &secp),
+        pub_x : serialize_grin_public_key(&pub_x, &secp),
+        part_sig_a : serialize_sig(&part_sig_a),
+        part_sig_b : serialize_sig(&part_sig_b),
+        apt_sig_b : serialize_sig(&apt_sig_b),
+        final_sig : serialize_sig(&final_sig),
+        witness : serialize_secret_key(&witness)
+    }
+}
+
+/// Compute the outputs of a bulletproof vector from its inputs
+///
+/// # Arguments
+///
+/// * `seed` seed recorded in the vector
+/// * `amount` value of the shared output
+/// * `keys` blinding factor share and nonce of the first, then of the second party
+/// * `shared_nonce` nonce known to both parties
+/// * `ctx_seed` seed of the proof context generator
+fn bulletproof_vector(seed : u64, amount : u64, keys : [SecretKey; 4], shared_nonce : SecretKey, ctx_seed : u64) -> BulletproofVector {
+    let secp = secp();
+    let [blind_a, nonce_a, blind_b, nonce_b] = keys;
+    let commit_a = secp.commit(amount, blind_a.clone()).unwrap();
+    let mut ctx = MPBPContext::new(shared_nonce.clone(), amount, commit_a, SwapRng::seeded(ctx_seed));
+    ctx = mp_bullet_proof_r1(ctx, blind_a.clone(), nonce_a.clone()).unwrap();
+    ctx = mp_bullet_proof_r1(ctx, blind_b.clone(), nonce_b.clone()).unwrap();
+    ctx.add_commit(secp.commit(0, blind_b.clone()).unwrap()).unwrap();
+    let commit = ctx.commit.clone();
+    ctx = mp_bullet_proof_r2(ctx, blind_a.clone(), nonce_a.clone()).unwrap();
+    ctx = mp_bullet_proof_r2(ctx, blind_b.clone(), nonce_b.clone()).unwrap();
+    // T1, T2 and tau_x are exchanged in the serialized context
+    let exchanged : Value = serde_json::from_str(&ctx.to_string()).unwrap();
+    let proof = mp_bullet_proof_fin(ctx, blind_a.clone(), nonce_a.clone()).unwrap();
+    assert!(secp.verify_bullet_proof(commit, proof, None).is_ok());
+    BulletproofVector {
+        seed : seed,
+        amount : amount,
+        blind_a : serialize_secret_key(&blind_a),
+        blind_b : serialize_secret_key(&blind_b),
+        nonce_a : serialize_secret_key(&nonce_a),
+        nonce_b : serialize_secret_key(&nonce_b),
+        shared_nonce : serialize_secret_key(&shared_nonce),
+        ctx_seed : ctx_seed,
+        commit : serialize_commitment(&commit),
+        t_1 : exchanged["t_1"].as_str().unwrap().to_string(),
+        t_2 : exchanged["t_2"].as_str().unwrap().to_string(),
+        tau_x : exchanged["tau_x"].as_str().unwrap().to_string(),
+        proof : serialize_proof(&proof)
+    }
+}
+
+/// Generate the vectors for a seed
+///
+/// # Arguments
+///
+/// * `seed` seed of the generator all inputs are drawn from
+pub fn generate(seed : u64) -> (AdaptorVector, BulletproofVector) {
+    let secp = secp();
+    let mut rng = SwapRng::seeded(seed);
+    let mut keys = || [
+        create_secret_key(&mut rng, &secp),
+        create_secret_key(&mut rng, &secp),
+        create_secret_key(&mut rng, &secp),
+        create_secret_key(&mut rng, &secp)
+    ];
+    let adaptor_keys = keys();
+    let bulletproof_keys = keys();
+    let msg : [u8; 32] = rng.gen();
+    let x = create_secret_key(&mut rng, &secp);
+    let amount = u64::from(rng.gen::<u32>()) + 1;
+    let shared_nonce = create_secret_key(&mut rng, &secp);
+    let ctx_seed : u64 = rng.gen();
+    (
+        adaptor_vector(seed, msg, adaptor_keys, x),
+        bulletproof_vector(seed, amount, bulletproof_keys, shared_nonce, ctx_seed)
+    )
+}
+
+/// Recompute an adaptor signature vector from its inputs, panics if an output differs
+///
+/// # Arguments
+///
+/// * `v` the vector to check
+pub fn verify_adaptor(v : &AdaptorVector) {
+    let secp = secp();
+    let key = |k : &String| deserialize_secret_key(k, &secp).unwrap();
+    let mut msg = [0u8; 32];
+    msg.copy_from_slice(&hex::decode(&v.msg).unwrap());
+    let keys = [key(&v.sec_key_a), key(&v.sec_nonce_a), key(&v.sec_key_b), key(&v.sec_nonce_b)];
+    let computed = adaptor_vector(v.seed, msg, keys, key(&v.x));
+    assert_eq!(v, &computed);
+    // the recorded signatures parse and the extracted witness is the secret
+    assert_eq!(v.apt_sig_b, serialize_sig(&deserialize_sig(&v.apt_sig_b)));
+    assert_eq!(v.x, v.witness);
+    assert_eq!(v.pub_x, serialize_grin_public_key(&deserialize_grin_pub_key(&v.pub_x, &secp).unwrap(), &secp));
+}
+
+/// Recompute a bulletproof vector from its inputs and verify its proof, panics if an output differs
+///
+/// # Arguments
+///
+/// * `v` the vector to check
+pub fn verify_bulletproof(v : &BulletproofVector) {
+    let secp = secp();
+    let key = |k : &String| deserialize_secret_key(k, &secp).unwrap();
+    let keys = [key(&v.blind_a), key(&v.nonce_a), key(&v.blind_b), key(&v.nonce_b)];
+    let computed = bulletproof_vector(v.seed, v.amount, keys, key(&v.shared_nonce), v.ctx_seed);
+    assert_eq!(v, &computed);
+    let proof = deserialize_proof(&v.proof);
+    assert!(secp.verify_bullet_proof(deserialize_commitment(&v.commit).unwrap(), proof, None).is_ok());
+}
+
+/// Read the checked in vectors
+pub fn load_vectors() -> Result<Vectors, String> {
+    let json = fs::read_to_string(VECTOR_FILE)
+        .map_err(|e| format!("Unable to read {}, run the ignored generate_vectors test to create it: {}", VECTOR_FILE, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Test vector file is invalid: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate, load_vectors, verify_adaptor, verify_bulletproof, Vectors, VECTOR_FILE, VECTOR_SEEDS};
+    use std::fs;
+
+    #[test]
+    fn test_generated_vectors() {
+        for seed in VECTOR_SEEDS.iter() {
+            let (adaptor, bulletproof) = generate(*seed);
+            verify_adaptor(&adaptor);
+            verify_bulletproof(&bulletproof);
+            assert_eq!((adaptor, bulletproof), generate(*seed));
+        }
+    }
+
+    #[test]
+    fn test_checked_in_vectors() {
+        let vectors = load_vectors().unwrap();
+        assert_eq!(VECTOR_SEEDS.len(), vectors.adaptor.len());
+        assert_eq!(VECTOR_SEEDS.len(), vectors.bulletproof.len());
+        vectors.adaptor.iter().for_each(verify_adaptor);
+        vectors.bulletproof.iter().for_each(verify_bulletproof);
+    }
+
+    #[test]
+    #[ignore]
+    fn generate_vectors() {
+        let (adaptor, bulletproof) : (Vec<_>, Vec<_>) = VECTOR_SEEDS.iter().map(|seed| generate(*seed)).unzip();
+        let vectors = Vectors {
+            adaptor : adaptor,
+            bulletproof : bulletproof
+        };
+        fs::create_dir_all("test_vectors").unwrap();
+        fs::write(VECTOR_FILE, serde_json::to_string_pretty(&vectors).unwrap()).unwrap();
+    }
+}
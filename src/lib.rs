@@ -0,0 +1,18 @@
+use crate::swap::swap_types::SwapSlate;
+use settings::Settings;
+
+pub mod settings;
+pub mod api;
+pub mod book;
+pub mod commands;
+pub mod enums;
+pub mod error;
+pub mod swap;
+pub mod constants;
+pub mod grin;
+pub mod bitcoin;
+pub mod net;
+pub mod util;
+pub mod units;
+#[cfg(test)]
+mod sim;
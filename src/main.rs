@@ -1,28 +1,21 @@
-use crate::swap::swap_types::SwapSlate;
+use mw_btc_swap::swap::swap_types::SwapSlate;
+use mw_btc_swap::swap::store::store_types::SwapStore;
+use mw_btc_swap::{commands, swap, util};
+use mw_btc_swap::error::SwapError;
 use std::fs;
 use std::env;
 
-mod settings;
-mod commands;
-mod enums;
-mod swap;
-mod constants;
-mod grin;
-mod bitcoin;
-mod net;
-mod util;
-
 use clap::{
     Arg,
     App,
     SubCommand
 };
 
-use settings::Settings;
+use mw_btc_swap::settings::{self, Settings};
 use grin_util::secp::{ContextFlag, Secp256k1 as GrinSecp256k1};
 
 fn usage() {
-    println!("usage: init|accept|import|lock|execute <options>");
+    println!("usage: init|accept|import|listen|lock|execute|cancel|refund|list|status|api|book <options>");
 }
 
 /// Setting variables can be overwritten with environment variables
@@ -35,21 +28,39 @@ fn overwrite_settings_with_env(settings : &Settings) -> Settings {
     let tcp_addr = env::var("SETTINGS_TCP_ADDR").unwrap_or(settings.tcp_addr.clone());
     let tcp_port = env::var("SETTINGS_TCP_PORT").unwrap_or(settings.tcp_port.clone());
     let slate_directory = env::var("SETTINGS_SLATE_DIRECTORY").unwrap_or(settings.slate_directory.clone());
+    let storage = env::var("SETTINGS_STORAGE").unwrap_or(settings.storage.clone());
+    let price_file = env::var("SETTINGS_PRICE_FILE").unwrap_or(settings.price_file.clone());
 
     Settings{
         btc : settings.btc.clone(),
         grin : settings.grin.clone(),
         tcp_addr : tcp_addr,
         tcp_port : tcp_port,
-        slate_directory : slate_directory
+        slate_directory : slate_directory,
+        storage : storage,
+        safety : settings.safety,
+        fees : settings.fees,
+        api : settings.api.clone(),
+        book : settings.book.clone(),
+        price_file : price_file
     }
 }
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error ({}): {}", e.kind(), e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Parse the command line and execute the command, the slate a command
+/// returns is persisted before exiting
+fn run() -> Result<(), SwapError> {
     let contents = fs::read_to_string("config/settings.json")
-        .expect("Something went wrong reading the settings file");
+        .map_err(|e| SwapError::Config(format!("Unable to read the settings file: {}", e)))?;
 
-    let read_settings = settings::Settings::parse_json_string(&contents);
+    let read_settings = settings::Settings::parse_json_string(&contents)
+        .map_err(SwapError::Config)?;
     let settings = overwrite_settings_with_env(&read_settings);
 
     // Initilize RNG
@@ -80,14 +91,46 @@ fn main() {
                             )
                             .arg(Arg::with_name("to-amount")
                                 .long("to-amount")
-                                .required(true)
                                 .takes_value(true)
                             )
+                            .arg(Arg::with_name("price")
+                                .long("price")
+                                .takes_value(true)
+                                .help("Compute the to amount from a price in BTC per GRIN")
+                            )
+                            .arg(Arg::with_name("spread")
+                                .long("spread")
+                                .takes_value(true)
+                                .allow_hyphen_values(true)
+                                .help("Compute the to amount from the price file plus a spread in basis points")
+                            )
                             .arg(Arg::with_name("timeout")
                                 .long("timeout")
                                 .required(true)
                                 .takes_value(true)
                             )
+                            .arg(Arg::with_name("expiry")
+                                .long("expiry")
+                                .takes_value(true)
+                            )
+                            .arg(Arg::with_name("btc-lock")
+                                .long("btc-lock")
+                                .takes_value(true)
+                            )
+                            .arg(Arg::with_name("grin-lock")
+                                .long("grin-lock")
+                                .takes_value(true)
+                            )
+                            .arg(Arg::with_name("min-fill")
+                                .long("min-fill")
+                                .takes_value(true)
+                                .help("Make the offer partially fillable, takers fill at least this amount")
+                            )
+                            .arg(Arg::with_name("max-fill")
+                                .long("max-fill")
+                                .takes_value(true)
+                                .help("Largest amount a single taker can fill, defaults to the from amount")
+                            )
                         )
                         .subcommand(SubCommand::with_name("import")
                             .subcommand(SubCommand::with_name("btc")
@@ -158,6 +201,15 @@ fn main() {
                                 .required(true)
                                 .takes_value(true)
                             )
+                            .arg(Arg::with_name("book")
+                                .long("book")
+                                .help("Fetch the offer from the offer book instead of the slate directory")
+                            )
+                            .arg(Arg::with_name("fill")
+                                .long("fill")
+                                .takes_value(true)
+                                .help("Take a part of a partially fillable offer, in the offered currency")
+                            )
                         )
                         .subcommand(SubCommand::with_name("lock")
                             .arg(Arg::with_name("swapid")
@@ -173,6 +225,14 @@ fn main() {
                                 .takes_value(true)
                             )
                         )
+                        .subcommand(SubCommand::with_name("refund")
+                            .about("Refund our locked funds once the lock time has passed, without contacting the peer")
+                            .arg(Arg::with_name("swapid")
+                                .long("swapid")
+                                .required(true)
+                                .takes_value(true)
+                            )
+                        )
                         .subcommand(SubCommand::with_name("execute")
                             .arg(Arg::with_name("swapid")
                                 .long("swapid")
@@ -180,6 +240,74 @@ fn main() {
                                 .takes_value(true)
                             )
                         )
+                        .subcommand(SubCommand::with_name("list")
+                            .about("List all stored swaps")
+                            .arg(Arg::with_name("status")
+                                .long("status")
+                                .takes_value(true)
+                            )
+                            .arg(Arg::with_name("currency")
+                                .long("currency")
+                                .takes_value(true)
+                            )
+                        )
+                        .subcommand(SubCommand::with_name("status")
+                            .about("Show the progress of a swap on both chains and the next action to take")
+                            .arg(Arg::with_name("swapid")
+                                .long("swapid")
+                                .required(true)
+                                .takes_value(true)
+                            )
+                        )
+                        .subcommand(SubCommand::with_name("api")
+                            .about("Run the JSON-RPC API server")
+                        )
+                        .subcommand(SubCommand::with_name("book")
+                            .about("Publish, find and withdraw offers on an offer book")
+                            .subcommand(SubCommand::with_name("serve")
+                                .about("Run the offer book server")
+                            )
+                            .subcommand(SubCommand::with_name("publish")
+                                .arg(Arg::with_name("swapid")
+                                    .long("swapid")
+                                    .required(true)
+                                    .takes_value(true)
+                                )
+                            )
+                            .subcommand(SubCommand::with_name("withdraw")
+                                .arg(Arg::with_name("swapid")
+                                    .long("swapid")
+                                    .required(true)
+                                    .takes_value(true)
+                                )
+                            )
+                            .subcommand(SubCommand::with_name("list")
+                                .arg(Arg::with_name("offered")
+                                    .long("offered")
+                                    .takes_value(true)
+                                )
+                                .arg(Arg::with_name("requested")
+                                    .long("requested")
+                                    .takes_value(true)
+                                )
+                                .arg(Arg::with_name("min-amount")
+                                    .long("min-amount")
+                                    .takes_value(true)
+                                )
+                                .arg(Arg::with_name("max-amount")
+                                    .long("max-amount")
+                                    .takes_value(true)
+                                )
+                                .arg(Arg::with_name("min-rate")
+                                    .long("min-rate")
+                                    .takes_value(true)
+                                )
+                                .arg(Arg::with_name("max-rate")
+                                    .long("max-rate")
+                                    .takes_value(true)
+                                )
+                            )
+                        )
                         .get_matches();
 
     let args: Vec<String> = env::args().collect();
@@ -188,11 +316,16 @@ fn main() {
         usage();
     }
     else {
-        let cmd = commands::parser::parse_arguments(matches)
-            .expect("Failed to parse command line arguments");
-        let slate : SwapSlate = cmd.execute(&settings, &mut rng, &btc_secp, &grin_secp)
-            .expect("Command execution failed");
-        
-        swap::slate::write_slate_to_disk(&slate, &settings.slate_directory, true, true);
+        let cmd = commands::parser::parse_arguments(matches)?;
+        let mut store = swap::slate::open_store(&settings)
+            .map_err(SwapError::Io)?;
+        let chain = commands::context::ChainContext::from_settings(&settings);
+        let slate : Option<SwapSlate> = cmd.execute(&settings, &chain, store.as_mut(), &mut rng, &btc_secp, &grin_secp)?;
+
+        if let Some(slate) = slate {
+            store.write_slate(&slate, true, true)
+                .map_err(|e| SwapError::Io(format!("Failed to write slate: {}", e)))?;
+        }
     }
+    Ok(())
 }
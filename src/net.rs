@@ -1,2 +1,6 @@
 pub mod tcp;
-pub mod http;
\ No newline at end of file
+pub mod http;
+#[cfg(test)]
+pub mod memory;
+#[cfg(test)]
+pub mod stub;
\ No newline at end of file
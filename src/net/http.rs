@@ -2,6 +2,7 @@ use crate::enums::HttpMethod;
 use grin_core::core::Transaction;
 use serde::{Deserialize, Serialize, Serializer, ser::SerializeSeq};
 use std::clone::Clone;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Serialize, Deserialize)]
@@ -11,7 +12,8 @@ pub enum JsonRpcParam {
     Int(u64),
     Bool(bool),
     Vec(Vec<String>),
-    Tx(Transaction)
+    Tx(Transaction),
+    Null
 }
 
 /**
@@ -50,8 +52,19 @@ pub struct BasicAuth {
 }
 
 
+/// Answers requests in place of a http server, used in testing
+pub trait RequestHandler : Send + Sync {
+    /// Answer a request
+    ///
+    /// # Arguments
+    /// * `url` the request URL
+    /// * `body` the JSON RPC request
+    fn handle(&self, url : &str, body : &JsonRpc) -> Result<HttpResponse, String>;
+}
+
 pub struct RequestFactory {
-    response_stub : Option<HttpResponse>
+    response_stub : Option<HttpResponse>,
+    handler : Option<Arc<dyn RequestHandler>>
 }
 
 
@@ -60,7 +73,8 @@ pub struct HttpRequest {
     method : HttpMethod,
     body : JsonRpc,
     auth : Option<BasicAuth>,
-    response_stub : Option<HttpResponse>
+    response_stub : Option<HttpResponse>,
+    handler : Option<Arc<dyn RequestHandler>>
 }
 
 pub struct HttpResponse {
@@ -104,7 +118,21 @@ impl RequestFactory {
     ///                   can be used in testing
     pub fn new(response_stub : Option<HttpResponse>) -> RequestFactory {
         RequestFactory{
-            response_stub
+            response_stub,
+            handler : None
+        }
+    }
+
+    /// Construct a RequestFactory whose requests are answered by a handler
+    /// instead of being sent over http
+    ///
+    /// # Arguments
+    /// * `handler` the handler answering the requests
+    #[cfg(test)]
+    pub fn with_handler(handler : Arc<dyn RequestHandler>) -> RequestFactory {
+        RequestFactory {
+            response_stub : None,
+            handler : Some(handler)
         }
     }
 
@@ -125,7 +153,8 @@ impl RequestFactory {
             method : HttpMethod::POST,
             body : body,
             auth : Some(auth),
-            response_stub : self.response_stub.clone()
+            response_stub : self.response_stub.clone(),
+            handler : self.handler.clone()
         }
     }
 }
@@ -134,7 +163,10 @@ impl HttpRequest {
 
     /// Execute a http request and return its result
     pub fn execute(&self) -> Result<HttpResponse,String> {
-        if self.response_stub.is_some() {
+        if let Some(handler) = &self.handler {
+            handler.handle(&self.url, &self.body)
+        }
+        else if self.response_stub.is_some() {
             let r = self.response_stub.as_ref().unwrap();
             if r.status != 200 {
                 Err(format!("RPC Request failed with invalid respcode: {}", r.status))
@@ -160,10 +192,10 @@ impl HttpRequest {
                 _ => ()
             } 
             let res = req.send()
-                .expect("Http Request failed");
+                .map_err(|e| format!("Http Request failed: {}", e))?;
             let status = res.status();
             let content = res.text()
-                    .expect("Failed to read http response");
+                    .map_err(|e| format!("Failed to read http response: {}", e))?;
             if status.is_success() {
                 Ok(HttpResponse {
                     status : status.as_u16(),
@@ -193,8 +225,9 @@ mod test {
         params.push(JsonRpcParam::Bool(true));
         params.push(JsonRpcParam::Int(64));
         params.push(JsonRpcParam::Vec(vec![String::from("abc"), String::from("cde")]));
+        params.push(JsonRpcParam::Null);
         let serialized = serde_json::to_string(&params).unwrap();
         println!("{}",serialized);
-        assert_eq!(r#"["Test",true,64,["abc","cde"]]"#, serialized);
+        assert_eq!(r#"["Test",true,64,["abc","cde"],null]"#, serialized);
     }
 }
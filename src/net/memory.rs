@@ -0,0 +1,96 @@
+use crate::net::tcp::MessageChannel;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+
+// a peer which doesn't answer within this time is treated like a dropped connection
+const READ_TIMEOUT : Duration = Duration::from_secs(60);
+
+/// One end of an in memory message channel, used to run both parties of a
+/// two-party protocol in a single test without sockets
+pub struct MemoryChannel {
+    tx : Sender<String>,
+    rx : Receiver<String>,
+    // rewrites the messages we send, used to play a misbehaving peer
+    tamper : Option<Box<dyn FnMut(String) -> String + Send>>
+}
+
+/// Create two connected channel ends, what is written to one is read from the other
+pub fn memory_pair() -> (MemoryChannel, MemoryChannel) {
+    let (tx_a, rx_b) = channel();
+    let (tx_b, rx_a) = channel();
+    (MemoryChannel::new(tx_a, rx_a), MemoryChannel::new(tx_b, rx_b))
+}
+
+impl MemoryChannel {
+    fn new(tx : Sender<String>, rx : Receiver<String>) -> MemoryChannel {
+        MemoryChannel {
+            tx : tx,
+            rx : rx,
+            tamper : None
+        }
+    }
+
+    /// Rewrite every message written to this end before it reaches the peer,
+    /// ACK messages included
+    ///
+    /// # Arguments
+    ///
+    /// * `f` maps a message to the message the peer receives
+    pub fn tamper<F : FnMut(String) -> String + Send + 'static>(&mut self, f : F) {
+        self.tamper = Some(Box::new(f));
+    }
+}
+
+impl MessageChannel for MemoryChannel {
+    fn write_msg(&mut self, msg : &str) -> Result<(), String> {
+        let msg = match &mut self.tamper {
+            Some(f) => f(String::from(msg)),
+            None => String::from(msg)
+        };
+        self.tx.send(msg)
+            .map_err(|_| String::from("Peer closed the channel"))
+    }
+
+    fn read_msg(&mut self) -> Result<String, String> {
+        self.rx.recv_timeout(READ_TIMEOUT)
+            .map_err(|e| format!("Unable to read from peer: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::memory_pair;
+    use crate::net::tcp::{receive_msg, send_msg, MessageChannel};
+    use std::thread;
+
+    #[test]
+    fn test_exchange() {
+        let (mut alice, mut bob) = memory_pair();
+        let peer = thread::spawn(move || {
+            let msg = receive_msg(&mut bob).unwrap();
+            send_msg(&mut bob, &format!("{} back", msg)).unwrap();
+        });
+        send_msg(&mut alice, &String::from("hello")).unwrap();
+        assert_eq!("hello back", receive_msg(&mut alice).unwrap());
+        peer.join().unwrap();
+        // the peer is gone
+        assert!(alice.write_msg("hello").is_err());
+        assert!(alice.read_msg().is_err());
+    }
+
+    #[test]
+    fn test_tamper() {
+        let (mut alice, mut bob) = memory_pair();
+        let mut count = 0;
+        bob.tamper(move |msg| {
+            count = count + 1;
+            if count == 2 { String::from("forged") } else { msg }
+        });
+        bob.write_msg("one").unwrap();
+        bob.write_msg("two").unwrap();
+        bob.write_msg("three").unwrap();
+        assert_eq!("one", alice.read_msg().unwrap());
+        assert_eq!("forged", alice.read_msg().unwrap());
+        assert_eq!("three", alice.read_msg().unwrap());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::net::http::{HttpResponse, JsonRpc, RequestFactory, RequestHandler};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// What the stub answers to a matched request
+pub enum StubReply {
+    /// A http response, a status other than 200 fails the request like a real node would
+    Response(HttpResponse),
+    /// The node didn't answer in time
+    Timeout,
+    /// The node couldn't be reached
+    ConnectionFailed
+}
+
+impl StubReply {
+    /// A successful response with a JSON body
+    ///
+    /// # Arguments
+    ///
+    /// * `content` the response body
+    pub fn ok(content : &str) -> StubReply {
+        StubReply::Response(HttpResponse {
+            status : 200,
+            content : String::from(content)
+        })
+    }
+}
+
+/// A request received by the stub
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub url : String,
+    pub method : String,
+    pub params : Value,
+    pub body : String
+}
+
+struct Script {
+    method : String,
+    // None matches any params
+    params : Option<Value>,
+    replies : VecDeque<StubReply>
+}
+
+struct StubState {
+    scripts : Vec<Script>,
+    requests : Vec<RecordedRequest>
+}
+
+/// Programmable stand-in for the http endpoint of a node.
+/// Requests are matched by their JSON-RPC method and optionally their params,
+/// the replies queued for a match are returned in order. Scripts with params are
+/// preferred over scripts matching the method only. Every request is recorded,
+/// a request without a queued reply fails like an unreachable node.
+#[derive(Clone)]
+pub struct ScriptedStub {
+    state : Arc<Mutex<StubState>>
+}
+
+impl ScriptedStub {
+    pub fn new() -> ScriptedStub {
+        ScriptedStub {
+            state : Arc::new(Mutex::new(StubState {
+                scripts : Vec::new(),
+                requests : Vec::new()
+            }))
+        }
+    }
+
+    /// Queue a reply for a request calling a method with any params
+    ///
+    /// # Arguments
+    ///
+    /// * `method` the JSON-RPC method
+    /// * `reply` the reply to return
+    pub fn expect(&self, method : &str, reply : StubReply) -> &ScriptedStub {
+        self.queue(method, None, reply)
+    }
+
+    /// Queue a reply for a request calling a method with exactly these params
+    ///
+    /// # Arguments
+    ///
+    /// * `method` the JSON-RPC method
+    /// * `params` the params array of the request
+    /// * `reply` the reply to return
+    pub fn expect_params(&self, method : &str, params : Value, reply : StubReply) -> &ScriptedStub {
+        self.queue(method, Some(params), reply)
+    }
+
+    /// A request factory whose requests are answered by this stub
+    pub fn factory(&self) -> RequestFactory {
+        RequestFactory::with_handler(Arc::new(self.clone()))
+    }
+
+    /// All requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The requests received so far which called a method
+    ///
+    /// # Arguments
+    ///
+    /// * `method` the JSON-RPC method
+    pub fn calls(&self, method : &str) -> Vec<RecordedRequest> {
+        self.requests().into_iter()
+            .filter(|r| r.method == method)
+            .collect()
+    }
+
+    /// Number of queued replies which haven't been returned yet
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().scripts.iter()
+            .map(|s| s.replies.len())
+            .sum()
+    }
+
+    fn queue(&self, method : &str, params : Option<Value>, reply : StubReply) -> &ScriptedStub {
+        {
+            let mut state = self.state.lock().unwrap();
+            match state.scripts.iter_mut().find(|s| s.method == method && s.params == params) {
+                Some(script) => script.replies.push_back(reply),
+                None => {
+                    let mut replies = VecDeque::new();
+                    replies.push_back(reply);
+                    state.scripts.push(Script {
+                        method : String::from(method),
+                        params : params,
+                        replies : replies
+                    });
+                }
+            }
+        }
+        self
+    }
+}
+
+impl RequestHandler for ScriptedStub {
+    fn handle(&self, url : &str, body : &JsonRpc) -> Result<HttpResponse, String> {
+        let value = serde_json::to_value(body)
+            .map_err(|e| format!("Unable to serialize request: {}", e))?;
+        let request = RecordedRequest {
+            url : String::from(url),
+            method : value["method"].as_str().unwrap_or("").to_string(),
+            params : value["params"].clone(),
+            body : value.to_string()
+        };
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(request.clone());
+        let reply = state.scripts.iter_mut()
+            .filter(|s| s.method == request.method && !s.replies.is_empty())
+            .filter(|s| s.params.is_none() || s.params.as_ref() == Some(&request.params))
+            .max_by_key(|s| s.params.is_some())
+            .and_then(|s| s.replies.pop_front());
+        match reply {
+            Some(StubReply::Response(r)) => {
+                if r.status != 200 {
+                    Err(format!("RPC Request failed with invalid respcode: {}", r.status))
+                }
+                else {
+                    Ok(r)
+                }
+            },
+            Some(StubReply::Timeout) => Err(String::from("Http Request failed: operation timed out")),
+            Some(StubReply::ConnectionFailed) => Err(String::from("Http Request failed: connection refused")),
+            None => Err(format!("Http Request failed: no scripted reply for {} {}", request.method, request.params))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ScriptedStub, StubReply};
+    use crate::bitcoin::bitcoin_core::BitcoinCore;
+    use crate::bitcoin::bitcoin_types::BTCInput;
+    use crate::bitcoin::btcroutines::{create_private_key, get_lock_pub_script, serialize_priv_key, serialize_pub_key};
+    use crate::enums::{ChainStatus, Currency};
+    use crate::error::SwapError;
+    use crate::grin::grin_core::GrinCore;
+    use crate::net::http::HttpResponse;
+    use crate::sim::node::offline_settings;
+    use crate::swap::protocol::refund_phase_swap_btc;
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+    use crate::SwapSlate;
+    use bitcoin::consensus::encode::deserialize;
+    use bitcoin::secp256k1::{All, Secp256k1};
+    use bitcoin::{PublicKey, Transaction};
+    use serde_json::{json, Value};
+
+    const TXID : &str = "211db342720933ca50f4b713c462823f0440f0fc29c3b61ba4f9d1d9e65d4e1b";
+
+    fn btc_result(result : Value) -> String {
+        json!({ "result" : result, "error" : null, "id" : "mw-btc-swap" }).to_string()
+    }
+
+    fn grin_tip(height : u64) -> String {
+        json!({ "id" : "mw-btc-swap", "jsonrpc" : "2.0", "result" : { "Ok" : {
+            "height" : height,
+            "last_block_pushed" : "00",
+            "prev_block_to_last" : "00",
+            "total_difficulty" : 1
+        }}}).to_string()
+    }
+
+    /// A swap whose Bitcoin is locked until height 2000 and Grin until height 1000
+    fn locked_btc_slate(secp : &Secp256k1<All>) -> SwapSlate {
+        let mut rng = get_os_rng();
+        let sk_a = create_private_key(&mut rng);
+        let sk_b = create_private_key(&mut rng);
+        let x = create_private_key(&mut rng);
+        let pub_a = PublicKey::from_private_key(secp, &sk_a);
+        let pub_b = PublicKey::from_private_key(secp, &sk_b);
+        let pub_x = PublicKey::from_private_key(secp, &x);
+        let mut slate = sample_slate(1, true);
+        slate.pub_slate.btc.pub_a = Some(serialize_pub_key(&pub_a));
+        slate.pub_slate.btc.lock_time = Some(2000);
+        slate.pub_slate.mw.lock_time = Some(1000);
+        slate.prv_slate.btc.sk = Some(serialize_priv_key(&sk_b));
+        slate.prv_slate.btc.x = Some(serialize_priv_key(&x));
+        let script = get_lock_pub_script(pub_a, pub_x, pub_b, 2000, true);
+        slate.prv_slate.btc.lock = Some(BTCInput::new2(String::from(TXID), 0, slate.pub_slate.btc.amount, sk_b, pub_b, script));
+        slate.set_chain_status(Currency::BTC, ChainStatus::LOCKED);
+        slate
+    }
+
+    #[test]
+    fn test_replies_in_order() {
+        let stub = ScriptedStub::new();
+        stub.expect("getblockcount", StubReply::ok(&btc_result(json!(100))))
+            .expect("getblockcount", StubReply::ok(&btc_result(json!(101))));
+        let core = BitcoinCore::new(offline_settings().btc, stub.factory());
+        assert_eq!(100, core.get_current_block_height().unwrap());
+        assert_eq!(101, core.get_current_block_height().unwrap());
+        assert_eq!(0, stub.pending());
+        // nothing left to answer
+        assert!(core.get_current_block_height().unwrap_err().contains("no scripted reply"));
+
+        let requests = stub.requests();
+        assert_eq!(3, requests.len());
+        assert_eq!("http://127.0.0.1:1", requests[0].url);
+        assert_eq!("getblockcount", requests[0].method);
+        assert_eq!(json!([]), requests[0].params);
+    }
+
+    #[test]
+    fn test_match_params() {
+        let stub = ScriptedStub::new();
+        let not_found = json!({ "result" : null, "error" : { "code" : -5, "message" : "No such mempool or blockchain transaction" }, "id" : "mw-btc-swap" });
+        stub.expect("getrawtransaction", StubReply::ok(&not_found.to_string()))
+            .expect_params("getrawtransaction", json!([TXID, true]), StubReply::ok(&btc_result(json!({ "txid" : TXID, "confirmations" : 12 }))));
+        let core = BitcoinCore::new(offline_settings().btc, stub.factory());
+        assert_eq!(12, core.get_tx_confirmations(TXID).unwrap());
+        assert_eq!(Err(String::from("No such mempool or blockchain transaction")), core.get_tx_confirmations("00"));
+        let calls = stub.calls("getrawtransaction");
+        assert_eq!(json!([TXID, true]), calls[0].params);
+        assert_eq!(json!(["00", true]), calls[1].params);
+    }
+
+    #[test]
+    fn test_failures() {
+        let stub = ScriptedStub::new();
+        stub.expect("get_tip", StubReply::Timeout)
+            .expect("get_tip", StubReply::ConnectionFailed)
+            .expect("get_tip", StubReply::Response(HttpResponse { status : 500, content : String::new() }))
+            .expect("get_tip", StubReply::ok(&grin_tip(700000)));
+        let core = GrinCore::new(offline_settings().grin, stub.factory());
+        assert!(core.get_block_height().unwrap_err().contains("timed out"));
+        assert!(core.get_block_height().unwrap_err().contains("connection refused"));
+        assert!(core.get_block_height().unwrap_err().contains("500"));
+        assert_eq!(700000, core.get_block_height().unwrap());
+    }
+
+    #[test]
+    fn test_refund_btc() {
+        let secp = get_secp256k1_curve();
+        let mut rng = get_os_rng();
+        let settings = offline_settings();
+        let btc_stub = ScriptedStub::new();
+        let grin_stub = ScriptedStub::new();
+        let btc = BitcoinCore::new(settings.btc, btc_stub.factory());
+        let grin = GrinCore::new(settings.grin, grin_stub.factory());
+        let mut slate = locked_btc_slate(&secp);
+
+        // the Bitcoin lock height has not passed yet
+        grin_stub.expect("get_tip", StubReply::ok(&grin_tip(1001)));
+        btc_stub.expect("getblockcount", StubReply::ok(&btc_result(json!(2000))));
+        assert_eq!(Err(SwapError::Timelock(String::from("Can't refund yet, too early"))), refund_phase_swap_btc(&mut slate, &btc, &grin, &secp, &mut rng));
+        assert!(btc_stub.calls("sendrawtransaction").is_empty());
+
+        grin_stub.expect("get_tip", StubReply::ok(&grin_tip(1001)));
+        btc_stub.expect("getblockcount", StubReply::ok(&btc_result(json!(2001))))
+            .expect("sendrawtransaction", StubReply::ok(&btc_result(json!(TXID))));
+        refund_phase_swap_btc(&mut slate, &btc, &grin, &secp, &mut rng).unwrap();
+        assert_eq!(0, btc_stub.pending());
+        assert_eq!(0, grin_stub.pending());
+        assert_eq!(ChainStatus::REFUND_BROADCAST, slate.pub_slate.btc.status);
+
+        let sent = btc_stub.calls("sendrawtransaction");
+        assert_eq!(1, sent.len());
+        let tx : Transaction = deserialize(&hex::decode(sent[0].params[0].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(2000, tx.lock_time);
+        assert_eq!(TXID, tx.input[0].previous_output.txid.to_string());
+        assert_eq!(slate.prv_slate.btc.refunded.unwrap().txid, tx.txid().to_string());
+    }
+}
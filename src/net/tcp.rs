@@ -1,42 +1,74 @@
-use std::{io::{BufReader, BufRead}, thread::sleep, time::Duration};
+use std::{io::Read, thread::sleep, time::Duration};
 use std::net::{TcpStream};
-use std::io::Write;
+use std::io::{ErrorKind, Write};
+use crate::constants::MAX_ATTEMPTS_VERF_FUNDS;
 
+// longest message we read from a peer, slates and signatures are far smaller
+const MAX_MSG_SIZE : usize = 1024 * 1024;
+// the peer may wait one minute per attempt for locked funds to confirm before it answers
+const READ_TIMEOUT : Duration = Duration::from_secs((MAX_ATTEMPTS_VERF_FUNDS + 10) * 60);
 
-/// Write a string message to a TcpStream
+/// A bidirectional channel to the peer of a swap, messages are single lines of text
+/// Implemented by TCP streams and, for tests, by in memory channels
+pub trait MessageChannel {
+    /// Write a message to the peer
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` the message to write
+    fn write_msg(&mut self, msg : &str) -> Result<(), String>;
+
+    /// Read the next message of the peer, blocks until it arrives
+    fn read_msg(&mut self) -> Result<String, String>;
+}
+
+impl MessageChannel for TcpStream {
+    fn write_msg(&mut self, msg : &str) -> Result<(), String> {
+        write_to_stream(self, msg)
+    }
+
+    fn read_msg(&mut self) -> Result<String, String> {
+        read_from_stream(self)
+    }
+}
+
+/// Write a string message to a channel
 /// Expects to receive an ACK message from the receiver
 ///
 /// # Arguments
 ///
-/// * `stream` TCP channel to exchange message bidirectionally
+/// * `stream` channel to exchange message bidirectionally
 /// * `msg` the message to write
-pub fn send_msg(stream : &mut TcpStream, msg : &String) {
+pub fn send_msg(stream : &mut dyn MessageChannel, msg : &String) -> Result<(), String> {
     // To each message we excpect an acknowlege response
     println!("Writing message to stream {}", msg);
-    write_to_stream(stream, msg).unwrap();
+    stream.write_msg(msg)?;
     println!("Waiting for ACK reply");
-    let r = read_from_stream(stream).unwrap();
-    assert_eq!(r, "ACK");
-    println!("Read ACK reply")
+    let r = stream.read_msg()?;
+    if r != "ACK" {
+        return Err(format!("Expected an ACK reply from the peer, got: {}", r));
+    }
+    println!("Read ACK reply");
+    Ok(())
 }
 
 
-/// Read a messag from the a TcpStream
+/// Read a messag from a channel
 /// Will return an ACK message to the sender
 ///
 /// # Arguments
 ///
-/// * `stream` TCP channel to exchange messages bidirectionally
-pub fn receive_msg(stream : &mut TcpStream) -> String {
+/// * `stream` channel to exchange messages bidirectionally
+pub fn receive_msg(stream : &mut dyn MessageChannel) -> Result<String, String> {
     println!("Wating for a message on stream...");
-    let msg = read_from_stream(stream).unwrap();
+    let msg = stream.read_msg()?;
     println!("Read message from stream {}", msg);
-    write_to_stream(stream, &String::from("ACK")).unwrap();
+    stream.write_msg("ACK")?;
     println!("Written ACK reply");
-    msg
+    Ok(msg)
 }
 
-fn write_to_stream(stream : &mut TcpStream, msg : &String) -> Result<(), String> {
+fn write_to_stream(stream : &mut TcpStream, msg : &str) -> Result<(), String> {
     match writeln!(stream, "{}", msg) {
         Ok(_) => Ok(()),
         Err(e) => Err(e.to_string())
@@ -44,15 +76,57 @@ fn write_to_stream(stream : &mut TcpStream, msg : &String) -> Result<(), String>
 }
 
 fn read_from_stream(stream : &mut TcpStream) -> Result<String, String> {
-    let mut reader = BufReader::new(stream);
-    let mut msg = String::new();
-    match reader.read_line(&mut msg) {
-        Ok(len) => {    
-            msg.truncate(len -1);
-            Ok(msg)
-        },
-        Err(e) => {
-            Err(e.to_string())
+    // Read byte by byte, a buffered reader would swallow the start of the next
+    // message when it arrives together with the current one
+    stream.set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let mut msg = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        match stream.read(&mut byte) {
+            Ok(0) => return Err(String::from("Connection closed by the peer")),
+            Ok(_) => {
+                if byte[0] == b'\n' {
+                    break;
+                }
+                if msg.len() >= MAX_MSG_SIZE {
+                    return Err(format!("Message of the peer is longer than {} bytes", MAX_MSG_SIZE));
+                }
+                msg.push(byte[0]);
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                return Err(format!("No message from the peer within {} minutes", READ_TIMEOUT.as_secs() / 60))
+            },
+            Err(e) => {
+                return Err(e.to_string())
+            }
         }
     }
-}
\ No newline at end of file
+    String::from_utf8(msg).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MessageChannel, MAX_MSG_SIZE};
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    #[test]
+    fn test_reject_oversized_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"hello\n").unwrap();
+            // a peer sending without ever ending the line
+            let _ = stream.write_all(&vec![b'a'; MAX_MSG_SIZE + 1]);
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        assert_eq!("hello", stream.read_msg().unwrap());
+        let err = stream.read_msg().unwrap_err();
+        assert!(err.starts_with("Message of the peer is longer than"));
+        drop(stream);
+        peer.join().unwrap();
+    }
+}
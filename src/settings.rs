@@ -1,4 +1,6 @@
 use serde::{Serialize, Deserialize};
+use grin_core::global::DEFAULT_ACCEPT_FEE_BASE;
+use crate::error::SwapError;
 
 #[derive(Serialize, Deserialize)]
 pub struct BtcNodeSettings {
@@ -49,12 +51,121 @@ pub struct Settings {
     pub tcp_addr: String,
     pub tcp_port : String,
     pub slate_directory : String,
+    #[serde(default = "default_storage")]
+    pub storage : String,
+    #[serde(default)]
+    pub safety : SafetySettings,
+    #[serde(default)]
+    pub fees : FeeSettings,
+    #[serde(default)]
+    pub api : ApiSettings,
+    #[serde(default)]
+    pub book : BookSettings,
+    // json file with the reference price offers can be quoted against
+    #[serde(default = "default_price_file")]
+    pub price_file : String
+}
+
+/// Safety margins used to validate the timelocks of a swap
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SafetySettings {
+    // minutes between the Grin refund lock height and the Bitcoin refund lock height
+    pub margin_minutes : u64,
+    // minimum time until the Grin refund becomes possible, the swap has to be executed within it
+    pub min_refund_minutes : u64
+}
+
+impl Default for SafetySettings {
+    fn default() -> SafetySettings {
+        SafetySettings {
+            margin_minutes : 120,
+            min_refund_minutes : 60
+        }
+    }
+}
+
+/// Grin fee policy, fees are the transaction weight times the fee base
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct FeeSettings {
+    // minimum fee base accepted by our Grin node in nanogrin per weight unit
+    pub fee_base : u64,
+    // multiplier on the fee base for offers we create, for a higher priority
+    pub fee_multiplier : u64,
+    // highest multiple of our fee base we accept in an offer of a peer
+    pub max_fee_multiplier : u64
+}
+
+impl Default for FeeSettings {
+    fn default() -> FeeSettings {
+        FeeSettings {
+            fee_base : DEFAULT_ACCEPT_FEE_BASE,
+            fee_multiplier : 1,
+            max_fee_multiplier : 4
+        }
+    }
+}
+
+impl FeeSettings {
+    /// Fee base recorded in the offers we create, the fee base times the multiplier
+    pub fn offer_fee_base(&self) -> Result<u64, SwapError> {
+        self.fee_base.checked_mul(self.fee_multiplier)
+            .ok_or(SwapError::Config(format!("fee_base {} times fee_multiplier {} is too high", self.fee_base, self.fee_multiplier)))
+    }
+
+    /// Highest fee base we accept in an offer of a peer
+    pub fn max_fee_base(&self) -> Result<u64, SwapError> {
+        self.fee_base.checked_mul(self.max_fee_multiplier)
+            .ok_or(SwapError::Config(format!("fee_base {} times max_fee_multiplier {} is too high", self.fee_base, self.max_fee_multiplier)))
+    }
+}
+
+/// Address the JSON-RPC API server listens on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiSettings {
+    pub addr : String,
+    pub port : u16
+}
+
+impl Default for ApiSettings {
+    fn default() -> ApiSettings {
+        ApiSettings {
+            addr : String::from("127.0.0.1"),
+            port : 3421
+        }
+    }
+}
+
+/// Offer book settings, the address the book server listens on
+/// and the url of the book offers are published to and fetched from
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BookSettings {
+    pub addr : String,
+    pub port : u16,
+    pub url : String
+}
+
+impl Default for BookSettings {
+    fn default() -> BookSettings {
+        BookSettings {
+            addr : String::from("127.0.0.1"),
+            port : 3422,
+            url : String::from("http://127.0.0.1:3422")
+        }
+    }
+}
+
+fn default_storage() -> String {
+    String::from("file")
+}
+
+fn default_price_file() -> String {
+    String::from("config/prices.json")
 }
 
 impl Settings {
     // Parse JSON string
-    pub fn parse_json_string(json : &str) -> Settings {
-        let s : Settings = serde_json::from_str(&json).unwrap();
-        s
+    pub fn parse_json_string(json : &str) -> Result<Settings, String> {
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid settings file: {}", e))
     }
 }
\ No newline at end of file
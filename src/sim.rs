@@ -0,0 +1,5 @@
+pub mod adversary;
+pub mod btc_chain;
+pub mod grin_chain;
+pub mod node;
+pub mod party;
@@ -0,0 +1,522 @@
+use crate::bitcoin::btcroutines::create_private_key;
+use crate::enums::ChainStatus;
+use crate::grin::grin_tx::GrinTx;
+use crate::net::memory::MemoryChannel;
+use crate::net::tcp::MessageChannel;
+use crate::settings::SafetySettings;
+use crate::sim::node::SimNode;
+use crate::sim::party::Party;
+use crate::swap::protocol::{
+    exec_phase_swap_btc, exec_phase_swap_mw, locking_phase_swap_btc, locking_phase_swap_mw,
+    refund_phase_swap_btc, refund_phase_swap_mw,
+};
+use crate::util::{get_os_rng, get_secp256k1_curve};
+use crate::SwapSlate;
+use bitcoin::network::constants::Network;
+use bitcoin::util::address::Address;
+use bitcoin::PublicKey;
+use grin_core::global::{set_local_chain_type, ChainTypes};
+use grin_util::secp::{ContextFlag, Secp256k1 as GrinSecp256k1};
+use grin_wallet_libwallet::Slate;
+use serde_json::Value;
+use std::any::Any;
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::{self, JoinHandle};
+
+/// The two sides of the swap protocol, Alice owns the Grin and Bob the Bitcoin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Alice,
+    Bob
+}
+
+/// The phases of the swap protocol run between the two clients
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    Lock,
+    Execute
+}
+
+/// A client taking part in a protocol run. The slate keeps the state the client
+/// reached, also if the protocol stopped with an error or a panic.
+pub struct Client {
+    pub party : Party,
+    pub role : Role,
+    pub slate : SwapSlate,
+    pub safety : SafetySettings,
+    pub result : Result<(), String>
+}
+
+impl Client {
+    /// Load the swap of a party for a protocol run
+    ///
+    /// # Arguments
+    ///
+    /// * `party` the client with the swap in its store
+    /// * `role` the side the client runs
+    /// * `id` the id of the Atomic Swap
+    pub fn new(party : Party, role : Role, id : u64) -> Result<Client, String> {
+        Ok(Client {
+            slate : party.slate(id)?,
+            safety : party.settings.safety,
+            party : party,
+            role : role,
+            result : Ok(())
+        })
+    }
+}
+
+/// Run a protocol phase of a client on a background thread. A panic is reported
+/// as error, the client and its slate are handed back in any case.
+///
+/// # Arguments
+///
+/// * `client` the client to run
+/// * `phase` the protocol phase to run
+/// * `channel` channel to the peer
+pub fn run_phase<C : MessageChannel + Send + 'static>(mut client : Client, phase : Phase, mut channel : C) -> JoinHandle<Client> {
+    thread::spawn(move || {
+        let mut rng = get_os_rng();
+        let btc_secp = get_secp256k1_curve();
+        let grin_secp = GrinSecp256k1::with_caps(ContextFlag::Commit);
+        let mut grin_tx = GrinTx::new(rng.fork());
+        let btc = client.party.chain.btc.as_ref();
+        let grin = client.party.chain.grin.as_ref();
+        let slate = &mut client.slate;
+        let safety = &client.safety;
+        let role = client.role;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| match (phase, role) {
+            (Phase::Lock, Role::Alice) => locking_phase_swap_mw(slate, &mut channel, &mut rng, &btc_secp, grin, btc, &mut grin_tx, safety),
+            (Phase::Lock, Role::Bob) => locking_phase_swap_btc(slate, &mut channel, &mut rng, &btc_secp, grin, btc, &mut grin_tx, safety),
+            (Phase::Execute, Role::Alice) => exec_phase_swap_mw(slate, &mut channel, btc, &mut rng, &mut grin_tx, grin, &grin_secp, &btc_secp),
+            (Phase::Execute, Role::Bob) => exec_phase_swap_btc(slate, &mut channel, btc, grin, &mut grin_tx, &grin_secp)
+        }));
+        // the peer notices that we are gone
+        drop(channel);
+        client.result = match result {
+            Ok(r) => r.map_err(String::from),
+            Err(e) => Err(format!("Panicked: {}", panic_message(e)))
+        };
+        client
+    })
+}
+
+/// Run a protocol phase for two clients until both have stopped
+///
+/// # Arguments
+///
+/// * `first` the first client
+/// * `second` the second client
+/// * `phase` the protocol phase to run
+/// * `first_channel` channel of the first client to the second one
+/// * `second_channel` channel of the second client to the first one
+pub fn run_both<A, B>(first : Client, second : Client, phase : Phase, first_channel : A, second_channel : B) -> (Client, Client)
+    where A : MessageChannel + Send + 'static, B : MessageChannel + Send + 'static {
+    let first = run_phase(first, phase, first_channel);
+    let second = run_phase(second, phase, second_channel);
+    (first.join().unwrap(), second.join().unwrap())
+}
+
+fn panic_message(e : Box<dyn Any + Send>) -> String {
+    match e.downcast_ref::<&str>() {
+        Some(msg) => String::from(*msg),
+        None => e.downcast_ref::<String>().cloned().unwrap_or_default()
+    }
+}
+
+/// Channel end which hangs up after writing a number of messages, ACKs included
+pub struct HangUp {
+    inner : Option<MemoryChannel>,
+    writes : usize
+}
+
+impl HangUp {
+    /// Wrap a channel end
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` the wrapped channel end
+    /// * `writes` number of messages written before hanging up
+    pub fn new(inner : MemoryChannel, writes : usize) -> HangUp {
+        HangUp {
+            inner : Some(inner),
+            writes : writes
+        }
+    }
+}
+
+impl MessageChannel for HangUp {
+    fn write_msg(&mut self, msg : &str) -> Result<(), String> {
+        if self.writes == 0 {
+            self.inner = None;
+        }
+        match &mut self.inner {
+            Some(inner) => {
+                self.writes = self.writes - 1;
+                inner.write_msg(msg)
+            },
+            None => Err(String::from("Hung up"))
+        }
+    }
+
+    fn read_msg(&mut self) -> Result<String, String> {
+        match &mut self.inner {
+            Some(inner) => inner.read_msg(),
+            None => Err(String::from("Hung up"))
+        }
+    }
+}
+
+fn is_slate(msg : &str) -> bool {
+    Slate::deserialize_upgrade(msg).is_ok()
+}
+
+/// Replace any Bitcoin address sent with a fresh address of somebody else
+pub fn replace_address() -> impl FnMut(String) -> String + Send {
+    |msg : String| {
+        if msg.parse::<Address>().is_ok() {
+            let secp = get_secp256k1_curve();
+            let pk = PublicKey::from_private_key(&secp, &create_private_key(&mut get_os_rng()));
+            Address::p2pkh(&pk, Network::Testnet).to_string()
+        }
+        else {
+            msg
+        }
+    }
+}
+
+/// Move any lock height sent further into the future
+///
+/// # Arguments
+///
+/// * `blocks` number of blocks added to the lock height
+pub fn shift_lock_height(blocks : u64) -> impl FnMut(String) -> String + Send {
+    move |msg : String| match msg.parse::<u64>() {
+        Ok(height) => (height + blocks).to_string(),
+        Err(_) => msg
+    }
+}
+
+/// Swap the T1 and T2 points of any multi-party rangeproof context sent
+pub fn corrupt_proof_context() -> impl FnMut(String) -> String + Send {
+    |msg : String| match serde_json::from_str::<Value>(&msg) {
+        Ok(mut ctx) if ctx.get("tau_x").is_some() => {
+            let t_1 = ctx["t_1"].take();
+            ctx["t_1"] = ctx["t_2"].take();
+            ctx["t_2"] = t_1;
+            ctx.to_string()
+        },
+        _ => msg
+    }
+}
+
+/// Flip the last digit of every partial signature in the slates sent
+pub fn corrupt_part_sigs() -> impl FnMut(String) -> String + Send {
+    |msg : String| {
+        if !is_slate(&msg) {
+            return msg;
+        }
+        // slates are sent in the V4 layout, the participants are in `sigs`
+        let mut slate : Value = serde_json::from_str(&msg).unwrap();
+        if let Some(participants) = slate["sigs"].as_array_mut() {
+            for p in participants.iter_mut() {
+                if let Some(sig) = p["part"].as_str() {
+                    let mut sig = String::from(sig);
+                    let last = if sig.pop() == Some('0') { '1' } else { '0' };
+                    sig.push(last);
+                    p["part"] = Value::String(sig);
+                }
+            }
+        }
+        slate.to_string()
+    }
+}
+
+/// Send an earlier slate again in place of a later one
+///
+/// # Arguments
+///
+/// * `replayed` messages sent before, the last slate among them is replayed
+/// * `replaced` index of the slate which is replaced, counting the slates sent through this tamper
+pub fn replay_slate(replayed : Vec<String>, replaced : usize) -> impl FnMut(String) -> String + Send {
+    let mut sent : Vec<String> = replayed.into_iter().filter(|m| is_slate(m)).collect();
+    let mut count = 0;
+    move |msg : String| {
+        if !is_slate(&msg) {
+            return msg;
+        }
+        count = count + 1;
+        if count == replaced + 1 && !sent.is_empty() {
+            sent.last().unwrap().clone()
+        }
+        else {
+            sent.push(msg.clone());
+            msg
+        }
+    }
+}
+
+/// Mine both chains past the lock times set in a slate
+///
+/// # Arguments
+///
+/// * `node` the simulated nodes
+/// * `slate` the swap slate
+pub fn mine_past_locks(node : &SimNode, slate : &SwapSlate) {
+    let btc_lock = u64::try_from(slate.pub_slate.btc.lock_time.unwrap_or(0)).unwrap();
+    let grin_lock = u64::try_from(slate.pub_slate.mw.lock_time.unwrap_or(0)).unwrap();
+    let btc_blocks = (btc_lock + 1).saturating_sub(node.btc.lock().unwrap().height());
+    let grin_blocks = (grin_lock + 1).saturating_sub(node.grin.lock().unwrap().height());
+    node.mine(btc_blocks, grin_blocks);
+}
+
+/// Assert that a client didn't lose its funds, whatever its peer did. The funds
+/// either were swapped, are still in the inputs of the client or can be refunded
+/// once the lock times have passed. Locked funds are refunded.
+///
+/// # Arguments
+///
+/// * `node` the simulated nodes
+/// * `client` the honest client after the protocol run
+pub fn assert_safe(node : &SimNode, client : &mut Client) {
+    set_local_chain_type(ChainTypes::Testnet);
+    let btc = client.party.chain.btc.as_ref();
+    let grin = client.party.chain.grin.as_ref();
+    let slate = &mut client.slate;
+    match client.role {
+        Role::Alice => {
+            if slate.pub_slate.btc.status == ChainStatus::REDEEMED {
+                let lock = slate.prv_slate.btc.lock.clone().unwrap();
+                assert!(slate.prv_slate.btc.swapped.is_some());
+                assert!(node.btc.lock().unwrap().is_spent(&lock.txid, lock.vout));
+            }
+            else if slate.pub_slate.mw.status == ChainStatus::LOCKED {
+                mine_past_locks(node, slate);
+                refund_phase_swap_mw(slate, btc, grin).unwrap();
+                let shared = slate.prv_slate.mw.shared_coin.clone().unwrap();
+                assert!(node.grin.lock().unwrap().get_output(&shared.commitment).unwrap().spent);
+            }
+            else {
+                for inp in slate.prv_slate.mw.inputs.iter() {
+                    let unspent = node.grin.lock().unwrap().get_output(&inp.commitment).map(|o| !o.spent);
+                    assert_eq!(Some(true), unspent, "Grin input of Alice is gone");
+                }
+            }
+        },
+        Role::Bob => {
+            if slate.pub_slate.mw.status == ChainStatus::REDEEMED {
+                let swapped = slate.prv_slate.mw.swapped_coin.clone().unwrap();
+                assert!(node.grin.lock().unwrap().get_output(&swapped.commitment).is_some());
+            }
+            else if slate.pub_slate.btc.status == ChainStatus::LOCKED {
+                mine_past_locks(node, slate);
+                refund_phase_swap_btc(slate, btc, grin, &get_secp256k1_curve(), &mut get_os_rng()).unwrap();
+                let lock = slate.prv_slate.btc.lock.clone().unwrap();
+                assert!(node.btc.lock().unwrap().is_spent(&lock.txid, lock.vout));
+            }
+            else {
+                for inp in slate.prv_slate.btc.inputs.iter() {
+                    assert!(!node.btc.lock().unwrap().is_spent(&inp.txid, inp.vout), "Bitcoin input of Bob is gone");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        assert_safe, corrupt_part_sigs, corrupt_proof_context, replace_address, replay_slate,
+        run_both, shift_lock_height, Client, HangUp, Phase, Role,
+    };
+    use crate::commands::cmd_types::{accept::Accept, init::Init};
+    use crate::constants::DEFAULT_OFFER_EXPIRY;
+    use crate::enums::{BtcLockType, Currency, GrinLockType, SwapStatus};
+    use crate::grin::grin_routines::grin_to_nanogrin;
+    use crate::net::memory::memory_pair;
+    use crate::settings::SafetySettings;
+    use crate::sim::node::SimNode;
+    use crate::sim::party::Party;
+    use crate::swap::pricing::CounterAmount;
+    use crate::swap::validator::required_btc_timelock;
+    use std::sync::{Arc, Mutex};
+
+    const BTC_AMOUNT : u64 = 100_000;
+
+    /// Bob offers Bitcoin for Grin and Alice accepts, both have funded the swap
+    fn new_swap(node : &SimNode) -> (Client, Client) {
+        let mut alice = Party::new("alice", node).unwrap();
+        let mut bob = Party::new("bob", node).unwrap();
+        let init = Init::new(Currency::BTC, Currency::GRIN, BTC_AMOUNT, CounterAmount::Amount(grin_to_nanogrin(2)), 600, DEFAULT_OFFER_EXPIRY, BtcLockType::HEIGHT, GrinLockType::HEIGHT, None);
+        let id = bob.run(&init).unwrap().unwrap();
+        bob.share_offer(id, &alice).unwrap();
+        alice.run(&Accept::new(id, false, None)).unwrap();
+        bob.fund_btc(node, id, 150_000).unwrap();
+        alice.fund_grin(node, id, grin_to_nanogrin(3)).unwrap();
+        (Client::new(alice, Role::Alice, id).unwrap(), Client::new(bob, Role::Bob, id).unwrap())
+    }
+
+    /// Both clients locked their funds honestly, the messages Bob sent are recorded
+    fn locked_swap(node : &SimNode) -> (Client, Client, Vec<String>) {
+        let (alice, bob) = new_swap(node);
+        let (alice_channel, mut bob_channel) = memory_pair();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let record = sent.clone();
+        bob_channel.tamper(move |msg| {
+            record.lock().unwrap().push(msg.clone());
+            msg
+        });
+        let (alice, bob) = run_both(alice, bob, Phase::Lock, alice_channel, bob_channel);
+        alice.result.clone().unwrap();
+        bob.result.clone().unwrap();
+        assert_eq!(SwapStatus::LOCKED, alice.slate.pub_slate.status);
+        assert_eq!(SwapStatus::LOCKED, bob.slate.pub_slate.status);
+        node.mine(1, 1);
+        let sent = sent.lock().unwrap().clone();
+        (alice, bob, sent)
+    }
+
+    /// Run a phase with the adversary's messages tampered, returns the honest client and the adversary
+    fn attack<F>(honest : Client, adversary : Client, phase : Phase, tamper : F) -> (Client, Client)
+        where F : FnMut(String) -> String + Send + 'static {
+        let (honest_channel, mut adversary_channel) = memory_pair();
+        adversary_channel.tamper(tamper);
+        run_both(honest, adversary, phase, honest_channel, adversary_channel)
+    }
+
+    /// Split the clients of a swap into the honest one and the adversary
+    fn sides(alice : Client, bob : Client, adversary : Role) -> (Client, Client) {
+        match adversary {
+            Role::Alice => (bob, alice),
+            Role::Bob => (alice, bob)
+        }
+    }
+
+    #[test]
+    fn test_wrong_lock_address() {
+        let node = SimNode::start().unwrap();
+        let (alice, bob) = new_swap(&node);
+        let (mut alice, _) = attack(alice, bob, Phase::Lock, replace_address());
+        assert!(alice.result.clone().unwrap_err().contains("Lock address"));
+        assert_eq!(SwapStatus::ABORTED, alice.slate.pub_slate.status);
+        assert_safe(&node, &mut alice);
+    }
+
+    #[test]
+    fn test_wrong_lock_amount() {
+        let node = SimNode::start().unwrap();
+        let (alice, mut bob) = new_swap(&node);
+        bob.slate.pub_slate.btc.amount = BTC_AMOUNT - 10_000;
+        let (mut alice, _) = attack(alice, bob, Phase::Lock, |msg| msg);
+        assert!(alice.result.clone().unwrap_err().contains("correctly locked"));
+        assert_eq!(SwapStatus::ABORTED, alice.slate.pub_slate.status);
+        assert_safe(&node, &mut alice);
+    }
+
+    #[test]
+    fn test_short_btc_lock_time() {
+        let node = SimNode::start().unwrap();
+        let (alice, mut bob) = new_swap(&node);
+        // Bob locks his Bitcoin for as short as his own checks allow
+        bob.safety = SafetySettings { margin_minutes : 0, min_refund_minutes : 0 };
+        bob.slate.pub_slate.mw.timelock = 0;
+        bob.slate.pub_slate.btc.timelock = required_btc_timelock(0, &bob.safety);
+        let (mut alice, _) = attack(alice, bob, Phase::Lock, |msg| msg);
+        assert!(alice.result.clone().unwrap_err().contains("Unsafe timelocks"));
+        assert_eq!(SwapStatus::ABORTED, alice.slate.pub_slate.status);
+        assert_safe(&node, &mut alice);
+    }
+
+    #[test]
+    fn test_mismatched_btc_lock_type() {
+        let node = SimNode::start().unwrap();
+        let (alice, mut bob) = new_swap(&node);
+        // Bob locks with a timestamp while the offer uses a lock height
+        bob.slate.pub_slate.btc.lock_type = BtcLockType::TIME;
+        let (mut alice, _) = attack(alice, bob, Phase::Lock, |msg| msg);
+        assert!(alice.result.clone().unwrap_err().contains("lock type"));
+        assert_eq!(SwapStatus::ABORTED, alice.slate.pub_slate.status);
+        assert_safe(&node, &mut alice);
+    }
+
+    #[test]
+    fn test_long_grin_lock_height() {
+        let node = SimNode::start().unwrap();
+        let (alice, bob) = new_swap(&node);
+        let (mut bob, _) = attack(bob, alice, Phase::Lock, shift_lock_height(300));
+        assert!(bob.result.clone().unwrap_err().contains("Unsafe timelocks"));
+        assert_safe(&node, &mut bob);
+    }
+
+    #[test]
+    fn test_mismatched_proof_context() {
+        for adversary in vec![Role::Alice, Role::Bob] {
+            let node = SimNode::start().unwrap();
+            let (alice, bob) = new_swap(&node);
+            let (honest, adversary) = sides(alice, bob, adversary);
+            let (mut honest, _) = attack(honest, adversary, Phase::Lock, corrupt_proof_context());
+            assert!(honest.result.is_err());
+            assert_safe(&node, &mut honest);
+        }
+    }
+
+    #[test]
+    fn test_invalid_adapted_signature() {
+        for adversary in vec![Role::Alice, Role::Bob] {
+            let node = SimNode::start().unwrap();
+            let (alice, bob, _) = locked_swap(&node);
+            let (honest, adversary) = sides(alice, bob, adversary);
+            let (mut honest, _) = attack(honest, adversary, Phase::Execute, corrupt_part_sigs());
+            assert!(honest.result.is_err());
+            assert_safe(&node, &mut honest);
+        }
+    }
+
+    #[test]
+    fn test_replayed_slates() {
+        for adversary in vec![Role::Alice, Role::Bob] {
+            let node = SimNode::start().unwrap();
+            let (alice, bob) = new_swap(&node);
+            let (honest, adversary) = sides(alice, bob, adversary);
+            // the second slate of the locking phase is replaced by the first one
+            let (mut honest, _) = attack(honest, adversary, Phase::Lock, replay_slate(Vec::new(), 1));
+            assert_safe(&node, &mut honest);
+        }
+        // Bob answers the contract transaction with a slate of the locking phase
+        let node = SimNode::start().unwrap();
+        let (alice, bob, sent) = locked_swap(&node);
+        let (mut alice, _) = attack(alice, bob, Phase::Execute, replay_slate(sent, 0));
+        assert!(alice.result.is_err());
+        assert_safe(&node, &mut alice);
+    }
+
+    #[test]
+    fn test_abort_after_each_message() {
+        for phase in vec![Phase::Lock, Phase::Execute] {
+            for adversary in vec![Role::Alice, Role::Bob] {
+                let mut writes = 0;
+                loop {
+                    let node = SimNode::start().unwrap();
+                    let (alice, bob) = match phase {
+                        Phase::Lock => new_swap(&node),
+                        Phase::Execute => {
+                            let (alice, bob, _) = locked_swap(&node);
+                            (alice, bob)
+                        }
+                    };
+                    let (honest, adversary) = sides(alice, bob, adversary);
+                    let (honest_channel, adversary_channel) = memory_pair();
+                    let (mut honest, adversary) = run_both(honest, adversary, phase, honest_channel, HangUp::new(adversary_channel, writes));
+                    assert_safe(&node, &mut honest);
+                    // the adversary went through the whole phase without hanging up
+                    if adversary.result.is_ok() {
+                        break;
+                    }
+                    writes = writes + 1;
+                    assert!(writes < 100, "{:?} phase doesn't end", phase);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use bitcoin::blockdata::script::Builder;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::network::constants::Network;
+use bitcoin::util::address::Address;
+use bitcoin::{OutPoint, Script, Transaction, TxIn, TxOut, Txid};
+use std::collections::{HashMap, HashSet};
+use crate::constants::LOCKTIME_THRESHOLD;
+
+const SEQUENCE_FINAL : u32 = 0xFFFFFFFF;
+const BLOCK_INTERVAL_SECS : u64 = 600;
+
+/// Error returned by the simulated node, with the Bitcoin Core RPC error code
+#[derive(Debug, PartialEq)]
+pub struct BtcRpcError {
+    pub code : i32,
+    pub message : String
+}
+
+/// Unspent output known to the simulated chain
+pub struct SimUtxo {
+    pub txid : Txid,
+    pub vout : u32,
+    pub value : u64,
+    pub script_pubkey : Script,
+    // None while the creating transaction is in the mempool
+    pub height : Option<u64>
+}
+
+/// In memory Bitcoin chain with a mempool, blocks are mined on request.
+/// Transactions are checked for missing or spent inputs, the amounts and the lock time,
+/// scripts and signatures are not executed.
+pub struct BtcChain {
+    height : u64,
+    genesis_time : u64,
+    utxos : HashMap<OutPoint, SimUtxo>,
+    // height each transaction was mined at, None while in the mempool
+    txs : HashMap<Txid, Option<u64>>,
+    // transaction which spent an output
+    spent_by : HashMap<OutPoint, Txid>,
+    watched : HashSet<String>,
+    funded : u32
+}
+
+impl BtcChain {
+    /// Create a new chain at a height, the tip is timestamped with the given time
+    ///
+    /// # Arguments
+    ///
+    /// * `height` height of the chain tip
+    /// * `tip_time` unix timestamp of the tip
+    pub fn new(height : u64, tip_time : u64) -> BtcChain {
+        BtcChain {
+            height : height,
+            genesis_time : tip_time - height * BLOCK_INTERVAL_SECS,
+            utxos : HashMap::new(),
+            txs : HashMap::new(),
+            spent_by : HashMap::new(),
+            watched : HashSet::new(),
+            funded : 0
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Timestamp of a block, blocks are exactly 10 minutes apart
+    ///
+    /// # Arguments
+    ///
+    /// * `height` height of the block
+    pub fn block_time(&self, height : u64) -> u64 {
+        self.genesis_time + height * BLOCK_INTERVAL_SECS
+    }
+
+    /// Median time past of a block, the median of the last 11 block timestamps
+    ///
+    /// # Arguments
+    ///
+    /// * `height` height of the block
+    pub fn median_time_past(&self, height : u64) -> u64 {
+        self.block_time(height.saturating_sub(5))
+    }
+
+    /// Hash of the block at a height, the simulated blocks have no content
+    ///
+    /// # Arguments
+    ///
+    /// * `height` height of the block
+    pub fn block_hash(height : u64) -> String {
+        format!("{:064x}", height)
+    }
+
+    /// Height of the block with a hash created by `block_hash`
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` hash of the block
+    pub fn block_height(&self, hash : &str) -> Option<u64> {
+        u64::from_str_radix(hash, 16).ok()
+            .filter(|h| hash.len() == 64 && *h <= self.height)
+    }
+
+    /// Mine blocks, all transactions in the mempool are included in the first one
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` the number of blocks to mine
+    pub fn mine(&mut self, blocks : u64) {
+        if blocks == 0 {
+            return;
+        }
+        let next = self.height + 1;
+        for h in self.txs.values_mut().filter(|h| h.is_none()) {
+            *h = Some(next);
+        }
+        for u in self.utxos.values_mut().filter(|u| u.height.is_none()) {
+            u.height = Some(next);
+        }
+        self.height = self.height + blocks;
+    }
+
+    /// Create a confirmed output out of thin air, used to fund the wallets of a test
+    /// Returns the txid and vout of the new output
+    ///
+    /// # Arguments
+    ///
+    /// * `script_pubkey` the script the output is locked to
+    /// * `value` value of the output in satoshis
+    pub fn fund(&mut self, script_pubkey : Script, value : u64) -> (String, u32) {
+        self.funded = self.funded + 1;
+        // a coinbase input, the counter makes every funding transaction unique
+        let tx = Transaction {
+            version : 2,
+            lock_time : 0,
+            input : vec![TxIn {
+                previous_output : OutPoint::null(),
+                script_sig : Builder::new().push_int(i64::from(self.funded)).into_script(),
+                sequence : SEQUENCE_FINAL,
+                witness : Vec::new()
+            }],
+            output : vec![TxOut {
+                value : value,
+                script_pubkey : script_pubkey
+            }]
+        };
+        let txid = tx.txid();
+        self.add_outputs(&tx, Some(self.height));
+        self.txs.insert(txid, Some(self.height));
+        (txid.to_string(), 0)
+    }
+
+    /// Accept a raw transaction into the mempool, returns its txid
+    ///
+    /// # Arguments
+    ///
+    /// * `tx_hex` the hex encoded transaction
+    pub fn send_raw_transaction(&mut self, tx_hex : &str) -> Result<String, BtcRpcError> {
+        let bytes = hex::decode(tx_hex)
+            .map_err(|_| rpc_error(-22, "TX decode failed"))?;
+        let tx : Transaction = deserialize(&bytes)
+            .map_err(|_| rpc_error(-22, "TX decode failed"))?;
+        let txid = tx.txid();
+        if self.txs.contains_key(&txid) {
+            return Err(rpc_error(-27, "Transaction already in block chain"));
+        }
+        if tx.input.is_empty() || tx.output.is_empty() {
+            return Err(rpc_error(-26, "bad-txns-vin-empty"));
+        }
+        let mut spent : HashSet<OutPoint> = HashSet::new();
+        let mut in_value : u64 = 0;
+        for inp in &tx.input {
+            match self.utxos.get(&inp.previous_output) {
+                Some(u) if spent.insert(inp.previous_output) => in_value = in_value + u.value,
+                _ => return Err(rpc_error(-25, "bad-txns-inputs-missingorspent"))
+            }
+        }
+        let out_value : u64 = tx.output.iter().map(|o| o.value).sum();
+        if out_value > in_value {
+            return Err(rpc_error(-26, "bad-txns-in-belowout"));
+        }
+        if !self.is_final(&tx) {
+            return Err(rpc_error(-26, "non-final"));
+        }
+        for inp in &tx.input {
+            self.utxos.remove(&inp.previous_output);
+            self.spent_by.insert(inp.previous_output, txid);
+        }
+        self.add_outputs(&tx, None);
+        self.txs.insert(txid, None);
+        Ok(txid.to_string())
+    }
+
+    /// Number of confirmations of a transaction, 0 while it is in the mempool
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction
+    pub fn confirmations(&self, txid : &str) -> Option<u64> {
+        self.txs.iter()
+            .find(|(id, _)| id.to_string() == txid)
+            .map(|(_, h)| h.map_or(0, |h| self.height + 1 - h))
+    }
+
+    /// Height a transaction was mined at, None if it is unknown or in the mempool
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction
+    pub fn tx_height(&self, txid : &str) -> Option<u64> {
+        self.txs.iter()
+            .find(|(id, _)| id.to_string() == txid)
+            .and_then(|(_, h)| *h)
+    }
+
+    /// Returns true if the output has been spent by a transaction in a block or the mempool
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction which created the output
+    /// * `vout` index of the output
+    pub fn is_spent(&self, txid : &str, vout : u32) -> bool {
+        self.tx_known(txid) && !self.utxos.values().any(|u| u.txid.to_string() == txid && u.vout == vout)
+    }
+
+    /// Returns true if the output has been spent by a transaction in a block
+    ///
+    /// # Arguments
+    ///
+    /// * `txid` id of the transaction which created the output
+    /// * `vout` index of the output
+    pub fn is_spent_in_block(&self, txid : &str, vout : u32) -> bool {
+        self.spent_by.iter()
+            .find(|(o, _)| o.txid.to_string() == txid && o.vout == vout)
+            .map_or(false, |(_, spender)| self.txs.get(spender).map_or(false, |h| h.is_some()))
+    }
+
+    fn tx_known(&self, txid : &str) -> bool {
+        self.txs.keys().any(|id| id.to_string() == txid)
+    }
+
+    /// Watch an address, like a watch only wallet
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` the address to watch
+    pub fn import_address(&mut self, addr : &str) {
+        self.watched.insert(String::from(addr));
+    }
+
+    /// Unspent outputs on watched addresses, including unconfirmed ones
+    ///
+    /// # Arguments
+    ///
+    /// * `addresses` the addresses to list outputs for
+    pub fn list_unspent(&self, addresses : &[String]) -> Vec<&SimUtxo> {
+        self.utxos.values()
+            .filter(|u| match Address::from_script(&u.script_pubkey, Network::Testnet) {
+                Some(a) => {
+                    let addr = a.to_string();
+                    self.watched.contains(&addr) && addresses.contains(&addr)
+                },
+                None => false
+            })
+            .collect()
+    }
+
+    // the next block has to be past the lock time, unless all inputs opted out of it
+    fn is_final(&self, tx : &Transaction) -> bool {
+        if tx.lock_time == 0 || tx.input.iter().all(|i| i.sequence == SEQUENCE_FINAL) {
+            return true;
+        }
+        if tx.lock_time < LOCKTIME_THRESHOLD {
+            u64::from(tx.lock_time) <= self.height
+        }
+        else {
+            u64::from(tx.lock_time) < self.median_time_past(self.height)
+        }
+    }
+
+    fn add_outputs(&mut self, tx : &Transaction, height : Option<u64>) {
+        let txid = tx.txid();
+        for (vout, out) in tx.output.iter().enumerate() {
+            let vout = vout as u32;
+            self.utxos.insert(OutPoint::new(txid, vout), SimUtxo {
+                txid : txid,
+                vout : vout,
+                value : out.value,
+                script_pubkey : out.script_pubkey.clone(),
+                height : height
+            });
+        }
+    }
+}
+
+fn rpc_error(code : i32, message : &str) -> BtcRpcError {
+    BtcRpcError {
+        code : code,
+        message : String::from(message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BtcChain;
+    use crate::bitcoin::btcroutines::{create_private_key, create_spend_lock_transaction, get_p2pkh_pub_script, script_to_address, serialize_btc_tx};
+    use crate::bitcoin::bitcoin_types::BTCInput;
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+    use bitcoin::PublicKey;
+
+    #[test]
+    fn test_spend_and_lock_time() {
+        let mut rng = get_os_rng();
+        let secp = get_secp256k1_curve();
+        let sk = create_private_key(&mut rng);
+        let pk = PublicKey::from_private_key(&secp, &sk);
+        let script = get_p2pkh_pub_script(&pk);
+        let mut chain = BtcChain::new(100, 1_600_000_000);
+        let (txid, vout) = chain.fund(script.clone(), 50_000);
+        let input = BTCInput::new2(txid.clone(), vout, 50_000, sk, pk, script.clone());
+
+        // spending is refused until the lock height has been reached
+        let tx = create_spend_lock_transaction(&pk, input.clone(), 50_000, 1_000, 105).unwrap();
+        let tx_hex = serialize_btc_tx(&tx);
+        assert_eq!("non-final", chain.send_raw_transaction(&tx_hex).unwrap_err().message);
+        chain.mine(5);
+        let spend_id = chain.send_raw_transaction(&tx_hex).unwrap();
+        assert_eq!(Some(0), chain.confirmations(&spend_id));
+        assert!(chain.is_spent(&txid, vout));
+        assert!(!chain.is_spent_in_block(&txid, vout));
+
+        // the output is gone
+        let tx = create_spend_lock_transaction(&pk, input, 50_000, 2_000, 0).unwrap();
+        assert_eq!(-25, chain.send_raw_transaction(&serialize_btc_tx(&tx)).unwrap_err().code);
+
+        chain.mine(1);
+        assert_eq!(Some(1), chain.confirmations(&spend_id));
+        assert!(chain.is_spent_in_block(&txid, vout));
+        let addr = script_to_address(get_p2pkh_pub_script(&pk)).to_string();
+        assert!(chain.list_unspent(&[addr.clone()]).is_empty());
+        chain.import_address(&addr);
+        assert_eq!(49_000, chain.list_unspent(&[addr]).iter().map(|u| u.value).sum::<u64>());
+    }
+
+    #[test]
+    fn test_output_value_too_high() {
+        let mut rng = get_os_rng();
+        let secp = get_secp256k1_curve();
+        let sk = create_private_key(&mut rng);
+        let pk = PublicKey::from_private_key(&secp, &sk);
+        let script = get_p2pkh_pub_script(&pk);
+        let mut chain = BtcChain::new(100, 1_600_000_000);
+        let (txid, vout) = chain.fund(script.clone(), 50_000);
+        let input = BTCInput::new2(txid, vout, 60_000, sk, pk, script);
+        let tx = create_spend_lock_transaction(&pk, input, 60_000, 1_000, 0).unwrap();
+        assert_eq!("bad-txns-in-belowout", chain.send_raw_transaction(&serialize_btc_tx(&tx)).unwrap_err().message);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::grin::grin_routines::serialize_commitment;
+use grin_core::core::verifier_cache::LruVerifierCache;
+use grin_core::core::{Inputs, KernelFeatures, Transaction, TxKernel, Weighting};
+use grin_util::secp::pedersen::Commitment;
+use grin_util::RwLock;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Output known to the simulated chain
+pub struct SimOutput {
+    // None while the creating transaction is in the pool
+    pub height : Option<u64>,
+    pub spent : bool,
+    pub mmr_index : u64
+}
+
+/// Kernel known to the simulated chain
+pub struct SimKernel {
+    pub kernel : TxKernel,
+    // None while the transaction is in the pool
+    pub height : Option<u64>,
+    pub mmr_index : u64
+}
+
+/// In memory Grin chain with a transaction pool, blocks are mined on request.
+/// Pushed transactions are fully validated (kernel sums, signatures and rangeproofs),
+/// their inputs need to be unspent and height locked kernels need to be past their lock height.
+/// NRD kernels need to be their relative height past an earlier NRD kernel with the same excess.
+pub struct GrinChain {
+    height : u64,
+    outputs : HashMap<String, SimOutput>,
+    kernels : Vec<SimKernel>,
+    // transactions pushed since the last block
+    pool : Vec<Transaction>
+}
+
+impl GrinChain {
+    /// Create a new chain at a height
+    ///
+    /// # Arguments
+    ///
+    /// * `height` height of the chain tip
+    pub fn new(height : u64) -> GrinChain {
+        GrinChain {
+            height : height,
+            outputs : HashMap::new(),
+            kernels : Vec::new(),
+            pool : Vec::new()
+        }
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Hash of the block at a height, the simulated blocks have no content
+    ///
+    /// # Arguments
+    ///
+    /// * `height` height of the block
+    pub fn block_hash(height : u64) -> String {
+        format!("{:064x}", height)
+    }
+
+    /// Mine blocks, all transactions in the pool are included in the first one
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` the number of blocks to mine
+    pub fn mine(&mut self, blocks : u64) {
+        if blocks == 0 {
+            return;
+        }
+        let next = self.height + 1;
+        for o in self.outputs.values_mut().filter(|o| o.height.is_none()) {
+            o.height = Some(next);
+        }
+        for k in self.kernels.iter_mut().filter(|k| k.height.is_none()) {
+            k.height = Some(next);
+        }
+        self.pool.clear();
+        self.height = self.height + blocks;
+    }
+
+    /// Add a confirmed output out of thin air, used to fund the wallets of a test
+    ///
+    /// # Arguments
+    ///
+    /// * `commit` commitment of the output
+    pub fn fund(&mut self, commit : &Commitment) {
+        let mmr_index = self.outputs.len() as u64 + 1;
+        self.outputs.insert(serialize_commitment(commit), SimOutput {
+            height : Some(self.height),
+            spent : false,
+            mmr_index : mmr_index
+        });
+    }
+
+    /// Validate a transaction and accept it into the pool
+    ///
+    /// # Arguments
+    ///
+    /// * `tx` the transaction to push
+    pub fn push_transaction(&mut self, tx : &Transaction) -> Result<(), String> {
+        tx.validate(Weighting::AsTransaction, Arc::new(RwLock::new(LruVerifierCache::new())), self.height + 1)
+            .map_err(|e| format!("Invalid transaction: {:?}", e))?;
+        let inputs : Vec<String> = match tx.inputs() {
+            Inputs::CommitOnly(inp) => inp.iter().map(|i| serialize_commitment(&i.commitment())).collect(),
+            Inputs::FeaturesAndCommit(inp) => inp.iter().map(|i| serialize_commitment(&i.commitment())).collect()
+        };
+        for commit in &inputs {
+            match self.outputs.get(commit) {
+                Some(o) if !o.spent => (),
+                _ => return Err(format!("Input {} is unknown or already spent", commit))
+            }
+        }
+        for out in tx.outputs() {
+            if self.outputs.contains_key(&serialize_commitment(&out.commitment())) {
+                return Err(String::from("Duplicate commitment"));
+            }
+        }
+        for kernel in tx.kernels() {
+            if let KernelFeatures::HeightLocked { lock_height, .. } = kernel.features {
+                if lock_height > self.height + 1 {
+                    return Err(format!("Kernel is locked until height {}, current height is {}", lock_height, self.height));
+                }
+            }
+            if let KernelFeatures::NoRecentDuplicate { relative_height, .. } = kernel.features {
+                let relative_height = u64::from(relative_height);
+                // a duplicate in the pool is too recent as well
+                let recent = self.kernels.iter()
+                    .filter(|k| k.kernel.excess == kernel.excess && k.kernel.is_nrd())
+                    .any(|k| k.height.map_or(true, |h| h + relative_height > self.height + 1));
+                if recent {
+                    return Err(format!("NRD kernel is locked for {} blocks after a kernel with the same excess", relative_height));
+                }
+            }
+        }
+        for commit in &inputs {
+            self.outputs.get_mut(commit).unwrap().spent = true;
+        }
+        for out in tx.outputs() {
+            let mmr_index = self.outputs.len() as u64 + 1;
+            self.outputs.insert(serialize_commitment(&out.commitment()), SimOutput {
+                height : None,
+                spent : false,
+                mmr_index : mmr_index
+            });
+        }
+        for kernel in tx.kernels() {
+            let mmr_index = self.kernels.len() as u64 + 1;
+            self.kernels.push(SimKernel {
+                kernel : kernel.clone(),
+                height : None,
+                mmr_index : mmr_index
+            });
+        }
+        self.pool.push(tx.clone());
+        Ok(())
+    }
+
+    /// Transactions in the pool, waiting for the next block
+    pub fn pool(&self) -> &[Transaction] {
+        &self.pool
+    }
+
+    /// Look up an output by its hex encoded commitment
+    ///
+    /// # Arguments
+    ///
+    /// * `commit` the hex encoded commitment
+    pub fn get_output(&self, commit : &str) -> Option<&SimOutput> {
+        self.outputs.get(commit)
+    }
+
+    /// Look up a mined kernel by its hex encoded excess
+    ///
+    /// # Arguments
+    ///
+    /// * `excess` the hex encoded kernel excess
+    pub fn get_kernel(&self, excess : &str) -> Option<&SimKernel> {
+        self.kernels.iter()
+            .rev()
+            .find(|k| k.height.is_some() && serialize_commitment(&k.kernel.excess) == excess)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GrinChain;
+    use crate::grin::grin_core::GrinCore;
+    use crate::grin::grin_routines::{create_secret_key, grin_to_nanogrin, serialize_commitment};
+    use crate::grin::grin_types::{KernelLock, MWCoin};
+    use crate::net::http::RequestFactory;
+    use crate::sim::node::offline_settings;
+    use grin_core::global::{set_local_chain_type, ChainTypes};
+
+    #[test]
+    fn test_push_height_locked() {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let mut core = GrinCore::new(offline_settings().grin, RequestFactory::new(None));
+        let mut chain = GrinChain::new(1000);
+
+        let value = grin_to_nanogrin(2);
+        let blind = create_secret_key(&mut core.rng, &core.secp);
+        let commit = core.secp.commit(value, blind.clone()).unwrap();
+        chain.fund(&commit);
+        let coin = MWCoin::new(&commit, &blind, value);
+
+        let fund_value = grin_to_nanogrin(1);
+        let spend = core.spend_coins(vec![coin], fund_value, KernelLock::HEIGHT(1005), 2, 2).unwrap();
+        let recv = core.recv_coins(spend.slate, fund_value).unwrap();
+        let fin = core.fin_tx(recv.slate, &spend.sig_key, &spend.sig_nonce, true, None, None).unwrap();
+        let tx = fin.tx.unwrap();
+
+        let err = chain.push_transaction(&tx).unwrap_err();
+        assert!(err.starts_with("Kernel is locked until height 1005"));
+        chain.mine(4);
+        chain.push_transaction(&tx).unwrap();
+        assert!(chain.get_output(&serialize_commitment(&commit)).unwrap().spent);
+        let out = chain.get_output(&recv.output_coin.commitment).unwrap();
+        assert_eq!(None, out.height);
+        chain.mine(1);
+        assert_eq!(Some(1005), chain.get_output(&recv.output_coin.commitment).unwrap().height);
+
+        // the input can't be spent twice
+        assert!(chain.push_transaction(&tx).is_err());
+    }
+}
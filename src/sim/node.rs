@@ -0,0 +1,423 @@
+use crate::sim::btc_chain::{BtcChain, BtcRpcError};
+use crate::sim::grin_chain::GrinChain;
+use crate::settings::{BtcNodeSettings, GrinNodeSettings};
+use crate::Settings;
+use crate::util::now_secs;
+use bitcoin::network::constants::Network;
+use bitcoin::util::address::Address;
+use grin_core::core::Transaction;
+use grin_core::global::{set_local_chain_type, set_local_nrd_enabled, ChainTypes};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::{Request, Response, Server, StatusCode};
+
+pub const SIM_USER : &str = "sim";
+pub const SIM_PASS : &str = "sim";
+// the JSON-RPC id the swap sends with every request
+pub const SIM_ID : &str = "mw-btc-swap";
+const GRIN_PATH : &str = "/v2/foreign";
+const BTC_START_HEIGHT : u64 = 1_900_000;
+const GRIN_START_HEIGHT : u64 = 700_000;
+// a pushed Grin transaction with a few inputs and outputs stays well below this
+const MAX_BODY_SIZE : u64 = 1024 * 1024;
+
+/// Simulated Bitcoin Core and Grin node serving their JSON-RPC APIs on a local port.
+/// The Bitcoin Core RPC is served on `/`, the Grin foreign API on `/v2/foreign`.
+/// The chains only advance when blocks are mined, the server stops when the node is dropped.
+pub struct SimNode {
+    pub btc : Arc<Mutex<BtcChain>>,
+    pub grin : Arc<Mutex<GrinChain>>,
+    port : u16,
+    stop : Arc<AtomicBool>,
+    handle : Option<JoinHandle<()>>
+}
+
+impl SimNode {
+    /// Start the simulated nodes on a free local port
+    pub fn start() -> Result<SimNode, String> {
+        let server = Server::http("127.0.0.1:0")
+            .map_err(|e| format!("Unable to start simulated node: {}", e))?;
+        let port = server.server_addr().port();
+        let btc = Arc::new(Mutex::new(BtcChain::new(BTC_START_HEIGHT, now_secs())));
+        let grin = Arc::new(Mutex::new(GrinChain::new(GRIN_START_HEIGHT)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (srv_btc, srv_grin, srv_stop) = (btc.clone(), grin.clone(), stop.clone());
+        let handle = thread::spawn(move || {
+            // transactions are validated for the chain the swap runs on, with NRD kernels enabled
+            set_local_chain_type(ChainTypes::Testnet);
+            set_local_nrd_enabled(true);
+            while !srv_stop.load(Ordering::SeqCst) {
+                match server.recv_timeout(Duration::from_millis(50)) {
+                    Ok(Some(mut request)) => {
+                        let response = handle_request(&srv_btc, &srv_grin, &mut request);
+                        if let Err(e) = request.respond(response) {
+                            println!("Simulated node failed to respond: {}", e);
+                        }
+                    },
+                    Ok(None) => (),
+                    Err(_) => break
+                }
+            }
+        });
+        Ok(SimNode {
+            btc : btc,
+            grin : grin,
+            port : port,
+            stop : stop,
+            handle : Some(handle)
+        })
+    }
+
+    /// Mine blocks on both chains
+    ///
+    /// # Arguments
+    ///
+    /// * `btc_blocks` the number of Bitcoin blocks to mine
+    /// * `grin_blocks` the number of Grin blocks to mine
+    pub fn mine(&self, btc_blocks : u64, grin_blocks : u64) {
+        self.btc.lock().unwrap().mine(btc_blocks);
+        self.grin.lock().unwrap().mine(grin_blocks);
+    }
+
+    /// Settings of a swap client using the simulated nodes
+    ///
+    /// # Arguments
+    ///
+    /// * `slate_directory` the directory the client stores its slates in
+    /// * `tcp_port` the port the client listens on for peers
+    pub fn settings(&self, slate_directory : &str, tcp_port : u16) -> Settings {
+        test_settings(self.port, slate_directory, tcp_port)
+    }
+
+    pub fn btc_settings(&self) -> BtcNodeSettings {
+        self.settings("", 0).btc
+    }
+
+    pub fn grin_settings(&self) -> GrinNodeSettings {
+        self.settings("", 0).grin
+    }
+}
+
+impl Drop for SimNode {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Settings of a swap client, both nodes are expected on the given local port.
+/// Missing settings get their defaults.
+///
+/// # Arguments
+///
+/// * `node_port` the port of the simulated nodes
+/// * `slate_directory` the directory the client stores its slates in
+/// * `tcp_port` the port the client listens on for peers
+pub fn test_settings(node_port : u16, slate_directory : &str, tcp_port : u16) -> Settings {
+    let node = json!({
+        "url" : "127.0.0.1",
+        "user" : SIM_USER,
+        "pass" : SIM_PASS,
+        "port" : node_port,
+        "id" : SIM_ID
+    });
+    serde_json::from_value(json!({
+        "btc" : node,
+        "grin" : node,
+        "tcp_addr" : "127.0.0.1",
+        "tcp_port" : tcp_port.to_string(),
+        "slate_directory" : slate_directory
+    })).unwrap()
+}
+
+/// Settings for tests which stub the node responses and never connect to a node
+pub fn offline_settings() -> Settings {
+    test_settings(1, "slates", 0)
+}
+
+fn handle_request(btc : &Mutex<BtcChain>, grin : &Mutex<GrinChain>, request : &mut Request) -> Response<Cursor<Vec<u8>>> {
+    let expected_auth = format!("Basic {}", base64::encode(format!("{}:{}", SIM_USER, SIM_PASS)));
+    let authorized = request.headers().iter()
+        .any(|h| h.field.equiv("Authorization") && h.value.as_str() == expected_auth);
+    if !authorized {
+        return Response::from_string("Unauthorized").with_status_code(StatusCode(401));
+    }
+    let mut body = String::new();
+    if request.as_reader().take(MAX_BODY_SIZE).read_to_string(&mut body).is_err() {
+        return Response::from_string("Unable to read request").with_status_code(StatusCode(400));
+    }
+    let rpc : Value = match serde_json::from_str(&body) {
+        Ok(v) => v,
+        Err(e) => return Response::from_string(format!("Parse error: {}", e)).with_status_code(StatusCode(400))
+    };
+    let id = rpc["id"].clone();
+    let method = rpc["method"].as_str().unwrap_or("");
+    let params : Vec<Value> = rpc["params"].as_array().cloned().unwrap_or_default();
+    if request.url() == GRIN_PATH {
+        let content = match grin_call(&mut grin.lock().unwrap(), method, &params) {
+            Ok(result) => json!({ "id" : id, "jsonrpc" : "2.0", "result" : result }),
+            Err(e) => json!({ "id" : id, "jsonrpc" : "2.0", "error" : { "code" : -32000, "message" : e } })
+        };
+        Response::from_string(content.to_string())
+    }
+    else {
+        // Bitcoin Core answers failed calls with status 500
+        match btc_call(&mut btc.lock().unwrap(), method, &params) {
+            Ok(result) => Response::from_string(json!({ "result" : result, "error" : null, "id" : id }).to_string()),
+            Err(e) => Response::from_string(json!({ "result" : null, "error" : { "code" : e.code, "message" : e.message }, "id" : id }).to_string())
+                .with_status_code(StatusCode(500))
+        }
+    }
+}
+
+fn btc_call(chain : &mut BtcChain, method : &str, params : &[Value]) -> Result<Value, BtcRpcError> {
+    let height = chain.height();
+    match method {
+        "getnetworkinfo" => Ok(json!({
+            "version" : 200100,
+            "subversion" : "/Satoshi:0.20.1(sim)/",
+            "localservices" : "0000000000000409",
+            "localservicesnames" : ["NETWORK", "WITNESS", "NETWORK_LIMITED"],
+            "localrelay" : true,
+            "timeoffset" : 0,
+            "networkactive" : true,
+            "connections" : 0,
+            "relayfee" : 0.00001,
+            "incrementalfee" : 0.00001,
+            "warnings" : ""
+        })),
+        "getblockcount" => Ok(json!(height)),
+        "getblockchaininfo" => Ok(json!({
+            "chain" : "test",
+            "blocks" : height,
+            "bestblockhash" : BtcChain::block_hash(height),
+            "mediantime" : chain.median_time_past(height)
+        })),
+        "getblockheader" => {
+            let hash = str_param(params, 0)?;
+            let h = chain.block_height(hash)
+                .ok_or(btc_error(-5, "Block not found"))?;
+            Ok(json!({
+                "hash" : hash,
+                "height" : h,
+                "time" : chain.block_time(h),
+                "mediantime" : chain.median_time_past(h)
+            }))
+        },
+        "importaddress" => {
+            chain.import_address(str_param(params, 0)?);
+            Ok(Value::Null)
+        },
+        "listunspent" => {
+            let min_conf = params.get(0).and_then(|v| v.as_u64()).unwrap_or(1);
+            let addresses : Vec<String> = params.get(2)
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().filter_map(|s| s.as_str().map(String::from)).collect())
+                .unwrap_or_default();
+            let utxos : Vec<Value> = chain.list_unspent(&addresses).into_iter()
+                .map(|u| (u, u.height.map_or(0, |h| height + 1 - h)))
+                .filter(|(_, conf)| *conf >= min_conf)
+                .map(|(u, conf)| json!({
+                    "txid" : u.txid.to_string(),
+                    "vout" : u.vout,
+                    "address" : Address::from_script(&u.script_pubkey, Network::Testnet).map(|a| a.to_string()),
+                    "label" : "",
+                    "scriptPubKey" : hex::encode(u.script_pubkey.as_bytes()),
+                    "amount" : u.value as f64 / 100_000_000.0,
+                    "confirmations" : conf,
+                    "spendable" : false,
+                    "solvable" : false,
+                    "safe" : true
+                }))
+                .collect();
+            Ok(Value::Array(utxos))
+        },
+        "sendrawtransaction" => chain.send_raw_transaction(str_param(params, 0)?)
+            .map(Value::String),
+        "getrawtransaction" => {
+            let txid = str_param(params, 0)?;
+            let confirmations = chain.confirmations(txid)
+                .ok_or(btc_error(-5, "No such mempool or blockchain transaction"))?;
+            // unconfirmed transactions have no block fields
+            match chain.tx_height(txid) {
+                Some(h) => Ok(json!({
+                    "txid" : txid,
+                    "confirmations" : confirmations,
+                    "blockhash" : BtcChain::block_hash(h),
+                    "blocktime" : chain.block_time(h)
+                })),
+                None => Ok(json!({ "txid" : txid }))
+            }
+        },
+        "gettxout" => {
+            let txid = str_param(params, 0)?;
+            let vout = params.get(1)
+                .and_then(|v| v.as_u64())
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or(btc_error(-8, "Invalid parameter"))?;
+            let include_mempool = params.get(2).and_then(|v| v.as_bool()).unwrap_or(true);
+            let spent = if include_mempool { chain.is_spent(txid, vout) } else { chain.is_spent_in_block(txid, vout) };
+            // spent and unknown outputs are both answered with null
+            match chain.confirmations(txid) {
+                Some(c) if !spent && (include_mempool || c > 0) => Ok(json!({
+                    "bestblock" : BtcChain::block_hash(height),
+                    "confirmations" : c
+                })),
+                _ => Ok(Value::Null)
+            }
+        },
+        other => Err(btc_error(-32601, &format!("Method not found: {}", other)))
+    }
+}
+
+fn str_param<'a>(params : &'a [Value], ix : usize) -> Result<&'a str, BtcRpcError> {
+    params.get(ix)
+        .and_then(|v| v.as_str())
+        .ok_or(btc_error(-8, "Invalid parameter"))
+}
+
+fn btc_error(code : i32, message : &str) -> BtcRpcError {
+    BtcRpcError {
+        code : code,
+        message : String::from(message)
+    }
+}
+
+fn grin_call(chain : &mut GrinChain, method : &str, params : &[Value]) -> Result<Value, String> {
+    let height = chain.height();
+    match method {
+        "get_tip" => Ok(json!({ "Ok" : {
+            "height" : height,
+            "last_block_pushed" : GrinChain::block_hash(height),
+            "prev_block_to_last" : GrinChain::block_hash(height.saturating_sub(1)),
+            "total_difficulty" : height
+        }})),
+        "get_outputs" => {
+            let commits : Vec<&str> = params.get(0)
+                .and_then(|v| v.as_array())
+                .map(|a| a.iter().filter_map(|c| c.as_str()).collect())
+                .unwrap_or_default();
+            // like the node only outputs in a block are known, not the ones in the pool
+            let outputs : Vec<Value> = commits.into_iter()
+                .filter_map(|c| chain.get_output(c).and_then(|o| o.height.map(|h| json!({
+                    "output_type" : "Transaction",
+                    "commit" : c,
+                    "spent" : o.spent,
+                    "proof" : null,
+                    "proof_hash" : "",
+                    "block_height" : h,
+                    "merkle_proof" : null,
+                    "mmr_index" : o.mmr_index
+                }))))
+                .collect();
+            Ok(json!({ "Ok" : outputs }))
+        },
+        "get_kernel" => {
+            let excess = params.get(0).and_then(|v| v.as_str()).unwrap_or("");
+            match chain.get_kernel(excess) {
+                Some(k) => Ok(json!({ "Ok" : {
+                    "tx_kernel" : serde_json::to_value(&k.kernel).map_err(|e| e.to_string())?,
+                    "height" : k.height,
+                    "mmr_index" : k.mmr_index
+                }})),
+                None => Ok(json!({ "Err" : { "NotFound" : format!("kernel {}", excess) } }))
+            }
+        },
+        "get_unconfirmed_transactions" => {
+            let entries : Vec<Value> = chain.pool().iter()
+                .map(|tx| Ok(json!({
+                    "src" : "Broadcast",
+                    "tx" : serde_json::to_value(tx).map_err(|e| e.to_string())?,
+                    "tx_at" : "2021-01-01T00:00:00Z"
+                })))
+                .collect::<Result<Vec<Value>, String>>()?;
+            Ok(json!({ "Ok" : entries }))
+        },
+        "push_transaction" => {
+            let tx : Transaction = params.get(0)
+                .ok_or(String::from("Missing transaction"))
+                .and_then(|v| serde_json::from_value(v.clone()).map_err(|e| format!("Invalid transaction json: {}", e)))?;
+            chain.push_transaction(&tx)?;
+            Ok(json!({ "Ok" : null }))
+        },
+        other => Err(format!("Method not found: {}", other))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SimNode;
+    use crate::bitcoin::bitcoin_core::BitcoinCore;
+    use crate::bitcoin::btcroutines::{create_private_key, get_p2pkh_pub_script, script_to_address};
+    use crate::grin::grin_core::GrinCore;
+    use crate::grin::grin_routines::{create_secret_key, grin_to_nanogrin};
+    use crate::grin::grin_types::{KernelLock, MWCoin};
+    use crate::net::http::RequestFactory;
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+    use bitcoin::PublicKey;
+    use grin_core::global::{set_local_chain_type, ChainTypes};
+
+    #[test]
+    fn test_node_rpc() {
+        let node = SimNode::start().unwrap();
+        let btc_core = BitcoinCore::new(node.btc_settings(), RequestFactory::new(None));
+        let grin_core = GrinCore::new(node.grin_settings(), RequestFactory::new(None));
+        let btc_height = btc_core.get_current_block_height().unwrap();
+        let grin_height = grin_core.get_block_height().unwrap();
+        node.mine(2, 3);
+        assert_eq!(btc_height + 2, btc_core.get_current_block_height().unwrap());
+        assert_eq!(grin_height + 3, grin_core.get_block_height().unwrap());
+
+        let info = btc_core.get_blockchain_info().unwrap();
+        let header = btc_core.get_block_header(&info.bestblockhash).unwrap();
+        assert_eq!(btc_height + 2, header.height);
+        assert_eq!(info.mediantime, btc_core.get_median_time_past().unwrap());
+
+        // funds on an address are only seen once the address is imported
+        let mut rng = get_os_rng();
+        let secp = get_secp256k1_curve();
+        let pk = PublicKey::from_private_key(&secp, &create_private_key(&mut rng));
+        let script = get_p2pkh_pub_script(&pk);
+        let (txid, _) = node.btc.lock().unwrap().fund(script.clone(), 1600);
+        let addr = script_to_address(script);
+        assert_eq!(0, btc_core.get_address_final_balance_addr(addr.clone()).unwrap());
+        btc_core.import_btc_address(addr.clone()).unwrap();
+        assert_eq!(1600, btc_core.get_address_final_balance_addr(addr).unwrap());
+        assert_eq!(1, btc_core.get_tx_confirmations(&txid).unwrap());
+        assert!(btc_core.get_tx_confirmations("00").is_err());
+        assert!(grin_core.get_output("08aa").unwrap().is_none());
+        assert!(grin_core.get_kernel("08aa").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_node_fee_base() {
+        set_local_chain_type(ChainTypes::AutomatedTesting);
+        let node = SimNode::start().unwrap();
+        let mut core = GrinCore::new(node.grin_settings(), RequestFactory::new(None));
+        // nothing to estimate the fee base from in an empty pool
+        assert_eq!(None, core.get_fee_base().unwrap());
+
+        let value = grin_to_nanogrin(2);
+        let blind = create_secret_key(&mut core.rng, &core.secp);
+        let commit = core.secp.commit(value, blind.clone()).unwrap();
+        node.grin.lock().unwrap().fund(&commit);
+        let coin = MWCoin::new(&commit, &blind, value);
+        let fund_value = grin_to_nanogrin(1);
+        let spend = core.spend_coins(vec![coin], fund_value, KernelLock::NONE, 2, 2).unwrap();
+        let recv = core.recv_coins(spend.slate, fund_value).unwrap();
+        let fin = core.fin_tx(recv.slate, &spend.sig_key, &spend.sig_nonce, true, None, None).unwrap();
+        core.push_transaction(fin.tx.unwrap()).unwrap();
+
+        // the transaction pays exactly its weight times our fee base
+        assert_eq!(Some(core.fee_base), core.get_fee_base().unwrap());
+        node.mine(0, 1);
+        assert_eq!(None, core.get_fee_base().unwrap());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::bitcoin::btcroutines::{create_private_key, get_p2pkh_pub_script, serialize_script};
+use crate::commands::cmd_types::command::Command;
+use crate::commands::cmd_types::import_btc::ImportBtc;
+use crate::commands::cmd_types::import_grin::ImportGrin;
+use crate::commands::cmd_types::listen::Listen;
+use crate::commands::context::ChainContext;
+use crate::grin::grin_routines::{create_secret_key, serialize_commitment, serialize_secret_key};
+use crate::sim::node::SimNode;
+use crate::swap::store::file_store::FileStore;
+use crate::swap::store::store_types::SwapStore;
+use crate::util::{get_os_rng, get_secp256k1_curve};
+use crate::SwapSlate;
+use crate::Settings;
+use bitcoin::secp256k1::{All, Secp256k1};
+use bitcoin::PublicKey;
+use grin_util::secp::{ContextFlag, Secp256k1 as GrinSecp256k1};
+use crate::util::SwapRng;
+use rand::Rng;
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+
+/// A swap client with its own slate directory and TCP port, using the simulated nodes
+pub struct Party {
+    pub settings : Settings,
+    pub chain : ChainContext,
+    pub store : FileStore,
+    // bound for the lifetime of the client, so the port can't be taken by someone else
+    listener : TcpListener,
+    rng : SwapRng,
+    btc_secp : Secp256k1<All>,
+    grin_secp : GrinSecp256k1
+}
+
+impl Party {
+    /// Create a client in a new temporary slate directory
+    ///
+    /// # Arguments
+    ///
+    /// * `name` name of the client, used in the directory name
+    /// * `node` the simulated nodes the client uses
+    pub fn new(name : &str, node : &SimNode) -> Result<Party, String> {
+        let dir = env::temp_dir().join(format!("mw-btc-swap-{}-{}", name, rand::thread_rng().gen::<u64>()));
+        let dir = dir.to_string_lossy().to_string();
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|e| format!("Unable to bind a port: {}", e))?;
+        let port = listener.local_addr()
+            .map_err(|e| format!("Unable to read the bound port: {}", e))?
+            .port();
+        let settings = node.settings(&dir, port);
+        Ok(Party {
+            chain : ChainContext::from_settings(&settings),
+            settings : settings,
+            store : FileStore::new(&dir)?,
+            listener : listener,
+            rng : get_os_rng(),
+            btc_secp : get_secp256k1_curve(),
+            grin_secp : GrinSecp256k1::with_caps(ContextFlag::Commit)
+        })
+    }
+
+    /// Execute a command and persist the slate it returns, like the command line does
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` the command to run
+    pub fn run(&mut self, cmd : &dyn Command) -> Result<Option<u64>, String> {
+        let slate = cmd.execute(&self.settings, &self.chain, &mut self.store, &mut self.rng, &self.btc_secp, &self.grin_secp)?;
+        match slate {
+            Some(slate) => {
+                self.store.write_slate(&slate, true, true)?;
+                Ok(Some(slate.id))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Execute a command on a background thread, the client is handed back with the result
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` the command to run
+    pub fn spawn<C : Command + Send + 'static>(mut self, cmd : C) -> JoinHandle<(Party, Result<Option<u64>, String>)> {
+        thread::spawn(move || {
+            let result = self.run(&cmd);
+            (self, result)
+        })
+    }
+
+    /// Serve a swap on a background thread like `spawn` with the listen command.
+    /// The port of the client is already bound, a peer can connect right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    pub fn listen(self, id : u64) -> Result<JoinHandle<(Party, Result<Option<u64>, String>)>, String> {
+        let listener = self.listener.try_clone()
+            .map_err(|e| format!("Unable to share the listener: {}", e))?;
+        Ok(self.spawn(Listen::with_listener(id, listener)))
+    }
+
+    /// Read a stored swap
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    pub fn slate(&self, id : u64) -> Result<SwapSlate, String> {
+        self.store.read_slate(id)
+    }
+
+    /// Hand the public slate file of an offer to a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `peer` the client which receives the offer
+    pub fn share_offer(&self, id : u64, peer : &Party) -> Result<(), String> {
+        let from = format!("{}/{}.pub.json", self.settings.slate_directory, id);
+        let to = format!("{}/{}.pub.json", peer.settings.slate_directory, id);
+        fs::copy(&from, &to)
+            .map(|_| ())
+            .map_err(|e| format!("Unable to copy {} to {}: {}", from, to, e))
+    }
+
+    /// Create a Bitcoin output on the simulated chain and import it into a swap
+    ///
+    /// # Arguments
+    ///
+    /// * `node` the simulated nodes
+    /// * `id` the id of the Atomic Swap
+    /// * `value` value of the output in satoshis
+    pub fn fund_btc(&mut self, node : &SimNode, id : u64, value : u64) -> Result<(), String> {
+        let sk = create_private_key(&mut self.rng);
+        let pk = PublicKey::from_private_key(&self.btc_secp, &sk);
+        let script = get_p2pkh_pub_script(&pk);
+        let (txid, vout) = node.btc.lock().unwrap().fund(script.clone(), value);
+        self.run(&ImportBtc::new(id, txid, vout, value, sk.to_wif(), serialize_script(&script)))?;
+        Ok(())
+    }
+
+    /// Create a Grin output on the simulated chain and import it into a swap
+    ///
+    /// # Arguments
+    ///
+    /// * `node` the simulated nodes
+    /// * `id` the id of the Atomic Swap
+    /// * `value` value of the output in nanogrin
+    pub fn fund_grin(&mut self, node : &SimNode, id : u64, value : u64) -> Result<(), String> {
+        let blind = create_secret_key(&mut self.rng, &self.grin_secp);
+        let commit = self.grin_secp.commit(value, blind.clone())
+            .map_err(|e| format!("Unable to create commitment: {:?}", e))?;
+        node.grin.lock().unwrap().fund(&commit);
+        self.run(&ImportGrin::new(id, serialize_commitment(&commit), serialize_secret_key(&blind), value))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Party;
+    use crate::commands::cmd_types::{accept::Accept, execute::Execute, init::Init, lock::Lock, refund::Refund, status::Status};
+    use crate::constants::DEFAULT_OFFER_EXPIRY;
+    use crate::enums::{BtcLockType, ChainStatus, Currency, GrinLockType, SwapStatus};
+    use crate::grin::grin_routines::grin_to_nanogrin;
+    use crate::sim::node::SimNode;
+    use crate::swap::pricing::CounterAmount;
+    use std::convert::TryFrom;
+
+    const BTC_AMOUNT : u64 = 100_000;
+
+    /// Bob offers Bitcoin for Grin, Alice takes the offer and both lock their funds
+    fn locked_swap(node : &SimNode, grin_lock : GrinLockType) -> (Party, Party, u64) {
+        let mut alice = Party::new("alice", node).unwrap();
+        let mut bob = Party::new("bob", node).unwrap();
+        let init = Init::new(Currency::BTC, Currency::GRIN, BTC_AMOUNT, CounterAmount::Amount(grin_to_nanogrin(2)), 600, DEFAULT_OFFER_EXPIRY, BtcLockType::HEIGHT, grin_lock, None);
+        let id = bob.run(&init).unwrap().unwrap();
+        bob.share_offer(id, &alice).unwrap();
+        alice.run(&Accept::new(id, false, None)).unwrap();
+        bob.fund_btc(node, id, 150_000).unwrap();
+        alice.fund_grin(node, id, grin_to_nanogrin(3)).unwrap();
+
+        let listener = bob.listen(id).unwrap();
+        alice.run(&Lock::new(id)).unwrap();
+        let (bob, result) = listener.join().unwrap();
+        result.unwrap();
+        assert_eq!(SwapStatus::LOCKED, alice.slate(id).unwrap().pub_slate.status);
+        assert_eq!(SwapStatus::LOCKED, bob.slate(id).unwrap().pub_slate.status);
+        (alice, bob, id)
+    }
+
+    #[test]
+    fn test_swap_execute() {
+        let node = SimNode::start().unwrap();
+        let (mut alice, bob, id) = locked_swap(&node, GrinLockType::HEIGHT);
+        node.mine(1, 1);
+
+        let listener = bob.listen(id).unwrap();
+        alice.run(&Execute::new(id)).unwrap();
+        let (mut bob, result) = listener.join().unwrap();
+        result.unwrap();
+
+        // Bob only sees the Bitcoin redeemed once the spend of the lock output is in a block
+        bob.run(&Status::new(id)).unwrap();
+        assert_eq!(SwapStatus::GRIN_REDEEMED, bob.slate(id).unwrap().pub_slate.status);
+        node.mine(1, 1);
+        bob.run(&Status::new(id)).unwrap();
+
+        let alice_slate = alice.slate(id).unwrap();
+        let bob_slate = bob.slate(id).unwrap();
+        assert_eq!(SwapStatus::FINISHED, alice_slate.pub_slate.status);
+        assert_eq!(SwapStatus::FINISHED, bob_slate.pub_slate.status);
+        // Alice redeemed the locked Bitcoin, Bob the shared Grin output
+        let lock = alice_slate.prv_slate.btc.lock.unwrap();
+        assert!(node.btc.lock().unwrap().is_spent(&lock.txid, lock.vout));
+        let shared = bob_slate.prv_slate.mw.shared_coin.unwrap();
+        assert!(node.grin.lock().unwrap().get_output(&shared.commitment).unwrap().spent);
+    }
+
+    #[test]
+    fn test_swap_refund() {
+        let node = SimNode::start().unwrap();
+        let (mut alice, mut bob, id) = locked_swap(&node, GrinLockType::HEIGHT);
+
+        // nothing can be refunded before the lock times have passed
+        assert!(alice.run(&Refund::new(id)).is_err());
+        assert!(bob.run(&Refund::new(id)).is_err());
+
+        let slate = alice.slate(id).unwrap();
+        let btc_lock = u64::try_from(slate.pub_slate.btc.lock_time.unwrap()).unwrap();
+        let grin_lock = u64::try_from(slate.pub_slate.mw.lock_time.unwrap()).unwrap();
+        let btc_blocks = btc_lock + 1 - node.btc.lock().unwrap().height();
+        let grin_blocks = grin_lock + 1 - node.grin.lock().unwrap().height();
+        node.mine(btc_blocks, grin_blocks);
+
+        alice.run(&Refund::new(id)).unwrap();
+        bob.run(&Refund::new(id)).unwrap();
+        let alice_slate = alice.slate(id).unwrap();
+        let bob_slate = bob.slate(id).unwrap();
+        assert_eq!(ChainStatus::REFUND_BROADCAST, alice_slate.pub_slate.mw.status);
+        assert_eq!(ChainStatus::REFUND_BROADCAST, bob_slate.pub_slate.btc.status);
+        let lock = bob_slate.prv_slate.btc.lock.unwrap();
+        assert!(node.btc.lock().unwrap().is_spent(&lock.txid, lock.vout));
+        let shared = alice_slate.prv_slate.mw.shared_coin.unwrap();
+        assert!(node.grin.lock().unwrap().get_output(&shared.commitment).unwrap().spent);
+    }
+
+    #[test]
+    fn test_swap_refund_nrd() {
+        let node = SimNode::start().unwrap();
+        let (mut alice, mut bob, id) = locked_swap(&node, GrinLockType::NRD);
+        let slate = alice.slate(id).unwrap();
+        let btc_lock = u64::try_from(slate.pub_slate.btc.lock_time.unwrap()).unwrap();
+        let btc_blocks = btc_lock + 1 - node.btc.lock().unwrap().height();
+        node.mine(btc_blocks, 1);
+
+        // the Grin refund is locked relative to the block the funding transaction was mined in
+        let refund_tx = slate.prv_slate.mw.refund_tx.unwrap();
+        assert!(refund_tx.kernels()[0].is_nrd());
+        let shared = slate.prv_slate.mw.shared_coin.unwrap();
+        let funded = node.grin.lock().unwrap().get_output(&shared.commitment).unwrap().height.unwrap();
+        let grin_refund = funded + slate.pub_slate.mw.timelock;
+        let grin_blocks = grin_refund - 2 - node.grin.lock().unwrap().height();
+        node.mine(0, grin_blocks);
+        assert!(node.grin.lock().unwrap().push_transaction(&refund_tx).unwrap_err().starts_with("NRD kernel is locked"));
+        assert!(alice.run(&Refund::new(id)).is_err());
+        assert!(bob.run(&Refund::new(id)).is_err());
+
+        node.mine(0, 3);
+        alice.run(&Refund::new(id)).unwrap();
+        bob.run(&Refund::new(id)).unwrap();
+        assert_eq!(ChainStatus::REFUND_BROADCAST, alice.slate(id).unwrap().pub_slate.mw.status);
+        assert_eq!(ChainStatus::REFUND_BROADCAST, bob.slate(id).unwrap().pub_slate.btc.status);
+        assert!(node.grin.lock().unwrap().get_output(&shared.commitment).unwrap().spent);
+    }
+}
@@ -1,3 +1,9 @@
 pub mod swap_types;
 pub mod slate;
-pub mod protocol;
\ No newline at end of file
+pub mod slate_versions;
+pub mod protocol;
+pub mod offer;
+pub mod pricing;
+pub mod fill;
+pub mod validator;
+pub mod store;
\ No newline at end of file
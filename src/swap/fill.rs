@@ -0,0 +1,403 @@
+use crate::constants::CURRENT_SLATE_VERSION;
+use crate::enums::{ChainStatus, Currency, SwapRole, SwapStatus, SwapType};
+use crate::swap::slate::new_priv_slate;
+use crate::swap::swap_types::{BTCPub, ChildFill, FillOf, FillRange, MWPub, Meta, SwapSlate, SwapSlatePub};
+use crate::units::format_amount;
+
+// First message of a taker locking a child swap
+const FILL_MSG : &str = "FILL";
+
+/// Validate the fill range of a new partially fillable offer
+///
+/// # Arguments
+///
+/// * `fill` the fill range in units of the offered currency
+/// * `from_amount` the total offered amount
+pub fn validate_fill_range(fill : &FillRange, from_amount : u64) -> Result<(), String> {
+    if fill.min == 0 {
+        Err(String::from("Minimum fill must be greater than 0"))
+    }
+    else if fill.min > fill.max {
+        Err(format!("Minimum fill {} is greater than the maximum fill {}", fill.min, fill.max))
+    }
+    else if fill.max > from_amount {
+        Err(format!("Maximum fill {} is greater than the offered amount {}", fill.max, from_amount))
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// Build the fill range of a new offer from the command arguments, a minimum fill
+/// makes the offer partially fillable and the maximum fill defaults to the offered amount
+///
+/// # Arguments
+///
+/// * `min` the minimum fill, if the offer is partially fillable
+/// * `max` the maximum fill
+/// * `from_amount` the total offered amount
+pub fn fill_range_from_args(min : Option<u64>, max : Option<u64>, from_amount : u64) -> Result<Option<FillRange>, String> {
+    match (min, max) {
+        (None, None) => Ok(None),
+        (None, Some(_)) => Err(String::from("A maximum fill needs a minimum fill")),
+        (Some(min), max) => {
+            let fill = FillRange {
+                min : min,
+                max : max.unwrap_or(from_amount)
+            };
+            validate_fill_range(&fill, from_amount)?;
+            Ok(Some(fill))
+        }
+    }
+}
+
+/// Amounts of a child swap filling a part of an offer.
+/// The requested amount keeps the rate of the offer and is rounded up,
+/// so the offer creator never receives less than the signed rate.
+///
+/// # Arguments
+///
+/// * `btc_offered` if the offer sells Bitcoin
+/// * `btc_amount` the Bitcoin amount of the whole offer in satoshis
+/// * `mw_amount` the Grin amount of the whole offer in nanogrin
+/// * `fill` the fill range of the offer
+/// * `amount` the filled amount of the offered currency
+///
+/// Returns the Bitcoin and Grin amounts of the child swap
+pub fn child_amounts(btc_offered : bool, btc_amount : u64, mw_amount : u64, fill : &FillRange, amount : u64) -> Result<(u64, u64), String> {
+    if amount < fill.min || amount > fill.max {
+        return Err(format!("Fill of {} is outside the fill range {} to {}", amount, fill.min, fill.max));
+    }
+    let (offered, requested) = if btc_offered { (btc_amount, mw_amount) } else { (mw_amount, btc_amount) };
+    if amount > offered || offered == 0 {
+        return Err(format!("Fill of {} is more than the offered amount {}", amount, offered));
+    }
+    // fits, both factors are below 2^64
+    let product = amount as u128 * requested as u128;
+    let counter = ((product + offered as u128 - 1) / offered as u128) as u64;
+    if btc_offered {
+        Ok((amount, counter))
+    }
+    else {
+        Ok((counter, amount))
+    }
+}
+
+/// Offered amount of a partially fillable offer which is not taken by a child swap yet
+///
+/// # Arguments
+///
+/// * `slate` the slate of the offer
+pub fn remaining_fill(slate : &SwapSlate) -> u64 {
+    let offered = if slate.pub_slate.btc.swap_type == SwapType::OFFERED { slate.pub_slate.btc.amount } else { slate.pub_slate.mw.amount };
+    let filled : u64 = slate.prv_slate.fills.iter().map(|f| f.amount).sum();
+    offered.saturating_sub(filled)
+}
+
+/// Derive the public slate of a child swap taking a part of an offer.
+/// Both peers derive the same slate, the offer signature is carried over.
+///
+/// # Arguments
+///
+/// * `parent_id` the id of the partially fillable offer
+/// * `parent` the public slate of the offer
+/// * `child_id` the id of the child swap
+/// * `amount` the filled amount of the offered currency
+pub fn derive_child(parent_id : u64, parent : &SwapSlatePub, child_id : u64, amount : u64) -> Result<SwapSlatePub, String> {
+    let fill = parent.fill
+        .ok_or(format!("Offer {} can't be partially filled", parent_id))?;
+    if parent.fill_of.is_some() {
+        return Err(format!("Swap {} is a child swap and can't be filled again", parent_id));
+    }
+    if parent.status != SwapStatus::INITIALIZED {
+        return Err(format!("Offer {} can't be filled in status {:?}", parent_id, parent.status));
+    }
+    if child_id == parent_id {
+        return Err(String::from("Child swap needs an id of its own"));
+    }
+    let btc_offered = parent.btc.swap_type == SwapType::OFFERED;
+    let (btc_amount, mw_amount) = child_amounts(btc_offered, parent.btc.amount, parent.mw.amount, &fill, amount)?;
+    Ok(SwapSlatePub {
+        version : CURRENT_SLATE_VERSION,
+        status : SwapStatus::INITIALIZED,
+        mw : MWPub {
+            amount : mw_amount,
+            timelock : parent.mw.timelock,
+            lock_time : None,
+            swap_type : parent.mw.swap_type,
+            status : ChainStatus::NONE,
+            fee_base : parent.mw.fee_base,
+            lock_type : parent.mw.lock_type
+        },
+        btc : BTCPub {
+            amount : btc_amount,
+            timelock : parent.btc.timelock,
+            swap_type : parent.btc.swap_type,
+            lock_time : None,
+            pub_a : None,
+            pub_b : None,
+            pub_x : None,
+            status : ChainStatus::NONE,
+            lock_type : parent.btc.lock_type
+        },
+        meta : Meta {
+            server : parent.meta.server.clone(),
+            port : parent.meta.port.clone()
+        },
+        validity : parent.validity.clone(),
+        offer_sig : parent.offer_sig.clone(),
+        quote : parent.quote.clone(),
+        fill : Some(fill),
+        fill_of : Some(FillOf {
+            parent_id : parent_id,
+            btc_amount : parent.btc.amount,
+            mw_amount : parent.mw.amount
+        })
+    })
+}
+
+/// Check that the amounts of a child swap follow from its parent offer
+///
+/// # Arguments
+///
+/// * `pub_slate` the public slate of the child swap
+pub fn verify_child_amounts(pub_slate : &SwapSlatePub) -> Result<(), String> {
+    let parent = pub_slate.fill_of
+        .ok_or(String::from("Swap is not a child swap"))?;
+    let fill = pub_slate.fill
+        .ok_or(String::from("Child swap has no fill range"))?;
+    let btc_offered = pub_slate.btc.swap_type == SwapType::OFFERED;
+    let amount = if btc_offered { pub_slate.btc.amount } else { pub_slate.mw.amount };
+    let expected = child_amounts(btc_offered, parent.btc_amount, parent.mw_amount, &fill, amount)?;
+    if expected != (pub_slate.btc.amount, pub_slate.mw.amount) {
+        Err(format!("Child swap amounts {} / {} don't follow from offer {}, expected {} / {}",
+            pub_slate.btc.amount, pub_slate.mw.amount, parent.parent_id, expected.0, expected.1))
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// Create the slate of a child swap on the side of the offer creator, reserving
+/// imported inputs of the offer for it so no input is locked by two child swaps
+///
+/// # Arguments
+///
+/// * `parent` the slate of the partially fillable offer
+/// * `child_id` the id of the child swap
+/// * `amount` the filled amount of the offered currency
+pub fn spawn_child(parent : &mut SwapSlate, child_id : u64, amount : u64) -> Result<SwapSlate, String> {
+    let remaining = remaining_fill(parent);
+    let offered = if parent.pub_slate.btc.swap_type == SwapType::OFFERED { Currency::BTC } else { Currency::GRIN };
+    if amount > remaining {
+        return Err(format!("Fill of {} exceeds the remaining {}", format_amount(amount, offered), format_amount(remaining, offered)));
+    }
+    if parent.prv_slate.fills.iter().any(|f| f.id == child_id) {
+        return Err(format!("Child swap {} already exists", child_id));
+    }
+    let mut child = SwapSlate {
+        id : child_id,
+        pub_slate : derive_child(parent.id, &parent.pub_slate, child_id, amount)?,
+        prv_slate : new_priv_slate(SwapRole::MAKER),
+        events : Vec::new()
+    };
+    let reserved = match offered {
+        Currency::BTC => {
+            let inputs = select_inputs(&mut parent.prv_slate.btc.inputs, amount, |i| i.value)?;
+            let count = inputs.len();
+            child.prv_slate.btc.inputs.extend(inputs);
+            count
+        },
+        Currency::GRIN => {
+            let inputs = select_inputs(&mut parent.prv_slate.mw.inputs, amount, |i| i.value)?;
+            let count = inputs.len();
+            child.prv_slate.mw.inputs.extend(inputs);
+            count
+        }
+    };
+    parent.prv_slate.fills.push(ChildFill {
+        id : child_id,
+        amount : amount
+    });
+    parent.log_event(None, None, None, &format!("Child swap {} fills {}, reserved {} inputs", child_id, format_amount(amount, offered), reserved));
+    child.log_event(None, None, None, &format!("Spawned from offer {}", parent.id));
+    Ok(child)
+}
+
+/// Return the inputs of a child swap which did not lock any funds to its parent offer
+///
+/// # Arguments
+///
+/// * `parent` the slate of the partially fillable offer
+/// * `child` the slate of the child swap
+pub fn release_child(parent : &mut SwapSlate, child : &mut SwapSlate) {
+    parent.prv_slate.btc.inputs.append(&mut child.prv_slate.btc.inputs);
+    parent.prv_slate.mw.inputs.append(&mut child.prv_slate.mw.inputs);
+    parent.prv_slate.fills.retain(|f| f.id != child.id);
+    parent.log_event(None, None, None, &format!("Released the inputs of child swap {}", child.id));
+    child.set_status(SwapStatus::ABORTED);
+}
+
+/// Message announcing a child swap to the offer creator, sent before the slate checksum
+///
+/// # Arguments
+///
+/// * `parent_id` the id of the partially fillable offer
+/// * `child_id` the id of the child swap
+/// * `amount` the filled amount of the offered currency
+pub fn fill_request(parent_id : u64, child_id : u64, amount : u64) -> String {
+    format!("{} {} {} {}", FILL_MSG, parent_id, child_id, amount)
+}
+
+/// Parse a message announcing a child swap
+/// Returns the parent id, the child id and the filled amount, None for other messages
+///
+/// # Arguments
+///
+/// * `msg` the received message
+pub fn parse_fill_request(msg : &str) -> Option<(u64, u64, u64)> {
+    let parts : Vec<&str> = msg.trim().split(' ').collect();
+    if parts.len() != 4 || parts[0] != FILL_MSG {
+        return None;
+    }
+    match (parts[1].parse::<u64>(), parts[2].parse::<u64>(), parts[3].parse::<u64>()) {
+        (Ok(parent_id), Ok(child_id), Ok(amount)) => Some((parent_id, child_id, amount)),
+        _ => None
+    }
+}
+
+/// Take inputs from the front of the list until they cover the amount
+fn select_inputs<T, F : Fn(&T) -> u64>(inputs : &mut Vec<T>, amount : u64, value : F) -> Result<Vec<T>, String> {
+    let mut sum : u64 = 0;
+    let mut count = 0;
+    for input in inputs.iter() {
+        if sum >= amount {
+            break;
+        }
+        sum = sum + value(input);
+        count = count + 1;
+    }
+    if sum < amount {
+        Err(format!("Not enough unreserved inputs, {} available for a fill of {}, please import more Coins", sum, amount))
+    }
+    else {
+        Ok(inputs.drain(..count).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bitcoin::btcroutines::create_private_key;
+    use crate::grin::grin_types::MWCoin;
+    use crate::enums::SwapStatus;
+    use crate::swap::offer::{verify_offer, OfferTerms};
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::swap::swap_types::{FillRange, SwapSlate};
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+
+    use super::{child_amounts, derive_child, fill_range_from_args, fill_request, parse_fill_request, release_child, remaining_fill, spawn_child, validate_fill_range};
+
+    fn coin(value : u64) -> MWCoin {
+        MWCoin {
+            commitment : format!("c{}", value),
+            blinding_factor : String::from("00"),
+            value : value
+        }
+    }
+
+    // 10 GRIN offered for 100000 sats in fills of 1 to 5 GRIN
+    fn fillable_offer() -> SwapSlate {
+        let mut slate = sample_slate(1, false);
+        slate.pub_slate.mw.amount = 10000000000;
+        slate.pub_slate.fill = Some(FillRange { min : 1000000000, max : 5000000000 });
+        slate
+    }
+
+    #[test]
+    fn test_child_amounts() {
+        let fill = FillRange { min : 1, max : 3 };
+        // 3 sats for 10 nanogrin, a fill of 1 sat asks for 3.33 rounded up
+        assert_eq!(Ok((1, 4)), child_amounts(true, 3, 10, &fill, 1));
+        assert_eq!(Ok((3, 10)), child_amounts(true, 3, 10, &fill, 3));
+        // 10 nanogrin for 3 sats, a fill of 1 nanogrin asks for 0.3 sats rounded up
+        assert_eq!(Ok((1, 1)), child_amounts(false, 3, 10, &fill, 1));
+        assert!(child_amounts(true, 3, 10, &fill, 0).is_err());
+        assert!(child_amounts(true, 3, 10, &fill, 4).is_err());
+        assert!(child_amounts(true, 2, 10, &FillRange { min : 1, max : 3 }, 3).is_err());
+
+        assert!(validate_fill_range(&FillRange { min : 1, max : 10 }, 10).is_ok());
+        assert!(validate_fill_range(&FillRange { min : 0, max : 10 }, 10).is_err());
+        assert!(validate_fill_range(&FillRange { min : 5, max : 4 }, 10).is_err());
+        assert!(validate_fill_range(&FillRange { min : 1, max : 11 }, 10).is_err());
+        assert_eq!(Ok(None), fill_range_from_args(None, None, 10));
+        assert_eq!(Ok(Some(FillRange { min : 2, max : 10 })), fill_range_from_args(Some(2), None, 10));
+        assert!(fill_range_from_args(None, Some(5), 10).is_err());
+    }
+
+    #[test]
+    fn test_child_carries_parent_signature() {
+        let secp = get_secp256k1_curve();
+        let sk = create_private_key(&mut get_os_rng());
+        let mut offer = fillable_offer();
+        offer.pub_slate.offer_sig = Some(OfferTerms::from_pub_slate(1, &offer.pub_slate).sign(&sk, &secp));
+
+        let child = derive_child(1, &offer.pub_slate, 2, 2500000000).unwrap();
+        assert_eq!(2500000000, child.mw.amount);
+        assert_eq!(25000, child.btc.amount);
+        assert!(verify_offer(2, &child, &secp).is_ok());
+        // the child terms differ from the offer, so do the checksums
+        assert_ne!(OfferTerms::from_pub_slate(1, &offer.pub_slate).hash(), OfferTerms::from_pub_slate(2, &child).hash());
+
+        // a taker can't lower the price of a child
+        let mut cheap = derive_child(1, &offer.pub_slate, 2, 2500000000).unwrap();
+        cheap.btc.amount = 24999;
+        assert!(verify_offer(2, &cheap, &secp).is_err());
+        // nor claim other parent amounts
+        let mut forged = derive_child(1, &offer.pub_slate, 2, 2500000000).unwrap();
+        forged.fill_of.as_mut().unwrap().btc_amount = 50000;
+        forged.btc.amount = 12500;
+        assert!(verify_offer(2, &forged, &secp).is_err());
+
+        assert!(derive_child(1, &offer.pub_slate, 2, 6000000000).is_err());
+        assert!(derive_child(1, &offer.pub_slate, 1, 2500000000).is_err());
+        assert!(derive_child(2, &child, 3, 1000000000).is_err());
+        assert!(derive_child(1, &sample_slate(1, false).pub_slate, 2, 1).is_err());
+    }
+
+    #[test]
+    fn test_inputs_are_reserved_once() {
+        let mut offer = fillable_offer();
+        offer.prv_slate.mw.inputs = vec![coin(3000000000), coin(3000000000), coin(4000000000)];
+
+        let mut first = spawn_child(&mut offer, 2, 5000000000).unwrap();
+        assert_eq!(2, first.prv_slate.mw.inputs.len());
+        assert_eq!(1, offer.prv_slate.mw.inputs.len());
+        assert_eq!(5000000000, remaining_fill(&offer));
+
+        // the last input covers a second fill, then nothing is left to reserve
+        let second = spawn_child(&mut offer, 3, 4000000000).unwrap();
+        assert_eq!(1, second.prv_slate.mw.inputs.len());
+        assert_eq!(1000000000, remaining_fill(&offer));
+        assert!(spawn_child(&mut offer, 4, 1000000000).is_err());
+        assert!(offer.prv_slate.fills.iter().all(|f| f.id != 4));
+        assert!(spawn_child(&mut offer, 2, 1000000000).is_err());
+
+        // a child which didn't lock returns its inputs
+        release_child(&mut offer, &mut first);
+        assert_eq!(SwapStatus::ABORTED, first.pub_slate.status);
+        assert!(first.prv_slate.mw.inputs.is_empty());
+        assert_eq!(2, offer.prv_slate.mw.inputs.len());
+        assert_eq!(6000000000, remaining_fill(&offer));
+        assert!(spawn_child(&mut offer, 5, 6000000000).is_err());
+        assert!(spawn_child(&mut offer, 5, 5000000000).is_ok());
+    }
+
+    #[test]
+    fn test_fill_request() {
+        assert_eq!(Some((1, 2, 300)), parse_fill_request(&fill_request(1, 2, 300)));
+        assert_eq!(None, parse_fill_request("FILL 1 2"));
+        assert_eq!(None, parse_fill_request("FILL 1 2 x"));
+        assert_eq!(None, parse_fill_request("EXECUTE"));
+        assert_eq!(None, parse_fill_request("ab12"));
+    }
+}
@@ -0,0 +1,442 @@
+use crate::bitcoin::btcroutines::{create_private_key, serialize_priv_key, serialize_pub_key};
+use crate::constants::TEST_NET;
+use crate::enums::{BtcLockType, GrinLockType, SwapType};
+use crate::error::SwapError;
+use crate::swap::store::file_store::write_atomic;
+use crate::swap::fill::verify_child_amounts;
+use crate::swap::swap_types::{FillOf, FillRange, OfferValidity, PriceQuote, SwapSlatePub};
+use crate::bitcoin::bitcoin_backend::BitcoinBackend;
+use crate::grin::grin_backend::GrinBackend;
+use crate::util::now_secs;
+use bitcoin::secp256k1::{All, Message, Secp256k1, Signature};
+use bitcoin::{PrivateKey, PublicKey};
+use crate::util::SwapRng;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const IDENTITY_FILE : &str = "identity.key";
+// Version of the canonical encoding, part of the signed bytes
+const TERMS_ENCODING_VERSION : u8 = 7;
+
+/// Signature of the offer creator over the canonical offer terms
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OfferSignature {
+    pub pub_key : String,
+    pub signature : String
+}
+
+/// The terms of an offer both peers have to agree on.
+/// Runtime data such as the status or keys exchanged during the protocol is not part of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OfferTerms {
+    pub id : u64,
+    pub btc_offered : bool,
+    pub btc_amount : u64,
+    pub mw_amount : u64,
+    pub btc_timelock : u64,
+    pub mw_timelock : u64,
+    pub btc_lock_type : BtcLockType,
+    pub mw_lock_type : GrinLockType,
+    pub mw_fee_base : Option<u64>,
+    pub network : String,
+    pub server : String,
+    pub port : String,
+    pub validity : Option<OfferValidity>,
+    pub quote : Option<PriceQuote>,
+    pub fill : Option<FillRange>,
+    pub fill_of : Option<FillOf>
+}
+
+impl OfferTerms {
+    /// Extract the offer terms from a public slate
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `pub_slate` the public slate of the swap
+    pub fn from_pub_slate(id : u64, pub_slate : &SwapSlatePub) -> OfferTerms {
+        OfferTerms {
+            id : id,
+            btc_offered : pub_slate.btc.swap_type == SwapType::OFFERED,
+            btc_amount : pub_slate.btc.amount,
+            mw_amount : pub_slate.mw.amount,
+            btc_timelock : pub_slate.btc.timelock,
+            mw_timelock : pub_slate.mw.timelock,
+            btc_lock_type : pub_slate.btc.lock_type,
+            mw_lock_type : pub_slate.mw.lock_type,
+            mw_fee_base : pub_slate.mw.fee_base,
+            network : network_name(),
+            server : pub_slate.meta.server.clone(),
+            port : pub_slate.meta.port.clone(),
+            validity : pub_slate.validity.clone(),
+            quote : pub_slate.quote.clone(),
+            fill : pub_slate.fill,
+            fill_of : pub_slate.fill_of
+        }
+    }
+
+    /// Terms of the partially fillable offer a child swap was spawned from,
+    /// these are the terms the offer creator signed
+    ///
+    /// # Arguments
+    ///
+    /// * `pub_slate` the public slate of the child swap
+    pub fn parent_of(pub_slate : &SwapSlatePub) -> Option<OfferTerms> {
+        pub_slate.fill_of.map(|parent| {
+            let mut terms = OfferTerms::from_pub_slate(parent.parent_id, pub_slate);
+            terms.btc_amount = parent.btc_amount;
+            terms.mw_amount = parent.mw_amount;
+            terms.fill_of = None;
+            terms
+        })
+    }
+
+    /// Canonical binary encoding of the terms, integers are big endian,
+    /// strings are prefixed with their length as u32 and optional values with a 0/1 byte
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes : Vec<u8> = vec![TERMS_ENCODING_VERSION];
+        bytes.extend_from_slice(&self.id.to_be_bytes());
+        bytes.push(if self.btc_offered { 1 } else { 0 });
+        bytes.extend_from_slice(&self.btc_amount.to_be_bytes());
+        bytes.extend_from_slice(&self.mw_amount.to_be_bytes());
+        bytes.extend_from_slice(&self.btc_timelock.to_be_bytes());
+        bytes.extend_from_slice(&self.mw_timelock.to_be_bytes());
+        bytes.push(match self.btc_lock_type { BtcLockType::HEIGHT => 0, BtcLockType::TIME => 1 });
+        bytes.push(match self.mw_lock_type { GrinLockType::HEIGHT => 0, GrinLockType::NRD => 1 });
+        push_optional(&mut bytes, self.mw_fee_base);
+        for s in &[&self.network, &self.server, &self.port] {
+            push_string(&mut bytes, s);
+        }
+        match &self.validity {
+            Some(v) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&v.created.to_be_bytes());
+                bytes.extend_from_slice(&v.expires.to_be_bytes());
+                push_optional(&mut bytes, v.btc_height);
+                push_optional(&mut bytes, v.mw_height);
+                push_string(&mut bytes, &v.nonce);
+            },
+            None => bytes.push(0)
+        }
+        match &self.quote {
+            Some(q) => {
+                bytes.push(1);
+                push_string(&mut bytes, &q.price);
+                bytes.extend_from_slice(&q.spread_bps.to_be_bytes());
+            },
+            None => bytes.push(0)
+        }
+        match &self.fill {
+            Some(f) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&f.min.to_be_bytes());
+                bytes.extend_from_slice(&f.max.to_be_bytes());
+            },
+            None => bytes.push(0)
+        }
+        match &self.fill_of {
+            Some(p) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&p.parent_id.to_be_bytes());
+                bytes.extend_from_slice(&p.btc_amount.to_be_bytes());
+                bytes.extend_from_slice(&p.mw_amount.to_be_bytes());
+            },
+            None => bytes.push(0)
+        }
+        bytes
+    }
+
+    /// Sha256 of the canonical encoding
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.to_bytes());
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.finalize());
+        hash
+    }
+
+    /// Hex encoded hash of the terms, exchanged by the peers in the handshake
+    pub fn hash_hex(&self) -> String {
+        hex::encode(self.hash())
+    }
+
+    /// Sign the terms with the offer identity key
+    ///
+    /// # Arguments
+    ///
+    /// * `sk` the identity key of the offer creator
+    /// * `secp` Secp256k1 functions
+    pub fn sign(&self, sk : &PrivateKey, secp : &Secp256k1<All>) -> OfferSignature {
+        let msg = Message::from_slice(&self.hash()).unwrap();
+        let sig = secp.sign(&msg, &sk.key);
+        OfferSignature {
+            pub_key : serialize_pub_key(&sk.public_key(secp)),
+            signature : hex::encode(sig.serialize_der())
+        }
+    }
+
+    /// Verify that the signature was created over these terms by the key it contains
+    ///
+    /// # Arguments
+    ///
+    /// * `sig` the offer signature
+    /// * `secp` Secp256k1 functions
+    pub fn verify(&self, sig : &OfferSignature, secp : &Secp256k1<All>) -> Result<(), String> {
+        let pk = PublicKey::from_str(&sig.pub_key)
+            .map_err(|e| format!("Invalid offer identity key: {}", e))?;
+        let der = hex::decode(&sig.signature)
+            .map_err(|e| format!("Invalid offer signature encoding: {}", e))?;
+        let signature = Signature::from_der(&der)
+            .map_err(|e| format!("Invalid offer signature: {}", e))?;
+        let msg = Message::from_slice(&self.hash()).unwrap();
+        secp.verify(&msg, &signature, &pk.key)
+            .map_err(|_| String::from("Offer signature doesn't match the offer terms"))
+    }
+}
+
+/// Verify the signature embedded in a public slate.
+/// A child swap carries the signature of its parent offer, its amounts have to follow from the parent.
+///
+/// # Arguments
+///
+/// * `id` the id of the Atomic Swap
+/// * `pub_slate` the public slate of the swap
+/// * `secp` Secp256k1 functions
+pub fn verify_offer(id : u64, pub_slate : &SwapSlatePub, secp : &Secp256k1<All>) -> Result<(), String> {
+    let sig = pub_slate.offer_sig.as_ref()
+        .ok_or(String::from("Offer is not signed"))?;
+    match OfferTerms::parent_of(pub_slate) {
+        Some(parent) => {
+            verify_child_amounts(pub_slate)?;
+            parent.verify(sig, secp)
+        },
+        None => OfferTerms::from_pub_slate(id, pub_slate).verify(sig, secp)
+    }
+}
+
+/// Load the offer identity key from the slate directory, a new key is created on first use
+///
+/// # Arguments
+///
+/// * `directory` the directory in which the slate files are stored. (Can be configured in settings.json)
+/// * `rng` Randomness generator
+pub fn load_identity_key(directory : &str, rng : &mut SwapRng) -> Result<PrivateKey, String> {
+    let path = format!("{}/{}", directory, IDENTITY_FILE);
+    if Path::new(&path).exists() {
+        let wif = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read identity key: {}", e))?;
+        PrivateKey::from_wif(wif.trim())
+            .map_err(|e| format!("Unable to parse identity key: {}", e))
+    }
+    else {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Unable to create slate directory {}: {}", directory, e))?;
+        let sk = create_private_key(rng);
+        write_atomic(&path, &serialize_priv_key(&sk))?;
+        Ok(sk)
+    }
+}
+
+/// Check that an offer can still be taken
+///
+/// # Arguments
+///
+/// * `pub_slate` the public slate of the swap
+/// * `now` the current unix timestamp
+/// * `btc_height` the current Bitcoin block height, if known
+/// * `mw_height` the current Grin block height, if known
+pub fn check_offer_validity(pub_slate : &SwapSlatePub, now : u64, btc_height : Option<u64>, mw_height : Option<u64>) -> Result<(), String> {
+    let validity = match &pub_slate.validity {
+        Some(v) => v,
+        // Offers created before expiry was recorded
+        None => return Ok(())
+    };
+    if now >= validity.expires {
+        return Err(format!("Offer expired at {} (now {})", validity.expires, now));
+    }
+    if let (Some(expiry), Some(height)) = (validity.btc_height, btc_height) {
+        if height >= expiry {
+            return Err(format!("Offer expired at Bitcoin height {} (current height {})", expiry, height));
+        }
+    }
+    if let (Some(expiry), Some(height)) = (validity.mw_height, mw_height) {
+        if height >= expiry {
+            return Err(format!("Offer expired at Grin height {} (current height {})", expiry, height));
+        }
+    }
+    Ok(())
+}
+
+/// Check that an offer can still be taken, querying the nodes for the current heights.
+/// A node is only queried if the offer records an expiry height for its chain,
+/// the offer can't be taken if that node can't be reached.
+///
+/// # Arguments
+///
+/// * `pub_slate` the public slate of the swap
+/// * `btc_core` Bitcoin chain backend
+/// * `grin_core` Grin chain backend
+pub fn check_offer_expiry(pub_slate : &SwapSlatePub, btc_core : &dyn BitcoinBackend, grin_core : &dyn GrinBackend) -> Result<(), SwapError> {
+    let validity = match &pub_slate.validity {
+        Some(v) => v,
+        None => {
+            println!("Offer has no expiry recorded");
+            return Ok(());
+        }
+    };
+    let btc_height = match validity.btc_height {
+        Some(_) => Some(btc_core.get_current_block_height()
+            .map_err(|e| SwapError::NodeRpc(format!("Unable to query Bitcoin node to check the offer expiry height: {}", e)))?),
+        None => None
+    };
+    let mw_height = match validity.mw_height {
+        Some(_) => Some(grin_core.get_block_height()
+            .map_err(|e| SwapError::NodeRpc(format!("Unable to query Grin node to check the offer expiry height: {}", e)))?),
+        None => None
+    };
+    check_offer_validity(pub_slate, now_secs(), btc_height, mw_height).map_err(SwapError::Timelock)
+}
+
+fn push_string(bytes : &mut Vec<u8>, s : &str) {
+    bytes.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+fn push_optional(bytes : &mut Vec<u8>, value : Option<u64>) {
+    match value {
+        Some(h) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&h.to_be_bytes());
+        },
+        None => bytes.push(0)
+    }
+}
+
+fn network_name() -> String {
+    String::from(if TEST_NET { "testnet" } else { "mainnet" })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::util::{get_os_rng, get_secp256k1_curve};
+    use crate::bitcoin::btcroutines::create_private_key;
+
+    use crate::swap::swap_types::{FillRange, OfferValidity, PriceQuote};
+    use crate::bitcoin::bitcoin_core::BitcoinCore;
+    use crate::error::SwapError;
+    use crate::grin::grin_core::GrinCore;
+    use crate::net::stub::ScriptedStub;
+    use crate::sim::node::offline_settings;
+    use crate::util::now_secs;
+
+    use super::{check_offer_expiry, check_offer_validity, verify_offer, OfferTerms};
+
+    #[test]
+    fn test_canonical_encoding() {
+        let slate = sample_slate(9, true);
+        let terms = OfferTerms::from_pub_slate(9, &slate.pub_slate);
+        // status and exchanged keys are not part of the terms
+        let mut other = sample_slate(9, true);
+        other.set_status(crate::enums::SwapStatus::LOCKED);
+        other.pub_slate.btc.pub_a = Some(String::from("02ab"));
+        assert_eq!(terms.hash(), OfferTerms::from_pub_slate(9, &other.pub_slate).hash());
+
+        other.pub_slate.mw.amount = other.pub_slate.mw.amount + 1;
+        assert_ne!(terms.hash(), OfferTerms::from_pub_slate(9, &other.pub_slate).hash());
+        let mut fees = sample_slate(9, true);
+        fees.pub_slate.mw.fee_base = Some(1000000);
+        assert_ne!(terms.hash(), OfferTerms::from_pub_slate(9, &fees.pub_slate).hash());
+        let mut nrd = sample_slate(9, true);
+        nrd.pub_slate.mw.lock_type = crate::enums::GrinLockType::NRD;
+        assert_ne!(terms.hash(), OfferTerms::from_pub_slate(9, &nrd.pub_slate).hash());
+        let mut quoted = sample_slate(9, true);
+        quoted.pub_slate.quote = Some(PriceQuote { price : String::from("0.0008"), spread_bps : 0 });
+        assert_ne!(terms.hash(), OfferTerms::from_pub_slate(9, &quoted.pub_slate).hash());
+        let mut fillable = sample_slate(9, true);
+        fillable.pub_slate.fill = Some(FillRange { min : 1, max : 100000 });
+        assert_ne!(terms.hash(), OfferTerms::from_pub_slate(9, &fillable.pub_slate).hash());
+        assert_ne!(terms.hash(), OfferTerms::from_pub_slate(10, &slate.pub_slate).hash());
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let secp = get_secp256k1_curve();
+        let mut rng = get_os_rng();
+        let sk = create_private_key(&mut rng);
+        let mut slate = sample_slate(1, false);
+        let terms = OfferTerms::from_pub_slate(1, &slate.pub_slate);
+        assert!(verify_offer(1, &slate.pub_slate, &secp).is_err());
+
+        slate.pub_slate.offer_sig = Some(terms.sign(&sk, &secp));
+        assert!(verify_offer(1, &slate.pub_slate, &secp).is_ok());
+
+        slate.pub_slate.btc.amount = 1;
+        assert!(verify_offer(1, &slate.pub_slate, &secp).is_err());
+    }
+
+    #[test]
+    fn test_offer_validity() {
+        let mut slate = sample_slate(3, true);
+        assert!(check_offer_validity(&slate.pub_slate, 1000, None, None).is_ok());
+        slate.pub_slate.validity = Some(OfferValidity {
+            created : 1000,
+            expires : 2000,
+            btc_height : Some(100),
+            mw_height : Some(5000),
+            nonce : String::from("00ff")
+        });
+        assert!(check_offer_validity(&slate.pub_slate, 1500, Some(99), Some(4999)).is_ok());
+        assert!(check_offer_validity(&slate.pub_slate, 1500, None, None).is_ok());
+        assert!(check_offer_validity(&slate.pub_slate, 2000, Some(99), Some(4999)).is_err());
+        assert!(check_offer_validity(&slate.pub_slate, 1500, Some(100), None).is_err());
+        assert!(check_offer_validity(&slate.pub_slate, 1500, None, Some(5000)).is_err());
+    }
+
+    #[test]
+    fn test_offer_expiry_unreachable_node() {
+        // a stub without replies fails every request like an unreachable node
+        let stub = ScriptedStub::new();
+        let settings = offline_settings();
+        let btc_core = BitcoinCore::new(settings.btc, stub.factory());
+        let grin_core = GrinCore::new(settings.grin, stub.factory());
+        let mut slate = sample_slate(6, true);
+        slate.pub_slate.validity = Some(OfferValidity {
+            created : now_secs(),
+            expires : now_secs() + 3600,
+            btc_height : Some(100),
+            mw_height : None,
+            nonce : String::from("00ff")
+        });
+        match check_offer_expiry(&slate.pub_slate, &btc_core, &grin_core) {
+            Err(SwapError::NodeRpc(_)) => (),
+            other => panic!("Expected a node error, got {:?}", other)
+        }
+        // without expiry heights the nodes are not queried
+        slate.pub_slate.validity.as_mut().unwrap().btc_height = None;
+        let requests = stub.requests().len();
+        assert!(check_offer_expiry(&slate.pub_slate, &btc_core, &grin_core).is_ok());
+        assert_eq!(requests, stub.requests().len());
+    }
+
+    #[test]
+    fn test_nonce_is_signed() {
+        let secp = get_secp256k1_curve();
+        let sk = create_private_key(&mut get_os_rng());
+        let mut slate = sample_slate(4, true);
+        slate.pub_slate.validity = Some(OfferValidity {
+            created : 1000,
+            expires : 2000,
+            btc_height : None,
+            mw_height : None,
+            nonce : String::from("01")
+        });
+        slate.pub_slate.offer_sig = Some(OfferTerms::from_pub_slate(4, &slate.pub_slate).sign(&sk, &secp));
+        assert!(verify_offer(4, &slate.pub_slate, &secp).is_ok());
+        slate.pub_slate.validity.as_mut().unwrap().nonce = String::from("02");
+        assert!(verify_offer(4, &slate.pub_slate, &secp).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use crate::enums::{Currency, SwapType};
+use crate::swap::swap_types::{PriceQuote, SwapSlatePub};
+use crate::units::{format_decimal, parse_decimal, DecimalError};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+// Prices are kept in units of 10^-12 BTC per GRIN
+const PRICE_DECIMALS : u32 = 12;
+const PRICE_UNIT : u128 = 1_000_000_000_000;
+// nanogrin * price units / PRICE_UNITS_PER_SAT = satoshis, 10^9 * 10^12 / 10^8
+const PRICE_UNITS_PER_SAT : u128 = 10_000_000_000_000;
+// Spreads are given in basis points
+const BPS : i64 = 10000;
+const MAX_SPREAD_BPS : i32 = 5000;
+
+/// Price of one GRIN in BTC with a fixed precision of 12 decimal places
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Price {
+    units : u128
+}
+
+impl FromStr for Price {
+    type Err = String;
+
+    /// Parse a decimal price like `0.0000123`, more than 12 decimal places are refused
+    fn from_str(s : &str) -> Result<Price, String> {
+        let units = parse_decimal(s.trim(), PRICE_DECIMALS)
+            .map_err(|e| match e {
+                DecimalError::Precision => format!("Price {} has more than {} decimal places", s, PRICE_DECIMALS),
+                DecimalError::Invalid => format!("Invalid price {}", s),
+                DecimalError::Overflow => format!("Price {} is too high", s)
+            })?;
+        if units == 0 {
+            return Err(String::from("Price must be greater than zero"));
+        }
+        Ok(Price {
+            units : units
+        })
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_decimal(self.units, PRICE_UNIT, PRICE_DECIMALS))
+    }
+}
+
+/// How the requested amount of a new offer is determined
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CounterAmount {
+    // requested amount in satoshis or nanogrin
+    Amount(u64),
+    // computed from a price in BTC per GRIN
+    Price(Price),
+    // computed from the reference price of the price feed plus a spread in basis points
+    Spread(i32)
+}
+
+impl CounterAmount {
+    /// Select the counter amount from the arguments of init, exactly one has to be set
+    ///
+    /// # Arguments
+    ///
+    /// * `to_amount` the requested amount
+    /// * `price` the price in BTC per GRIN
+    /// * `spread_bps` the spread on the reference price in basis points
+    pub fn from_args(to_amount : Option<u64>, price : Option<&str>, spread_bps : Option<i32>) -> Result<CounterAmount, String> {
+        match (to_amount, price, spread_bps) {
+            (Some(a), None, None) => Ok(CounterAmount::Amount(a)),
+            (None, Some(p), None) => Ok(CounterAmount::Price(Price::from_str(p)?)),
+            (None, None, Some(s)) => {
+                validate_spread(s)?;
+                Ok(CounterAmount::Spread(s))
+            },
+            _ => Err(String::from("Supply exactly one of a to amount, a price or a spread"))
+        }
+    }
+}
+
+/// Source of the reference price offers are quoted against
+pub trait PriceFeed {
+    /// The current price of one GRIN in BTC
+    fn btc_per_grin(&self) -> Result<Price, String>;
+}
+
+/// Price feed reading a local json file like `{ "btc_per_grin" : "0.0000123" }`
+pub struct FilePriceFeed {
+    path : String
+}
+
+impl FilePriceFeed {
+    pub fn new(path : &str) -> FilePriceFeed {
+        FilePriceFeed {
+            path : String::from(path)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PriceFile {
+    btc_per_grin : String
+}
+
+impl PriceFeed for FilePriceFeed {
+    fn btc_per_grin(&self) -> Result<Price, String> {
+        let contents = fs::read_to_string(&self.path)
+            .map_err(|e| format!("Unable to read price file {}: {}", self.path, e))?;
+        let file : PriceFile = serde_json::from_str(&contents)
+            .map_err(|e| format!("Unable to parse price file {}: {}", self.path, e))?;
+        Price::from_str(&file.btc_per_grin)
+    }
+}
+
+/// Price feed stub returning a fixed price
+pub struct FixedPriceFeed {
+    price : Price
+}
+
+impl FixedPriceFeed {
+    pub fn new(price : Price) -> FixedPriceFeed {
+        FixedPriceFeed {
+            price : price
+        }
+    }
+}
+
+impl PriceFeed for FixedPriceFeed {
+    fn btc_per_grin(&self) -> Result<Price, String> {
+        Ok(self.price)
+    }
+}
+
+/// Determine the requested amount of a new offer and the quote it is based on
+///
+/// # Arguments
+///
+/// * `from` the offered currency
+/// * `from_amount` the offered amount in satoshis or nanogrin
+/// * `counter` how the requested amount is determined
+/// * `feed` the price feed, only queried for spreads
+pub fn resolve_counter_amount(from : Currency, from_amount : u64, counter : CounterAmount, feed : &dyn PriceFeed) -> Result<(u64, Option<PriceQuote>), String> {
+    let quote = match counter {
+        CounterAmount::Amount(a) => return Ok((a, None)),
+        CounterAmount::Price(p) => PriceQuote {
+            price : p.to_string(),
+            spread_bps : 0
+        },
+        CounterAmount::Spread(s) => PriceQuote {
+            price : feed.btc_per_grin()?.to_string(),
+            spread_bps : s
+        }
+    };
+    let to_amount = quoted_amount(from, from_amount, &quote)?;
+    Ok((to_amount, Some(quote)))
+}
+
+/// Compute the requested amount of an offer from its quote.
+/// The maker gets the spread on top of the price, the result is rounded down
+/// to whole satoshis or nanogrin so the maker never asks for more than the quote.
+///
+/// # Arguments
+///
+/// * `from` the offered currency
+/// * `from_amount` the offered amount in satoshis or nanogrin
+/// * `quote` the price quote
+pub fn quoted_amount(from : Currency, from_amount : u64, quote : &PriceQuote) -> Result<u64, String> {
+    validate_spread(quote.spread_bps)?;
+    let price = Price::from_str(&quote.price)?;
+    let spread = (BPS + quote.spread_bps as i64) as u128;
+    let overflow = || String::from("Amount is too high for the quoted price");
+    let (numerator, denominator) = match from {
+        // satoshis = nanogrin * price / 10^13
+        Currency::GRIN => ((from_amount as u128).checked_mul(price.units).and_then(|n| n.checked_mul(spread)).ok_or_else(overflow)?,
+            PRICE_UNITS_PER_SAT * BPS as u128),
+        // nanogrin = satoshis * 10^13 / price
+        Currency::BTC => ((from_amount as u128).checked_mul(PRICE_UNITS_PER_SAT).and_then(|n| n.checked_mul(spread)).ok_or_else(overflow)?,
+            price.units.checked_mul(BPS as u128).ok_or_else(overflow)?)
+    };
+    let amount = numerator / denominator;
+    if amount == 0 {
+        return Err(String::from("The quoted price results in a requested amount of zero"));
+    }
+    if amount > u64::MAX as u128 {
+        return Err(overflow());
+    }
+    Ok(amount as u64)
+}
+
+/// Check that the amounts of an offer match the quote it records
+///
+/// # Arguments
+///
+/// * `pub_slate` the public slate of the offer
+pub fn verify_quote(pub_slate : &SwapSlatePub) -> Result<(), String> {
+    let quote = match &pub_slate.quote {
+        Some(q) => q,
+        None => return Ok(())
+    };
+    let btc_offered = pub_slate.btc.swap_type == SwapType::OFFERED;
+    let (from, from_amount, to_amount) = if btc_offered {
+        (Currency::BTC, pub_slate.btc.amount, pub_slate.mw.amount)
+    }
+    else {
+        (Currency::GRIN, pub_slate.mw.amount, pub_slate.btc.amount)
+    };
+    let expected = quoted_amount(from, from_amount, quote)?;
+    if expected != to_amount {
+        Err(format!("Requested amount {} doesn't match the quoted price {} with spread {} bps, expected {}", to_amount, quote.price, quote.spread_bps, expected))
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// The effective rate of an offer as text, in BTC per GRIN and GRIN per BTC
+///
+/// # Arguments
+///
+/// * `btc_amount` the Bitcoin amount in satoshis
+/// * `mw_amount` the Grin amount in nanogrin
+pub fn format_effective_rate(btc_amount : u64, mw_amount : u64) -> String {
+    if btc_amount == 0 || mw_amount == 0 {
+        return String::from("undefined");
+    }
+    // BTC per GRIN = satoshis * 10 / nanogrin
+    let btc_per_grin = format_decimal(btc_amount as u128 * 10, mw_amount as u128, PRICE_DECIMALS);
+    let grin_per_btc = format_decimal(mw_amount as u128, btc_amount as u128 * 10, PRICE_DECIMALS);
+    format!("{} BTC per GRIN, {} GRIN per BTC", btc_per_grin, grin_per_btc)
+}
+
+fn validate_spread(spread_bps : i32) -> Result<(), String> {
+    if spread_bps.abs() > MAX_SPREAD_BPS {
+        Err(format!("Spread must be between -{} and {} basis points", MAX_SPREAD_BPS, MAX_SPREAD_BPS))
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::enums::Currency;
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::swap::swap_types::PriceQuote;
+    use std::str::FromStr;
+
+    use super::{format_effective_rate, quoted_amount, resolve_counter_amount, verify_quote, CounterAmount, FixedPriceFeed, Price};
+
+    #[test]
+    fn test_parse_price() {
+        assert_eq!("0.0001", Price::from_str("0.000100").unwrap().to_string());
+        assert_eq!("2", Price::from_str("2").unwrap().to_string());
+        assert_eq!("0.5", Price::from_str(".5").unwrap().to_string());
+        assert_eq!("0.000000000001", Price::from_str("0.000000000001").unwrap().to_string());
+        assert!(Price::from_str("0.0000000000001").is_err());
+        assert!(Price::from_str("0").is_err());
+        assert!(Price::from_str("-1").is_err());
+        assert!(Price::from_str("1e-5").is_err());
+        assert!(Price::from_str("1.2.3").is_err());
+        assert!(Price::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_counter_amount_args() {
+        assert_eq!(CounterAmount::Amount(5), CounterAmount::from_args(Some(5), None, None).unwrap());
+        assert_eq!(CounterAmount::Spread(-25), CounterAmount::from_args(None, None, Some(-25)).unwrap());
+        assert!(CounterAmount::from_args(None, None, None).is_err());
+        assert!(CounterAmount::from_args(Some(5), Some("0.001"), None).is_err());
+        assert!(CounterAmount::from_args(None, None, Some(5001)).is_err());
+    }
+
+    #[test]
+    fn test_quoted_amount() {
+        let quote = |price : &str, spread_bps : i32| PriceQuote {
+            price : String::from(price),
+            spread_bps : spread_bps
+        };
+        // 2 GRIN at 0.0008 BTC per GRIN
+        assert_eq!(160000, quoted_amount(Currency::GRIN, 2000000000, &quote("0.0008", 0)).unwrap());
+        // 1600 sats at 0.0008 BTC per GRIN
+        assert_eq!(20000000, quoted_amount(Currency::BTC, 1600, &quote("0.0008", 0)).unwrap());
+        // the maker gets 1% more
+        assert_eq!(161600, quoted_amount(Currency::GRIN, 2000000000, &quote("0.0008", 100)).unwrap());
+        assert_eq!(20200000, quoted_amount(Currency::BTC, 1600, &quote("0.0008", 100)).unwrap());
+        // rounded down: 1 nanogrin is worth 0.00008 sats
+        assert!(quoted_amount(Currency::GRIN, 1, &quote("0.0008", 0)).is_err());
+        assert_eq!(1, quoted_amount(Currency::GRIN, 12500, &quote("0.0008", 0)).unwrap());
+        assert_eq!(1, quoted_amount(Currency::GRIN, 24999, &quote("0.0008", 0)).unwrap());
+        // 1 sat at 0.0003 BTC per GRIN is 33333.33.. nanogrin
+        assert_eq!(33333, quoted_amount(Currency::BTC, 1, &quote("0.0003", 0)).unwrap());
+    }
+
+    #[test]
+    fn test_resolve_and_verify() {
+        let feed = FixedPriceFeed::new(Price::from_str("0.0008").unwrap());
+        let (amount, quote) = resolve_counter_amount(Currency::BTC, 100000, CounterAmount::Spread(50), &feed).unwrap();
+        assert_eq!(1256250000, amount);
+        let quote = quote.unwrap();
+        assert_eq!("0.0008", quote.price);
+        assert_eq!(50, quote.spread_bps);
+        assert_eq!((7, None), resolve_counter_amount(Currency::BTC, 100000, CounterAmount::Amount(7), &feed).unwrap());
+
+        let mut slate = sample_slate(1, true);
+        slate.pub_slate.mw.amount = amount;
+        slate.pub_slate.quote = Some(quote);
+        assert!(verify_quote(&slate.pub_slate).is_ok());
+        slate.pub_slate.mw.amount = amount + 1;
+        assert!(verify_quote(&slate.pub_slate).is_err());
+    }
+
+    #[test]
+    fn test_effective_rate() {
+        assert_eq!("0.0008 BTC per GRIN, 1250 GRIN per BTC", format_effective_rate(160000, 2000000000));
+        assert_eq!("0.0003 BTC per GRIN, 3333.333333333333 GRIN per BTC", format_effective_rate(30000, 1000000000));
+    }
+}
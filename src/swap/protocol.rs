@@ -1,12 +1,14 @@
-use crate::net::tcp::send_msg;
+use crate::enums::{BtcLockType, ChainStatus, Currency, GrinLockType, SwapStatus};
+use crate::error::SwapError;
+use crate::net::tcp::{send_msg, MessageChannel};
 use crate::SwapSlate;
 use crate::{
     bitcoin::{
-        bitcoin_core::BitcoinCore,
+        bitcoin_backend::BitcoinBackend,
         btcroutines::{create_lock_transaction, get_lock_pub_script},
     },
-    constants::{BTC_FEE, MAX_ATTEMPTS_VERF_FUNDS},
-    grin::{grin_core::GrinCore, grin_tx::GrinTx},
+    constants::MAX_ATTEMPTS_VERF_FUNDS,
+    grin::{grin_backend::GrinBackend, grin_tx::GrinTx, grin_types::{KernelLock, MWCoin}},
     net::tcp::receive_msg,
 };
 use crate::{
@@ -21,43 +23,59 @@ use crate::{
     },
     constants::{BTC_BLOCK_TIME, GRIN_BLOCK_TIME},
     grin::grin_routines::{
-        deserialize_grin_pub_key, deserialize_secret_key, estimate_fees, grin_pk_from_btc_pk,
-        grin_sk_from_btc_sk,
+        deserialize_grin_pub_key, deserialize_secret_key, estimate_fees, get_kernel_excess,
+        grin_pk_from_btc_pk, grin_sk_from_btc_sk,
     },
 };
+use crate::settings::SafetySettings;
+use crate::swap::validator::{
+    btc_lock_passed, btc_minutes_left, validate_btc_lock_time, validate_lock_times,
+    validate_offer_timelocks,
+};
 use bitcoin::util::key::PublicKey;
 use bitcoin::util::psbt::serialize::Serialize;
 use bitcoin::{secp256k1::All, PrivateKey};
 use bitcoin::{secp256k1::Secp256k1, Address};
 use grin_core::global::set_local_chain_type;
 use grin_util::secp::Secp256k1 as GrinSecp256k1;
-use rand::rngs::OsRng;
+use crate::util::SwapRng;
 use std::convert::TryFrom;
-use std::{net::TcpStream, thread, time::Duration};
+use std::{thread, time::Duration};
 
 /// Runs the mimblewimble side of the setup phase of the atomic swap
 ///
 /// # Arguments
 ///
 /// * `slate` reference to the atomic swap slate
-/// * `stream` TCP Channel
+/// * `stream` channel to the peer
 /// * `rng` Randomness generator
 /// * `secp` Secp256k1 functions
-/// * `grin_core` Grin core functions
-/// * `btc_core` Bitcoin core functions
+/// * `grin_core` Grin chain backend
+/// * `btc_core` Bitcoin chain backend
 /// * `grin_tx` Grin transaction functions
+/// * `safety` Safety margins the lock heights are validated against
 pub fn locking_phase_swap_mw(
     slate: &mut SwapSlate,
-    stream: &mut TcpStream,
-    rng: &mut OsRng,
+    stream: &mut dyn MessageChannel,
+    rng: &mut SwapRng,
     secp: &Secp256k1<All>,
-    grin_core: &mut GrinCore,
-    btc_core: &mut BitcoinCore,
+    grin_core: &dyn GrinBackend,
+    btc_core: &dyn BitcoinBackend,
     grin_tx: &mut GrinTx,
-) -> Result<(), String> {
+    safety: &SafetySettings,
+) -> Result<(), SwapError> {
+    if slate.pub_slate.status != SwapStatus::INITIALIZED {
+        return Err(SwapError::Validation(format!(
+            "Swap can only be locked once, current status is {:?}",
+            slate.pub_slate.status
+        )));
+    }
+    validate_offer_timelocks(&slate.pub_slate, safety).map_err(SwapError::Timelock)?;
     set_local_chain_type(grin_core::global::ChainTypes::Testnet);
+    let fee_base = slate.pub_slate.mw.get_fee_base();
+    grin_tx.set_fee_base(fee_base);
     println!("Starting setup phase MW");
-    slate.pub_slate.status = crate::enums::SwapStatus::SETUP;
+    slate.set_status(SwapStatus::SETUP);
     let mut msg_bob = "".to_string();
 
     println!("Creating and exchanging keys...");
@@ -69,40 +87,54 @@ pub fn locking_phase_swap_mw(
     slate.pub_slate.btc.pub_a = Some(serialize_pub_key(&pub_a));
 
     // Send public key to peer
-    send_msg(stream, &serialize_pub_key(&pub_a));
+    send_msg(stream, &serialize_pub_key(&pub_a)).map_err(SwapError::PeerProtocol)?;
 
     // Bobs pubkey
-    msg_bob = receive_msg(stream);
-    let pub_b = deserialize_pub_key(&msg_bob);
+    msg_bob = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let pub_b = deserialize_pub_key(&msg_bob).map_err(SwapError::PeerProtocol)?;
     slate.pub_slate.btc.pub_b = Some(serialize_pub_key(&pub_b));
 
     // Statement x
-    msg_bob = receive_msg(stream);
-    let pub_x = deserialize_pub_key(&msg_bob);
+    msg_bob = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let pub_x = deserialize_pub_key(&msg_bob).map_err(SwapError::PeerProtocol)?;
     slate.pub_slate.btc.pub_x = Some(serialize_pub_key(&pub_x));
 
-    // Bitcoin lock height
-    msg_bob = receive_msg(stream);
-    let lock_time_btc: i64 = msg_bob.parse::<i64>().unwrap();
+    // Bitcoin lock height or timestamp
+    msg_bob = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let lock_time_btc: i64 = msg_bob
+        .parse::<i64>()
+        .map_err(|e| SwapError::PeerProtocol(format!("Invalid Bitcoin lock time received: {}", e)))?;
+    // Bob has to use the lock type and timelock of the offer
+    let expected_lock_time = expected_btc_lock_time(btc_core, slate)?;
+    if let Err(e) = validate_btc_lock_time(slate.pub_slate.btc.lock_type, lock_time_btc, expected_lock_time) {
+        slate.set_status(SwapStatus::ABORTED);
+        return Err(SwapError::Timelock(e));
+    }
     slate.pub_slate.btc.lock_time = Some(lock_time_btc);
+    slate.log_event(
+        Some(Currency::BTC),
+        None,
+        u64::try_from(lock_time_btc).ok(),
+        "Received Bitcoin refund lock time",
+    );
     // We now calculate the bitcoin address on which Bob is supposed to lock his BTC
     let pub_script = get_lock_pub_script(pub_a, pub_x, pub_b, lock_time_btc, true);
     let addr = Address::from_script(&pub_script, bitcoin::Network::Testnet).unwrap();
     // index the address on our Bitcoin Core node
-    btc_core.import_btc_address(addr.clone()).unwrap();
+    btc_core.import_btc_address(addr.clone()).map_err(SwapError::NodeRpc)?;
 
     // Now wait for Bob to send the lock address himself and then verify the locked funds
-    let address = receive_msg(stream);
-    let txid = receive_msg(stream);
+    let address = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let txid = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
     println!(
         "Verifing the locked funds on address : {} and txid: {}",
         address, txid
     );
     if addr.clone().to_string() != address {
-        slate.pub_slate.status = crate::enums::SwapStatus::FAILED;
-        Err(String::from(
+        slate.set_status(SwapStatus::ABORTED);
+        Err(SwapError::PeerProtocol(String::from(
             "Lock address sent by Bob doesn't match what we have calculated, stopping swap",
-        ))
+        )))
     } else {
         let mut verified_funds = false;
         let mut attempts = 0;
@@ -125,12 +157,19 @@ pub fn locking_phase_swap_mw(
         }
 
         if !verified_funds {
-            slate.pub_slate.status = crate::enums::SwapStatus::FAILED;
-            Err(String::from(
+            slate.set_status(SwapStatus::ABORTED);
+            Err(SwapError::Validation(String::from(
                 "Failed to verify that btc funds are correctly locked",
-            ))
+            )))
         } else {
             println!("Successfully verified the locked funds!");
+            slate.log_event(
+                Some(Currency::BTC),
+                Some(txid.clone()),
+                None,
+                "Verified Bitcoin lock transaction",
+            );
+            slate.set_chain_status(Currency::BTC, ChainStatus::LOCKED);
             slate.prv_slate.btc.lock = Some(BTCInput::new2(
                 txid,
                 0,
@@ -139,11 +178,27 @@ pub fn locking_phase_swap_mw(
                 pub_a,
                 pub_script,
             ));
-            let grin_height = grin_core.get_block_height().unwrap();
+            let grin_height = grin_core.get_block_height().map_err(SwapError::NodeRpc)?;
             let grin_lock_height = grin_height + slate.pub_slate.mw.timelock;
+            // Bob chose the Bitcoin lock time, make sure it leaves us enough time to redeem
+            if let Err(e) = validate_lock_times(
+                btc_refund_minutes_left(btc_core, slate)?,
+                grin_lock_height,
+                grin_height,
+                safety,
+            ) {
+                slate.set_status(SwapStatus::ABORTED);
+                return Err(SwapError::Timelock(e));
+            }
             slate.pub_slate.mw.lock_time = Some(i64::try_from(grin_lock_height).unwrap());
+            slate.log_event(
+                Some(Currency::GRIN),
+                None,
+                Some(grin_lock_height),
+                "Grin refund lock height set",
+            );
             // Send over grin_lock_height to Bob
-            send_msg(stream, &grin_lock_height.to_string());
+            send_msg(stream, &grin_lock_height.to_string()).map_err(SwapError::PeerProtocol)?;
 
             // Create shared MW output
             println!("Running protocol to create shared Mimblewimble output...");
@@ -151,20 +206,20 @@ pub fn locking_phase_swap_mw(
                 slate.prv_slate.mw.inputs.clone(),
                 slate.pub_slate.mw.amount,
                 0,
+                grin_anchor(slate),
                 stream,
-            )?;
+            ).map_err(SwapError::PeerProtocol)?;
             slate.prv_slate.mw.shared_coin = Some(shared_out_result.shared_coin.clone());
             slate.prv_slate.mw.change_coin = shared_out_result.change_coin.clone();
-            println!(
-                "Mimblewimble change coin: {}",
-                slate.prv_slate.mw.change_coin.clone().unwrap().to_string()
-            );
+            if let Some(change_coin) = &slate.prv_slate.mw.change_coin {
+                println!("Mimblewimble change coin: {}", change_coin.to_string());
+            }
             println!(
                 "Share coin mw side: {}",
                 shared_out_result.shared_coin.to_string()
             );
-            let fee = estimate_fees(1, 1, 1);
-            let fund_value = shared_out_result.shared_coin.clone().value - fee;
+            let fee = estimate_fees(1, 1, 1, fee_base);
+            let fund_value = refund_value(&shared_out_result.shared_coin, fee)?;
             println!("Refund tx fund value: {}", fund_value);
 
             // Timelocked transaction spending back to Alice
@@ -172,14 +227,25 @@ pub fn locking_phase_swap_mw(
             let refund_result = grin_tx.dshared_inp_mw_tx_bob(
                 shared_out_result.shared_coin.clone(),
                 fund_value,
-                grin_lock_height,
+                grin_refund_lock(slate, grin_lock_height),
+                shared_out_result.anchor.as_ref(),
                 stream,
-            )?;
+            ).map_err(SwapError::PeerProtocol)?;
             slate.prv_slate.mw.refund_coin = refund_result.coin;
             slate.prv_slate.mw.refund_tx = refund_result.tx.tx.clone();
 
             // publish the funding transactions
-            grin_core.push_transaction(shared_out_result.tx.tx.unwrap())?;
+            let funding_tx = shared_out_result.tx.tx
+                .ok_or(SwapError::PeerProtocol(String::from("The funding transaction is missing")))?;
+            let funding_excess = get_kernel_excess(&funding_tx);
+            grin_core.push_transaction(funding_tx).map_err(SwapError::NodeRpc)?;
+            slate.log_event(
+                Some(Currency::GRIN),
+                funding_excess,
+                Some(grin_height),
+                "Published Grin funding transaction",
+            );
+            slate.set_chain_status(Currency::GRIN, ChainStatus::LOCKED);
 
             println!("Successfully finished setup protocol on Mimblewimble side");
 
@@ -193,24 +259,35 @@ pub fn locking_phase_swap_mw(
 /// # Arguments
 ///
 /// * `slate` reference to the atomic swap slate
-/// * `stream` TCP channel
+/// * `stream` channel to the peer
 /// * `rng` Randomness generator
 /// * `secp` Secp256k1 functions
-/// * `grin_core` Grin core functions
-/// * `btc_core` Bitcoin core functions
+/// * `grin_core` Grin chain backend
+/// * `btc_core` Bitcoin chain backend
 /// * `grin_tx` Grin transaction functions
+/// * `safety` Safety margins the lock heights are validated against
 pub fn locking_phase_swap_btc(
     slate: &mut SwapSlate,
-    stream: &mut TcpStream,
-    rng: &mut OsRng,
+    stream: &mut dyn MessageChannel,
+    rng: &mut SwapRng,
     secp: &Secp256k1<All>,
-    grin_core: &mut GrinCore,
-    btc_core: &mut BitcoinCore,
+    grin_core: &dyn GrinBackend,
+    btc_core: &dyn BitcoinBackend,
     grin_tx: &mut GrinTx,
-) -> Result<(), String> {
+    safety: &SafetySettings,
+) -> Result<(), SwapError> {
+    if slate.pub_slate.status != SwapStatus::INITIALIZED {
+        return Err(SwapError::Validation(format!(
+            "Swap can only be locked once, current status is {:?}",
+            slate.pub_slate.status
+        )));
+    }
+    validate_offer_timelocks(&slate.pub_slate, safety).map_err(SwapError::Timelock)?;
     set_local_chain_type(grin_core::global::ChainTypes::Testnet);
+    let fee_base = slate.pub_slate.mw.get_fee_base();
+    grin_tx.set_fee_base(fee_base);
     println!("Starting setup phase BTC");
-    slate.pub_slate.status = crate::enums::SwapStatus::SETUP;
+    slate.set_status(SwapStatus::SETUP);
     let mut msg_alice = "".to_string();
 
     println!("Exchanging keys...");
@@ -232,23 +309,22 @@ pub fn locking_phase_swap_btc(
     slate.pub_slate.btc.pub_x = Some(serialize_pub_key(&pub_x));
 
     // get the receivers pub key
-    msg_alice = receive_msg(stream);
-    let pub_a = deserialize_pub_key(&msg_alice);
+    msg_alice = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let pub_a = deserialize_pub_key(&msg_alice).map_err(SwapError::PeerProtocol)?;
     slate.pub_slate.btc.pub_a = Some(serialize_pub_key(&pub_a));
 
     // Send sender pub key and statement pub_x
-    send_msg(stream, &serialize_pub_key(&pub_b));
-    send_msg(stream, &serialize_pub_key(&pub_x));
+    send_msg(stream, &serialize_pub_key(&pub_b)).map_err(SwapError::PeerProtocol)?;
+    send_msg(stream, &serialize_pub_key(&pub_x)).map_err(SwapError::PeerProtocol)?;
 
     // Now we lock up those bitcoins
     println!("Building Bitcoin lock transaction...");
-    let btc_current_height = btc_core.get_current_block_height()?;
+    let btc_current_height = btc_core.get_current_block_height().map_err(SwapError::NodeRpc)?;
     let inputs = slate.prv_slate.btc.inputs.clone();
     let btc_amount = slate.pub_slate.btc.amount;
-    let btc_lock_height: i64 =
-        i64::try_from(btc_current_height + slate.pub_slate.btc.timelock).unwrap();
+    let btc_lock_height: i64 = i64::try_from(expected_btc_lock_time(btc_core, slate)?).unwrap();
     // Send the bitcoin locktime to alice
-    send_msg(stream, &btc_lock_height.to_string());
+    send_msg(stream, &btc_lock_height.to_string()).map_err(SwapError::PeerProtocol)?;
     slate.pub_slate.btc.lock_time = Some(btc_lock_height);
 
     let tx_lock = create_lock_transaction(
@@ -258,17 +334,19 @@ pub fn locking_phase_swap_btc(
         pub_r,
         inputs.clone(),
         btc_amount,
-        BTC_FEE,
+        btc_core.estimate_fee().map_err(SwapError::NodeRpc)?,
         btc_lock_height,
-    )?;
+    )
+    .map_err(SwapError::InsufficientFunds)?;
     let tx_lock_clone = tx_lock.clone();
     let pub_script = tx_lock_clone.output.get(0).unwrap().script_pubkey.clone();
     let ch_out = tx_lock_clone.output.get(1).unwrap();
     let address = Address::from_script(&pub_script, bitcoin::Network::Testnet).unwrap();
 
-    let inp = inputs.get(0).unwrap();
-    let inp_pub_script = deserialize_script(&inp.pub_script);
-    let inp_sk = deserialize_priv_key(&inp.secret);
+    let inp = inputs.get(0)
+        .ok_or(SwapError::InsufficientFunds(String::from("No Bitcoin inputs imported")))?;
+    let inp_pub_script = deserialize_script(&inp.pub_script).map_err(SwapError::Validation)?;
+    let inp_sk = deserialize_priv_key(&inp.secret).map_err(SwapError::Validation)?;
     let inp_pk = PublicKey::from_private_key(secp, &inp_sk);
     let signed_tx = sign_p2pkh_transaction(
         tx_lock,
@@ -278,7 +356,7 @@ pub fn locking_phase_swap_btc(
         secp,
     );
     let txid = signed_tx.txid().to_string();
-    btc_core.send_raw_transaction(signed_tx.clone())?;
+    btc_core.send_raw_transaction(signed_tx.clone()).map_err(SwapError::NodeRpc)?;
     let change = BTCInput::new2(
         txid.clone(),
         1,
@@ -288,6 +366,15 @@ pub fn locking_phase_swap_btc(
         ch_out.script_pubkey.clone(),
     );
     slate.prv_slate.btc.change = Some(change.clone());
+    slate.log_event(
+        Some(Currency::BTC),
+        Some(txid.clone()),
+        Some(btc_current_height),
+        "Published Bitcoin lock transaction",
+    );
+    slate.set_chain_status(Currency::BTC, ChainStatus::LOCKED);
+    // Record the lock output before talking to the peer again, we need it for the refund
+    slate.prv_slate.btc.lock = Some(BTCInput::new2(txid.clone(), 0, btc_amount, sk_b, pub_b, pub_script));
     println!(
         "Published Bitcoin lock transaction with txid: {}, address: {}",
         txid,
@@ -296,17 +383,34 @@ pub fn locking_phase_swap_btc(
     println!("Bitcoin change output: {}", change.clone().to_string());
 
     // Send the address, txid over to Alice and let her verify the locked funds
-    send_msg(stream, &address.to_string());
-    send_msg(stream, &txid);
-    slate.prv_slate.btc.lock = Some(BTCInput::new2(txid, 0, btc_amount, sk_b, pub_b, pub_script));
+    send_msg(stream, &address.to_string()).map_err(SwapError::PeerProtocol)?;
+    send_msg(stream, &txid).map_err(SwapError::PeerProtocol)?;
 
     // Receive the grin side lock height from alice
-    msg_alice = receive_msg(stream);
-    let lock_height_grin = msg_alice.parse::<i64>().unwrap();
+    msg_alice = receive_msg(stream).map_err(SwapError::PeerProtocol)?;
+    let lock_height_grin = msg_alice
+        .parse::<i64>()
+        .map_err(|e| SwapError::PeerProtocol(format!("Invalid Grin lock height received: {}", e)))?;
     slate.pub_slate.mw.lock_time = Some(lock_height_grin);
+    slate.log_event(
+        Some(Currency::GRIN),
+        None,
+        u64::try_from(lock_height_grin).ok(),
+        "Received Grin refund lock height",
+    );
+    // Our Bitcoin is locked already, on failure it can be refunded after the lock height
+    validate_lock_times(
+        btc_refund_minutes_left(btc_core, slate)?,
+        u64::try_from(lock_height_grin).unwrap_or(0),
+        grin_core.get_block_height().map_err(SwapError::NodeRpc)?,
+        safety,
+    )
+    .map_err(SwapError::Timelock)?;
 
     println!("Running protocol to create shared Mimblewimble output...");
-    let shared_out_result = grin_tx.dshared_out_mw_tx_bob(slate.pub_slate.mw.amount, stream)?;
+    let shared_out_result = grin_tx
+        .dshared_out_mw_tx_bob(slate.pub_slate.mw.amount, grin_anchor(slate), stream)
+        .map_err(SwapError::PeerProtocol)?;
     slate.prv_slate.mw.shared_coin = Some(shared_out_result.shared_coin.clone());
     println!(
         "Shared coin on BTC side: {}",
@@ -314,15 +418,18 @@ pub fn locking_phase_swap_btc(
     );
 
     println!("Running protocol to refund shared Mimblewimble output...");
-    let fee = estimate_fees(1, 1, 1);
-    let fund_value = shared_out_result.shared_coin.clone().value - fee;
+    let fee = estimate_fees(1, 1, 1, fee_base);
+    let fund_value = refund_value(&shared_out_result.shared_coin, fee)?;
     println!("Fund value of refund transaction: {}", fund_value);
     let shared_inp_result = grin_tx.dshared_inp_mw_tx_alice(
         shared_out_result.shared_coin.clone(),
         fund_value,
-        u64::try_from(lock_height_grin).unwrap(),
+        grin_refund_lock(slate, u64::try_from(lock_height_grin).unwrap_or(0)),
+        shared_out_result.anchor.as_ref(),
         stream,
-    )?;
+    ).map_err(SwapError::PeerProtocol)?;
+    // Alice publishes the funding transaction once the refund is signed
+    slate.set_chain_status(Currency::GRIN, ChainStatus::LOCKED);
 
     println!("Successfully finished Setup protocol on Bitcoin side");
     Ok(())
@@ -334,70 +441,85 @@ pub fn locking_phase_swap_btc(
 /// # Arguments
 ///
 /// * `slate` The swap slate, needs to be setup
-/// * `stream` TCP channel
-/// * `btc_core` Bitcoin core functions
+/// * `stream` channel to the peer
+/// * `btc_core` Bitcoin chain backend
 /// * `rng` Randomness generator
 /// * `grin_tx` Grin transaction functions
 /// * `grin_secp` Grin version of Secp256k1 functions
 /// * `btc_secp` Bitcoin version of Secp256k1 functions
 pub fn exec_phase_swap_mw(
     slate: &mut SwapSlate,
-    stream: &mut TcpStream,
-    btc_core: &mut BitcoinCore,
-    rng: &mut OsRng,
+    stream: &mut dyn MessageChannel,
+    btc_core: &dyn BitcoinBackend,
+    rng: &mut SwapRng,
     grin_tx: &mut GrinTx,
-    grin_core: &mut GrinCore,
+    grin_core: &dyn GrinBackend,
     grin_secp: &GrinSecp256k1,
     btc_secp: &Secp256k1<All>,
-) -> Result<(), String> {
-    if check_if_enough_time(grin_core, btc_core, slate) {
+) -> Result<(), SwapError> {
+    if slate.pub_slate.status != SwapStatus::LOCKED {
+        return Err(SwapError::Validation(format!(
+            "Both sides need to be locked before executing, current status is {:?}",
+            slate.pub_slate.status
+        )));
+    }
+    let fee_base = slate.pub_slate.mw.get_fee_base();
+    grin_tx.set_fee_base(fee_base);
+    if check_if_enough_time(grin_core, btc_core, slate)? {
         set_local_chain_type(grin_core::global::ChainTypes::Testnet);
-        slate.pub_slate.status = crate::enums::SwapStatus::EXECUTING;
+        slate.set_status(SwapStatus::EXECUTING);
         println!("Running Atomic Swap execution phase on mimblewimble side");
-        let shared_coin = slate.prv_slate.mw.shared_coin.clone().unwrap();
-        let value = shared_coin.value;
-        let pub_x = deserialize_pub_key(&slate.pub_slate.btc.pub_x.clone().unwrap());
+        let shared_coin = slate_field(&slate.prv_slate.mw.shared_coin, "shared Grin coin")?;
+        let pub_x = slate_pub_key(&slate.pub_slate.btc.pub_x, "public statement X")?;
         let pub_x_grin = grin_pk_from_btc_pk(&pub_x, grin_secp);
 
         println!("Running Mimblewimble Contract transaction protocol");
-        let fee = estimate_fees(1, 1, 1);
-        let fund_value = value - fee;
-        let result =
-            grin_tx.dcontract_mw_tx_alice(shared_coin, fund_value, 0, pub_x_grin, stream)?;
+        let fee = estimate_fees(1, 1, 1, fee_base);
+        let fund_value = refund_value(&shared_coin, fee)?;
+        let result = grin_tx
+            .dcontract_mw_tx_alice(shared_coin, fund_value, 0, pub_x_grin, stream)
+            .map_err(SwapError::PeerProtocol)?;
+        slate.set_chain_status(Currency::GRIN, ChainStatus::REDEEMED);
 
         let sk_a2 = create_private_key(rng);
         let pub_a2 = PublicKey::from_private_key(btc_secp, &sk_a2);
 
-        let pub_a = deserialize_pub_key(&slate.pub_slate.btc.pub_a.clone().unwrap());
-        let pub_b = deserialize_pub_key(&slate.pub_slate.btc.pub_b.clone().unwrap());
-        let pub_x = deserialize_pub_key(&slate.pub_slate.btc.pub_x.clone().unwrap());
+        let pub_a = slate_pub_key(&slate.pub_slate.btc.pub_a, "public key A")?;
+        let pub_b = slate_pub_key(&slate.pub_slate.btc.pub_b, "public key B")?;
 
         let x_btc = private_key_from_grin_sk(&result.x);
         println!("Extracted x value: {}", serialize_priv_key(&x_btc));
 
-        let sk_a = deserialize_priv_key(&slate.prv_slate.btc.sk.clone().unwrap());
+        let sk_a = slate_priv_key(&slate.prv_slate.btc.sk, "Bitcoin secret key")?;
 
         println!("Creating Bitcoin redeem transaction");
         // Now we can spent the Bitcoin
         let redeem_tx = create_spend_lock_transaction(
             &pub_a2,
-            slate.prv_slate.btc.lock.clone().unwrap(),
+            slate_field(&slate.prv_slate.btc.lock, "Bitcoin lock output")?,
             slate.pub_slate.btc.amount,
-            BTC_FEE,
+            btc_core.estimate_fee().map_err(SwapError::NodeRpc)?,
             0,
-        )?;
+        )
+        .map_err(SwapError::Validation)?;
         let lock_script = get_lock_pub_script(
             pub_a,
             pub_x,
             pub_b,
-            slate.pub_slate.btc.lock_time.unwrap(),
+            slate_field(&slate.pub_slate.btc.lock_time, "Bitcoin lock time")?,
             false,
         );
         let signed_redeem_tx =
             sign_lock_transaction_redeemer(redeem_tx, 0, lock_script, sk_a, x_btc, btc_secp);
         let o = signed_redeem_tx.output.get(0).unwrap();
         let txid = signed_redeem_tx.txid().to_string();
-        btc_core.send_raw_transaction(signed_redeem_tx.clone())?;
+        btc_core.send_raw_transaction(signed_redeem_tx.clone()).map_err(SwapError::NodeRpc)?;
+        slate.log_event(
+            Some(Currency::BTC),
+            Some(txid.clone()),
+            None,
+            "Published Bitcoin redeem transaction",
+        );
         slate.prv_slate.btc.swapped = Some(BTCInput::new2(
             txid,
             0,
@@ -406,12 +528,12 @@ pub fn exec_phase_swap_mw(
             pub_a2,
             o.script_pubkey.clone(),
         ));
+        slate.set_chain_status(Currency::BTC, ChainStatus::REDEEMED);
         println!("Successfully completed Atomic Swap on Mimblewimble side");
-        slate.pub_slate.status = crate::enums::SwapStatus::FINISHED;
 
         Ok(())
     } else {
-        Err(String::from("Not enough time left to execute atomic swap"))
+        Err(SwapError::Timelock(String::from("Not enough time left to execute atomic swap")))
     }
 }
 
@@ -421,35 +543,57 @@ pub fn exec_phase_swap_mw(
 /// # Arguments
 ///
 /// * `slate` the Atomic swap state, needs to be setup
-/// * `stream` TCP channel
-/// * `btc_core` Bitcoin core functions
-/// * `grin_core` Grin core functions
+/// * `stream` channel to the peer
+/// * `btc_core` Bitcoin chain backend
+/// * `grin_core` Grin chain backend
 /// * `grin_tx` Grin Transaction functions
 /// * `secp` Grin version of Secp256k1 functionality
 pub fn exec_phase_swap_btc(
     slate: &mut SwapSlate,
-    stream: &mut TcpStream,
-    btc_core: &mut BitcoinCore,
-    grin_core: &mut GrinCore,
+    stream: &mut dyn MessageChannel,
+    btc_core: &dyn BitcoinBackend,
+    grin_core: &dyn GrinBackend,
     grin_tx: &mut GrinTx,
     secp: &GrinSecp256k1,
-) -> Result<(), String> {
+) -> Result<(), SwapError> {
+    if slate.pub_slate.status != SwapStatus::LOCKED {
+        return Err(SwapError::Validation(format!(
+            "Both sides need to be locked before executing, current status is {:?}",
+            slate.pub_slate.status
+        )));
+    }
+    let fee_base = slate.pub_slate.mw.get_fee_base();
+    grin_tx.set_fee_base(fee_base);
     set_local_chain_type(grin_core::global::ChainTypes::Testnet);
-    if check_if_enough_time(grin_core, btc_core, slate) {
-        slate.pub_slate.status = crate::enums::SwapStatus::EXECUTING;
-        let shared_coin = slate.prv_slate.mw.shared_coin.clone().unwrap();
-        let value = shared_coin.value;
-        let x = deserialize_priv_key(&slate.prv_slate.btc.x.clone().unwrap());
+    if check_if_enough_time(grin_core, btc_core, slate)? {
+        slate.set_status(SwapStatus::EXECUTING);
+        let shared_coin = slate_field(&slate.prv_slate.mw.shared_coin, "shared Grin coin")?;
+        let x = slate_priv_key(&slate.prv_slate.btc.x, "secret witness x")?;
         let x_grin = grin_sk_from_btc_sk(&x, secp);
-        let fee = estimate_fees(1, 1, 1);
-        let fund_value = value - fee;
-        let result = grin_tx.dcontract_mw_tx_bob(shared_coin, fund_value, 0, x_grin, stream)?;
+        let fee = estimate_fees(1, 1, 1, fee_base);
+        let fund_value = refund_value(&shared_coin, fee)?;
+        let result = grin_tx
+            .dcontract_mw_tx_bob(shared_coin, fund_value, 0, x_grin, stream)
+            .map_err(SwapError::PeerProtocol)?;
         slate.prv_slate.mw.swapped_coin = result.coin;
-        grin_core.push_transaction(result.tx.tx.unwrap())?;
-        slate.pub_slate.status = crate::enums::SwapStatus::FINISHED;
+        let contract_tx = result.tx.tx
+            .ok_or(SwapError::PeerProtocol(String::from("The contract transaction is missing")))?;
+        let contract_excess = get_kernel_excess(&contract_tx);
+        grin_core.push_transaction(contract_tx).map_err(SwapError::NodeRpc)?;
+        slate.log_event(
+            Some(Currency::GRIN),
+            contract_excess,
+            None,
+            "Published Grin contract transaction",
+        );
+        slate.set_chain_status(Currency::GRIN, ChainStatus::REDEEMED);
+        // The contract transaction reveals x, Alice is now able to redeem the Bitcoin.
+        // The Bitcoin stay locked until the status command sees the redeem in a block,
+        // until then Bob can still refund them once the lock time has passed
+        println!("Alice is now able to redeem the Bitcoin, run status to follow the redeem");
         Ok(())
     } else {
-        Err(String::from("Not enough time left to execute atomic swap"))
+        Err(SwapError::Timelock(String::from("Not enough time left to execute atomic swap")))
     }
 }
 
@@ -459,20 +603,33 @@ pub fn exec_phase_swap_btc(
 /// # Arguments
 ///
 /// * `slate` Atomic Swap Slate
-/// * `btc_core` Bitcoin core node functionality
-/// * `grin_core` Grin core node functionality
+/// * `btc_core` Bitcoin chain backend
+/// * `grin_core` Grin chain backend
 pub fn refund_phase_swap_mw(
     slate: &mut SwapSlate,
-    btc_core: &mut BitcoinCore,
-    grin_core: &mut GrinCore,
-) -> Result<(), String> {
-    if can_refund(grin_core, btc_core, slate) {
-        let refund_tx = slate.prv_slate.mw.refund_tx.clone().unwrap();
-        grin_core.push_transaction(refund_tx)?;
-        slate.pub_slate.status = crate::enums::SwapStatus::FAILED;
+    btc_core: &dyn BitcoinBackend,
+    grin_core: &dyn GrinBackend,
+) -> Result<(), SwapError> {
+    if slate.pub_slate.mw.status != ChainStatus::LOCKED {
+        return Err(SwapError::Validation(format!(
+            "Grin funds can't be refunded, they are {:?}",
+            slate.pub_slate.mw.status
+        )));
+    }
+    if can_refund(grin_core, btc_core, slate)? {
+        let refund_tx = slate_field(&slate.prv_slate.mw.refund_tx, "Grin refund transaction")?;
+        let refund_excess = get_kernel_excess(&refund_tx);
+        grin_core.push_transaction(refund_tx).map_err(SwapError::NodeRpc)?;
+        slate.log_event(
+            Some(Currency::GRIN),
+            refund_excess,
+            None,
+            "Published Grin refund transaction",
+        );
+        slate.set_chain_status(Currency::GRIN, ChainStatus::REFUND_BROADCAST);
         Ok(())
     } else {
-        Err(String::from("Can't refund yet, too early"))
+        Err(SwapError::Timelock(String::from("Can't refund yet, too early")))
     }
 }
 
@@ -482,34 +639,42 @@ pub fn refund_phase_swap_mw(
 /// # Arguments
 ///
 /// * `slate` Atomic Swap Slate
-/// * `btc_core` Bitcoin core node functionality
-/// * `grin_core` Grin core node functionality
+/// * `btc_core` Bitcoin chain backend
+/// * `grin_core` Grin chain backend
 /// * `rng` randomness tape
 pub fn refund_phase_swap_btc(
     slate: &mut SwapSlate,
-    btc_core: &mut BitcoinCore,
-    grin_core: &mut GrinCore,
+    btc_core: &dyn BitcoinBackend,
+    grin_core: &dyn GrinBackend,
     btc_secp: &Secp256k1<All>,
-    rng: &mut OsRng,
-) -> Result<(), String> {
-    if can_refund(grin_core, btc_core, slate) {
+    rng: &mut SwapRng,
+) -> Result<(), SwapError> {
+    if slate.pub_slate.btc.status != ChainStatus::LOCKED {
+        return Err(SwapError::Validation(format!(
+            "Bitcoin funds can't be refunded, they are {:?}",
+            slate.pub_slate.btc.status
+        )));
+    }
+    if can_refund(grin_core, btc_core, slate)? {
         let sk = create_private_key(rng);
         let pk = PublicKey::from_private_key(btc_secp, &sk);
 
-        let pub_a = deserialize_pub_key(&slate.pub_slate.btc.pub_a.clone().unwrap());
-        let sk_b = deserialize_priv_key(&slate.prv_slate.btc.sk.clone().unwrap());
-        let x = deserialize_priv_key(&slate.prv_slate.btc.x.clone().unwrap());
+        let pub_a = slate_pub_key(&slate.pub_slate.btc.pub_a, "public key A")?;
+        let sk_b = slate_priv_key(&slate.prv_slate.btc.sk, "Bitcoin secret key")?;
+        let x = slate_priv_key(&slate.prv_slate.btc.x, "secret witness x")?;
         let pub_b = PublicKey::from_private_key(btc_secp, &sk_b);
         let pub_x = PublicKey::from_private_key(btc_secp, &x);
-        let lock_time = slate.pub_slate.btc.lock_time.unwrap();
+        let lock_time = slate_field(&slate.pub_slate.btc.lock_time, "Bitcoin lock time")?;
 
         let refund_tx = create_spend_lock_transaction(
             &pk,
-            slate.prv_slate.btc.lock.clone().unwrap(),
+            slate_field(&slate.prv_slate.btc.lock, "Bitcoin lock output")?,
             slate.pub_slate.btc.amount,
-            BTC_FEE,
-            u32::try_from(lock_time).unwrap()
-        )?;
+            btc_core.estimate_fee().map_err(SwapError::NodeRpc)?,
+            u32::try_from(lock_time)
+                .map_err(|_| SwapError::Validation(format!("Invalid Bitcoin lock time {}", lock_time)))?
+        )
+        .map_err(SwapError::Validation)?;
         let lock_script = get_lock_pub_script(
             pub_a,
             pub_x,
@@ -519,7 +684,13 @@ pub fn refund_phase_swap_btc(
         );
 
         let signed_tx = sign_lock_transaction_refund(refund_tx, 0, lock_script, sk_b, btc_secp);
-        btc_core.send_raw_transaction(signed_tx.clone())?;
+        btc_core.send_raw_transaction(signed_tx.clone()).map_err(SwapError::NodeRpc)?;
+        slate.log_event(
+            Some(Currency::BTC),
+            Some(signed_tx.txid().to_string()),
+            None,
+            "Published Bitcoin refund transaction",
+        );
         let o = signed_tx.output.get(0).unwrap();
         slate.prv_slate.btc.refunded = Some(BTCInput {
             txid: signed_tx.clone().txid().to_string(),
@@ -529,52 +700,189 @@ pub fn refund_phase_swap_btc(
             pub_key: serialize_pub_key(&pk),
             pub_script: serialize_script(&o.script_pubkey),
         });
-        slate.pub_slate.status = crate::enums::SwapStatus::FAILED;
+        slate.set_chain_status(Currency::BTC, ChainStatus::REFUND_BROADCAST);
 
         Ok(())
     } else {
-        Err(String::from("Can't refund yet, too early"))
+        Err(SwapError::Timelock(String::from("Can't refund yet, too early")))
     }
 }
 
-/// Query current block heights to see if enough time is left to complete the swap
+/// Query the current state of both chains to see if enough time is left to complete the swap
 /// Will return true if yes, false otherwise. Enough time is given if there
-/// is at least 1 hour left in average block length
+/// is at least 1 hour left in average block length (or median time past for Bitcoin timestamp locks)
 ///
 /// # Arguments
 ///
-/// * `grin_core` Grin core node functions
-/// * `btc_core` Bitcoin core node functions
+/// * `grin_core` Grin chain backend
+/// * `btc_core` Bitcoin chain backend
 /// * `slate` Swap slate
 fn check_if_enough_time(
-    grin_core: &mut GrinCore,
-    btc_core: &mut BitcoinCore,
+    grin_core: &dyn GrinBackend,
+    btc_core: &dyn BitcoinBackend,
     slate: &SwapSlate,
-) -> bool {
-    let locktime_grin = slate.pub_slate.mw.lock_time.unwrap();
-    let locktime_btc = slate.pub_slate.btc.lock_time.unwrap();
+) -> Result<bool, SwapError> {
+    let block_height_grin = grin_core.get_block_height().map_err(SwapError::NodeRpc)?;
+    let locktime_grin = match grin_refund_height(grin_core, slate)? {
+        Some(h) => h,
+        // a NRD refund can be mined its relative height after the funding confirms at the earliest
+        None if slate.pub_slate.mw.lock_type == GrinLockType::NRD => block_height_grin + 1 + slate.pub_slate.mw.timelock,
+        None => u64::try_from(slate_field(&slate.pub_slate.mw.lock_time, "Grin lock height")?).unwrap_or(0),
+    };
+    let btc_minutes = btc_refund_minutes_left(btc_core, slate)?;
+
+    Ok(((block_height_grin + (60 / GRIN_BLOCK_TIME)) <= locktime_grin)
+        && btc_minutes > 60)
+}
 
-    let block_height_grin = grin_core.get_block_height().unwrap();
-    let block_height_btc = btc_core.get_current_block_height().unwrap();
+/// If block times have passed the respective lock times we can refund
+///
+/// # Arguments
+///
+/// * `grin_core` Grin chain backend
+/// * `btc_core` Bitcoin chain backend
+/// * `slate` Swap slate
+fn can_refund(grin_core: &dyn GrinBackend, btc_core: &dyn BitcoinBackend, slate: &SwapSlate) -> Result<bool, SwapError> {
+    // The peer may have stopped before the Grin lock height was exchanged or the funding
+    // confirmed, the Bitcoin refund only depends on the Bitcoin lock then
+    let locktime_grin = grin_refund_height(grin_core, slate)?.unwrap_or(0);
+    let locktime_btc = slate_field(&slate.pub_slate.btc.lock_time, "Bitcoin lock time")?;
+
+    let block_height_grin = grin_core.get_block_height().map_err(SwapError::NodeRpc)?;
+    let block_height_btc = btc_core.get_current_block_height().map_err(SwapError::NodeRpc)?;
+    let mtp_btc = match slate.pub_slate.btc.lock_type {
+        BtcLockType::HEIGHT => 0,
+        BtcLockType::TIME => btc_core.get_median_time_past().map_err(SwapError::NodeRpc)?,
+    };
+
+    Ok((block_height_grin > locktime_grin)
+        && btc_lock_passed(
+            slate.pub_slate.btc.lock_type,
+            u64::try_from(locktime_btc).unwrap_or(0),
+            block_height_btc,
+            mtp_btc,
+        ))
+}
 
-    return ((block_height_grin + (60 / GRIN_BLOCK_TIME)) <= u64::try_from(locktime_grin).unwrap())
-        && ((block_height_btc + (60 / BTC_BLOCK_TIME)) <= u64::try_from(locktime_btc).unwrap());
+/// Height from which the Grin refund transaction can be mined. A NRD locked refund is
+/// relative to the anchor kernel of the funding transaction, its height is None until
+/// the funding transaction confirmed
+///
+/// # Arguments
+///
+/// * `grin_core` Grin chain backend
+/// * `slate` Swap slate
+pub fn grin_refund_height(grin_core: &dyn GrinBackend, slate: &SwapSlate) -> Result<Option<u64>, SwapError> {
+    match slate.pub_slate.mw.lock_type {
+        GrinLockType::HEIGHT => Ok(slate.pub_slate.mw.lock_time.and_then(|l| u64::try_from(l).ok())),
+        GrinLockType::NRD => {
+            let shared_coin = match &slate.prv_slate.mw.shared_coin {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+            let output = grin_core.get_output(&shared_coin.commitment).map_err(SwapError::NodeRpc)?;
+            Ok(output
+                .and_then(|o| o.block_height)
+                .map(|h| h + slate.pub_slate.mw.timelock))
+        }
+    }
 }
 
-/// If block times have passed the respective lock times we can refund
+/// Relative height of the NRD anchor kernel in the funding transaction,
+/// None if the Grin refund is locked by height
+///
+/// # Arguments
+///
+/// * `slate` Swap slate
+fn grin_anchor(slate: &SwapSlate) -> Option<u64> {
+    match slate.pub_slate.mw.lock_type {
+        GrinLockType::HEIGHT => None,
+        GrinLockType::NRD => Some(slate.pub_slate.mw.timelock),
+    }
+}
+
+/// Kernel lock of the Grin refund transaction
+///
+/// # Arguments
+///
+/// * `slate` Swap slate
+/// * `lock_height` the absolute refund lock height
+fn grin_refund_lock(slate: &SwapSlate, lock_height: u64) -> KernelLock {
+    match grin_anchor(slate) {
+        Some(relative_height) => KernelLock::NRD(relative_height),
+        None => KernelLock::HEIGHT(lock_height),
+    }
+}
+
+/// Bitcoin refund lock height or timestamp for a swap locked now,
+/// the timelock of the offer added to the current tip of the chain
+///
+/// # Arguments
+///
+/// * `btc_core` Bitcoin chain backend
+/// * `slate` Swap slate
+fn expected_btc_lock_time(btc_core: &dyn BitcoinBackend, slate: &SwapSlate) -> Result<u64, SwapError> {
+    match slate.pub_slate.btc.lock_type {
+        BtcLockType::HEIGHT => Ok(btc_core.get_current_block_height().map_err(SwapError::NodeRpc)?
+            + slate.pub_slate.btc.timelock),
+        // CLTV compares timestamps against the median time past, so the lock is
+        // independent of how fast the next blocks are mined
+        BtcLockType::TIME => Ok(btc_core.get_median_time_past().map_err(SwapError::NodeRpc)?
+            + slate.pub_slate.btc.timelock * BTC_BLOCK_TIME * 60),
+    }
+}
+
+/// Minutes left until the Bitcoin refund lock of the swap has passed,
+/// queries the median time past only for timestamp locks
 ///
 /// # Arguments
 ///
-/// * `grin_core` Grin core node functions
-/// * `btc_core` Bitcoin core node functions
+/// * `btc_core` Bitcoin chain backend
 /// * `slate` Swap slate
-fn can_refund(grin_core: &mut GrinCore, btc_core: &mut BitcoinCore, slate: &SwapSlate) -> bool {
-    let locktime_grin = slate.pub_slate.mw.lock_time.unwrap();
-    let locktime_btc = slate.pub_slate.btc.lock_time.unwrap();
+fn btc_refund_minutes_left(btc_core: &dyn BitcoinBackend, slate: &SwapSlate) -> Result<u64, SwapError> {
+    let lock_time = u64::try_from(slate.pub_slate.btc.lock_time.unwrap_or(0)).unwrap_or(0);
+    let height = btc_core.get_current_block_height().map_err(SwapError::NodeRpc)?;
+    let mtp = match slate.pub_slate.btc.lock_type {
+        BtcLockType::HEIGHT => 0,
+        BtcLockType::TIME => btc_core.get_median_time_past().map_err(SwapError::NodeRpc)?,
+    };
+    Ok(btc_minutes_left(
+        slate.pub_slate.btc.lock_type,
+        lock_time,
+        height,
+        mtp,
+    ))
+}
+
+/// Value left of a coin after paying the fee of spending it
+///
+/// # Arguments
+///
+/// * `coin` the coin to spend
+/// * `fee` the fee of the spending transaction
+fn refund_value(coin: &MWCoin, fee: u64) -> Result<u64, SwapError> {
+    coin.value.checked_sub(fee).ok_or(SwapError::InsufficientFunds(format!(
+        "Coin value {} doesn't cover the fee {}",
+        coin.value, fee
+    )))
+}
 
-    let block_height_grin = grin_core.get_block_height().unwrap();
-    let block_height_btc = btc_core.get_current_block_height().unwrap();
+/// Read a field of the swap slate which an earlier phase should have set
+///
+/// # Arguments
+///
+/// * `field` the slate field
+/// * `name` name of the field for the error message
+fn slate_field<T: Clone>(field: &Option<T>, name: &str) -> Result<T, SwapError> {
+    field
+        .clone()
+        .ok_or(SwapError::Validation(format!("Swap slate is missing the {}", name)))
+}
+
+fn slate_pub_key(field: &Option<String>, name: &str) -> Result<PublicKey, SwapError> {
+    deserialize_pub_key(&slate_field(field, name)?).map_err(SwapError::Validation)
+}
 
-    return (block_height_grin > u64::try_from(locktime_grin).unwrap())
-        && (block_height_btc > u64::try_from(locktime_btc).unwrap());
+fn slate_priv_key(field: &Option<String>, name: &str) -> Result<PrivateKey, SwapError> {
+    deserialize_priv_key(&slate_field(field, name)?).map_err(SwapError::Validation)
 }
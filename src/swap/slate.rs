@@ -1,140 +1,203 @@
+use crate::constants::CURRENT_SLATE_VERSION;
+use crate::swap::slate_versions::v1::{SwapSlatePrivV1, SwapSlatePubV1};
+use crate::swap::store::db_store::DbStore;
+use crate::swap::store::file_store::FileStore;
+use crate::swap::store::store_types::SwapStore;
+use crate::enums::SwapRole;
 use crate::swap::swap_types::BTCPriv;
 use crate::swap::swap_types::MWPriv;
 use crate::swap::swap_types::SwapSlatePriv;
 use crate::swap::swap_types::SwapSlatePub;
-use std::fs::File;
+use crate::Settings;
 use std::fs;
 use std::path::Path;
 use crate::SwapSlate;
-use sha2::{Sha256, Digest};
+use crate::swap::offer::{verify_offer, OfferTerms};
+use bitcoin::secp256k1::{All, Secp256k1};
+use serde::Deserialize;
 
-/// Write Atomic Swap slate into files on disk
-/// 
-/// # Arguments 
-/// 
-/// * `slate` - The Atomic Swap slate struct containing both private and public slate state
-/// * `directory` - The directory in which the slate files are stored (can be configured in settings.json)
-/// * `wrt_priv` - If the function should write the private file
-/// * `wrt_pub` - If the function should write the public file
-pub fn write_slate_to_disk(slate : &SwapSlate, directory : &str, wrt_priv: bool, wrt_pub: bool) {
-    let pv_slate_path = get_slate_path(slate.id, &directory, false);
-    let pb_slate_path = get_slate_path(slate.id, &directory, true);
+/// Only reads the version of a serialized slate, slates without one are version 1
+#[derive(Deserialize)]
+struct SlateVersionProbe {
+    #[serde(default = "first_slate_version")]
+    version : u16
+}
 
-    let pv_exists = Path::new(&pv_slate_path).exists();
-    let pb_exists = Path::new(&pb_slate_path).exists();
+fn first_slate_version() -> u16 {
+    1
+}
 
-    if !pv_exists {
-        File::create(&pv_slate_path).expect("Unable to create private slate file");
+/// Read the version of a serialized slate and reject versions this build doesn't know
+///
+/// # Arguments
+///
+/// * `contents` the serialized slate
+fn get_slate_version(contents : &[u8]) -> Result<u16, String> {
+    let probe : SlateVersionProbe = serde_json::from_slice(contents)
+        .map_err(|e| format!("Unable to read slate version: {}", e))?;
+    if probe.version > CURRENT_SLATE_VERSION {
+        Err(format!("Slate version {} is not supported, this build supports up to version {}. Please upgrade", probe.version, CURRENT_SLATE_VERSION))
     }
-    if !pb_exists {
-        File::create(&pb_slate_path).expect("Unable to creat public slate file");
+    else if probe.version == 0 {
+        Err(String::from("Invalid slate version 0"))
     }
-
-    let ser_prv_slate = serde_json::to_string_pretty(&slate.prv_slate).expect("Failed to serialize private slate data");
-    let ser_pub_slate = serde_json::to_string_pretty(&slate.pub_slate).expect("Failed to serialize public slate data");
-
-    if wrt_priv {
-        println!("Writing private slate file to {}", pv_slate_path);
-        fs::write(pv_slate_path, ser_prv_slate).expect("Unable to write private slate file");
-    }
-    if wrt_pub {
-        println!("Writing public slate file to {}", pb_slate_path);
-        fs::write(pb_slate_path, ser_pub_slate).expect("Unable to write public slate file");
+    else {
+        Ok(probe.version)
     }
 }
 
-/// Read Atomic Swap Slate from files stored on disk
-/// 
+/// Parse a public slate, slates of older versions are upgraded to the current layout
+///
 /// # Arguments
-/// 
-/// * `id` the id of the Atomi Swap
-/// * `directory` the directory in which the slate files are stored. (Can be configured in settings.json)
-pub fn read_slate_from_disk(id : u64, directory : &str) -> Result<SwapSlate, &'static str> {
-    let pv_slate_path = get_slate_path(id, &directory, false);
-    let pb_slate_path = get_slate_path(id, &directory, true);
+///
+/// * `contents` the serialized public slate
+pub fn parse_pub_slate(contents : &[u8]) -> Result<SwapSlatePub, String> {
+    match get_slate_version(contents)? {
+        1 => {
+            let slate : SwapSlatePubV1 = serde_json::from_slice(contents)
+                .map_err(|e| format!("Unable to parse version 1 public slate: {}", e))?;
+            Ok(SwapSlatePub::from(slate))
+        },
+        _ => {
+            // Later layouts only add fields with defaults, so every other supported version reads as the current one
+            let mut slate : SwapSlatePub = serde_json::from_slice(contents)
+                .map_err(|e| format!("Unable to parse public slate: {}", e))?;
+            slate.version = CURRENT_SLATE_VERSION;
+            Ok(slate)
+        }
+    }
+}
 
-    if Path::new(&pv_slate_path).exists() == false || Path::new(&pb_slate_path).exists() == false {
-        Err("Unable to read slate files, as the files don't exist")
+/// Parse a private slate, slates of older versions are upgraded to the current layout
+///
+/// # Arguments
+///
+/// * `contents` the serialized private slate
+pub fn parse_priv_slate(contents : &[u8]) -> Result<SwapSlatePriv, String> {
+    match get_slate_version(contents)? {
+        1 => {
+            let slate : SwapSlatePrivV1 = serde_json::from_slice(contents)
+                .map_err(|e| format!("Unable to parse version 1 private slate: {}", e))?;
+            Ok(SwapSlatePriv::from(slate))
+        },
+        _ => {
+            // Later layouts only add fields with defaults, so every other supported version reads as the current one
+            let mut slate : SwapSlatePriv = serde_json::from_slice(contents)
+                .map_err(|e| format!("Unable to parse private slate: {}", e))?;
+            slate.version = CURRENT_SLATE_VERSION;
+            Ok(slate)
+        }
     }
-    else {
-        let pub_contents = fs::read_to_string(pb_slate_path)
-            .expect("Error during reading of pub file");
-        let prv_contents = fs::read_to_string(pv_slate_path)
-            .expect("Error during readong of prv file");
-        let pub_slate : SwapSlatePub = serde_json::from_str(&pub_contents).unwrap();
-        let prv_slate : SwapSlatePriv = serde_json::from_str(&prv_contents).unwrap();
-        Ok(SwapSlate {
-            id : id,
-            pub_slate : pub_slate,
-            prv_slate : prv_slate
-        })
+}
+
+/// Open the slate storage backend configured in settings.json
+/// `file` keeps json files in the slate directory, `db` uses an embedded database in it
+///
+/// # Arguments
+///
+/// * `settings` the application settings
+pub fn open_store(settings : &Settings) -> Result<Box<dyn SwapStore>, String> {
+    match settings.storage.as_str() {
+        "file" => Ok(Box::new(FileStore::new(&settings.slate_directory)?)),
+        "db" => Ok(Box::new(DbStore::new(&format!("{}/swaps.db", settings.slate_directory))?)),
+        other => Err(format!("Unknown storage backend {}, use file or db", other))
     }
 }
 
-pub fn get_slate_checksum(id : u64, directory : &str) -> Result<String, &'static str> {
+/// Read a public slate file shared by a peer from the slate directory
+///
+/// # Arguments
+///
+/// * `id` the id of the Atomic Swap
+/// * `directory` the directory in which the slate files are stored. (Can be configured in settings.json)
+pub fn read_pub_slate_file(id : u64, directory : &str) -> Result<SwapSlatePub, String> {
     let pb_slate_path = get_slate_path(id, &directory, true);
 
     if Path::new(&pb_slate_path).exists() == false {
-        Err("Unable to read slate files, as the files don't exist")
+        Err(String::from("Unable to read public slate file, as the file doesn't exist"))
     }
     else {
         let pub_contents = fs::read_to_string(pb_slate_path)
-            .expect("Error during reading of pub slate file");
-        let mut hasher = Sha256::new();
-        hasher.update(pub_contents);
-        let bytes = hasher.finalize();
-        Ok(format!("{:x}", bytes))
+            .map_err(|e| format!("Error during reading of pub file: {}", e))?;
+        parse_pub_slate(pub_contents.as_bytes())
     }
 }
 
-/// Create a fresh private slate file for a swap identified by the id
-/// 
+/// Calculate the checksum of the agreed offer terms which both peers compare
+/// before running a protocol phase
+///
 /// # Arguments
-/// 
+///
+/// * `store` the slate storage
 /// * `id` the id of the Atomic Swap
-/// * `directory` the directory in which the slate files are store. (Can be configured in settions.json)
-pub fn create_priv_from_pub(id : u64, directory : &str) -> Result<SwapSlate, &'static str> {
-    let pb_slate_path = get_slate_path(id, &directory, true);
+pub fn get_slate_checksum(store : &dyn SwapStore, id : u64) -> Result<String, String> {
+    let pub_slate = store.read_pub_slate(id)?;
+    Ok(OfferTerms::from_pub_slate(id, &pub_slate).hash_hex())
+}
 
-    if Path::new(&pb_slate_path).exists() == false {
-        Err("Unable to create private slate file, as the public file doesn't exist")
+/// Create a fresh private slate for a swap identified by the id
+/// The public slate offered by the peer is stored together with the new private slate
+///
+/// # Arguments
+///
+/// * `store` the slate storage
+/// * `id` the id of the Atomic Swap
+/// * `pub_slate` the public slate offered by the peer, read from the slate directory or the offer book
+/// * `secp` Secp256k1 functions, used to verify the offer signature
+pub fn create_priv_from_pub(store : &mut dyn SwapStore, id : u64, pub_slate : SwapSlatePub, secp : &Secp256k1<All>) -> Result<SwapSlate, String> {
+    if store.contains(id) {
+        return Err(String::from("Swap has already been accepted"));
     }
-    else {
-        let pub_contents = fs::read_to_string(pb_slate_path).expect("Error during reading of pub file");
-        let pub_slate : SwapSlatePub = serde_json::from_str(&pub_contents).unwrap();
-
-        let mwpriv = MWPriv{
-            inputs : Vec::new(),
-            partial_key : 0,
-            shared_coin : None,
-            refund_coin : None,
-            swapped_coin : None,
-            change_coin : None,
-            refund_tx : None
-        };        
-        let btcpriv = BTCPriv{
-            inputs : Vec::new(),
-            witness : 0,
-            sk : None,
-            x : None,
-            r_sk : None,
-            swapped : None,
-            change : None,
-            lock : None,
-            refunded : None
-        };
-        let prv_slate = SwapSlatePriv{
-            mw : mwpriv,
-            btc : btcpriv
-        };
-        let slate : SwapSlate = SwapSlate {
-            id : id,
-            pub_slate : pub_slate,
-            prv_slate : prv_slate
-        };
-        write_slate_to_disk(&slate, directory, true, false);
-
-        Ok(slate)
+    verify_offer(id, &pub_slate, secp)?;
+    if let Some(sig) = &pub_slate.offer_sig {
+        println!("Offer signed by identity key {}", sig.pub_key);
+    }
+
+    let mut slate : SwapSlate = SwapSlate {
+        id : id,
+        pub_slate : pub_slate,
+        prv_slate : new_priv_slate(SwapRole::TAKER),
+        events : Vec::new()
+    };
+    slate.log_event(None, None, None, "Accepted offer");
+    store.write_slate(&slate, true, true)?;
+
+    Ok(slate)
+}
+
+/// Create an empty private slate, the private parts are set during the protocol
+///
+/// # Arguments
+///
+/// * `role` our role in the swap
+pub fn new_priv_slate(role : SwapRole) -> SwapSlatePriv {
+    let mwpriv = MWPriv{
+        inputs : Vec::new(),
+        partial_key : 0,
+        shared_coin : None,
+        refund_coin : None,
+        swapped_coin : None,
+        change_coin : None,
+        refund_tx : None
+    };
+    let btcpriv = BTCPriv{
+        inputs : Vec::new(),
+        witness : 0,
+        sk : None,
+        x : None,
+        r_sk : None,
+        swapped : None,
+        change : None,
+        lock : None,
+        refunded : None
+    };
+    SwapSlatePriv{
+        version : CURRENT_SLATE_VERSION,
+        mw : mwpriv,
+        btc : btcpriv,
+        role : Some(role),
+        book : None,
+        fills : Vec::new()
     }
 }
 
@@ -147,4 +210,98 @@ fn get_slate_path(id : u64, directory : &str, public : bool) -> String {
         let dir = format!("{}/{}.prv.json", directory, id);
         dir
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::constants::CURRENT_SLATE_VERSION;
+    use crate::enums::{BtcLockType, ChainStatus, GrinLockType, SwapRole, SwapStatus};
+    use crate::swap::store::store_types::test::sample_slate;
+
+    use super::{parse_priv_slate, parse_pub_slate};
+
+    const PUB_V1 : &str = r#"{
+        "status": "SETUP",
+        "mw": { "amount": 100000000, "timelock": 10, "lock_time": 718559, "swap_type": "REQUESTED" },
+        "btc": { "amount": 1600, "timelock": 1, "swap_type": "OFFERED", "lock_time": 1937142, "pub_a": null, "pub_b": null, "pub_x": null },
+        "meta": { "server": "127.0.0.1", "port": "3000" }
+    }"#;
+
+    const PRV_V1 : &str = r#"{
+        "mw": { "inputs": [], "partial_key": 0, "shared_coin": null, "change_coin": null, "refund_coin": null, "swapped_coin": null, "refund_tx": null },
+        "btc": { "inputs": [], "witness": 0, "sk": null, "x": null, "r_sk": null, "change": null, "swapped": null, "lock": null, "refunded": null }
+    }"#;
+
+    const PUB_V2 : &str = r#"{
+        "version": 2,
+        "status": "EXECUTING",
+        "mw": { "amount": 100000000, "timelock": 10, "lock_time": 718559, "swap_type": "REQUESTED", "status": "LOCKED" },
+        "btc": { "amount": 1600, "timelock": 1, "swap_type": "OFFERED", "lock_time": 1937142, "pub_a": null, "pub_b": null, "pub_x": null, "status": "REDEEMED" },
+        "meta": { "server": "127.0.0.1", "port": "3000" }
+    }"#;
+
+    const PRV_V2 : &str = r#"{
+        "version": 2,
+        "mw": { "inputs": [], "partial_key": 0, "shared_coin": null, "change_coin": null, "refund_coin": null, "swapped_coin": null, "refund_tx": null },
+        "btc": { "inputs": [], "witness": 0, "sk": null, "x": null, "r_sk": null, "change": null, "swapped": null, "lock": null, "refunded": null },
+        "role": "TAKER"
+    }"#;
+
+    #[test]
+    fn test_upgrade_v1_pub_slate() {
+        let slate = parse_pub_slate(PUB_V1.as_bytes()).unwrap();
+        assert_eq!(CURRENT_SLATE_VERSION, slate.version);
+        assert_eq!(SwapStatus::LOCKED, slate.status);
+        assert_eq!(ChainStatus::LOCKED, slate.btc.status);
+        assert_eq!(ChainStatus::LOCKED, slate.mw.status);
+        assert_eq!(Some(718559), slate.mw.lock_time);
+    }
+
+    #[test]
+    fn test_upgrade_v1_priv_slate() {
+        let slate = parse_priv_slate(PRV_V1.as_bytes()).unwrap();
+        assert_eq!(CURRENT_SLATE_VERSION, slate.version);
+        assert_eq!(None, slate.role);
+    }
+
+    #[test]
+    fn test_upgrade_v2_pub_slate() {
+        let slate = parse_pub_slate(PUB_V2.as_bytes()).unwrap();
+        assert_eq!(CURRENT_SLATE_VERSION, slate.version);
+        assert_eq!(SwapStatus::EXECUTING, slate.status);
+        assert_eq!(ChainStatus::LOCKED, slate.mw.status);
+        assert_eq!(ChainStatus::REDEEMED, slate.btc.status);
+        assert_eq!(Some(1937142), slate.btc.lock_time);
+        assert_eq!(None, slate.mw.fee_base);
+        assert_eq!(GrinLockType::HEIGHT, slate.mw.lock_type);
+        assert_eq!(BtcLockType::HEIGHT, slate.btc.lock_type);
+        assert!(slate.validity.is_none() && slate.offer_sig.is_none() && slate.quote.is_none());
+        assert!(slate.fill.is_none() && slate.fill_of.is_none());
+    }
+
+    #[test]
+    fn test_upgrade_v2_priv_slate() {
+        let slate = parse_priv_slate(PRV_V2.as_bytes()).unwrap();
+        assert_eq!(CURRENT_SLATE_VERSION, slate.version);
+        assert_eq!(Some(SwapRole::TAKER), slate.role);
+        assert_eq!(None, slate.book);
+        assert!(slate.fills.is_empty());
+    }
+
+    #[test]
+    fn test_parse_current_slate() {
+        let slate = sample_slate(1, true);
+        let pub_ser = serde_json::to_vec(&slate.pub_slate).unwrap();
+        let prv_ser = serde_json::to_vec(&slate.prv_slate).unwrap();
+        assert_eq!(SwapStatus::INITIALIZED, parse_pub_slate(&pub_ser).unwrap().status);
+        assert_eq!(slate.prv_slate.role, parse_priv_slate(&prv_ser).unwrap().role);
+    }
+
+    #[test]
+    fn test_reject_future_version() {
+        let mut value : serde_json::Value = serde_json::to_value(&sample_slate(1, true).pub_slate).unwrap();
+        value["version"] = serde_json::Value::from(CURRENT_SLATE_VERSION + 1);
+        let err = parse_pub_slate(value.to_string().as_bytes()).err().unwrap();
+        assert!(err.contains("not supported"));
+    }
+}
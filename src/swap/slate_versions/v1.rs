@@ -0,0 +1,102 @@
+use crate::constants::CURRENT_SLATE_VERSION;
+use crate::enums::BtcLockType;
+use crate::enums::GrinLockType;
+use crate::enums::ChainStatus;
+use crate::enums::SwapStatus;
+use crate::enums::SwapType;
+use crate::swap::swap_types::{BTCPriv, BTCPub, MWPriv, MWPub, Meta, SwapSlatePriv, SwapSlatePub};
+use serde::{Serialize, Deserialize};
+
+/// Slate layout written before slates were versioned.
+/// There was no status per chain and no role, a swap was only tracked
+/// as INITIALIZED, SETUP, EXECUTING, FINISHED or FAILED.
+#[derive(Serialize, Deserialize)]
+pub struct SwapSlatePubV1 {
+    pub status : SwapStatus,
+    pub mw : MWPubV1,
+    pub btc : BTCPubV1,
+    pub meta : Meta
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SwapSlatePrivV1 {
+    pub mw : MWPriv,
+    pub btc : BTCPriv
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MWPubV1 {
+    pub amount : u64,
+    pub timelock : u64,
+    pub lock_time : Option<i64>,
+    pub swap_type : SwapType
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BTCPubV1 {
+    pub amount : u64,
+    pub timelock : u64,
+    pub swap_type : SwapType,
+    pub lock_time : Option<i64>,
+    pub pub_a : Option<String>,
+    pub pub_b : Option<String>,
+    pub pub_x : Option<String>
+}
+
+impl From<SwapSlatePubV1> for SwapSlatePub {
+    fn from(slate : SwapSlatePubV1) -> SwapSlatePub {
+        // Both lock heights are only known once the locking phase ran through
+        let locked = slate.btc.lock_time.is_some() && slate.mw.lock_time.is_some();
+        let (status, chain_status) = match slate.status {
+            SwapStatus::SETUP if locked => (SwapStatus::LOCKED, ChainStatus::LOCKED),
+            SwapStatus::EXECUTING => (SwapStatus::EXECUTING, ChainStatus::LOCKED),
+            SwapStatus::FINISHED => (SwapStatus::FINISHED, ChainStatus::REDEEMED),
+            // FAILED was used for aborted and for refunded swaps, which can't be told apart
+            other => (other, ChainStatus::NONE)
+        };
+        SwapSlatePub {
+            version : CURRENT_SLATE_VERSION,
+            status : status,
+            mw : MWPub {
+                amount : slate.mw.amount,
+                timelock : slate.mw.timelock,
+                lock_time : slate.mw.lock_time,
+                swap_type : slate.mw.swap_type,
+                status : chain_status,
+                fee_base : None,
+                lock_type : GrinLockType::HEIGHT
+            },
+            btc : BTCPub {
+                amount : slate.btc.amount,
+                timelock : slate.btc.timelock,
+                swap_type : slate.btc.swap_type,
+                lock_time : slate.btc.lock_time,
+                pub_a : slate.btc.pub_a,
+                pub_b : slate.btc.pub_b,
+                pub_x : slate.btc.pub_x,
+                status : chain_status,
+                lock_type : BtcLockType::HEIGHT
+            },
+            meta : slate.meta,
+            validity : None,
+            offer_sig : None,
+            quote : None,
+            fill : None,
+            fill_of : None
+        }
+    }
+}
+
+impl From<SwapSlatePrivV1> for SwapSlatePriv {
+    fn from(slate : SwapSlatePrivV1) -> SwapSlatePriv {
+        SwapSlatePriv {
+            version : CURRENT_SLATE_VERSION,
+            mw : slate.mw,
+            btc : slate.btc,
+            // the side we own is recovered from the private data, see SwapSlate::owns_btc
+            role : None,
+            book : None,
+            fills : Vec::new()
+        }
+    }
+}
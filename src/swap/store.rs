@@ -0,0 +1,3 @@
+pub mod store_types;
+pub mod file_store;
+pub mod db_store;
@@ -0,0 +1,231 @@
+use crate::swap::slate::{parse_priv_slate, parse_pub_slate};
+use crate::swap::store::store_types::{SwapIndexEntry, SwapQuery, SwapStore};
+use crate::swap::swap_types::{SwapEvent, SwapSlate, SwapSlatePriv, SwapSlatePub};
+use crate::util::now_secs;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, Transactional, Tree};
+
+/// Slate storage backed by an embedded sled database.
+/// Public slates, private slates, event logs and index entries are kept in separate
+/// trees keyed by the big endian swap id, a secondary tree orders swaps by creation date.
+/// All trees of a swap are updated in one transaction.
+pub struct DbStore {
+    db : Db,
+    pub_tree : Tree,
+    prv_tree : Tree,
+    events_tree : Tree,
+    index_tree : Tree,
+    created_tree : Tree
+}
+
+impl DbStore {
+    /// Open (or create) the database at the given path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` directory of the database
+    pub fn new(path : &str) -> Result<DbStore, String> {
+        let db = sled::open(path)
+            .map_err(|e| format!("Unable to open swap database at {}: {}", path, e))?;
+        DbStore::from_db(db)
+    }
+
+    /// Open a temporary database which is removed when dropped
+    #[cfg(test)]
+    pub fn temporary() -> Result<DbStore, String> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(|e| e.to_string())?;
+        DbStore::from_db(db)
+    }
+
+    fn from_db(db : Db) -> Result<DbStore, String> {
+        let open = |name : &str| db.open_tree(name).map_err(|e| format!("Unable to open tree {}: {}", name, e));
+        Ok(DbStore {
+            pub_tree : open("pub")?,
+            prv_tree : open("prv")?,
+            events_tree : open("events")?,
+            index_tree : open("index")?,
+            created_tree : open("by_created")?,
+            db : db
+        })
+    }
+
+    fn read_index_entry(&self, id : u64) -> Result<Option<SwapIndexEntry>, String> {
+        match self.index_tree.get(id.to_be_bytes()).map_err(|e| e.to_string())? {
+            Some(v) => serde_json::from_slice(&v)
+                .map(Some)
+                .map_err(|e| format!("Unable to parse index entry: {}", e)),
+            None => Ok(None)
+        }
+    }
+}
+
+fn created_key(created : u64, id : u64) -> Vec<u8> {
+    let mut key = created.to_be_bytes().to_vec();
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+impl SwapStore for DbStore {
+    fn write_slate(&mut self, slate : &SwapSlate, wrt_priv : bool, wrt_pub : bool) -> Result<(), String> {
+        let key = slate.id.to_be_bytes();
+        let now = now_secs();
+        let created = self.read_index_entry(slate.id)?
+            .map(|e| e.created)
+            .unwrap_or(now);
+        let entry = SwapIndexEntry::from_pub_slate(slate.id, &slate.pub_slate, created, now);
+
+        let ser_prv_slate = serde_json::to_vec(&slate.prv_slate)
+            .map_err(|e| format!("Failed to serialize private slate data: {}", e))?;
+        let ser_pub_slate = serde_json::to_vec(&slate.pub_slate)
+            .map_err(|e| format!("Failed to serialize public slate data: {}", e))?;
+        let ser_events = serde_json::to_vec(&slate.events)
+            .map_err(|e| format!("Failed to serialize swap events: {}", e))?;
+        let ser_entry = serde_json::to_vec(&entry)
+            .map_err(|e| format!("Failed to serialize index entry: {}", e))?;
+
+        let trees = (&self.pub_tree, &self.prv_tree, &self.events_tree, &self.index_tree, &self.created_tree);
+        let result : Result<(), TransactionError<()>> = trees.transaction(|(pb, pv, ev, ix, cr)| {
+            if wrt_priv {
+                pv.insert(&key[..], ser_prv_slate.clone())?;
+            }
+            if wrt_pub {
+                pb.insert(&key[..], ser_pub_slate.clone())?;
+            }
+            // A swap is only indexed once both halves are stored
+            let has_priv = wrt_priv || pv.get(&key[..])?.is_some();
+            let has_pub = wrt_pub || pb.get(&key[..])?.is_some();
+            if has_priv && has_pub {
+                ev.insert(&key[..], ser_events.clone())?;
+                ix.insert(&key[..], ser_entry.clone())?;
+                cr.insert(created_key(created, slate.id), &key[..])?;
+            }
+            Ok::<(), ConflictableTransactionError<()>>(())
+        });
+        result.map_err(|e| format!("Failed to write slate to database: {:?}", e))?;
+        self.db.flush()
+            .map_err(|e| format!("Failed to flush swap database: {}", e))?;
+        Ok(())
+    }
+
+    fn read_slate(&self, id : u64) -> Result<SwapSlate, String> {
+        let prv = self.prv_tree.get(id.to_be_bytes())
+            .map_err(|e| e.to_string())?
+            .ok_or(String::from("Unable to read slate, swap is not stored"))?;
+        let prv_slate : SwapSlatePriv = parse_priv_slate(&prv)?;
+        Ok(SwapSlate {
+            id : id,
+            pub_slate : self.read_pub_slate(id)?,
+            prv_slate : prv_slate,
+            events : self.read_events(id)?
+        })
+    }
+
+    fn read_pub_slate(&self, id : u64) -> Result<SwapSlatePub, String> {
+        let pb = self.pub_tree.get(id.to_be_bytes())
+            .map_err(|e| e.to_string())?
+            .ok_or(String::from("Unable to read slate, swap is not stored"))?;
+        parse_pub_slate(&pb)
+    }
+
+    fn contains(&self, id : u64) -> bool {
+        self.pub_tree.contains_key(id.to_be_bytes()).unwrap_or(false)
+            && self.prv_tree.contains_key(id.to_be_bytes()).unwrap_or(false)
+    }
+
+    fn read_events(&self, id : u64) -> Result<Vec<SwapEvent>, String> {
+        match self.events_tree.get(id.to_be_bytes()).map_err(|e| e.to_string())? {
+            Some(v) => serde_json::from_slice(&v)
+                .map_err(|e| format!("Unable to parse swap events: {}", e)),
+            None => Ok(Vec::new())
+        }
+    }
+
+    fn query(&self, query : &SwapQuery) -> Result<Vec<SwapIndexEntry>, String> {
+        let from = created_key(query.from.unwrap_or(0), 0);
+        let to = created_key(query.to.unwrap_or(u64::MAX), u64::MAX);
+        let mut result : Vec<SwapIndexEntry> = Vec::new();
+        for item in self.created_tree.range(from..=to) {
+            let (_, id_bytes) = item.map_err(|e| e.to_string())?;
+            let mut id = [0u8; 8];
+            id.copy_from_slice(&id_bytes);
+            if let Some(entry) = self.read_index_entry(u64::from_be_bytes(id))? {
+                if query.matches(&entry) {
+                    result.push(entry);
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::enums::{Currency, SwapStatus};
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::swap::store::store_types::{SwapQuery, SwapStore};
+    use crate::util::now_secs;
+
+    use super::DbStore;
+
+    #[test]
+    fn test_write_read_slate() {
+        let mut store = DbStore::temporary().unwrap();
+        let mut slate = sample_slate(42, false);
+        slate.set_status(SwapStatus::SETUP);
+        slate.log_event(Some(Currency::GRIN), None, Some(1000), "Grin lock height agreed");
+        store.write_slate(&slate, true, true).unwrap();
+
+        assert!(store.contains(42));
+        assert!(!store.contains(43));
+        let read = store.read_slate(42).unwrap();
+        assert_eq!(SwapStatus::SETUP, read.pub_slate.status);
+        assert_eq!(2, read.events.len());
+        assert_eq!(Some(Currency::GRIN), read.events[1].chain);
+    }
+
+    #[test]
+    fn test_write_only_priv() {
+        let mut store = DbStore::temporary().unwrap();
+        let mut slate = sample_slate(5, true);
+        slate.log_event(None, None, None, "Swap offered");
+        store.write_slate(&slate, true, false).unwrap();
+        assert!(!store.contains(5));
+        assert!(store.read_slate(5).is_err());
+        assert_eq!(0, store.read_events(5).unwrap().len());
+        assert_eq!(0, store.query(&SwapQuery::default()).unwrap().len());
+
+        store.write_slate(&slate, false, true).unwrap();
+        assert!(store.contains(5));
+        assert_eq!(1, store.read_events(5).unwrap().len());
+        assert_eq!(1, store.query(&SwapQuery::default()).unwrap().len());
+    }
+
+    #[test]
+    fn test_query() {
+        let mut store = DbStore::temporary().unwrap();
+        let mut s1 = sample_slate(1, true);
+        store.write_slate(&s1, true, true).unwrap();
+        store.write_slate(&sample_slate(2, false), true, true).unwrap();
+        s1.set_status(SwapStatus::FINISHED);
+        store.write_slate(&s1, true, true).unwrap();
+
+        assert_eq!(2, store.query(&SwapQuery::default()).unwrap().len());
+
+        let mut q = SwapQuery::default();
+        q.status = Some(SwapStatus::FINISHED);
+        let finished = store.query(&q).unwrap();
+        assert_eq!(1, finished.len());
+        assert_eq!(1, finished[0].id);
+
+        let mut q = SwapQuery::default();
+        q.currency = Some(Currency::GRIN);
+        assert_eq!(2, store.query(&q).unwrap()[0].id);
+
+        let mut q = SwapQuery::default();
+        q.from = Some(now_secs() + 3600);
+        assert_eq!(0, store.query(&q).unwrap().len());
+    }
+}
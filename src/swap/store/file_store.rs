@@ -0,0 +1,409 @@
+use crate::swap::slate::{parse_priv_slate, parse_pub_slate};
+use crate::swap::store::store_types::{SwapIndexEntry, SwapQuery, SwapStore};
+use crate::swap::swap_types::{SwapEvent, SwapSlate, SwapSlatePriv, SwapSlatePub};
+use crate::util::now_secs;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INDEX_FILE : &str = "index.json";
+const INDEX_LOCK_FILE : &str = "index.lock";
+// A lock file older than this was left behind by a crashed process
+const STALE_LOCK_SECS : u64 = 30;
+const LOCK_RETRY_MILLIS : u64 = 10;
+
+// Makes the temporary file names of one process unique
+static TMP_COUNTER : AtomicU64 = AtomicU64::new(0);
+
+/// Slate storage keeping every swap in json files inside the slate directory
+/// `{id}.pub.json`, `{id}.prv.json` and `{id}.events.json`, plus an `index.json`
+/// summarizing all swaps. Every file is replaced atomically. Several processes,
+/// like the API server and a listen command, can share the directory: updates
+/// of the index are serialized with an `index.lock` file.
+///
+/// The two halves are separate files, so a swap can be left with only one of them,
+/// e.g. after a crash between the two writes or a write of a single half. Such a swap
+/// counts as not stored: `contains` and `read_slate` require both files, and its
+/// events and index entry are only written once both halves exist.
+pub struct FileStore {
+    directory : String
+}
+
+impl FileStore {
+    /// Open a file store, creating the directory if it doesn't exist yet
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` the directory in which the slate files are stored (can be configured in settings.json)
+    pub fn new(directory : &str) -> Result<FileStore, String> {
+        fs::create_dir_all(directory)
+            .map_err(|e| format!("Unable to create slate directory {}: {}", directory, e))?;
+        Ok(FileStore {
+            directory : String::from(directory)
+        })
+    }
+
+    fn path(&self, file : &str) -> String {
+        format!("{}/{}", self.directory, file)
+    }
+
+    fn read_index(&self) -> Result<Vec<SwapIndexEntry>, String> {
+        let index_path = self.path(INDEX_FILE);
+        if Path::new(&index_path).exists() {
+            let contents = fs::read_to_string(&index_path)
+                .map_err(|e| format!("Unable to read swap index: {}", e))?;
+            serde_json::from_str(&contents)
+                .map_err(|e| format!("Unable to parse swap index: {}", e))
+        }
+        else {
+            self.rebuild_index()
+        }
+    }
+
+    /// Slates written before the index existed are picked up by scanning the directory
+    fn rebuild_index(&self) -> Result<Vec<SwapIndexEntry>, String> {
+        let mut index : Vec<SwapIndexEntry> = Vec::new();
+        let entries = fs::read_dir(&self.directory)
+            .map_err(|e| format!("Unable to read slate directory: {}", e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.ends_with(".pub.json") {
+                continue;
+            }
+            let id = match name.trim_end_matches(".pub.json").parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => continue
+            };
+            if !self.contains(id) {
+                continue;
+            }
+            let created = entry.metadata()
+                .and_then(|m| m.modified())
+                .map(|t| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0))
+                .unwrap_or(0);
+            let pub_slate = self.read_pub_slate(id)?;
+            index.push(SwapIndexEntry::from_pub_slate(id, &pub_slate, created, created));
+        }
+        Ok(index)
+    }
+
+    fn update_index(&self, id : u64, pub_slate : &SwapSlatePub) -> Result<(), String> {
+        // The index is read and written under the lock, so concurrent writers don't drop entries
+        let _lock = IndexLock::acquire(&self.path(INDEX_LOCK_FILE))?;
+        let mut index = self.read_index()?;
+        let now = now_secs();
+        let created = index.iter()
+            .find(|e| e.id == id)
+            .map(|e| e.created)
+            .unwrap_or(now);
+        index.retain(|e| e.id != id);
+        index.push(SwapIndexEntry::from_pub_slate(id, pub_slate, created, now));
+        let ser_index = serde_json::to_string_pretty(&index)
+            .map_err(|e| format!("Failed to serialize swap index: {}", e))?;
+        write_atomic(&self.path(INDEX_FILE), &ser_index)
+    }
+}
+
+impl SwapStore for FileStore {
+    fn write_slate(&mut self, slate : &SwapSlate, wrt_priv : bool, wrt_pub : bool) -> Result<(), String> {
+        let pv_slate_path = self.path(&format!("{}.prv.json", slate.id));
+        let pb_slate_path = self.path(&format!("{}.pub.json", slate.id));
+        let ev_path = self.path(&format!("{}.events.json", slate.id));
+
+        if wrt_priv {
+            let ser_prv_slate = serde_json::to_string_pretty(&slate.prv_slate)
+                .map_err(|e| format!("Failed to serialize private slate data: {}", e))?;
+            println!("Writing private slate file to {}", pv_slate_path);
+            write_atomic(&pv_slate_path, &ser_prv_slate)?;
+        }
+        if wrt_pub {
+            let ser_pub_slate = serde_json::to_string_pretty(&slate.pub_slate)
+                .map_err(|e| format!("Failed to serialize public slate data: {}", e))?;
+            println!("Writing public slate file to {}", pb_slate_path);
+            write_atomic(&pb_slate_path, &ser_pub_slate)?;
+        }
+        if !self.contains(slate.id) {
+            // Only half of the swap is stored, it is indexed when the other half is written
+            return Ok(());
+        }
+        let ser_events = serde_json::to_string_pretty(&slate.events)
+            .map_err(|e| format!("Failed to serialize swap events: {}", e))?;
+        write_atomic(&ev_path, &ser_events)?;
+        self.update_index(slate.id, &slate.pub_slate)
+    }
+
+    fn read_slate(&self, id : u64) -> Result<SwapSlate, String> {
+        if !self.contains(id) {
+            return Err(String::from("Unable to read slate files, as the files don't exist"));
+        }
+        let prv_contents = fs::read_to_string(self.path(&format!("{}.prv.json", id)))
+            .map_err(|e| format!("Error during reading of prv file: {}", e))?;
+        let prv_slate : SwapSlatePriv = parse_priv_slate(prv_contents.as_bytes())?;
+        Ok(SwapSlate {
+            id : id,
+            pub_slate : self.read_pub_slate(id)?,
+            prv_slate : prv_slate,
+            events : self.read_events(id)?
+        })
+    }
+
+    fn read_pub_slate(&self, id : u64) -> Result<SwapSlatePub, String> {
+        let pb_slate_path = self.path(&format!("{}.pub.json", id));
+        if !Path::new(&pb_slate_path).exists() {
+            return Err(String::from("Unable to read slate files, as the files don't exist"));
+        }
+        let pub_contents = fs::read_to_string(pb_slate_path)
+            .map_err(|e| format!("Error during reading of pub file: {}", e))?;
+        parse_pub_slate(pub_contents.as_bytes())
+    }
+
+    fn contains(&self, id : u64) -> bool {
+        Path::new(&self.path(&format!("{}.pub.json", id))).exists()
+            && Path::new(&self.path(&format!("{}.prv.json", id))).exists()
+    }
+
+    fn read_events(&self, id : u64) -> Result<Vec<SwapEvent>, String> {
+        let ev_path = self.path(&format!("{}.events.json", id));
+        if !Path::new(&ev_path).exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(ev_path)
+            .map_err(|e| format!("Error during reading of events file: {}", e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Unable to parse swap events: {}", e))
+    }
+
+    fn query(&self, query : &SwapQuery) -> Result<Vec<SwapIndexEntry>, String> {
+        let mut result : Vec<SwapIndexEntry> = self.read_index()?
+            .into_iter()
+            .filter(|e| query.matches(e))
+            .collect();
+        result.sort_by_key(|e| (e.created, e.id));
+        Ok(result)
+    }
+}
+
+/// Write a file atomically by writing a temporary file first, syncing it to disk
+/// and then renaming it over the target. A crash leaves either the old or the new file.
+///
+/// # Arguments
+///
+/// * `path` path of the target file
+/// * `contents` the new content
+pub fn write_atomic(path : &str, contents : &str) -> Result<(), String> {
+    // Every write uses its own temporary file, concurrent writers never rename each others files
+    let tmp_path = format!("{}.{}.{}.tmp", path, process::id(), TMP_COUNTER.fetch_add(1, Ordering::SeqCst));
+    {
+        let mut file = File::create(&tmp_path)
+            .map_err(|e| format!("Unable to create {}: {}", tmp_path, e))?;
+        file.write_all(contents.as_bytes())
+            .map_err(|e| format!("Unable to write {}: {}", tmp_path, e))?;
+        file.sync_all()
+            .map_err(|e| format!("Unable to sync {}: {}", tmp_path, e))?;
+    }
+    fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Unable to replace {}: {}", path, e))?;
+    // Persist the rename itself, not supported on every platform
+    if let Some(parent) = Path::new(path).parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Lock file held while the swap index is updated, removed again when dropped
+struct IndexLock {
+    path : String
+}
+
+impl IndexLock {
+    /// Wait until the lock file can be created. A lock file left behind by a
+    /// crashed process is removed once it is older than STALE_LOCK_SECS.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` path of the lock file
+    fn acquire(path : &str) -> Result<IndexLock, String> {
+        let started = SystemTime::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => return Ok(IndexLock {
+                    path : String::from(path)
+                }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|t| t.elapsed().ok())
+                        .map(|d| d.as_secs());
+                    if age.map_or(false, |a| a > STALE_LOCK_SECS) {
+                        println!("Removing stale swap index lock {}", path);
+                        let _ = fs::remove_file(path);
+                    }
+                    else if started.elapsed().map(|d| d.as_secs()).unwrap_or(0) > STALE_LOCK_SECS {
+                        return Err(format!("Timed out waiting for the swap index lock {}", path));
+                    }
+                    thread::sleep(Duration::from_millis(LOCK_RETRY_MILLIS));
+                },
+                Err(e) => return Err(format!("Unable to create lock file {}: {}", path, e))
+            }
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::enums::{Currency, SwapStatus};
+    use crate::swap::store::store_types::test::sample_slate;
+    use crate::swap::store::store_types::{SwapQuery, SwapStore};
+    use rand::Rng;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+
+    use super::{FileStore, write_atomic};
+
+    fn temp_dir() -> String {
+        let dir = env::temp_dir().join(format!("mw-btc-swap-test-{}", rand::thread_rng().gen::<u64>()));
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_write_read_slate() {
+        let dir = temp_dir();
+        let mut store = FileStore::new(&dir).unwrap();
+        let mut slate = sample_slate(42, true);
+        slate.set_status(SwapStatus::SETUP);
+        slate.log_event(Some(Currency::BTC), Some(String::from("abcd")), Some(100), "Published lock transaction");
+        store.write_slate(&slate, true, true).unwrap();
+
+        assert!(store.contains(42));
+        let read = store.read_slate(42).unwrap();
+        assert_eq!(SwapStatus::SETUP, read.pub_slate.status);
+        assert_eq!(2, read.events.len());
+        assert_eq!(Some(String::from("abcd")), read.events[1].txid);
+        assert_eq!(Some(100), read.events[1].height);
+        let tmp_files = fs::read_dir(&dir).unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(0, tmp_files);
+        assert!(!Path::new(&format!("{}/index.lock", dir)).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_missing_slate() {
+        let dir = temp_dir();
+        let store = FileStore::new(&dir).unwrap();
+        assert!(!store.contains(1));
+        assert!(store.read_slate(1).is_err());
+        assert_eq!(0, store.read_events(1).unwrap().len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_partial_slate_not_indexed() {
+        let dir = temp_dir();
+        let mut store = FileStore::new(&dir).unwrap();
+        let mut slate = sample_slate(5, true);
+        slate.log_event(None, None, None, "Swap offered");
+        store.write_slate(&slate, true, false).unwrap();
+        assert!(!store.contains(5));
+        assert!(store.read_slate(5).is_err());
+        assert!(!Path::new(&format!("{}/5.events.json", dir)).exists());
+        assert_eq!(0, store.query(&SwapQuery::default()).unwrap().len());
+
+        store.write_slate(&slate, false, true).unwrap();
+        assert!(store.contains(5));
+        assert_eq!(1, store.read_events(5).unwrap().len());
+        assert_eq!(1, store.query(&SwapQuery::default()).unwrap().len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_query_index() {
+        let dir = temp_dir();
+        let mut store = FileStore::new(&dir).unwrap();
+        let mut s1 = sample_slate(1, true);
+        let s2 = sample_slate(2, false);
+        store.write_slate(&s1, true, true).unwrap();
+        store.write_slate(&s2, true, true).unwrap();
+        s1.set_status(SwapStatus::FINISHED);
+        store.write_slate(&s1, true, true).unwrap();
+
+        let all = store.query(&SwapQuery::default()).unwrap();
+        assert_eq!(2, all.len());
+
+        let mut q = SwapQuery::default();
+        q.status = Some(SwapStatus::FINISHED);
+        let finished = store.query(&q).unwrap();
+        assert_eq!(1, finished.len());
+        assert_eq!(1, finished[0].id);
+
+        let mut q = SwapQuery::default();
+        q.currency = Some(Currency::GRIN);
+        let grin = store.query(&q).unwrap();
+        assert_eq!(1, grin.len());
+        assert_eq!(2, grin[0].id);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let dir = temp_dir();
+        let mut store = FileStore::new(&dir).unwrap();
+        store.write_slate(&sample_slate(7, true), true, true).unwrap();
+        fs::remove_file(format!("{}/index.json", dir)).unwrap();
+        let all = store.query(&SwapQuery::default()).unwrap();
+        assert_eq!(1, all.len());
+        assert_eq!(7, all[0].id);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        // two stores on the same directory, like the API server and a listen command
+        let dir = temp_dir();
+        FileStore::new(&dir).unwrap();
+        let writers : Vec<_> = (0..4u64).map(|w| {
+            let dir = dir.clone();
+            thread::spawn(move || {
+                let mut store = FileStore::new(&dir).unwrap();
+                for i in 0..10u64 {
+                    store.write_slate(&sample_slate(w * 100 + i, i % 2 == 0), true, true).unwrap();
+                }
+            })
+        }).collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        let store = FileStore::new(&dir).unwrap();
+        assert_eq!(40, store.query(&SwapQuery::default()).unwrap().len());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_replaces() {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = format!("{}/file.json", dir);
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+use crate::enums::Currency;
+use crate::enums::SwapStatus;
+use crate::enums::SwapType;
+use crate::swap::swap_types::SwapEvent;
+use crate::swap::swap_types::SwapSlate;
+use crate::swap::swap_types::SwapSlatePub;
+use serde::{Serialize, Deserialize};
+
+/// Interface trait to be implemented by all slate storage backends
+pub trait SwapStore {
+    /// Persist a slate and its event log, updating the index. The event log and
+    /// the index entry are only written once both the public and private part are stored
+    ///
+    /// # Arguments
+    ///
+    /// * `slate` the swap slate to store
+    /// * `wrt_priv` if the private part should be written
+    /// * `wrt_pub` if the public part should be written
+    fn write_slate(&mut self, slate : &SwapSlate, wrt_priv : bool, wrt_pub : bool) -> Result<(), String>;
+
+    /// Read a full slate including its event log
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    fn read_slate(&self, id : u64) -> Result<SwapSlate, String>;
+
+    /// Read only the public part of a slate
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    fn read_pub_slate(&self, id : u64) -> Result<SwapSlatePub, String>;
+
+    /// Returns true if both the public and private slate are stored
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    fn contains(&self, id : u64) -> bool;
+
+    /// Read the event log of a swap
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    fn read_events(&self, id : u64) -> Result<Vec<SwapEvent>, String>;
+
+    /// Query the swap index, results are ordered by creation date
+    ///
+    /// # Arguments
+    ///
+    /// * `query` the filter to apply
+    fn query(&self, query : &SwapQuery) -> Result<Vec<SwapIndexEntry>, String>;
+}
+
+/// Filter for querying stored swaps, unset fields match everything
+#[derive(Default)]
+pub struct SwapQuery {
+    pub status : Option<SwapStatus>,
+    pub currency : Option<Currency>,
+    pub from : Option<u64>,
+    pub to : Option<u64>
+}
+
+impl SwapQuery {
+    /// Returns true if the index entry matches the query
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` the index entry to check
+    pub fn matches(&self, entry : &SwapIndexEntry) -> bool {
+        self.status.map_or(true, |s| s == entry.status)
+            && self.currency.map_or(true, |c| c == entry.offered)
+            && self.from.map_or(true, |f| entry.created >= f)
+            && self.to.map_or(true, |t| entry.created <= t)
+    }
+}
+
+/// Summary of a swap which is kept in the index for fast listing
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SwapIndexEntry {
+    pub id : u64,
+    pub status : SwapStatus,
+    pub offered : Currency,
+    pub btc_amount : u64,
+    pub mw_amount : u64,
+    pub created : u64,
+    pub updated : u64
+}
+
+impl SwapIndexEntry {
+    /// Build the index entry of a slate
+    ///
+    /// # Arguments
+    ///
+    /// * `id` the id of the Atomic Swap
+    /// * `pub_slate` the public part of the swap slate
+    /// * `created` creation timestamp, kept from an existing entry
+    /// * `updated` timestamp of the last write
+    pub fn from_pub_slate(id : u64, pub_slate : &SwapSlatePub, created : u64, updated : u64) -> SwapIndexEntry {
+        SwapIndexEntry {
+            id : id,
+            status : pub_slate.status,
+            offered : if pub_slate.btc.swap_type == SwapType::OFFERED { Currency::BTC } else { Currency::GRIN },
+            btc_amount : pub_slate.btc.amount,
+            mw_amount : pub_slate.mw.amount,
+            created : created,
+            updated : updated
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::constants::CURRENT_SLATE_VERSION;
+    use crate::enums::SwapStatus;
+    use crate::enums::SwapType;
+    use crate::enums::SwapRole;
+    use crate::enums::ChainStatus;
+    use crate::enums::BtcLockType;
+    use crate::enums::GrinLockType;
+    use crate::swap::swap_types::{BTCPriv, BTCPub, MWPriv, MWPub, Meta, SwapSlate, SwapSlatePriv, SwapSlatePub};
+
+    use super::{SwapIndexEntry, SwapQuery};
+    use crate::enums::Currency;
+
+    /// Minimal slate used by the storage backend tests
+    pub fn sample_slate(id : u64, btc_offered : bool) -> SwapSlate {
+        SwapSlate {
+            id : id,
+            pub_slate : SwapSlatePub {
+                version : CURRENT_SLATE_VERSION,
+                status : SwapStatus::INITIALIZED,
+                mw : MWPub {
+                    amount : 1000000000,
+                    timelock : 600,
+                    lock_time : None,
+                    swap_type : if btc_offered { SwapType::REQUESTED } else { SwapType::OFFERED },
+                    status : ChainStatus::NONE,
+                    fee_base : None,
+                    lock_type : GrinLockType::HEIGHT
+                },
+                btc : BTCPub {
+                    amount : 100000,
+                    timelock : 60,
+                    swap_type : if btc_offered { SwapType::OFFERED } else { SwapType::REQUESTED },
+                    lock_time : None,
+                    pub_a : None,
+                    pub_b : None,
+                    pub_x : None,
+                    status : ChainStatus::NONE,
+                    lock_type : BtcLockType::HEIGHT
+                },
+                meta : Meta {
+                    server : String::from("127.0.0.1"),
+                    port : String::from("3000")
+                },
+                validity : None,
+                offer_sig : None,
+                quote : None,
+                fill : None,
+                fill_of : None
+            },
+            prv_slate : SwapSlatePriv {
+                version : CURRENT_SLATE_VERSION,
+                mw : MWPriv {
+                    inputs : Vec::new(),
+                    partial_key : 0,
+                    shared_coin : None,
+                    change_coin : None,
+                    refund_coin : None,
+                    swapped_coin : None,
+                    refund_tx : None
+                },
+                btc : BTCPriv {
+                    inputs : Vec::new(),
+                    witness : 0,
+                    sk : None,
+                    x : None,
+                    r_sk : None,
+                    change : None,
+                    swapped : None,
+                    lock : None,
+                    refunded : None
+                },
+                role : Some(SwapRole::MAKER),
+                book : None,
+                fills : Vec::new()
+            },
+            events : Vec::new()
+        }
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let slate = sample_slate(1, true);
+        let entry = SwapIndexEntry::from_pub_slate(slate.id, &slate.pub_slate, 100, 200);
+        assert_eq!(Currency::BTC, entry.offered);
+        assert!(SwapQuery::default().matches(&entry));
+        let mut q = SwapQuery::default();
+        q.status = Some(SwapStatus::INITIALIZED);
+        q.currency = Some(Currency::BTC);
+        q.from = Some(100);
+        q.to = Some(100);
+        assert!(q.matches(&entry));
+        q.currency = Some(Currency::GRIN);
+        assert!(!q.matches(&entry));
+        q.currency = None;
+        q.from = Some(101);
+        assert!(!q.matches(&entry));
+    }
+}
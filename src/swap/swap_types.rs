@@ -1,30 +1,223 @@
 use crate::enums::SwapType;
+use crate::enums::SwapRole;
 use bitcoin::{PrivateKey, PublicKey};
 use grin_core::core::Transaction;
+use grin_core::global::DEFAULT_ACCEPT_FEE_BASE;
 use serde::{Serialize, Deserialize};
 
 use crate::enums::SwapStatus;
+use crate::enums::ChainStatus;
+use crate::enums::BtcLockType;
+use crate::enums::GrinLockType;
+use crate::enums::Currency;
+use crate::util::now_secs;
 use crate::grin::grin_types::MWCoin;
 use crate::bitcoin::bitcoin_types::BTCInput;
+use crate::swap::offer::OfferSignature;
 
 pub struct SwapSlate {
     pub id : u64,
     pub pub_slate : SwapSlatePub,
-    pub prv_slate : SwapSlatePriv
+    pub prv_slate : SwapSlatePriv,
+    pub events : Vec<SwapEvent>
+}
+
+impl SwapSlate {
+    /// Change the status of the swap and record the transition in the event log
+    ///
+    /// # Arguments
+    ///
+    /// * `status` the new status of the swap
+    pub fn set_status(&mut self, status : SwapStatus) {
+        if self.pub_slate.status != status {
+            let msg = format!("Status changed from {:?} to {:?}", self.pub_slate.status, status);
+            self.pub_slate.status = status;
+            self.log_event(None, None, None, &msg);
+        }
+    }
+
+    /// Returns if we own (sell) the Bitcoin side of the swap, None if it can't be told yet
+    pub fn owns_btc(&self) -> Option<bool> {
+        let btc_offered = self.pub_slate.btc.swap_type == SwapType::OFFERED;
+        match self.prv_slate.role {
+            Some(SwapRole::MAKER) => Some(btc_offered),
+            Some(SwapRole::TAKER) => Some(!btc_offered),
+            None => {
+                // Slates written before roles were recorded
+                if self.prv_slate.btc.x.is_some() || !self.prv_slate.btc.inputs.is_empty() {
+                    Some(true)
+                }
+                else if self.prv_slate.mw.refund_tx.is_some() || !self.prv_slate.mw.inputs.is_empty() {
+                    Some(false)
+                }
+                else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Change the status of the funds on one chain, the overall swap status
+    /// is derived from the status of both chains
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` the chain whose status changed
+    /// * `status` the new status of the chain
+    pub fn set_chain_status(&mut self, chain : Currency, status : ChainStatus) {
+        let current = match chain {
+            Currency::BTC => self.pub_slate.btc.status,
+            Currency::GRIN => self.pub_slate.mw.status
+        };
+        if current != status {
+            match chain {
+                Currency::BTC => self.pub_slate.btc.status = status,
+                Currency::GRIN => self.pub_slate.mw.status = status
+            };
+            self.log_event(Some(chain), None, None, &format!("{:?} funds changed from {:?} to {:?}", chain, current, status));
+            if let Some(derived) = derive_status(self.pub_slate.btc.status, self.pub_slate.mw.status) {
+                self.set_status(derived);
+            }
+        }
+    }
+
+    /// Append an entry to the event log of the swap
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` the chain the event happened on, if any
+    /// * `txid` id of the transaction involved (for grin the kernel excess)
+    /// * `height` relevant block height (lock height or current height)
+    /// * `message` human readable description
+    pub fn log_event(&mut self, chain : Option<Currency>, txid : Option<String>, height : Option<u64>, message : &str) {
+        self.events.push(SwapEvent {
+            timestamp : now_secs(),
+            status : self.pub_slate.status,
+            chain : chain,
+            txid : txid,
+            height : height,
+            message : String::from(message)
+        });
+    }
+}
+
+/// Derive the overall swap status from the status of both chains
+/// Returns None if no funds were locked yet, in which case the status is kept
+///
+/// # Arguments
+///
+/// * `btc` status of the Bitcoin side
+/// * `mw` status of the Grin side
+pub fn derive_status(btc : ChainStatus, mw : ChainStatus) -> Option<SwapStatus> {
+    if btc == ChainStatus::REFUND_BROADCAST || mw == ChainStatus::REFUND_BROADCAST {
+        Some(SwapStatus::REFUND_BROADCAST)
+    }
+    else if btc == ChainStatus::REFUNDED || mw == ChainStatus::REFUNDED {
+        Some(SwapStatus::REFUNDED)
+    }
+    else {
+        match (btc, mw) {
+            (ChainStatus::NONE, ChainStatus::NONE) => None,
+            (ChainStatus::LOCKED, ChainStatus::NONE) => Some(SwapStatus::BTC_LOCKED),
+            (ChainStatus::NONE, ChainStatus::LOCKED) => Some(SwapStatus::GRIN_LOCKED),
+            (ChainStatus::LOCKED, ChainStatus::LOCKED) => Some(SwapStatus::LOCKED),
+            (ChainStatus::REDEEMED, ChainStatus::REDEEMED) => Some(SwapStatus::FINISHED),
+            (ChainStatus::REDEEMED, _) => Some(SwapStatus::BTC_REDEEMED),
+            (_, ChainStatus::REDEEMED) => Some(SwapStatus::GRIN_REDEEMED),
+            _ => None
+        }
+    }
+}
+
+/// Entry of the per swap event log
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SwapEvent {
+    pub timestamp : u64,
+    pub status : SwapStatus,
+    pub chain : Option<Currency>,
+    pub txid : Option<String>,
+    pub height : Option<u64>,
+    pub message : String
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SwapSlatePub {
+    pub version : u16,
     pub status : SwapStatus,
     pub mw : MWPub,
     pub btc : BTCPub,
-    pub meta : Meta
+    pub meta : Meta,
+    #[serde(default)]
+    pub validity : Option<OfferValidity>,
+    #[serde(default)]
+    pub offer_sig : Option<OfferSignature>,
+    #[serde(default)]
+    pub quote : Option<PriceQuote>,
+    #[serde(default)]
+    pub fill : Option<FillRange>,
+    #[serde(default)]
+    pub fill_of : Option<FillOf>
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SwapSlatePriv {
+    pub version : u16,
     pub mw : MWPriv,
-    pub btc: BTCPriv
+    pub btc: BTCPriv,
+    #[serde(default)]
+    pub role : Option<SwapRole>,
+    // url of the offer book the offer was published to
+    #[serde(default)]
+    pub book : Option<String>,
+    // child swaps spawned from a partially fillable offer
+    #[serde(default)]
+    pub fills : Vec<ChildFill>
+}
+
+/// Validity window of an offer, the offer can't be taken anymore once the
+/// timestamp or one of the block heights has been reached
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OfferValidity {
+    pub created : u64,
+    pub expires : u64,
+    pub btc_height : Option<u64>,
+    pub mw_height : Option<u64>,
+    pub nonce : String
+}
+
+/// Price an offer was quoted at, the requested amount is computed from
+/// the offered amount with the rounding rules of swap::pricing
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PriceQuote {
+    // price of one GRIN in BTC
+    pub price : String,
+    // premium of the maker on the price in basis points
+    pub spread_bps : i32
+}
+
+/// Range a partially fillable offer can be taken in, in satoshis or nanogrin
+/// of the offered currency. Each taker spawns a child swap of its own.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FillRange {
+    pub min : u64,
+    pub max : u64
+}
+
+/// Parent offer of a child swap, the amounts of the parent are needed to
+/// verify the offer signature and the amounts of the child
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FillOf {
+    pub parent_id : u64,
+    pub btc_amount : u64,
+    pub mw_amount : u64
+}
+
+/// Child swap spawned from a partially fillable offer, kept in the parent
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChildFill {
+    pub id : u64,
+    // filled amount of the offered currency
+    pub amount : u64
 }
 
 #[derive(Serialize, Deserialize)]
@@ -38,7 +231,21 @@ pub struct MWPub {
     pub amount : u64,
     pub timelock : u64,
     pub lock_time : Option<i64>,
-    pub swap_type : SwapType
+    pub swap_type : SwapType,
+    #[serde(default)]
+    pub status : ChainStatus,
+    #[serde(default)]
+    pub fee_base : Option<u64>,
+    #[serde(default)]
+    pub lock_type : GrinLockType
+}
+
+impl MWPub {
+    /// The Grin fee base both peers use to build the swap transactions,
+    /// the library default for slates which did not record one
+    pub fn get_fee_base(&self) -> u64 {
+        self.fee_base.unwrap_or(DEFAULT_ACCEPT_FEE_BASE)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -60,7 +267,11 @@ pub struct BTCPub {
     pub lock_time : Option<i64>,
     pub pub_a : Option<String>,
     pub pub_b : Option<String>,
-    pub pub_x : Option<String>
+    pub pub_x : Option<String>,
+    #[serde(default)]
+    pub status : ChainStatus,
+    #[serde(default)]
+    pub lock_type : BtcLockType
 }
 
 #[derive(Serialize, Deserialize)]
@@ -74,4 +285,43 @@ pub struct BTCPriv {
     pub swapped : Option<BTCInput>,
     pub lock : Option<BTCInput>,
     pub refunded : Option<BTCInput>
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::enums::{ChainStatus, Currency, SwapStatus};
+    use crate::swap::store::store_types::test::sample_slate;
+
+    use super::derive_status;
+
+    #[test]
+    fn test_derive_status() {
+        assert_eq!(None, derive_status(ChainStatus::NONE, ChainStatus::NONE));
+        assert_eq!(Some(SwapStatus::BTC_LOCKED), derive_status(ChainStatus::LOCKED, ChainStatus::NONE));
+        assert_eq!(Some(SwapStatus::GRIN_LOCKED), derive_status(ChainStatus::NONE, ChainStatus::LOCKED));
+        assert_eq!(Some(SwapStatus::LOCKED), derive_status(ChainStatus::LOCKED, ChainStatus::LOCKED));
+        assert_eq!(Some(SwapStatus::GRIN_REDEEMED), derive_status(ChainStatus::LOCKED, ChainStatus::REDEEMED));
+        assert_eq!(Some(SwapStatus::BTC_REDEEMED), derive_status(ChainStatus::REDEEMED, ChainStatus::LOCKED));
+        assert_eq!(Some(SwapStatus::FINISHED), derive_status(ChainStatus::REDEEMED, ChainStatus::REDEEMED));
+        assert_eq!(Some(SwapStatus::REFUND_BROADCAST), derive_status(ChainStatus::LOCKED, ChainStatus::REFUND_BROADCAST));
+        assert_eq!(Some(SwapStatus::REFUNDED), derive_status(ChainStatus::REFUNDED, ChainStatus::LOCKED));
+    }
+
+    #[test]
+    fn test_set_chain_status() {
+        let mut slate = sample_slate(1, true);
+        slate.set_status(SwapStatus::SETUP);
+        slate.set_chain_status(Currency::BTC, ChainStatus::LOCKED);
+        assert_eq!(SwapStatus::BTC_LOCKED, slate.pub_slate.status);
+        slate.set_chain_status(Currency::GRIN, ChainStatus::LOCKED);
+        assert_eq!(SwapStatus::LOCKED, slate.pub_slate.status);
+        slate.set_status(SwapStatus::EXECUTING);
+        // setting an unchanged chain status keeps the overall status
+        slate.set_chain_status(Currency::GRIN, ChainStatus::LOCKED);
+        assert_eq!(SwapStatus::EXECUTING, slate.pub_slate.status);
+        slate.set_chain_status(Currency::GRIN, ChainStatus::REFUND_BROADCAST);
+        assert_eq!(SwapStatus::REFUND_BROADCAST, slate.pub_slate.status);
+        slate.set_chain_status(Currency::GRIN, ChainStatus::REFUNDED);
+        assert_eq!(SwapStatus::REFUNDED, slate.pub_slate.status);
+    }
+}
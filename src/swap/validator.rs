@@ -0,0 +1,293 @@
+use crate::constants::{BTC_BLOCK_TIME, GRIN_BLOCK_TIME, LOCKTIME_THRESHOLD, MAX_ATTEMPTS_VERF_FUNDS};
+use crate::enums::BtcLockType;
+use crate::error::SwapError;
+use crate::grin::grin_backend::GrinBackend;
+use crate::settings::{FeeSettings, SafetySettings};
+use crate::swap::swap_types::SwapSlatePub;
+use std::convert::TryFrom;
+
+// In this protocol the secret x is revealed to the Grin owner by the Grin contract
+// transaction, whoever offered which currency. The Grin owner then redeems the Bitcoin
+// with x, so the Bitcoin refund must only become possible well after the Grin refund.
+// Otherwise the Bitcoin owner could refund the Bitcoin and still claim the Grin.
+
+// The Bitcoin lock height is set before the Grin one, the locking phase can take
+// as long as the Grin owner waits for the Bitcoin lock (one attempt per minute)
+const LOCK_PHASE_MINUTES : u64 = MAX_ATTEMPTS_VERF_FUNDS;
+
+// The Bitcoin lock time received from the peer may differ this much from ours,
+// the nodes of both parties can see a slightly different chain tip
+const LOCK_TIME_TOLERANCE_MINUTES : u64 = 60;
+
+/// Minimum number of Bitcoin blocks for the Bitcoin timelock given the Grin timelock,
+/// the duration of the locking phase and the safety margin (rounded up)
+///
+/// # Arguments
+///
+/// * `mw_timelock` the Grin timelock in blocks
+/// * `safety` configured safety margins
+pub fn required_btc_timelock(mw_timelock : u64, safety : &SafetySettings) -> u64 {
+    let minutes = mw_timelock * GRIN_BLOCK_TIME + LOCK_PHASE_MINUTES + safety.margin_minutes;
+    (minutes + BTC_BLOCK_TIME - 1) / BTC_BLOCK_TIME
+}
+
+/// Validate the relative timelocks (in blocks) of an offer
+///
+/// # Arguments
+///
+/// * `btc_timelock` the Bitcoin timelock in blocks
+/// * `mw_timelock` the Grin timelock in blocks
+/// * `safety` configured safety margins
+pub fn validate_timelocks(btc_timelock : u64, mw_timelock : u64, safety : &SafetySettings) -> Result<(), String> {
+    check_order(btc_timelock * BTC_BLOCK_TIME, mw_timelock * GRIN_BLOCK_TIME, LOCK_PHASE_MINUTES + safety.margin_minutes, safety)
+}
+
+/// Validate the timelocks of the offer in a public slate
+///
+/// # Arguments
+///
+/// * `pub_slate` the public slate of the swap
+/// * `safety` configured safety margins
+pub fn validate_offer_timelocks(pub_slate : &SwapSlatePub, safety : &SafetySettings) -> Result<(), String> {
+    validate_timelocks(pub_slate.btc.timelock, pub_slate.mw.timelock, safety)
+}
+
+/// Returns if the Bitcoin refund lock has passed, so that the refund transaction can be mined
+///
+/// # Arguments
+///
+/// * `lock_type` if the lock is a block height or a timestamp
+/// * `lock_time` the Bitcoin refund lock height or timestamp
+/// * `height` the current Bitcoin block height
+/// * `mtp` the median time past of the Bitcoin chain tip
+pub fn btc_lock_passed(lock_type : BtcLockType, lock_time : u64, height : u64, mtp : u64) -> bool {
+    match lock_type {
+        BtcLockType::HEIGHT => height > lock_time,
+        BtcLockType::TIME => mtp > lock_time
+    }
+}
+
+/// Estimated minutes until the Bitcoin refund lock has passed, 0 if it passed already.
+/// Timestamp locks are compared against the median time past and need no block time estimate.
+///
+/// # Arguments
+///
+/// * `lock_type` if the lock is a block height or a timestamp
+/// * `lock_time` the Bitcoin refund lock height or timestamp
+/// * `height` the current Bitcoin block height
+/// * `mtp` the median time past of the Bitcoin chain tip
+pub fn btc_minutes_left(lock_type : BtcLockType, lock_time : u64, height : u64, mtp : u64) -> u64 {
+    if btc_lock_passed(lock_type, lock_time, height, mtp) {
+        0
+    }
+    else {
+        match lock_type {
+            BtcLockType::HEIGHT => (lock_time - height + 1) * BTC_BLOCK_TIME,
+            BtcLockType::TIME => (lock_time - mtp) / 60 + 1
+        }
+    }
+}
+
+/// Validate the Bitcoin lock time received from the peer. It has to be of the lock type
+/// of the offer and close to the lock time we expect from the timelock of the offer.
+///
+/// # Arguments
+///
+/// * `lock_type` if the lock is a block height or a timestamp
+/// * `lock_time` the Bitcoin refund lock height or timestamp received
+/// * `expected` the lock height or timestamp calculated from our node
+pub fn validate_btc_lock_time(lock_type : BtcLockType, lock_time : i64, expected : u64) -> Result<(), String> {
+    let lock_time = u64::try_from(lock_time).map_err(|_| format!("Invalid Bitcoin lock time {}", lock_time))?;
+    let is_height = lock_time < u64::from(LOCKTIME_THRESHOLD);
+    let tolerance = match lock_type {
+        BtcLockType::HEIGHT => LOCK_TIME_TOLERANCE_MINUTES / BTC_BLOCK_TIME,
+        BtcLockType::TIME => LOCK_TIME_TOLERANCE_MINUTES * 60
+    };
+    if lock_time == 0 || is_height != (lock_type == BtcLockType::HEIGHT) {
+        Err(format!("Bitcoin lock time {} doesn't match the {:?} lock type of the offer", lock_time, lock_type))
+    }
+    else if lock_time + tolerance < expected {
+        Err(format!("Unsafe timelocks: the Bitcoin lock time {} is before the expected lock time {}", lock_time, expected))
+    }
+    else if lock_time > expected + tolerance {
+        Err(format!("Bitcoin lock time {} is after the expected lock time {}", lock_time, expected))
+    }
+    else {
+        Ok(())
+    }
+}
+
+/// Validate the lock times exchanged in the locking phase against the current state of the chains
+///
+/// # Arguments
+///
+/// * `btc_minutes_left` minutes until the Bitcoin refund is possible, see `btc_minutes_left`
+/// * `mw_lock_height` the Grin refund lock height
+/// * `mw_height` the current Grin block height
+/// * `safety` configured safety margins
+pub fn validate_lock_times(btc_minutes_left : u64, mw_lock_height : u64, mw_height : u64, safety : &SafetySettings) -> Result<(), String> {
+    if btc_minutes_left == 0 || mw_lock_height <= mw_height {
+        return Err(String::from("Lock times have already been reached"));
+    }
+    let mw_minutes = (mw_lock_height - mw_height) * GRIN_BLOCK_TIME;
+    check_order(btc_minutes_left, mw_minutes, safety.margin_minutes, safety)
+}
+
+/// Our fee policy with the fee base of our Grin node, the configured fee base
+/// is used if the node can't be queried or doesn't report one
+///
+/// # Arguments
+///
+/// * `fees` configured fee policy
+/// * `grin` our Grin node
+pub fn node_fee_policy(fees : &FeeSettings, grin : &dyn GrinBackend) -> FeeSettings {
+    match grin.get_fee_base() {
+        Ok(Some(fee_base)) => {
+            println!("Using the fee base of {} reported by the Grin node", fee_base);
+            FeeSettings {
+                fee_base : fee_base,
+                ..*fees
+            }
+        },
+        Ok(None) => *fees,
+        Err(e) => {
+            println!("Unable to query the fee base of the Grin node, using the configured fee base: {}", e);
+            *fees
+        }
+    }
+}
+
+/// Validate the Grin fee base recorded in an offer against our fee policy.
+/// It has to be accepted by our node but must not be inflated beyond our maximum multiplier.
+///
+/// # Arguments
+///
+/// * `pub_slate` the public slate of the swap
+/// * `fees` our fee policy
+pub fn validate_fee_base(pub_slate : &SwapSlatePub, fees : &FeeSettings) -> Result<(), SwapError> {
+    let fee_base = pub_slate.mw.get_fee_base();
+    if fee_base < fees.fee_base {
+        Err(SwapError::Validation(format!("Grin fee base of {} is below our fee base of {}", fee_base, fees.fee_base)))
+    }
+    else if fee_base > fees.max_fee_base()? {
+        Err(SwapError::Validation(format!("Grin fee base of {} is higher than {} times our fee base of {}", fee_base, fees.max_fee_multiplier, fees.fee_base)))
+    }
+    else {
+        Ok(())
+    }
+}
+
+fn check_order(btc_minutes : u64, mw_minutes : u64, margin : u64, safety : &SafetySettings) -> Result<(), String> {
+    if mw_minutes < safety.min_refund_minutes {
+        Err(format!("Grin timelock of {} minutes is too short, at least {} minutes are required", mw_minutes, safety.min_refund_minutes))
+    }
+    else if btc_minutes < mw_minutes + margin {
+        Err(format!("Unsafe timelocks: the Bitcoin refund ({} minutes) must be at least {} minutes after the Grin refund ({} minutes)", btc_minutes, margin, mw_minutes))
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::enums::BtcLockType;
+    use crate::settings::{FeeSettings, SafetySettings};
+    use crate::swap::store::store_types::test::sample_slate;
+
+    use super::{btc_minutes_left, required_btc_timelock, validate_btc_lock_time, validate_fee_base, validate_lock_times, validate_timelocks};
+    use crate::error::SwapError;
+
+    fn safety() -> SafetySettings {
+        SafetySettings {
+            margin_minutes : 120,
+            min_refund_minutes : 60
+        }
+    }
+
+    #[test]
+    fn test_required_btc_timelock() {
+        // 600 grin blocks (600 minutes) + 120 minutes locking phase + 120 minutes margin = 84 btc blocks
+        assert_eq!(84, required_btc_timelock(600, &safety()));
+        // rounded up
+        assert_eq!(85, required_btc_timelock(601, &safety()));
+        assert!(validate_timelocks(required_btc_timelock(601, &safety()), 601, &safety()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_timelocks() {
+        assert!(validate_timelocks(84, 600, &safety()).is_ok());
+        // no time left for the locking phase
+        assert!(validate_timelocks(72, 600, &safety()).is_err());
+        // same wall clock time on both chains
+        assert!(validate_timelocks(60, 600, &safety()).is_err());
+        // bitcoin refund before grin refund
+        assert!(validate_timelocks(10, 600, &safety()).is_err());
+        // grin window too short
+        assert!(validate_timelocks(84, 30, &safety()).is_err());
+    }
+
+    #[test]
+    fn test_btc_minutes_left() {
+        assert_eq!(730, btc_minutes_left(BtcLockType::HEIGHT, 1072, 1000, 0));
+        assert_eq!(10, btc_minutes_left(BtcLockType::HEIGHT, 1000, 1000, 0));
+        assert_eq!(0, btc_minutes_left(BtcLockType::HEIGHT, 1000, 1001, 0));
+        // timestamp locks only depend on the median time past
+        assert_eq!(721, btc_minutes_left(BtcLockType::TIME, 1606814112 + 720 * 60, 0, 1606814112));
+        assert_eq!(0, btc_minutes_left(BtcLockType::TIME, 1606814112, 1906786, 1606814113));
+    }
+
+    #[test]
+    fn test_validate_lock_times() {
+        assert!(validate_lock_times(730, 5600, 5000, &safety()).is_ok());
+        assert!(validate_lock_times(610, 5600, 5000, &safety()).is_err());
+        assert!(validate_lock_times(0, 5600, 5000, &safety()).is_err());
+        assert!(validate_lock_times(730, 5000, 5001, &safety()).is_err());
+    }
+
+    #[test]
+    fn test_validate_btc_lock_time() {
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, 1084, 1084).is_ok());
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, 1090, 1084).is_ok());
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, 1078, 1084).is_ok());
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, 1077, 1084).is_err());
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, 1091, 1084).is_err());
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, -1, 1084).is_err());
+        assert!(validate_btc_lock_time(BtcLockType::TIME, 1606814112, 1606814112 - 3600).is_ok());
+        assert!(validate_btc_lock_time(BtcLockType::TIME, 1606814112, 1606814112 - 3601).is_err());
+        // a timestamp for a height lock and the other way around
+        assert!(validate_btc_lock_time(BtcLockType::HEIGHT, 1606814112, 1606814112).is_err());
+        assert!(validate_btc_lock_time(BtcLockType::TIME, 1084, 1084).is_err());
+        assert!(validate_btc_lock_time(BtcLockType::TIME, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_validate_fee_base() {
+        let fees = FeeSettings {
+            fee_base : 500000,
+            fee_multiplier : 1,
+            max_fee_multiplier : 4
+        };
+        let mut slate = sample_slate(1, true);
+        // slates without a fee base use the library default
+        assert!(validate_fee_base(&slate.pub_slate, &fees).is_ok());
+        slate.pub_slate.mw.fee_base = Some(2000000);
+        assert!(validate_fee_base(&slate.pub_slate, &fees).is_ok());
+        slate.pub_slate.mw.fee_base = Some(2000001);
+        assert!(validate_fee_base(&slate.pub_slate, &fees).is_err());
+        slate.pub_slate.mw.fee_base = Some(100000);
+        assert!(validate_fee_base(&slate.pub_slate, &fees).is_err());
+    }
+
+    #[test]
+    fn test_fee_multiplier_overflow() {
+        let fees = FeeSettings {
+            fee_base : 500000,
+            fee_multiplier : u64::MAX,
+            max_fee_multiplier : u64::MAX
+        };
+        let slate = sample_slate(1, true);
+        assert!(matches!(fees.offer_fee_base(), Err(SwapError::Config(_))));
+        assert!(matches!(validate_fee_base(&slate.pub_slate, &fees), Err(SwapError::Config(_))));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::constants::{BTC_MAX_SATS, BTC_SATS, GRIN_MAX_NANOGRIN, NANO_GRIN};
+use crate::enums::Currency;
+
+// Decimal places of a BTC in satoshis and of a GRIN in nanogrin
+const BTC_DECIMALS : u32 = 8;
+const GRIN_DECIMALS : u32 = 9;
+
+/// Parse an amount of a currency into satoshis or nanogrin.
+/// The amount can have a unit suffix, `0.0016btc`, `1600sat`, `1.5grin` or `1500000000ng`,
+/// amounts without a unit are satoshis or nanogrin. Amounts more precise than
+/// one satoshi or nanogrin are refused.
+///
+/// # Arguments
+///
+/// * `s` the amount
+/// * `currency` the currency the amount has to be in
+pub fn parse_amount(s : &str, currency : Currency) -> Result<u64, String> {
+    let lower = s.trim().to_lowercase();
+    let split = lower.find(|c : char| c.is_ascii_alphabetic()).unwrap_or(lower.len());
+    let (number, unit) = (lower[..split].trim(), &lower[split..]);
+    let (unit_currency, decimals) = match unit {
+        "" => (currency, 0),
+        "btc" => (Currency::BTC, BTC_DECIMALS),
+        "sat" | "sats" => (Currency::BTC, 0),
+        "grin" => (Currency::GRIN, GRIN_DECIMALS),
+        "ng" | "nanogrin" => (Currency::GRIN, 0),
+        other => return Err(format!("Unknown unit {} in amount {}, use btc, sat, grin or ng", other, s))
+    };
+    if unit_currency != currency {
+        return Err(format!("Amount {} is not a {:?} amount", s, currency));
+    }
+    let value = parse_decimal(number, decimals)
+        .map_err(|e| match e {
+            DecimalError::Precision => format!("Amount {} is more precise than one {}", s, base_unit_name(currency)),
+            DecimalError::Invalid => format!("Invalid amount {}", s),
+            DecimalError::Overflow => format!("Amount {} is too high", s)
+        })?;
+    let max = match currency {
+        Currency::BTC => BTC_MAX_SATS,
+        Currency::GRIN => GRIN_MAX_NANOGRIN
+    };
+    if value > max as u128 {
+        return Err(format!("Amount {} is higher than the maximum of {}", s, format_amount(max, currency)));
+    }
+    Ok(value as u64)
+}
+
+/// Format an amount in satoshis or nanogrin with the raw and the human readable value,
+/// for example `1600 sats (0.000016 BTC)`
+///
+/// # Arguments
+///
+/// * `amount` the amount in satoshis or nanogrin
+/// * `currency` the currency of the amount
+pub fn format_amount(amount : u64, currency : Currency) -> String {
+    format!("{} {} ({} {:?})", amount, base_unit_name(currency), format_units(amount, currency), currency)
+}
+
+/// Format an amount in satoshis or nanogrin as BTC or GRIN without trailing zeros
+///
+/// # Arguments
+///
+/// * `amount` the amount in satoshis or nanogrin
+/// * `currency` the currency of the amount
+pub fn format_units(amount : u64, currency : Currency) -> String {
+    match currency {
+        Currency::BTC => format_decimal(amount as u128, BTC_SATS as u128, BTC_DECIMALS),
+        Currency::GRIN => format_decimal(amount as u128, NANO_GRIN as u128, GRIN_DECIMALS)
+    }
+}
+
+/// Reasons a decimal number can't be parsed
+#[derive(Debug, PartialEq)]
+pub enum DecimalError {
+    // not a decimal number
+    Invalid,
+    // more decimal places than allowed
+    Precision,
+    // the value doesn't fit
+    Overflow
+}
+
+/// Parse a non negative decimal number exactly, as integer in units of 10^-decimals
+///
+/// # Arguments
+///
+/// * `s` the decimal number, like `0.0016` or `.5`
+/// * `decimals` the allowed decimal places
+pub fn parse_decimal(s : &str, decimals : u32) -> Result<u128, DecimalError> {
+    let (int_part, frac_part) = match s.find('.') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "")
+    };
+    let all_digits = |p : &str| p.chars().all(|c| c.is_ascii_digit());
+    if (int_part.is_empty() && frac_part.is_empty()) || !all_digits(int_part) || !all_digits(frac_part) {
+        return Err(DecimalError::Invalid);
+    }
+    // trailing zeros don't add precision
+    let frac_part = frac_part.trim_end_matches('0');
+    if frac_part.len() > decimals as usize {
+        return Err(DecimalError::Precision);
+    }
+    let int_value = if int_part.is_empty() { 0 } else { int_part.parse::<u128>().map_err(|_| DecimalError::Overflow)? };
+    let frac_value = if frac_part.is_empty() { 0 } else { frac_part.parse::<u128>().map_err(|_| DecimalError::Overflow)? };
+    let scale = 10u128.pow(decimals);
+    int_value.checked_mul(scale)
+        .and_then(|v| v.checked_add(frac_value * 10u128.pow(decimals - frac_part.len() as u32)))
+        .ok_or(DecimalError::Overflow)
+}
+
+/// Format numerator / denominator as decimal, rounded down to the given places without trailing zeros
+///
+/// # Arguments
+///
+/// * `numerator` the numerator
+/// * `denominator` the denominator
+/// * `places` the maximum decimal places
+pub fn format_decimal(numerator : u128, denominator : u128, places : u32) -> String {
+    let int_part = numerator / denominator;
+    let scale = 10u128.pow(places);
+    let frac = (numerator % denominator) * scale / denominator;
+    let frac = format!("{:0width$}", frac, width = places as usize);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        int_part.to_string()
+    }
+    else {
+        format!("{}.{}", int_part, frac)
+    }
+}
+
+fn base_unit_name(currency : Currency) -> &'static str {
+    match currency {
+        Currency::BTC => "sats",
+        Currency::GRIN => "nanogrin"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::enums::Currency;
+
+    use super::{format_amount, parse_amount, parse_decimal, DecimalError};
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(160000, parse_amount("0.0016btc", Currency::BTC).unwrap());
+        assert_eq!(160000, parse_amount("0.0016 BTC", Currency::BTC).unwrap());
+        assert_eq!(1600, parse_amount("1600sat", Currency::BTC).unwrap());
+        assert_eq!(1600, parse_amount("1600sats", Currency::BTC).unwrap());
+        assert_eq!(1600, parse_amount("1600", Currency::BTC).unwrap());
+        assert_eq!(1500000000, parse_amount("1.5grin", Currency::GRIN).unwrap());
+        assert_eq!(1500000000, parse_amount("1500000000ng", Currency::GRIN).unwrap());
+        assert_eq!(1, parse_amount("0.000000001grin", Currency::GRIN).unwrap());
+        assert_eq!(100000000, parse_amount("1.000000000btc", Currency::BTC).unwrap());
+
+        // sub unit precision
+        assert!(parse_amount("0.000000001btc", Currency::BTC).is_err());
+        assert!(parse_amount("1.5sat", Currency::BTC).is_err());
+        assert!(parse_amount("0.0000000001grin", Currency::GRIN).is_err());
+        assert!(parse_amount("1.5", Currency::GRIN).is_err());
+        // wrong currency, unknown units and garbage
+        assert!(parse_amount("1.5grin", Currency::BTC).is_err());
+        assert!(parse_amount("1600sat", Currency::GRIN).is_err());
+        assert!(parse_amount("1mbtc", Currency::BTC).is_err());
+        assert!(parse_amount("-1btc", Currency::BTC).is_err());
+        assert!(parse_amount("1e8", Currency::BTC).is_err());
+        assert!(parse_amount("btc", Currency::BTC).is_err());
+        // maximum supply
+        assert_eq!(2100000000000000, parse_amount("21000000btc", Currency::BTC).unwrap());
+        assert!(parse_amount("21000000.00000001btc", Currency::BTC).is_err());
+        assert!(parse_amount("99999999999999999999999999999999999999999btc", Currency::BTC).is_err());
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(Ok(5), parse_decimal(".5", 1));
+        assert_eq!(Ok(50), parse_decimal("5.", 1));
+        assert_eq!(Err(DecimalError::Invalid), parse_decimal(".", 1));
+        assert_eq!(Err(DecimalError::Invalid), parse_decimal("1.2.3", 3));
+        assert_eq!(Err(DecimalError::Precision), parse_decimal("0.01", 1));
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!("1600 sats (0.000016 BTC)", format_amount(1600, Currency::BTC));
+        assert_eq!("1500000000 nanogrin (1.5 GRIN)", format_amount(1500000000, Currency::GRIN));
+        assert_eq!("0 sats (0 BTC)", format_amount(0, Currency::BTC));
+        assert_eq!("200000000 sats (2 BTC)", format_amount(200000000, Currency::BTC));
+    }
+}
@@ -1,11 +1,105 @@
-use rand::rngs::OsRng;
+use rand::rngs::{OsRng, StdRng};
+use rand::{CryptoRng, Error, Rng, RngCore, SeedableRng};
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::secp256k1::All;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn get_os_rng() -> OsRng {
-    OsRng::new().expect("Unable to initialize OSRNG")
+/// Randomness used for keys, nonces and swap ids. Swaps use the operating
+/// system generator, the seeded generator makes key generation and the
+/// messages of a protocol run reproducible for tests and test vectors.
+pub enum SwapRng {
+    Os(OsRng),
+    Seeded(StdRng)
+}
+
+impl SwapRng {
+    /// Deterministic generator, the same seed always yields the same bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` the seed of the generator
+    pub fn seeded(seed : u64) -> SwapRng {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        SwapRng::Seeded(StdRng::from_seed(bytes))
+    }
+
+    /// Derive a generator for a component which keeps its own, a seeded
+    /// generator derives a seeded one so the whole run stays reproducible
+    pub fn fork(&mut self) -> SwapRng {
+        match self {
+            SwapRng::Os(_) => get_os_rng(),
+            SwapRng::Seeded(rng) => SwapRng::Seeded(StdRng::from_seed(rng.gen()))
+        }
+    }
+}
+
+impl RngCore for SwapRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SwapRng::Os(rng) => rng.next_u32(),
+            SwapRng::Seeded(rng) => rng.next_u32()
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SwapRng::Os(rng) => rng.next_u64(),
+            SwapRng::Seeded(rng) => rng.next_u64()
+        }
+    }
+
+    fn fill_bytes(&mut self, dest : &mut [u8]) {
+        match self {
+            SwapRng::Os(rng) => rng.fill_bytes(dest),
+            SwapRng::Seeded(rng) => rng.fill_bytes(dest)
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest : &mut [u8]) -> Result<(), Error> {
+        match self {
+            SwapRng::Os(rng) => rng.try_fill_bytes(dest),
+            SwapRng::Seeded(rng) => rng.try_fill_bytes(dest)
+        }
+    }
+}
+
+impl CryptoRng for SwapRng {}
+
+pub fn get_os_rng() -> SwapRng {
+    SwapRng::Os(OsRng::new().expect("Unable to initialize OSRNG"))
 }
 
 pub fn get_secp256k1_curve() -> Secp256k1<All> {
     Secp256k1::new()
-}
\ No newline at end of file
+}
+
+/// Current unix timestamp in seconds
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before the unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use super::SwapRng;
+    use rand::Rng;
+
+    #[test]
+    fn test_seeded_rng() {
+        let mut a = SwapRng::seeded(7);
+        let mut b = SwapRng::seeded(7);
+        let mut c = SwapRng::seeded(8);
+        let x : [u8; 32] = a.gen();
+        assert_eq!(x, b.gen::<[u8; 32]>());
+        assert_ne!(x, c.gen::<[u8; 32]>());
+        // forks are reproducible as well and differ from their parent
+        let mut fork_a = a.fork();
+        let mut fork_b = b.fork();
+        let y : u64 = fork_a.gen();
+        assert_eq!(y, fork_b.gen::<u64>());
+        assert_ne!(y, a.gen::<u64>());
+    }
+}